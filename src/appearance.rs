@@ -0,0 +1,526 @@
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+use chrono::DateTime;
+use chrono::Datelike;
+use chrono::FixedOffset;
+use chrono::Local;
+use chrono::NaiveDate;
+use chrono::NaiveTime;
+use chrono::TimeDelta;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Deserializer;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Duration;
+use tokio::time::sleep;
+use tracing::error;
+use tracing::info;
+
+use crate::reload::ConfigReloader;
+use crate::state::Appearance;
+use crate::state::DaemonState;
+use crate::state::StateEvent;
+use crate::theme::Theme;
+
+/// Theme for light mode unless the config names another
+pub const DEFAULT_LIGHT_THEME: &str = "latte";
+
+/// Theme for dark mode unless the config names another
+pub const DEFAULT_DARK_THEME: &str = "mocha";
+
+/// Longest a schedule waits before looking at the clock again; timers stop
+/// while the Mac sleeps, the clock does not
+const SCHEDULE_RECHECK: Duration = Duration::from_secs(60,);
+
+/// Angle between the zenith and the center of the sun as its upper edge
+/// crosses the horizon, refraction included
+const SUNRISE_ZENITH: f64 = 90.833;
+
+/// What picks between the light and the dark theme
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema,)]
+#[serde(rename_all = "snake_case")]
+pub enum Follow {
+	/// Light or dark mode, as macOS switches between them
+	System,
+	/// `light_at` and `dark_at`, local time
+	Times,
+	/// Sunrise and sunset at `latitude` and `longitude`
+	Sun,
+}
+
+/// `[appearance]`: switching between a light and a dark theme
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppearanceConfig {
+	/// What picks between `light` and `dark`; left out, `theme` is kept
+	pub follow:    Option<Follow,>,
+	/// Theme for light mode, or the day
+	pub light:     String,
+	/// Theme for dark mode, or the night
+	pub dark:      String,
+	/// When the light theme starts with `follow = "times"`, as `HH:MM`
+	#[serde(deserialize_with = "time_of_day")]
+	#[schemars(with = "String", extend("pattern" = TIME_PATTERN, "default" = "07:00"))]
+	pub light_at:  NaiveTime,
+	/// When the dark theme starts with `follow = "times"`, as `HH:MM`
+	#[serde(deserialize_with = "time_of_day")]
+	#[schemars(with = "String", extend("pattern" = TIME_PATTERN, "default" = "19:00"))]
+	pub dark_at:   NaiveTime,
+	/// Degrees north, for `follow = "sun"`
+	#[schemars(range(min = -90.0, max = 90.0))]
+	pub latitude:  Option<f64,>,
+	/// Degrees east, for `follow = "sun"`
+	#[schemars(range(min = -180.0, max = 180.0))]
+	pub longitude: Option<f64,>,
+}
+
+const TIME_PATTERN: &str = "^([01]?[0-9]|2[0-3]):[0-5][0-9]$";
+
+impl Default for AppearanceConfig {
+	fn default() -> Self {
+		Self {
+			follow:    None,
+			light:     DEFAULT_LIGHT_THEME.to_string(),
+			dark:      DEFAULT_DARK_THEME.to_string(),
+			light_at:  NaiveTime::from_hms_opt(7, 0, 0,).unwrap_or_default(),
+			dark_at:   NaiveTime::from_hms_opt(19, 0, 0,).unwrap_or_default(),
+			latitude:  None,
+			longitude: None,
+		}
+	}
+}
+
+impl AppearanceConfig {
+	/// Fail on themes that are not there and on schedules that never switch
+	pub fn check(&self, themes: &BTreeMap<String, Theme,>,) -> Result<(),> {
+		Theme::resolve(&self.light, themes,).context("appearance.light",)?;
+		Theme::resolve(&self.dark, themes,).context("appearance.dark",)?;
+		match self.follow {
+			Some(Follow::Times,) if self.light_at == self.dark_at => {
+				bail!("appearance.light_at and appearance.dark_at are both {}", self.light_at)
+			},
+			Some(Follow::Sun,) => {
+				let (Some(latitude,), Some(longitude,),) = (self.latitude, self.longitude,) else {
+					bail!("appearance.follow = \"sun\" needs a latitude and a longitude");
+				};
+				if !(-90.0..=90.0).contains(&latitude,) {
+					bail!("appearance.latitude: {} is not between -90 and 90", latitude);
+				}
+				if !(-180.0..=180.0).contains(&longitude,) {
+					bail!("appearance.longitude: {} is not between -180 and 180", longitude);
+				}
+			},
+			_ => {},
+		}
+		Ok((),)
+	}
+
+	/// The theme to switch to for `appearance`
+	pub fn theme_for(&self, appearance: Appearance,) -> &str {
+		match appearance {
+			Appearance::Light => &self.light,
+			Appearance::Dark => &self.dark,
+		}
+	}
+
+	/// When to switch, for the policies that go by the clock
+	pub fn schedule(&self,) -> Option<Schedule,> {
+		match (self.follow?, self.latitude, self.longitude,) {
+			(Follow::Times, ..,) => {
+				Some(Schedule::Times { light_at: self.light_at, dark_at: self.dark_at, },)
+			},
+			(Follow::Sun, Some(latitude,), Some(longitude,),) => {
+				Some(Schedule::Sun { latitude, longitude, },)
+			},
+			_ => None,
+		}
+	}
+}
+
+/// Light and dark on a clock
+#[derive(Debug, Clone, Copy, PartialEq,)]
+pub enum Schedule {
+	/// Light from `light_at`, dark from `dark_at`, every day
+	Times { light_at: NaiveTime, dark_at: NaiveTime, },
+	/// Light from sunrise, dark from sunset
+	Sun { latitude: f64, longitude: f64, },
+}
+
+impl Schedule {
+	/// Light or dark at `now`
+	pub fn appearance_at(&self, now: DateTime<FixedOffset,>,) -> Appearance {
+		let date = now.date_naive();
+		let mut switches: Vec<(DateTime<FixedOffset,>, Appearance,),> =
+			[date.pred_opt(), Some(date,),]
+				.into_iter()
+				.flatten()
+				.flat_map(|date| self.switches(date, *now.offset(),),)
+				.collect();
+		switches.sort_by_key(|(at, _,)| *at,);
+		switches.iter().rev().find(|(at, _,)| *at <= now,).map_or(Appearance::Light, |(_, a,)| *a,)
+	}
+
+	/// When the appearance next differs from the one at `now`, within a year
+	pub fn next_change(&self, now: DateTime<FixedOffset,>,) -> Option<DateTime<FixedOffset,>,> {
+		let current = self.appearance_at(now,);
+		now.date_naive()
+			.iter_days()
+			.take(366,)
+			.flat_map(|date| self.switches(date, *now.offset(),),)
+			.find(|(at, appearance,)| *at > now && *appearance != current,)
+			.map(|(at, _,)| at,)
+	}
+
+	/// The switches on `date` at `offset`, in order; a day without sunrise
+	/// or sunset switches at midnight
+	fn switches(
+		&self,
+		date: NaiveDate,
+		offset: FixedOffset,
+	) -> Vec<(DateTime<FixedOffset,>, Appearance,),> {
+		let local = |time: NaiveTime| {
+			DateTime::<FixedOffset,>::from_naive_utc_and_offset(
+				date.and_time(time,) - offset,
+				offset,
+			)
+		};
+		let mut switches = match *self {
+			Schedule::Times { light_at, dark_at, } => {
+				vec![(local(light_at,), Appearance::Light,), (local(dark_at,), Appearance::Dark,)]
+			},
+			Schedule::Sun { latitude, longitude, } => match daylight(date, latitude, longitude,) {
+				Daylight::Between { sunrise, sunset, } => vec![
+					(sunrise.with_timezone(&offset,), Appearance::Light,),
+					(sunset.with_timezone(&offset,), Appearance::Dark,),
+				],
+				Daylight::PolarDay => vec![(local(NaiveTime::MIN,), Appearance::Light,)],
+				Daylight::PolarNight => vec![(local(NaiveTime::MIN,), Appearance::Dark,)],
+			},
+		};
+		switches.sort_by_key(|(at, _,)| *at,);
+		switches
+	}
+}
+
+/// Whether and when the sun is up on one day
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum Daylight {
+	Between { sunrise: DateTime<Utc,>, sunset: DateTime<Utc,>, },
+	/// The sun does not set
+	PolarDay,
+	/// The sun does not rise
+	PolarNight,
+}
+
+/// Sunrise and sunset on `date` at `latitude` degrees north and `longitude`
+/// degrees east, after NOAA's general solar position calculations
+///
+/// Good to a minute or two away from the poles, which is plenty to switch
+/// themes by.
+pub fn daylight(date: NaiveDate, latitude: f64, longitude: f64,) -> Daylight {
+	let days_in_year = if date.leap_year() { 366.0 } else { 365.0 };
+	// Fractional year at noon, in radians
+	let year = 2.0 * PI / days_in_year * f64::from(date.ordinal0(),);
+	let equation_of_time = 229.18
+		* (0.000075 + 0.001868 * year.cos()
+			- 0.032077 * year.sin()
+			- 0.014615 * (2.0 * year).cos()
+			- 0.040849 * (2.0 * year).sin());
+	let declination = 0.006918 - 0.399912 * year.cos() + 0.070257 * year.sin()
+		- 0.006758 * (2.0 * year).cos()
+		+ 0.000907 * (2.0 * year).sin()
+		- 0.002697 * (3.0 * year).cos()
+		+ 0.00148 * (3.0 * year).sin();
+
+	let latitude = latitude.to_radians();
+	let cos_hour_angle = SUNRISE_ZENITH.to_radians().cos() / (latitude.cos() * declination.cos())
+		- latitude.tan() * declination.tan();
+	if cos_hour_angle < -1.0 {
+		return Daylight::PolarDay;
+	}
+	if cos_hour_angle > 1.0 {
+		return Daylight::PolarNight;
+	}
+
+	// Minutes after midnight UTC
+	let hour_angle = cos_hour_angle.acos().to_degrees();
+	let noon = 720.0 - 4.0 * longitude - equation_of_time;
+	let midnight = date.and_time(NaiveTime::MIN,).and_utc();
+	let at = |minutes: f64| midnight + TimeDelta::seconds((minutes * 60.0).round() as i64,);
+	Daylight::Between {
+		sunrise: at(noon - 4.0 * hour_angle,),
+		sunset:  at(noon + 4.0 * hour_angle,),
+	}
+}
+
+/// The local time, with the offset in effect
+pub type LocalClock = Arc<dyn Fn() -> DateTime<FixedOffset,> + Send + Sync,>;
+
+/// The system clock in the local time zone
+pub fn local_clock() -> LocalClock {
+	Arc::new(|| Local::now().fixed_offset(),)
+}
+
+/// Switches the theme between the light and the dark one of the active
+/// config, as its `[appearance]` says
+///
+/// Only acts when the wanted appearance changes, so a theme picked with
+/// `ctl theme` stays until the next switch.
+#[derive(Clone,)]
+pub struct AppearanceFollower {
+	reloader: ConfigReloader,
+	state:    DaemonState,
+	clock:    LocalClock,
+}
+
+impl std::fmt::Debug for AppearanceFollower {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		f.debug_struct("AppearanceFollower",)
+			.field("reloader", &self.reloader,)
+			.finish_non_exhaustive()
+	}
+}
+
+impl AppearanceFollower {
+	pub fn new(reloader: ConfigReloader, state: DaemonState,) -> Self {
+		Self { reloader, state, clock: local_clock(), }
+	}
+
+	/// Tell the time from `clock` instead of the system clock
+	pub fn with_clock(mut self, clock: LocalClock,) -> Self {
+		self.clock = clock;
+		self
+	}
+
+	/// Follow the appearance of every config applied, from now on
+	pub async fn run(&self,) -> Result<(),> {
+		let mut configs = self.reloader.subscribe();
+		let mut events = self.state.subscribe();
+		let mut applied: Option<(AppearanceConfig, Appearance,),> = None;
+
+		loop {
+			let config = configs.borrow_and_update().config.appearance.clone();
+			let now = (self.clock)();
+			let wanted = match config.follow {
+				None => None,
+				Some(Follow::System,) => *self.state.appearance.read().await,
+				Some(Follow::Times | Follow::Sun,) => {
+					config.schedule().map(|schedule| schedule.appearance_at(now,),)
+				},
+			};
+
+			if let Some(appearance,) = wanted
+				&& applied.as_ref() != Some(&(config.clone(), appearance,),)
+			{
+				let theme = config.theme_for(appearance,);
+				info!("🌗 Switching to the {} theme, {}", appearance, theme);
+				if let Err(e,) = self.reloader.switch_theme(theme,).await {
+					error!("❌ Keeping the running theme: {:#}", e);
+				}
+				applied = Some((config.clone(), appearance,),);
+			}
+
+			let wait = config.schedule().map(|schedule| match schedule.next_change(now,) {
+				Some(at,) => (at - now).to_std().unwrap_or_default().min(SCHEDULE_RECHECK,),
+				None => SCHEDULE_RECHECK,
+			},);
+			tokio::select! {
+				_ = configs.changed() => {},
+				_ = appearance_changed(&mut events,) => {},
+				_ = async {
+					match wait {
+						Some(wait,) => sleep(wait,).await,
+						None => std::future::pending().await,
+					}
+				} => {},
+			}
+		}
+	}
+}
+
+/// Wait for the state to publish a new appearance
+async fn appearance_changed(events: &mut broadcast::Receiver<StateEvent,>,) {
+	loop {
+		match events.recv().await {
+			Ok(StateEvent::AppearanceChanged(_,),) | Err(RecvError::Lagged(_,),) => return,
+			Ok(_,) => {},
+			Err(RecvError::Closed,) => std::future::pending().await,
+		}
+	}
+}
+
+fn time_of_day<'de, D: Deserializer<'de,>,>(d: D,) -> Result<NaiveTime, D::Error,> {
+	let time = String::deserialize(d,)?;
+	NaiveTime::parse_from_str(&time, "%H:%M",).map_err(|_| {
+		serde::de::Error::custom(format!("invalid time \"{}\", expected HH:MM", time),)
+	},)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::bars::BarManager;
+	use crate::config::Config;
+	use crate::items::ItemRegistry;
+	use crate::wm::ScriptedBackend;
+	use tokio::time::timeout;
+
+	fn at(time: &str,) -> DateTime<FixedOffset,> {
+		DateTime::parse_from_rfc3339(time,).unwrap()
+	}
+
+	fn minutes_between(a: DateTime<Utc,>, b: &str,) -> i64 {
+		(a - at(b,).with_timezone(&Utc,)).num_minutes().abs()
+	}
+
+	#[test]
+	fn test_sunrise_and_sunset() {
+		// London at the summer solstice: 04:43 and 21:21 BST
+		let date = NaiveDate::from_ymd_opt(2024, 6, 21,).unwrap();
+		let Daylight::Between { sunrise, sunset, } = daylight(date, 51.5074, -0.1278,) else {
+			panic!("the sun rises in London");
+		};
+		assert!(minutes_between(sunrise, "2024-06-21T03:43:00Z") <= 2, "{}", sunrise);
+		assert!(minutes_between(sunset, "2024-06-21T20:21:00Z") <= 2, "{}", sunset);
+
+		// Sydney rises on the day before in UTC: 05:41 and 20:05 AEDT
+		let date = NaiveDate::from_ymd_opt(2024, 12, 21,).unwrap();
+		let Daylight::Between { sunrise, sunset, } = daylight(date, -33.8688, 151.2093,) else {
+			panic!("the sun rises in Sydney");
+		};
+		assert!(minutes_between(sunrise, "2024-12-20T18:41:00Z") <= 2, "{}", sunrise);
+		assert!(minutes_between(sunset, "2024-12-21T09:05:00Z") <= 2, "{}", sunset);
+	}
+
+	#[test]
+	fn test_polar_day_and_night() {
+		let tromso = |month, day| {
+			daylight(NaiveDate::from_ymd_opt(2024, month, day,).unwrap(), 69.6492, 18.9553,)
+		};
+		assert_eq!(tromso(6, 21), Daylight::PolarDay);
+		assert_eq!(tromso(12, 21), Daylight::PolarNight);
+		assert!(matches!(tromso(3, 20), Daylight::Between { .. }));
+		let pole = daylight(NaiveDate::from_ymd_opt(2024, 6, 21,).unwrap(), -90.0, 0.0,);
+		assert_eq!(pole, Daylight::PolarNight);
+	}
+
+	#[test]
+	fn test_times_schedule() {
+		let schedule = Schedule::Times {
+			light_at: NaiveTime::from_hms_opt(7, 0, 0,).unwrap(),
+			dark_at:  NaiveTime::from_hms_opt(19, 30, 0,).unwrap(),
+		};
+		assert_eq!(schedule.appearance_at(at("2024-03-01T06:59:00+01:00")), Appearance::Dark);
+		assert_eq!(schedule.appearance_at(at("2024-03-01T07:00:00+01:00")), Appearance::Light);
+		assert_eq!(schedule.appearance_at(at("2024-03-01T23:00:00+01:00")), Appearance::Dark);
+		assert_eq!(
+			schedule.next_change(at("2024-03-01T12:00:00+01:00")),
+			Some(at("2024-03-01T19:30:00+01:00"))
+		);
+		assert_eq!(
+			schedule.next_change(at("2024-03-01T21:00:00+01:00")),
+			Some(at("2024-03-02T07:00:00+01:00"))
+		);
+
+		// Dark past midnight, into a light morning
+		let night_owl = Schedule::Times {
+			light_at: NaiveTime::from_hms_opt(10, 0, 0,).unwrap(),
+			dark_at:  NaiveTime::from_hms_opt(1, 0, 0,).unwrap(),
+		};
+		assert_eq!(night_owl.appearance_at(at("2024-03-01T00:30:00Z")), Appearance::Light);
+		assert_eq!(night_owl.appearance_at(at("2024-03-01T09:00:00Z")), Appearance::Dark);
+	}
+
+	#[test]
+	fn test_sun_schedule() {
+		let london = Schedule::Sun { latitude: 51.5074, longitude: -0.1278, };
+		assert_eq!(london.appearance_at(at("2024-06-21T12:00:00+01:00")), Appearance::Light);
+		assert_eq!(london.appearance_at(at("2024-06-21T23:00:00+01:00")), Appearance::Dark);
+		let sunrise = london.next_change(at("2024-06-21T23:00:00+01:00"),).unwrap();
+		assert_eq!(sunrise.date_naive(), NaiveDate::from_ymd_opt(2024, 6, 22).unwrap());
+		assert_eq!(london.appearance_at(sunrise), Appearance::Light);
+
+		// Light through the polar day, until the first sunset after it
+		let tromso = Schedule::Sun { latitude: 69.6492, longitude: 18.9553, };
+		assert_eq!(tromso.appearance_at(at("2024-06-21T02:00:00+02:00")), Appearance::Light);
+		let sunset = tromso.next_change(at("2024-06-21T02:00:00+02:00"),).unwrap();
+		assert_eq!(sunset.date_naive().month0(), 6, "{}", sunset);
+	}
+
+	#[test]
+	fn test_config_checks_the_policy() {
+		let error = |toml: &str| format!("{:#}", Config::parse(toml,).unwrap_err());
+
+		let times = "[appearance]\nfollow = \"times\"\ndark_at = \"20:15\"\n";
+		let config = Config::parse(times,).unwrap();
+		assert_eq!(config.appearance.dark_at, NaiveTime::from_hms_opt(20, 15, 0).unwrap());
+		assert!(error("[appearance]\nlight_at = \"7am\"\n").contains("invalid time \"7am\""));
+		let same = error("[appearance]\nfollow = \"times\"\nlight_at = \"19:00\"\n",);
+		assert!(same.contains("are both 19:00"), "{}", same);
+		let sun = error("[appearance]\nfollow = \"sun\"\nlatitude = 52.5\n",);
+		assert!(sun.contains("needs a latitude and a longitude"), "{}", sun);
+		let theme = error("[appearance]\ndark = \"nord\"\n",);
+		assert!(theme.contains("appearance.dark: unknown theme `nord`"), "{}", theme);
+	}
+
+	#[tokio::test]
+	async fn test_follower_switches_with_the_system() -> Result<(),> {
+		let config = Config::parse("[appearance]\nfollow = \"system\"\n",)?;
+		let state = DaemonState::new().with_backend(Arc::new(ScriptedBackend::new(),),);
+		let reloader = ConfigReloader::new(
+			std::env::temp_dir().join(format!("sbd-config-follow-{}.toml", std::process::id()),),
+			config.clone(),
+			ItemRegistry::from_config(&config,)?,
+			state.clone(),
+			BarManager::new(state.clone(),),
+		);
+		let mut configs = reloader.subscribe();
+		let follower = AppearanceFollower::new(reloader.clone(), state.clone(),);
+		let task = tokio::spawn(async move { follower.run().await },);
+
+		state.apply_appearance(Appearance::Light,).await;
+		timeout(Duration::from_secs(1,), configs.changed(),).await??;
+		assert_eq!(reloader.active().config.theme, "latte");
+
+		// A theme picked by hand stays until macOS switches again
+		reloader.switch_theme("gruvbox",).await?;
+		state.apply_appearance(Appearance::Light,).await;
+		sleep(Duration::from_millis(50,),).await;
+		assert_eq!(reloader.active().config.theme, "gruvbox");
+		configs.borrow_and_update();
+		state.apply_appearance(Appearance::Dark,).await;
+		timeout(Duration::from_secs(1,), configs.changed(),).await??;
+		assert_eq!(reloader.active().config.theme, "mocha");
+
+		task.abort();
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_follower_goes_by_the_clock() -> Result<(),> {
+		let config = Config::parse("[appearance]\nfollow = \"times\"\n",)?;
+		let state = DaemonState::new().with_backend(Arc::new(ScriptedBackend::new(),),);
+		let reloader = ConfigReloader::new(
+			std::env::temp_dir().join(format!("sbd-config-clock-{}.toml", std::process::id()),),
+			config.clone(),
+			ItemRegistry::from_config(&config,)?,
+			state.clone(),
+			BarManager::new(state.clone(),),
+		);
+		let mut configs = reloader.subscribe();
+		let follower = AppearanceFollower::new(reloader.clone(), state,)
+			.with_clock(Arc::new(|| at("2024-03-01T12:00:00+01:00",),),);
+		let task = tokio::spawn(async move { follower.run().await },);
+
+		timeout(Duration::from_secs(1,), configs.changed(),).await??;
+		assert_eq!(reloader.active().config.theme, "latte");
+		task.abort();
+		Ok((),)
+	}
+}
@@ -0,0 +1,445 @@
+use anyhow::Result;
+use futures::FutureExt;
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::oneshot;
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::BarItem;
+use crate::items::ItemRegistry;
+use crate::provision;
+use crate::provision::BarStatus;
+use crate::provision::BarWatchdog;
+use crate::sketchybar::SketchyBar;
+use crate::state::DaemonState;
+
+/// Commands queued for one bar before the rest are dropped
+const MAILBOX_SIZE: usize = 64;
+
+/// Name of the bar for a display: the builtin one is the default
+/// `sketchybar`, external ones are numbered after their display
+pub fn bar_name(display_info: &DisplayInfo,) -> String {
+	if display_info.is_builtin {
+		"sketchybar".to_string()
+	} else {
+		format!("external_{}", display_info.index)
+	}
+}
+
+/// Work for a bar actor
+enum BarCommand {
+	Render(Arc<dyn BarItem,>,),
+	Provision {
+		display_info: DisplayInfo,
+		registry:     ItemRegistry,
+		restyle:      bool,
+		reply:        oneshot::Sender<Result<(),>,>,
+	},
+	Heartbeat {
+		display_info: DisplayInfo,
+		registry:     ItemRegistry,
+	},
+	Status(oneshot::Sender<Option<BarStatus,>,>,),
+}
+
+impl std::fmt::Debug for BarCommand {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		match self {
+			BarCommand::Render(item,) => write!(f, "Render({})", item.name()),
+			BarCommand::Provision { .. } => write!(f, "Provision"),
+			BarCommand::Heartbeat { .. } => write!(f, "Heartbeat"),
+			BarCommand::Status(_,) => write!(f, "Status"),
+		}
+	}
+}
+
+/// Address of the actor that owns one bar
+///
+/// The actor works through its mailbox in order, so a bar that hangs only
+/// delays its own commands; senders never wait on it.
+#[derive(Debug, Clone,)]
+pub struct BarHandle {
+	display_id: String,
+	bar_name:   String,
+	mailbox:    mpsc::Sender<BarCommand,>,
+}
+
+impl BarHandle {
+	/// Start the actor for `bar` on the display `display_id`
+	pub fn spawn(display_id: &str, bar: SketchyBar, state: DaemonState,) -> Self {
+		let (mailbox, commands,) = mpsc::channel(MAILBOX_SIZE,);
+		let handle = Self {
+			display_id: display_id.to_string(),
+			bar_name: bar.get_bar_name().to_string(),
+			mailbox,
+		};
+		tokio::spawn(run_actor(display_id.to_string(), bar, state, commands,),);
+		handle
+	}
+
+	pub fn display_id(&self,) -> &str {
+		&self.display_id
+	}
+
+	pub fn bar_name(&self,) -> &str {
+		&self.bar_name
+	}
+
+	/// Queue a render of `item`, unless the bar is too far behind
+	pub fn render(&self, item: Arc<dyn BarItem,>,) -> bool {
+		self.send(BarCommand::Render(item,),)
+	}
+
+	/// Queue a full setup; the receiver resolves once it is done, or errors
+	/// if the bar is not taking commands
+	pub fn provision(
+		&self,
+		display_info: DisplayInfo,
+		registry: ItemRegistry,
+	) -> oneshot::Receiver<Result<(),>,> {
+		self.queue_provision(display_info, registry, false,)
+	}
+
+	/// [`Self::provision`] after the default properties changed: items
+	/// already on the bar take up the new ones too
+	pub fn restyle(
+		&self,
+		display_info: DisplayInfo,
+		registry: ItemRegistry,
+	) -> oneshot::Receiver<Result<(),>,> {
+		self.queue_provision(display_info, registry, true,)
+	}
+
+	fn queue_provision(
+		&self,
+		display_info: DisplayInfo,
+		registry: ItemRegistry,
+		restyle: bool,
+	) -> oneshot::Receiver<Result<(),>,> {
+		let (reply, result,) = oneshot::channel();
+		self.send(BarCommand::Provision { display_info, registry, restyle, reply, },);
+		result
+	}
+
+	/// Queue a heartbeat; the actor provisions the bar again if it came back empty
+	pub fn heartbeat(&self, display_info: DisplayInfo, registry: ItemRegistry,) -> bool {
+		self.send(BarCommand::Heartbeat { display_info, registry, },)
+	}
+
+	/// Last heartbeat result, once the actor gets to it
+	pub async fn status(&self,) -> Option<BarStatus,> {
+		let (reply, status,) = oneshot::channel();
+		if !self.send(BarCommand::Status(reply,),) {
+			return None;
+		}
+		status.await.ok().flatten()
+	}
+
+	fn send(&self, command: BarCommand,) -> bool {
+		match self.mailbox.try_send(command,) {
+			Ok(_,) => true,
+			Err(TrySendError::Full(command,),) => {
+				warn!("⏳ Bar '{}' is falling behind, dropping {:?}", self.bar_name, command);
+				false
+			},
+			Err(TrySendError::Closed(_,),) => false,
+		}
+	}
+}
+
+/// Owns one actor per bar, keyed by display id
+#[derive(Debug, Clone,)]
+pub struct BarManager {
+	state: DaemonState,
+	bars:  Arc<Mutex<HashMap<String, BarHandle,>,>,>,
+}
+
+impl BarManager {
+	pub fn new(state: DaemonState,) -> Self {
+		Self { state, bars: Arc::new(Mutex::new(HashMap::new(),),), }
+	}
+
+	/// Start an actor for `bar`, replacing the one for `display_id` if any
+	pub fn add(&self, display_id: &str, bar: SketchyBar,) -> BarHandle {
+		let handle = BarHandle::spawn(display_id, bar, self.state.clone(),);
+		self.bars.lock().unwrap().insert(display_id.to_string(), handle.clone(),);
+		handle
+	}
+
+	/// Forget the bar for `display_id`; its actor stops once its mailbox is drained
+	pub fn remove(&self, display_id: &str,) -> Option<BarHandle,> {
+		self.bars.lock().unwrap().remove(display_id,)
+	}
+
+	pub fn get(&self, display_id: &str,) -> Option<BarHandle,> {
+		self.bars.lock().unwrap().get(display_id,).cloned()
+	}
+
+	pub fn contains(&self, display_id: &str,) -> bool {
+		self.bars.lock().unwrap().contains_key(display_id,)
+	}
+
+	/// Every bar, in no particular order
+	pub fn handles(&self,) -> Vec<BarHandle,> {
+		self.bars.lock().unwrap().values().cloned().collect()
+	}
+
+	/// Queue a render of `item` on every bar
+	pub fn render(&self, item: &Arc<dyn BarItem,>,) {
+		for handle in self.handles() {
+			handle.render(item.clone(),);
+		}
+	}
+
+	/// Queue a render of `item` on the bar for `display_id`
+	pub fn render_on(&self, display_id: &str, item: &Arc<dyn BarItem,>,) {
+		if let Some(handle,) = self.get(display_id,) {
+			handle.render(item.clone(),);
+		}
+	}
+
+	/// Queue a heartbeat on every bar whose display is known
+	pub fn heartbeat(&self, displays: &HashMap<String, DisplayInfo,>, registry: &ItemRegistry,) {
+		for handle in self.handles() {
+			if let Some(display_info,) = displays.get(handle.display_id(),) {
+				handle.heartbeat(display_info.clone(), registry.clone(),);
+			}
+		}
+	}
+}
+
+/// Work through the commands for one bar until every handle is gone
+async fn run_actor(
+	display_id: String,
+	mut bar: SketchyBar,
+	state: DaemonState,
+	mut commands: mpsc::Receiver<BarCommand,>,
+) {
+	let bar_name = bar.get_bar_name().to_string();
+	let mut watchdog = BarWatchdog::new();
+	debug!("🎬 Bar actor for '{}' started", bar_name);
+
+	while let Some(command,) = commands.recv().await {
+		match command {
+			BarCommand::Render(item,) => render(item.as_ref(), &bar, &state, &display_id,).await,
+			BarCommand::Provision { display_info, registry, restyle, reply, } => {
+				let result = if restyle {
+					restyle_bar(&mut bar, &display_info, &registry, &state, &display_id,).await
+				} else {
+					provision(&mut bar, &display_info, &registry, &state, &display_id,).await
+				};
+				match &result {
+					Ok(_,) => {
+						watchdog.record(&bar_name, BarStatus::Provisioned,);
+						info!("✅ Bar '{}' configured for display {}", bar_name, display_id);
+					},
+					Err(e,) => {
+						warn!("⚠️  Failed to set up bar {}, will retry: {}", bar_name, e)
+					},
+				}
+				let _ = reply.send(result,);
+			},
+			BarCommand::Heartbeat { display_info, registry, } => {
+				if !watchdog.needs_provisioning(&bar,).await {
+					continue;
+				}
+				match provision(&mut bar, &display_info, &registry, &state, &display_id,).await {
+					Ok(_,) => {
+						watchdog.record(&bar_name, BarStatus::Provisioned,);
+						info!("✅ Bar '{}' re-provisioned", bar_name);
+					},
+					Err(e,) => warn!("❌ Failed to re-provision bar '{}': {}", bar_name, e),
+				}
+			},
+			BarCommand::Status(reply,) => {
+				let _ = reply.send(watchdog.status(&bar_name,),);
+			},
+		}
+	}
+
+	debug!("🎬 Bar actor for '{}' stopped", bar_name);
+}
+
+/// Set the bar up and tell items to draw on it again
+async fn provision(
+	bar: &mut SketchyBar,
+	display_info: &DisplayInfo,
+	registry: &ItemRegistry,
+	state: &DaemonState,
+	display_id: &str,
+) -> Result<(),> {
+	provision::provision_bar(bar, display_info, registry,).await?;
+	state.mark_provisioned(display_id,);
+	Ok((),)
+}
+
+/// [`provision`], restyling the items already on the bar as well
+async fn restyle_bar(
+	bar: &mut SketchyBar,
+	display_info: &DisplayInfo,
+	registry: &ItemRegistry,
+	state: &DaemonState,
+	display_id: &str,
+) -> Result<(),> {
+	provision::restyle_bar(bar, display_info, registry,).await?;
+	state.mark_provisioned(display_id,);
+	Ok((),)
+}
+
+/// Render `item` on `bar`, if it applies to the bar's display
+async fn render(item: &dyn BarItem, bar: &SketchyBar, state: &DaemonState, display_id: &str,) {
+	let applies = match state.displays.read().await.get(display_id,) {
+		Some(display,) => item.applies_to(display,),
+		None => true,
+	};
+	if !applies {
+		return;
+	}
+	// A panicking item must not take the bar down with it
+	match AssertUnwindSafe(item.render(bar, state,),).catch_unwind().await {
+		Ok(Ok(_,),) => {},
+		Ok(Err(e,),) => error!("❌ {} update error: {}", item.name(), e),
+		Err(_,) => {
+			error!("💥 {} panicked while rendering on '{}'", item.name(), bar.get_bar_name())
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::helpers::yabai::DisplayFrame;
+	use crate::items::ItemSpec;
+	use crate::sketchybar::ItemProperties;
+	use crate::sketchybar::RecordingTransport;
+	use crate::state::StateEvent;
+	use async_trait::async_trait;
+	use tokio::time::Duration;
+	use tokio::time::sleep;
+
+	fn display() -> DisplayInfo {
+		DisplayInfo {
+			index:      1,
+			is_builtin: true,
+			frame:      DisplayFrame { x: 0.0, y: 0.0, w: 1920.0, h: 1080.0, },
+		}
+	}
+
+	fn recording_bar(bar_name: &str, transport: &RecordingTransport,) -> SketchyBar {
+		let mut bar = SketchyBar::with_transport(Arc::new(transport.clone(),),);
+		bar.set_bar_name(bar_name,);
+		bar
+	}
+
+	/// Never finishes rendering on the bar named `stuck`
+	struct Stuck;
+
+	#[async_trait]
+	impl BarItem for Stuck {
+		fn name(&self,) -> &'static str {
+			"stuck"
+		}
+
+		fn setup(&self, _display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+			Vec::new()
+		}
+
+		fn cadence(&self,) -> Option<Duration,> {
+			None
+		}
+
+		async fn render(&self, bar: &SketchyBar, _state: &DaemonState,) -> Result<(),> {
+			if bar.get_bar_name() == "stuck" {
+				std::future::pending::<(),>().await;
+			}
+			bar.set("stuck", ItemProperties::label("done",),).await
+		}
+	}
+
+	#[tokio::test]
+	async fn test_provision_resolves_once_bar_is_set_up() -> Result<(),> {
+		let transport = RecordingTransport::new();
+		let state = DaemonState::new();
+		let mut events = state.subscribe();
+		let bars = BarManager::new(state.clone(),);
+		let handle = bars.add("1", recording_bar("sketchybar", &transport,),);
+
+		handle.provision(display(), ItemRegistry::with_defaults(),).await??;
+		assert!(transport.received("sketchybar", "--add item clock right"));
+		assert_eq!(events.try_recv()?, StateEvent::BarProvisioned("1".to_string()));
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_heartbeat_reprovisions_restarted_bar() -> Result<(),> {
+		let transport = RecordingTransport::new();
+		let bars = BarManager::new(DaemonState::new(),);
+		let handle = bars.add("1", recording_bar("sketchybar", &transport,),);
+		let displays = HashMap::from([("1".to_string(), display(),),],);
+		let registry = ItemRegistry::with_defaults();
+
+		// Daemon started before sketchybar: the first setup is left to the heartbeat
+		transport.set_unreachable("sketchybar", true,);
+		assert!(handle.provision(display(), registry.clone(),).await?.is_err());
+		bars.heartbeat(&displays, &registry,);
+		assert_eq!(handle.status().await, Some(BarStatus::Unreachable));
+
+		transport.set_unreachable("sketchybar", false,);
+		bars.heartbeat(&displays, &registry,);
+		assert_eq!(handle.status().await, Some(BarStatus::Provisioned));
+
+		// sketchybar restarts and comes back with no items
+		transport.restart("sketchybar",);
+		transport.clear();
+		bars.heartbeat(&displays, &registry,);
+		assert_eq!(handle.status().await, Some(BarStatus::Provisioned));
+		assert!(transport.received("sketchybar", "--add item clock right"));
+		Ok((),)
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_stuck_bar_does_not_hold_up_others() {
+		let transport = RecordingTransport::new();
+		let bars = BarManager::new(DaemonState::new(),);
+		let stuck = bars.add("1", recording_bar("stuck", &transport,),);
+		let healthy = bars.add("2", recording_bar("healthy", &transport,),);
+
+		let item: Arc<dyn BarItem,> = Arc::new(Stuck,);
+		bars.render(&item,);
+		bars.render(&item,);
+		sleep(Duration::from_secs(1,),).await;
+
+		assert_eq!(transport.sent_to("healthy").len(), 2);
+		assert!(transport.sent_to("stuck").is_empty());
+		// The stuck bar's mailbox fills up, and only its own renders are dropped
+		let queued = (0..=MAILBOX_SIZE).take_while(|_| stuck.render(item.clone(),),).count();
+		assert_eq!(queued, MAILBOX_SIZE - 1);
+		assert!(healthy.render(item.clone()));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_removed_bar_gets_no_renders() {
+		let transport = RecordingTransport::new();
+		let bars = BarManager::new(DaemonState::new(),);
+		let handle = bars.add("1", recording_bar("sketchybar", &transport,),);
+		let mailbox = handle.mailbox.downgrade();
+		drop(handle,);
+
+		assert!(bars.remove("1").is_some());
+		assert!(!bars.contains("1"));
+		// No handle is left, so the actor has nothing more to wait for
+		assert!(mailbox.upgrade().is_none());
+
+		bars.render(&(Arc::new(Stuck,) as Arc<dyn BarItem,>),);
+		sleep(Duration::from_secs(1,),).await;
+		assert!(transport.sent().is_empty());
+	}
+}
@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+
+use super::Config;
+use super::file::sample_display;
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::ItemRegistry;
+use crate::sketchybar::properties::ItemPosition;
+
+/// What is wrong with a config that loads: settings that are ignored, and
+/// ones that put bars or items where they cannot be drawn
+///
+/// Each problem starts with the key at fault.
+pub fn problems(config: &Config,) -> Vec<String,> {
+	let mut problems = Vec::new();
+
+	let intervals = [
+		("intervals.state_sync", config.intervals.state_sync,),
+		("intervals.safety_net_sync", config.intervals.safety_net_sync,),
+	];
+	for (key, interval,) in intervals {
+		if interval.is_zero() {
+			problems.push(format!("{}: must be at least 1 second", key),);
+		}
+	}
+
+	let bars = [("bar.builtin", &config.bar.builtin,), ("bar.external", &config.bar.external,),];
+	for (key, bar,) in bars {
+		if let Some(display,) = bar.display {
+			problems.push(format!(
+				"{}.display: every bar goes on its own display; pinning them to display {} \
+				 stacks them there",
+				key, display
+			),);
+		}
+	}
+
+	let defaults = [
+		("defaults.builtin", &config.defaults.builtin,),
+		("defaults.external", &config.defaults.external,),
+	];
+	for (key, defaults,) in defaults {
+		if defaults.position.is_some() {
+			problems.push(format!("{}.position: ignored, every item sets its own position", key),);
+		}
+	}
+
+	let registry = match ItemRegistry::from_config(config,) {
+		Ok(registry,) => registry,
+		Err(e,) => {
+			problems.push(format!("items: {}", e),);
+			return problems;
+		},
+	};
+	for item in &config.items {
+		let key = format!("items.{}", item.name);
+		if item.interval.is_some_and(|interval| interval.is_zero(),) {
+			problems.push(format!("{}.interval: must be at least 1 second", key),);
+		}
+		if item.properties.position.is_some() {
+			problems
+				.push(format!("{0}.properties.position: ignored, set {0}.position instead", key),);
+		}
+		if let Some(ItemPosition::Popup(host,),) = &item.position
+			&& let Some(problem,) = popup_problem(&registry, &item.name, host,)
+		{
+			problems.push(format!("{}.position: {}", key, problem),);
+		}
+	}
+
+	problems
+}
+
+/// Why the popup of `host` cannot hold `name` on some bar, if it cannot
+fn popup_problem(registry: &ItemRegistry, name: &str, host: &str,) -> Option<String,> {
+	let item = registry.get(name,)?;
+	for display_info in [sample_display(true,), sample_display(false,),] {
+		if !item.applies_to(&display_info,) {
+			continue;
+		}
+		if item.setup(&display_info,).iter().any(|spec| spec.name == host,) {
+			return Some(format!("{} cannot sit in its own popup", name),);
+		}
+		let drawn: HashSet<String,> =
+			registry.declared_items(&display_info,).into_iter().map(|spec| spec.name,).collect();
+		if !drawn.contains(host,) {
+			return Some(format!(
+				"there is no {} on bars for {} for its popup to hold {}",
+				host,
+				display_name(&display_info,),
+				name
+			),);
+		}
+	}
+	None
+}
+
+fn display_name(display_info: &DisplayInfo,) -> &'static str {
+	if display_info.is_builtin { "the builtin display" } else { "external displays" }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn problems_in(toml: &str,) -> Vec<String,> {
+		problems(&Config::parse(toml,).unwrap(),)
+	}
+
+	#[test]
+	fn test_built_in_config_has_no_problems() {
+		assert!(problems(&Config::default()).is_empty());
+		assert!(problems_in(&Config::template()).is_empty());
+	}
+
+	#[test]
+	fn test_zero_intervals() {
+		let problems = problems_in(
+			"[intervals]\nstate_sync = 0\n\n[[items]]\nname = \"clock\"\ninterval = 0\n",
+		);
+		assert_eq!(problems, [
+			"intervals.state_sync: must be at least 1 second",
+			"items.clock.interval: must be at least 1 second",
+		]);
+	}
+
+	#[test]
+	fn test_overlapping_positions() {
+		let bar = problems_in("[bar.external]\ndisplay = 2\n",);
+		assert!(bar[0].starts_with("bar.external.display: every bar goes on its own display"));
+
+		let ignored = problems_in(
+			"[defaults.builtin]\nposition = \"right\"\n\n\
+			 [[items]]\nname = \"clock\"\nproperties.position = \"left\"\n",
+		);
+		assert_eq!(ignored, [
+			"defaults.builtin.position: ignored, every item sets its own position",
+			"items.clock.properties.position: ignored, set items.clock.position instead",
+		]);
+	}
+
+	#[test]
+	fn test_popups_need_a_host_on_every_bar() {
+		let own = problems_in("[[items]]\nname = \"clock\"\nposition = \"popup.clock\"\n",);
+		assert_eq!(own, ["items.clock.position: clock cannot sit in its own popup"]);
+
+		let missing = problems_in("[[items]]\nname = \"clock\"\nposition = \"popup.battery\"\n",);
+		assert_eq!(missing, [
+			"items.clock.position: there is no battery on bars for the builtin display for its \
+			 popup to hold clock"
+		]);
+
+		// The battery is only drawn on the builtin display, the clock everywhere
+		let external = problems_in(
+			"[[items]]\nname = \"battery\"\n\n\
+			 [[items]]\nname = \"keyboard\"\nposition = \"popup.battery\"\n",
+		);
+		assert_eq!(external.len(), 1);
+		assert!(external[0].contains("no battery on bars for external displays"));
+		assert!(problems_in(
+			"[[items]]\nname = \"battery\"\n\n\
+			 [[items]]\nname = \"keyboard\"\nposition = \"popup.battery\"\ndisplays = \"builtin\"\n"
+		)
+		.is_empty());
+	}
+}
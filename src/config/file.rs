@@ -0,0 +1,622 @@
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+use schemars::JsonSchema;
+use schemars::Schema;
+use schemars::SchemaGenerator;
+use schemars::generate::SchemaSettings;
+use schemars::json_schema;
+use schemars::transform::RecursiveTransform;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use tokio::time::Duration;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+use super::BarStyle;
+use super::check;
+use crate::appearance::AppearanceConfig;
+use crate::events;
+use crate::helpers::properties::BarProperties;
+use crate::helpers::properties::DefaultProperties;
+use crate::helpers::properties::Properties;
+use crate::helpers::yabai::DisplayFrame;
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::registry::BUILTIN_ITEMS;
+use crate::sketchybar::PropertyList;
+use crate::sketchybar::properties::ItemPosition;
+use crate::sketchybar::properties::ItemProperties;
+use crate::theme::DEFAULT_THEME;
+use crate::theme::Theme;
+
+const TEMPLATE_HEADER: &str = "\
+# sketchybar-daemon configuration
+#
+# Every key is optional: a key left out keeps its built-in value, and this file
+# spells out all of them. Properties use sketchybar's names and values; colors
+# are written 0xAARRGGBB, #RRGGBB or #AARRGGBB.
+#
+# Check changes with `sketchybar-daemon check-config <file>`. A running daemon
+# applies them as soon as the file is saved.
+";
+
+const TEMPLATE_THEME: &str = "\
+# Colors of bars and items: mocha, latte, frappe, gruvbox, tokyo-night, or a
+# theme of your own, defined under [themes.<name>] or in themes/<name>.toml
+# next to this file. A theme of your own sets the colors it changes and takes
+# the rest from the one it extends, e.g.
+#
+# [themes.nord]
+# extends = \"frappe\"
+# focus = \"#88c0d0\"
+# accents.clock = \"#ebcb8b\"
+";
+
+const TEMPLATE_APPEARANCE: &str = "\
+# Light and dark themes to switch between instead of keeping the one above.
+# follow = \"system\" switches as macOS does, \"times\" at light_at and dark_at,
+# \"sun\" at sunrise and sunset at a latitude and longitude, e.g.
+#
+# follow = \"sun\"
+# latitude = 52.52
+# longitude = 13.40
+";
+
+const TEMPLATE_ITEMS: &str = "\
+# Items, in bar order; leaving one out removes it. Besides its name, an item
+# takes a position, an interval in seconds, the displays it shows on (all,
+# builtin or external) and properties laid over its own, e.g.
+#
+# [[items]]
+# name = \"battery\"
+# displays = \"all\"
+# interval = 60
+# properties.label.color = \"#a6e3a1\"
+";
+
+/// `~/.config/sketchybar-daemon/config.toml`, unless `SKETCHYBAR_DAEMON_CONFIG` names a file
+pub fn default_path() -> PathBuf {
+	if let Ok(path,) = std::env::var("SKETCHYBAR_DAEMON_CONFIG",) {
+		return PathBuf::from(path,);
+	}
+	let home = std::env::var("HOME",).unwrap_or_default();
+	PathBuf::from(home,).join(".config/sketchybar-daemon/config.toml",)
+}
+
+/// What the daemon draws and how often, as read from `config.toml`
+///
+/// Every key is optional. Bar and default properties are laid over the
+/// built-in look for their display type; a list of items replaces the
+/// built-in one.
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	pub intervals:  Intervals,
+	/// `--bar` properties, by display type
+	pub bar:        PerDisplay<BarProperties,>,
+	/// `--default` properties, by display type
+	pub defaults:   PerDisplay<DefaultProperties,>,
+	/// The items on every bar, in bar order
+	pub items:      Vec<ItemConfig,>,
+	/// Bundled or user theme bars and items are colored with
+	pub theme:      String,
+	/// User themes, by name; `themes/<name>.toml` next to the file adds more
+	pub themes:     BTreeMap<String, Theme,>,
+	/// Switching between a light and a dark theme instead of keeping `theme`
+	pub appearance: AppearanceConfig,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			intervals:  Intervals::default(),
+			bar:        PerDisplay::default(),
+			defaults:   PerDisplay::default(),
+			items:      BUILTIN_ITEMS.iter().map(|name| ItemConfig::new(name,),).collect(),
+			theme:      DEFAULT_THEME.to_string(),
+			themes:     BTreeMap::new(),
+			appearance: AppearanceConfig::default(),
+		}
+	}
+}
+
+impl Config {
+	pub fn parse(toml: &str,) -> Result<Self,> {
+		Self::parse_with_themes(toml, BTreeMap::new(),)
+	}
+
+	/// The config at `path`, with the theme files next to it
+	pub fn load(path: &Path,) -> Result<Self,> {
+		let toml = std::fs::read_to_string(path,)
+			.with_context(|| format!("Failed to read {}", path.display()),)?;
+		let mut themes = BTreeMap::new();
+		for theme_path in theme_files(path,) {
+			let theme = std::fs::read_to_string(&theme_path,)
+				.map_err(anyhow::Error::from,)
+				.and_then(|toml| Ok(toml::from_str(&toml,)?,),)
+				.with_context(|| format!("Invalid theme {}", theme_path.display()),)?;
+			let name = theme_path.file_stem().unwrap_or_default().to_string_lossy();
+			themes.insert(name.into_owned(), theme,);
+		}
+		Self::parse_with_themes(&toml, themes,)
+			.with_context(|| format!("Invalid config {}", path.display()),)
+	}
+
+	/// Parse `toml`, adding the themes read from theme files to the ones it
+	/// defines
+	fn parse_with_themes(toml: &str, themes: BTreeMap<String, Theme,>,) -> Result<Self,> {
+		let mut config: Self = toml::from_str(toml,)?;
+
+		let mut seen = HashSet::new();
+		for item in &config.items {
+			if !seen.insert(item.name.as_str(),) {
+				bail!("item `{}` is listed more than once in `items`", item.name);
+			}
+		}
+		for (name, theme,) in themes {
+			if config.themes.contains_key(&name,) {
+				bail!("theme `{}` is defined both in `themes` and in a theme file", name);
+			}
+			config.themes.insert(name, theme,);
+		}
+		if let Some(name,) = config.themes.keys().find(|name| Theme::bundled(name,).is_some(),) {
+			bail!("theme `{}` is bundled, give yours another name", name);
+		}
+		config.theme()?;
+		config.appearance.check(&config.themes,)?;
+		Ok(config,)
+	}
+
+	/// The config at `path`, or the built-in one when there is no file
+	pub fn load_or_default(path: &Path,) -> Result<Self,> {
+		if !path.exists() {
+			debug!("No config at {}, using the built-in one", path.display());
+			return Ok(Self::default(),);
+		}
+		info!("📝 Reading config from {}", path.display());
+		let config = Self::load(path,)?;
+		for problem in check::problems(&config,) {
+			warn!("⚠️  {}: {}", path.display(), problem);
+		}
+		Ok(config,)
+	}
+
+	/// The colors of [`Self::theme`]
+	pub fn theme(&self,) -> Result<Theme,> {
+		Theme::resolve(&self.theme, &self.themes,)
+	}
+
+	pub fn style(&self,) -> Result<BarStyle,> {
+		Ok(BarStyle {
+			bar:      self.bar.clone(),
+			defaults: self.defaults.clone(),
+			theme:    self.theme()?,
+		},)
+	}
+
+	/// JSON Schema of the file, for editors to complete and check it against
+	pub fn schema() -> Schema {
+		SchemaSettings::draft2020_12()
+			.with_transform(RecursiveTransform(without_null,),)
+			.into_generator()
+			.into_root_schema_for::<Config,>()
+	}
+
+	/// A commented config file that spells out the built-in configuration
+	pub fn template() -> String {
+		let mut toml = TEMPLATE_HEADER.to_string();
+		toml.push('\n',);
+		toml.push_str(TEMPLATE_THEME,);
+		let _ = writeln!(toml, "theme = \"{}\"", DEFAULT_THEME);
+
+		let appearance = AppearanceConfig::default();
+		toml.push('\n',);
+		toml.push_str(TEMPLATE_APPEARANCE,);
+		toml.push_str("[appearance]\n",);
+		let _ = writeln!(toml, "light = \"{}\"", appearance.light);
+		let _ = writeln!(toml, "dark = \"{}\"", appearance.dark);
+		let _ = writeln!(toml, "light_at = \"{}\"", appearance.light_at.format("%H:%M"));
+		let _ = writeln!(toml, "dark_at = \"{}\"", appearance.dark_at.format("%H:%M"));
+
+		let intervals = Intervals::default();
+		toml.push_str(
+			"\n# Seconds between window manager polls, and between the polls that only\n\
+			 # catch what a window manager reporting its own changes missed\n",
+		);
+		toml.push_str("[intervals]\n",);
+		let _ = writeln!(toml, "state_sync = {}", intervals.state_sync.as_secs());
+		let _ = writeln!(toml, "safety_net_sync = {}", intervals.safety_net_sync.as_secs());
+
+		for display_info in [sample_display(true,), sample_display(false,),] {
+			let (key, name,) = display_key(&display_info,);
+			let mut bar = Properties::bar_properties(&display_info,);
+			// Each bar goes on its own display, in the colors of the theme
+			bar.display = None;
+			bar.color = None;
+			let _ = write!(toml, "\n# --bar properties for {}\n[bar.{}]\n", name, key);
+			push_properties(&mut toml, &bar.into(),);
+		}
+		for display_info in [sample_display(true,), sample_display(false,),] {
+			let (key, name,) = display_key(&display_info,);
+			let mut defaults = Properties::default_properties(&display_info,);
+			// Every item sets its own position; the theme colors them
+			defaults.position = None;
+			defaults.background.color = None;
+			defaults.background.border_color = None;
+			let _ = write!(toml, "\n# --default properties items start from, for {}\n", name);
+			let _ = writeln!(toml, "[defaults.{}]", key);
+			push_properties(&mut toml, &defaults.into(),);
+		}
+
+		toml.push('\n',);
+		toml.push_str(TEMPLATE_ITEMS,);
+		for name in BUILTIN_ITEMS {
+			let _ = write!(toml, "\n[[items]]\nname = \"{}\"\n", name);
+		}
+		toml
+	}
+}
+
+/// The theme files next to the config at `path`, `themes/*.toml`, by name
+pub fn theme_files(path: &Path,) -> Vec<PathBuf,> {
+	let dir = path.parent().unwrap_or(Path::new("",),).join("themes",);
+	let Ok(entries,) = std::fs::read_dir(&dir,) else {
+		return Vec::new();
+	};
+	let mut files: Vec<PathBuf,> = entries
+		.filter_map(|entry| Some(entry.ok()?.path(),),)
+		.filter(|path| path.extension().is_some_and(|extension| extension == "toml",),)
+		.collect();
+	files.sort();
+	files
+}
+
+/// A display of either type, for what only depends on the type
+pub(crate) fn sample_display(is_builtin: bool,) -> DisplayInfo {
+	DisplayInfo {
+		index: if is_builtin { 1 } else { 2 },
+		is_builtin,
+		frame: DisplayFrame { x: 0.0, y: 0.0, w: 0.0, h: 0.0, },
+	}
+}
+
+/// The [`PerDisplay`] key for `display_info`, and what it stands for
+fn display_key(display_info: &DisplayInfo,) -> (&'static str, &'static str,) {
+	if display_info.is_builtin {
+		("builtin", "the builtin display",)
+	} else {
+		("external", "external displays",)
+	}
+}
+
+/// One `key = value` line per property, quoting the values TOML would not
+/// read as a number or a boolean
+fn push_properties(toml: &mut String, properties: &PropertyList,) {
+	for (key, value,) in properties.iter() {
+		if value.parse::<f64>().is_ok() || value == "true" || value == "false" {
+			let _ = writeln!(toml, "{} = {}", key, value);
+		} else {
+			let _ = writeln!(toml, "{} = {}", key, toml::Value::String(value.to_string(),));
+		}
+	}
+}
+
+/// How often the window manager is polled for spaces, windows and the focused app
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct Intervals {
+	/// Seconds between polls
+	#[serde(deserialize_with = "seconds")]
+	#[schemars(
+		with = "u64",
+		extend("minimum" = 1, "default" = events::STATE_SYNC_PERIOD.as_secs())
+	)]
+	pub state_sync:      Duration,
+	/// Seconds between polls while the window manager reports its own changes
+	#[serde(deserialize_with = "seconds")]
+	#[schemars(
+		with = "u64",
+		extend("minimum" = 1, "default" = events::SAFETY_NET_SYNC_PERIOD.as_secs())
+	)]
+	pub safety_net_sync: Duration,
+}
+
+impl Intervals {
+	/// How often to poll, depending on whether the window manager reports
+	/// its own changes
+	pub fn sync_period(&self, watched: bool,) -> Duration {
+		if watched { self.safety_net_sync } else { self.state_sync }
+	}
+}
+
+impl Default for Intervals {
+	fn default() -> Self {
+		Self {
+			state_sync:      events::STATE_SYNC_PERIOD,
+			safety_net_sync: events::SAFETY_NET_SYNC_PERIOD,
+		}
+	}
+}
+
+/// One value for bars on the builtin display, one for the rest
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+#[schemars(rename = "PerDisplay{T}")]
+pub struct PerDisplay<T: Default,> {
+	pub builtin:  T,
+	pub external: T,
+}
+
+impl<T: Default,> PerDisplay<T,> {
+	pub fn get(&self, display_info: &DisplayInfo,) -> &T {
+		if display_info.is_builtin { &self.builtin } else { &self.external }
+	}
+}
+
+/// One entry of `[[items]]`: a built-in item and what to change about it
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema,)]
+#[serde(deny_unknown_fields)]
+pub struct ItemConfig {
+	/// One of [`BUILTIN_ITEMS`]
+	#[serde(deserialize_with = "item_name")]
+	#[schemars(schema_with = "item_name_schema")]
+	pub name:       String,
+	/// Where every entry of the item sits, instead of where the item puts it
+	#[serde(default)]
+	pub position:   Option<ItemPosition,>,
+	/// Seconds between refreshes, instead of the item's own cadence
+	#[serde(default, deserialize_with = "some_seconds")]
+	#[schemars(with = "Option<u64>", extend("minimum" = 1))]
+	pub interval:   Option<Duration,>,
+	/// Which bars show the item, instead of the ones it picks itself
+	#[serde(default)]
+	pub displays:   Option<Displays,>,
+	/// Laid over the properties of every entry of the item
+	#[serde(default)]
+	pub properties: ItemProperties,
+}
+
+impl ItemConfig {
+	/// The item as it is built in
+	pub fn new(name: &str,) -> Self {
+		Self {
+			name:       name.to_string(),
+			position:   None,
+			interval:   None,
+			displays:   None,
+			properties: ItemProperties::default(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema,)]
+#[serde(rename_all = "snake_case")]
+pub enum Displays {
+	All,
+	Builtin,
+	External,
+}
+
+impl Displays {
+	pub fn matches(&self, display_info: &DisplayInfo,) -> bool {
+		match self {
+			Displays::All => true,
+			Displays::Builtin => display_info.is_builtin,
+			Displays::External => !display_info.is_builtin,
+		}
+	}
+}
+
+fn seconds<'de, D: Deserializer<'de,>,>(d: D,) -> Result<Duration, D::Error,> {
+	u64::deserialize(d,).map(Duration::from_secs,)
+}
+
+fn some_seconds<'de, D: Deserializer<'de,>,>(d: D,) -> Result<Option<Duration,>, D::Error,> {
+	seconds(d,).map(Some,)
+}
+
+fn item_name<'de, D: Deserializer<'de,>,>(d: D,) -> Result<String, D::Error,> {
+	let name = String::deserialize(d,)?;
+	if !BUILTIN_ITEMS.contains(&name.as_str(),) {
+		return Err(serde::de::Error::custom(format!(
+			"unknown item `{}`, expected one of {}",
+			name,
+			BUILTIN_ITEMS.join(", ")
+		),),);
+	}
+	Ok(name,)
+}
+
+/// Drop the nulls [`Config::schema`] would otherwise allow for unset
+/// options: TOML has no null, a key is just left out
+fn without_null(schema: &mut Schema,) {
+	if schema.get("default",) == Some(&Value::Null,) {
+		schema.remove("default",);
+	}
+	if let Some(Value::Array(types,),) = schema.get_mut("type",) {
+		types.retain(|t| t != "null",);
+		if let [only,] = types.as_slice() {
+			let only = only.clone();
+			schema.insert("type".to_string(), only,);
+		}
+	}
+	if let Some(Value::Array(any_of,),) = schema.get_mut("anyOf",) {
+		any_of.retain(|s| s.get("type",) != Some(&Value::from("null",),),);
+		if let [Value::Object(only,),] = any_of.as_slice() {
+			let only = only.clone();
+			schema.remove("anyOf",);
+			schema.ensure_object().extend(only,);
+		}
+	}
+}
+
+fn item_name_schema(_: &mut SchemaGenerator,) -> Schema {
+	json_schema!({ "type": "string", "enum": BUILTIN_ITEMS })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::helpers::yabai::DisplayFrame;
+	use crate::sketchybar::properties::BarPosition;
+	use crate::sketchybar::properties::Color;
+
+	fn display(is_builtin: bool,) -> DisplayInfo {
+		DisplayInfo {
+			index: if is_builtin { 1 } else { 2 },
+			is_builtin,
+			frame: DisplayFrame { x: 0.0, y: 0.0, w: 1920.0, h: 1080.0, },
+		}
+	}
+
+	#[test]
+	fn test_empty_file_is_the_built_in_config() -> Result<(),> {
+		assert_eq!(Config::parse("")?, Config::default());
+		assert_eq!(Config::default().items.len(), BUILTIN_ITEMS.len());
+		Ok((),)
+	}
+
+	#[test]
+	fn test_properties_are_laid_over_the_built_in_look() -> Result<(),> {
+		let config = Config::parse(
+			r##"
+			[intervals]
+			safety_net_sync = 60
+
+			[bar.external]
+			position = "top"
+			color = "#1e1e2e"
+
+			[defaults.builtin]
+			label.font.size = 18
+			"##,
+		)?;
+		assert_eq!(config.intervals.state_sync, events::STATE_SYNC_PERIOD);
+		assert_eq!(config.intervals.safety_net_sync, Duration::from_secs(60));
+
+		let style = config.style()?;
+		let external = style.bar_properties(&display(false,),);
+		assert_eq!(external.position, Some(BarPosition::Top));
+		assert_eq!(external.color, Some(Color(0xff1e1e2e)));
+		// Everything else is still the built-in external bar
+		assert_eq!(external.height, Some(26));
+		assert_eq!(style.bar_properties(&display(true,),).color, Some(Color(0)));
+
+		let defaults = style.default_properties(&display(true,),);
+		assert_eq!(defaults.label.font.size, Some(18.0));
+		assert_eq!(defaults.label.font.family.as_deref(), Some("MesloLGL Nerd Font"));
+		Ok((),)
+	}
+
+	#[test]
+	fn test_items_are_listed_in_order() -> Result<(),> {
+		let config = Config::parse(
+			r#"
+			[[items]]
+			name = "battery"
+			displays = "all"
+			interval = 120
+
+			[[items]]
+			name = "clock"
+			position = "left"
+			properties.label.color = 0xffa6e3a1
+			"#,
+		)?;
+		let names: Vec<&str,> = config.items.iter().map(|item| item.name.as_str(),).collect();
+		assert_eq!(names, ["battery", "clock"]);
+		assert_eq!(config.items[0].displays, Some(Displays::All));
+		assert_eq!(config.items[0].interval, Some(Duration::from_secs(120)));
+		assert_eq!(config.items[1].position, Some(ItemPosition::Left));
+		assert_eq!(config.items[1].properties.label.color, Some(Color(0xffa6e3a1)));
+		Ok((),)
+	}
+
+	#[test]
+	fn test_themes_come_from_the_file_and_beside_it() -> Result<(),> {
+		let dir = std::env::temp_dir().join(format!("sbd-themes-{}", std::process::id()),);
+		std::fs::create_dir_all(dir.join("themes",),)?;
+		let path = dir.join("config.toml",);
+		std::fs::write(
+			&path,
+			"theme = \"nord-dim\"\n\n[themes.nord-dim]\nextends = \"nord\"\n\
+			 item_background = \"#2e3440\"\n",
+		)?;
+		let nord = "extends = \"frappe\"\nfocus = \"#88c0d0\"\n";
+		std::fs::write(dir.join("themes/nord.toml",), nord,)?;
+		let config = Config::load(&path,);
+		std::fs::remove_dir_all(&dir,)?;
+
+		let style = config?.style()?;
+		assert_eq!(style.theme.focus, Some(Color(0xff88c0d0)));
+		let defaults = style.default_properties(&display(false,),);
+		assert_eq!(defaults.background.color, Some(Color(0xff2e3440)));
+		assert_eq!(defaults.background.border_color, crate::theme::FRAPPE.border);
+
+		let error = |toml: &str| format!("{:#}", Config::parse(toml,).unwrap_err());
+		let unknown = error("theme = \"nord\"\n",);
+		assert!(unknown.contains("unknown theme `nord`, expected one of mocha,"), "{}", unknown);
+		assert!(error("[themes.latte]\nfocus = 0xffffffff\n").contains("theme `latte` is bundled"));
+		Ok((),)
+	}
+
+	#[test]
+	fn test_template_spells_out_the_built_in_config() -> Result<(),> {
+		let template = Config::template();
+		assert!(template.starts_with("# "), "{}", template);
+
+		let config = Config::parse(&template,)?;
+		assert_eq!(config.intervals, Config::default().intervals);
+		let names: Vec<&str,> = config.items.iter().map(|item| item.name.as_str(),).collect();
+		assert_eq!(names, BUILTIN_ITEMS);
+
+		let (style, built_in,) = (config.style()?, Config::default().style()?,);
+		for display in [display(true,), display(false,),] {
+			assert_eq!(style.bar_properties(&display,), built_in.bar_properties(&display,));
+			assert_eq!(style.default_properties(&display,), built_in.default_properties(&display,));
+		}
+		Ok((),)
+	}
+
+	#[test]
+	fn test_schema_describes_every_key() -> Result<(),> {
+		let schema = serde_json::to_value(Config::schema(),)?;
+		assert_eq!(schema["additionalProperties"], false);
+		assert_eq!(schema["$defs"]["Intervals"]["properties"]["state_sync"]["default"], 2);
+
+		let item = &schema["$defs"]["ItemConfig"];
+		assert_eq!(item["required"], serde_json::json!(["name"]));
+		assert_eq!(item["properties"]["name"]["enum"], serde_json::json!(BUILTIN_ITEMS));
+		let appearance = &schema["$defs"]["AppearanceConfig"]["properties"];
+		assert_eq!(appearance["light_at"]["default"], "07:00");
+		assert_eq!(appearance["latitude"]["maximum"], 90.0);
+		assert!(!schema.to_string().contains("null"), "{}", schema);
+		Ok((),)
+	}
+
+	#[test]
+	fn test_errors_point_at_the_offending_key() {
+		let error = |toml: &str| format!("{:#}", Config::parse(toml,).unwrap_err());
+
+		let typo = error("[bar.builtin]\nheigth = 30\n",);
+		assert!(typo.contains("line 2"), "{}", typo);
+		assert!(typo.contains("unknown field `heigth`"), "{}", typo);
+
+		let color = error("[defaults.external]\nbackground.color = \"blue\"\n",);
+		assert!(color.contains("line 2"), "{}", color);
+		assert!(color.contains("invalid color \"blue\""), "{}", color);
+
+		let item = error("[[items]]\nname = \"cpu\"\n",);
+		assert!(item.contains("unknown item `cpu`, expected one of clock,"), "{}", item);
+
+		let twice = error("[[items]]\nname = \"clock\"\n[[items]]\nname = \"clock\"\n",);
+		assert!(twice.contains("item `clock` is listed more than once"), "{}", twice);
+	}
+}
@@ -0,0 +1,537 @@
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use anyhow::bail;
+use futures::future::join_all;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use serde_json::json;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::UnixListener;
+use tokio::net::UnixStream;
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tokio::time::timeout;
+use tracing::debug;
+use tracing::info;
+
+use crate::bars::BarManager;
+use crate::events::Refresh;
+use crate::events::refresh_state;
+use crate::listener::bind_socket;
+use crate::listener::remove_socket;
+use crate::reload::ConfigReloader;
+use crate::state::DaemonState;
+use crate::supervisor::TaskStatuses;
+use crate::theme;
+
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2,);
+
+/// How long `ctl` waits for an answer; a reload waits on every bar
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(15,);
+
+/// How long a status request waits on one bar
+const BAR_TIMEOUT: Duration = Duration::from_secs(10,);
+
+/// The control socket for the current user, unless
+/// `SKETCHYBAR_DAEMON_CONTROL_SOCKET` names one
+pub fn default_socket_path() -> PathBuf {
+	if let Ok(path,) = std::env::var("SKETCHYBAR_DAEMON_CONTROL_SOCKET",) {
+		return PathBuf::from(path,);
+	}
+	let user = std::env::var("USER",).unwrap_or_default();
+	PathBuf::from(format!("/tmp/sketchybar-daemon_{}.control.socket", user),)
+}
+
+/// A request on the control socket, sent as one JSON object per line,
+/// e.g. `{"command": "refresh", "item": "battery"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize,)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum ControlRequest {
+	/// Version, window manager, bars and supervised tasks
+	Status,
+	/// Apply the config file, and set every bar up again
+	Reload,
+	/// Probe and render one item now
+	Refresh { item: String, },
+	/// Everything in [`DaemonState`]
+	DumpState,
+	/// Replace the log filter, e.g. `debug` or `sketchybar_daemon=trace`
+	SetLogLevel { level: String, },
+	/// Color every bar with the theme called `name`, or list the themes
+	/// without one
+	Theme {
+		#[serde(default)]
+		name: Option<String,>,
+	},
+	Shutdown,
+}
+
+/// Commands `ctl` accepts, for its usage text
+pub const CTL_USAGE: &str = concat!(
+	"  status                 Version, window manager, bars and tasks\n",
+	"  reload                 Apply the config file, set every bar up again\n",
+	"  refresh <item>         Re-read what one item shows and render it\n",
+	"  dump-state             Print the synced desktop state\n",
+	"  set-log-level <level>  Replace the log filter, e.g. debug\n",
+	"  theme [name]           Switch every bar to a theme, or list them\n",
+	"  shutdown               Stop the daemon\n",
+);
+
+impl ControlRequest {
+	/// The request for `ctl` arguments, e.g. `["refresh", "battery"]`
+	pub fn from_args(args: &[String],) -> Result<Self,> {
+		let args: Vec<&str,> = args.iter().map(String::as_str,).collect();
+		match args.as_slice() {
+			["status",] => Ok(ControlRequest::Status,),
+			["reload",] => Ok(ControlRequest::Reload,),
+			["refresh", item,] => Ok(ControlRequest::Refresh { item: item.to_string(), },),
+			["dump-state",] => Ok(ControlRequest::DumpState,),
+			["set-log-level", level,] => {
+				Ok(ControlRequest::SetLogLevel { level: level.to_string(), },)
+			},
+			["theme",] => Ok(ControlRequest::Theme { name: None, },),
+			["theme", name,] => Ok(ControlRequest::Theme { name: Some(name.to_string(),), },),
+			["shutdown",] => Ok(ControlRequest::Shutdown,),
+			[] => bail!("Missing command, expected one of\n{}", CTL_USAGE.trim_end()),
+			_ => {
+				let command = args.join(" ",);
+				bail!("Unknown command {:?}, expected one of\n{}", command, CTL_USAGE.trim_end())
+			},
+		}
+	}
+}
+
+/// The answer to one [`ControlRequest`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize,)]
+pub struct ControlResponse {
+	pub ok:     bool,
+	#[serde(default, skip_serializing_if = "Value::is_null")]
+	pub result: Value,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub error:  Option<String,>,
+}
+
+impl ControlResponse {
+	pub fn ok(result: Value,) -> Self {
+		Self { ok: true, result, error: None, }
+	}
+
+	pub fn error(error: impl ToString,) -> Self {
+		Self { ok: false, result: Value::Null, error: Some(error.to_string(),), }
+	}
+}
+
+/// Replaces the daemon's log filter
+pub type LogLevelSetter = Arc<dyn Fn(&str,) -> Result<(),> + Send + Sync,>;
+
+/// Carries out control requests against the running daemon
+#[derive(Clone,)]
+pub struct Controller {
+	state:       DaemonState,
+	bars:        BarManager,
+	reloader:    ConfigReloader,
+	statuses:    TaskStatuses,
+	shutdown_tx: broadcast::Sender<(),>,
+	log_level:   Option<LogLevelSetter,>,
+	started:     Instant,
+}
+
+impl std::fmt::Debug for Controller {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		f.debug_struct("Controller",).field("reloader", &self.reloader,).finish_non_exhaustive()
+	}
+}
+
+impl Controller {
+	pub fn new(
+		state: DaemonState,
+		bars: BarManager,
+		reloader: ConfigReloader,
+		statuses: TaskStatuses,
+		shutdown_tx: broadcast::Sender<(),>,
+	) -> Self {
+		Self {
+			state,
+			bars,
+			reloader,
+			statuses,
+			shutdown_tx,
+			log_level: None,
+			started: Instant::now(),
+		}
+	}
+
+	/// Let `set-log-level` replace the log filter through `setter`
+	pub fn with_log_level_setter(mut self, setter: LogLevelSetter,) -> Self {
+		self.log_level = Some(setter,);
+		self
+	}
+
+	pub async fn handle(&self, request: ControlRequest,) -> ControlResponse {
+		debug!("🎛️  Control request {:?}", request);
+		match self.execute(request,).await {
+			Ok(result,) => ControlResponse::ok(result,),
+			Err(e,) => ControlResponse::error(format!("{:#}", e),),
+		}
+	}
+
+	async fn execute(&self, request: ControlRequest,) -> Result<Value,> {
+		match request {
+			ControlRequest::Status => Ok(self.status().await,),
+			ControlRequest::Reload => Ok(serde_json::to_value(self.reloader.reload().await?,)?,),
+			ControlRequest::Refresh { item, } => {
+				let item = self
+					.reloader
+					.registry()
+					.get(&item,)
+					.ok_or_else(|| anyhow!("Unknown item {:?}", item),)?
+					.clone();
+				let mut events_rx = self.state.subscribe();
+				let dependencies = item.dependencies();
+				refresh_state(&self.state, Refresh::for_dependencies(dependencies,),).await;
+				item.refresh(&self.state,).await?;
+
+				// A change the item depends on already has its task render it
+				let mut changed = false;
+				while let Ok(event,) = events_rx.try_recv() {
+					changed |= event.dependency().is_some_and(|d| dependencies.contains(&d,),);
+				}
+				if !changed {
+					self.bars.render(&item,);
+				}
+				Ok(json!({ "refreshed": item.name() }),)
+			},
+			ControlRequest::DumpState => Ok(serde_json::to_value(self.state.snapshot().await,)?,),
+			ControlRequest::SetLogLevel { level, } => {
+				let setter =
+					self.log_level.as_ref().ok_or_else(|| anyhow!("The log level is fixed"),)?;
+				setter(&level,)?;
+				info!("🔊 Log level set to {}", level);
+				Ok(json!({ "log_level": level }),)
+			},
+			ControlRequest::Theme { name: Some(name,), } => {
+				info!("🎨 Switching to theme {}", name);
+				Ok(serde_json::to_value(self.reloader.switch_theme(&name,).await?,)?,)
+			},
+			ControlRequest::Theme { name: None, } => {
+				let config = self.reloader.active().config.clone();
+				Ok(json!({ "theme": config.theme, "themes": theme::names(&config.themes) }),)
+			},
+			ControlRequest::Shutdown => {
+				info!("🎛️  Shutdown requested over the control socket");
+				let _ = self.shutdown_tx.send((),);
+				Ok(Value::Null,)
+			},
+		}
+	}
+
+	async fn status(&self,) -> Value {
+		let bars = join_all(self.bars.handles().into_iter().map(|handle| async move {
+			let status = match timeout(BAR_TIMEOUT, handle.status(),).await {
+				Ok(Some(status,),) => format!("{:?}", status),
+				Ok(None,) => "Unknown".to_string(),
+				Err(_,) => "Busy".to_string(),
+			};
+			json!({ "display": handle.display_id(), "bar": handle.bar_name(), "status": status })
+		},),)
+		.await;
+
+		let tasks: serde_json::Map<String, Value,> = self
+			.statuses
+			.snapshot()
+			.into_iter()
+			.map(|(name, task,)| {
+				let task = json!({
+					"running": task.running,
+					"restarts": task.restarts,
+					"failures": task.failures,
+					"last_error": task.last_error,
+				});
+				(name, task,)
+			},)
+			.collect();
+
+		json!({
+			"version": env!("CARGO_PKG_VERSION"),
+			"uptime_secs": self.started.elapsed().as_secs(),
+			"window_manager": self.state.backend().name(),
+			"bars": bars,
+			"tasks": tasks,
+		})
+	}
+}
+
+/// Unix socket that `sketchybar-daemon ctl` talks to
+///
+/// Each connection carries one request line and gets one response line.
+#[derive(Debug, Clone,)]
+pub struct ControlServer {
+	path:     PathBuf,
+	listener: Arc<UnixListener,>,
+}
+
+impl ControlServer {
+	/// Listen on `path`, replacing a socket left behind by an earlier daemon
+	pub fn bind(path: impl Into<PathBuf,>,) -> Result<Self,> {
+		let path = path.into();
+		let listener = bind_socket(&path,)?;
+		debug!("🎛️  Listening for control requests on {}", path.display());
+		Ok(Self { path, listener: Arc::new(listener,), },)
+	}
+
+	pub fn path(&self,) -> &Path {
+		&self.path
+	}
+
+	/// Answer requests with `controller` until the socket fails
+	pub async fn serve(&self, controller: Controller,) -> Result<(),> {
+		loop {
+			let (stream, _,) = self.listener.accept().await.context("Control socket failed",)?;
+			let controller = controller.clone();
+			tokio::spawn(async move {
+				if let Err(e,) = answer(stream, &controller,).await {
+					debug!("Control connection failed: {}", e);
+				}
+			},);
+		}
+	}
+
+	/// Remove the socket file
+	pub fn close(&self,) {
+		remove_socket(&self.path,);
+	}
+}
+
+async fn answer(stream: UnixStream, controller: &Controller,) -> Result<(),> {
+	let (reader, mut writer,) = stream.into_split();
+	let mut line = String::new();
+	timeout(REQUEST_TIMEOUT, BufReader::new(reader,).read_line(&mut line,),)
+		.await
+		.context("No request before the timeout",)??;
+
+	let response = match serde_json::from_str::<ControlRequest,>(&line,) {
+		Ok(request,) => controller.handle(request,).await,
+		Err(e,) => ControlResponse::error(format!("Invalid request: {}", e),),
+	};
+	let mut encoded = serde_json::to_vec(&response,)?;
+	encoded.push(b'\n',);
+	writer.write_all(&encoded,).await?;
+	Ok((),)
+}
+
+/// Send `request` to the daemon listening on `socket` and wait for its answer
+pub async fn request(socket: &Path, request: &ControlRequest,) -> Result<ControlResponse,> {
+	let exchange = async {
+		let stream = UnixStream::connect(socket,).await.with_context(|| {
+			format!("Is the daemon running? Could not connect to {}", socket.display())
+		},)?;
+		let (reader, mut writer,) = stream.into_split();
+		let mut encoded = serde_json::to_vec(request,)?;
+		encoded.push(b'\n',);
+		writer.write_all(&encoded,).await?;
+
+		let mut line = String::new();
+		BufReader::new(reader,).read_line(&mut line,).await?;
+		serde_json::from_str(&line,).context("Invalid response from the daemon",)
+	};
+	timeout(RESPONSE_TIMEOUT, exchange,).await.context("The daemon did not answer",)?
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::Config;
+	use crate::events::EventManager;
+	use crate::items::ItemRegistry;
+	use crate::items::clock::Clock;
+	use crate::items::current_app::CurrentApp;
+	use crate::sketchybar::RecordingTransport;
+	use crate::sketchybar::SketchyBar;
+	use crate::wm::ScriptedBackend;
+	use std::sync::Mutex;
+
+	fn args(args: &[&str],) -> Vec<String,> {
+		args.iter().map(|a| a.to_string(),).collect()
+	}
+
+	/// A controller over one recording bar, serving on a socket of its own
+	struct Fixture {
+		server:      ControlServer,
+		transport:   RecordingTransport,
+		state:       DaemonState,
+		backend:     ScriptedBackend,
+		bars:        BarManager,
+		shutdown_rx: broadcast::Receiver<(),>,
+		log_levels:  Arc<Mutex<Vec<String,>,>,>,
+	}
+
+	fn serve(test: &str,) -> Fixture {
+		let backend = ScriptedBackend::new();
+		let state = DaemonState::new().with_backend(Arc::new(backend.clone(),),);
+		let transport = RecordingTransport::new();
+		let mut bar = SketchyBar::with_transport(Arc::new(transport.clone(),),);
+		bar.set_bar_name("sketchybar",);
+		let bars = BarManager::new(state.clone(),);
+		bars.add("1", bar,);
+		let mut registry = ItemRegistry::new();
+		registry.register(Clock::default(),);
+		registry.register(CurrentApp::default(),);
+		// No file there, so a reload keeps these items
+		let config = std::env::temp_dir()
+			.join(format!("sbd-control-{}-{}.toml", test, std::process::id()),);
+		let reloader =
+			ConfigReloader::new(config, Config::default(), registry, state.clone(), bars.clone(),);
+
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let log_levels = Arc::new(Mutex::new(Vec::new(),),);
+		let recorded = log_levels.clone();
+		let controller = Controller::new(
+			state.clone(),
+			bars.clone(),
+			reloader,
+			TaskStatuses::default(),
+			shutdown_tx,
+		)
+		.with_log_level_setter(Arc::new(move |level| {
+			recorded.lock().unwrap().push(level.to_string(),);
+			Ok((),)
+		},),);
+
+		let socket = std::env::temp_dir()
+			.join(format!("sbd-control-{}-{}.socket", test, std::process::id()),);
+		let server = ControlServer::bind(socket,).unwrap();
+		let serving = server.clone();
+		tokio::spawn(async move { serving.serve(controller,).await },);
+		Fixture { server, transport, state, backend, bars, shutdown_rx, log_levels, }
+	}
+
+	#[test]
+	fn test_ctl_arguments() {
+		assert_eq!(ControlRequest::from_args(&args(&["status"])).unwrap(), ControlRequest::Status);
+		assert_eq!(
+			ControlRequest::from_args(&args(&["refresh", "battery"])).unwrap(),
+			ControlRequest::Refresh { item: "battery".to_string() }
+		);
+		assert!(ControlRequest::from_args(&args(&["refresh"])).is_err());
+		assert_eq!(
+			ControlRequest::from_args(&args(&["theme", "latte"])).unwrap(),
+			ControlRequest::Theme { name: Some("latte".to_string()) }
+		);
+		assert_eq!(
+			serde_json::from_str::<ControlRequest,>(r#"{"command":"theme"}"#).unwrap(),
+			ControlRequest::Theme { name: None }
+		);
+		assert!(ControlRequest::from_args(&args(&[])).is_err());
+
+		// The wire format scripts can write by hand
+		let request = ControlRequest::SetLogLevel { level: "debug".to_string(), };
+		assert_eq!(
+			serde_json::to_string(&request).unwrap(),
+			r#"{"command":"set-log-level","level":"debug"}"#
+		);
+	}
+
+	#[tokio::test]
+	async fn test_requests_over_the_socket() -> Result<(),> {
+		let mut fixture = serve("requests",);
+		let socket = fixture.server.path().to_path_buf();
+
+		let status = request(&socket, &ControlRequest::Status,).await?;
+		assert!(status.ok);
+		assert_eq!(status.result["window_manager"], "scripted");
+		assert_eq!(status.result["bars"][0]["bar"], "sketchybar");
+
+		fixture.state.apply_current_app("Finder".to_string(),).await;
+		let dump = request(&socket, &ControlRequest::DumpState,).await?;
+		assert_eq!(dump.result["current_app"], "Finder");
+
+		let refresh = ControlRequest::Refresh { item: "clock".to_string(), };
+		assert!(request(&socket, &refresh,).await?.ok);
+		tokio::time::sleep(Duration::from_millis(50,),).await;
+		let sent = fixture.transport.sent_to("sketchybar",);
+		assert!(sent.iter().any(|m| m.starts_with("--set clock")));
+
+		let unknown = ControlRequest::Refresh { item: "cpu".to_string(), };
+		let response = request(&socket, &unknown,).await?;
+		assert_eq!(response.error.as_deref(), Some("Unknown item \"cpu\""));
+
+		let level = ControlRequest::SetLogLevel { level: "debug".to_string(), };
+		assert!(request(&socket, &level,).await?.ok);
+		assert_eq!(*fixture.log_levels.lock().unwrap(), ["debug"]);
+
+		assert!(request(&socket, &ControlRequest::Shutdown,).await?.ok);
+		assert!(fixture.shutdown_rx.try_recv().is_ok());
+		fixture.server.close();
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_refresh_reads_the_state_again() -> Result<(),> {
+		let fixture = serve("refresh",);
+		fixture.backend.set_focused_app("Terminal",);
+		fixture.state.apply_current_app("Terminal".to_string(),).await;
+
+		// The current app's own task renders it whenever the app switches
+		let mut registry = ItemRegistry::new();
+		registry.register(CurrentApp::default(),);
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let mut manager =
+			EventManager::new(fixture.state.clone(), fixture.bars.clone(), registry, shutdown_rx,);
+		let task = tokio::spawn(async move { manager.start_event_loops().await },);
+		tokio::time::sleep(Duration::from_millis(50,),).await;
+		fixture.transport.clear();
+
+		fixture.backend.set_focused_app("Safari",);
+		let refresh = ControlRequest::Refresh { item: "current_app".to_string(), };
+		assert!(request(fixture.server.path(), &refresh,).await?.ok);
+		tokio::time::sleep(Duration::from_millis(50,),).await;
+
+		assert_eq!(fixture.state.current_app.read().await.as_deref(), Some("Safari"));
+		assert_eq!(fixture.transport.sent_to("sketchybar"), vec![
+			"--set current_app label=Safari"
+		]);
+
+		shutdown_tx.send((),)?;
+		task.await??;
+		fixture.server.close();
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_reload_provisions_every_bar() -> Result<(),> {
+		let fixture = serve("reload",);
+		let display = crate::wm::default_displays();
+		fixture.state.apply_displays(display,).await;
+
+		let response = request(fixture.server.path(), &ControlRequest::Reload,).await?;
+		assert_eq!(response.result["bars"]["sketchybar"], "reloaded");
+		assert_eq!(response.result["changes"]["added"], json!([]));
+		assert!(fixture.transport.received("sketchybar", "--subscribe clock system_woke"));
+		fixture.server.close();
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_garbled_request_gets_an_error() -> Result<(),> {
+		let fixture = serve("garbled",);
+		let mut stream = UnixStream::connect(fixture.server.path(),).await?;
+		stream.write_all(b"{\"command\": \"dance\"}\n",).await?;
+		let mut line = String::new();
+		BufReader::new(stream,).read_line(&mut line,).await?;
+
+		let response: ControlResponse = serde_json::from_str(&line,)?;
+		assert!(!response.ok);
+		assert!(response.error.unwrap().starts_with("Invalid request"));
+		fixture.server.close();
+		Ok((),)
+	}
+}
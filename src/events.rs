@@ -1,299 +1,480 @@
 use anyhow::Result;
-use std::collections::HashMap;
+use futures::future::join_all;
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::sync::watch;
 use tokio::time::Duration;
-use tokio::time::interval;
+use tokio::time::sleep;
 use tracing::debug;
-use tracing::error;
 use tracing::info;
 
-use crate::sketchybar::SketchyBar;
+use crate::bars::BarManager;
+use crate::items::BarItem;
+use crate::items::ItemRegistry;
+use crate::items::appearance::APPEARANCE_EVENT;
+use crate::listener::DaemonEvent;
+use crate::listener::EventListener;
+use crate::reload::ActiveConfig;
+use crate::scheduler::Scheduler;
+use crate::scheduler::SchedulerStats;
+use crate::scheduler::WallClock;
+use crate::scheduler::system_clock;
 use crate::state::DaemonState;
+use crate::state::StateDependency;
+use crate::state::StateEvent;
+use crate::supervisor::RestartPolicy;
+use crate::supervisor::Supervisor;
+use crate::supervisor::TaskStatuses;
+
+/// How often yabai is polled for spaces, windows and the focused app
+pub const STATE_SYNC_PERIOD: Duration = Duration::from_secs(2,);
+
+/// How often to poll when the window manager reports its own changes, only
+/// to catch any it failed to report
+pub const SAFETY_NET_SYNC_PERIOD: Duration = Duration::from_secs(30,);
+
+/// How long to gather a burst of events into one refresh
+const EVENT_COALESCE: Duration = Duration::from_millis(20,);
+
+/// Parts of the state an event on the daemon socket makes stale
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq,)]
+pub struct Refresh {
+	pub spaces:       bool,
+	pub windows:      bool,
+	pub current_app:  bool,
+	pub displays:     bool,
+	/// Probe the items that depend on the battery
+	pub battery:      bool,
+	/// Probe the items that depend on the input source
+	pub input_source: bool,
+	/// Probe the items that depend on light or dark mode
+	pub appearance:   bool,
+}
+
+impl Refresh {
+	pub const NONE: Self = Self {
+		spaces:       false,
+		windows:      false,
+		current_app:  false,
+		displays:     false,
+		battery:      false,
+		input_source: false,
+		appearance:   false,
+	};
+	/// Window focus, and with it the front app
+	pub const FOCUS: Self = Self { windows: true, current_app: true, ..Self::NONE };
+	/// Spaces, windows and the focused app, as the state sync polls them
+	pub const DESKTOP: Self = Self { spaces: true, ..Self::FOCUS };
+	pub const ALL: Self = Self {
+		spaces:       true,
+		windows:      true,
+		current_app:  true,
+		displays:     true,
+		battery:      true,
+		input_source: true,
+		appearance:   true,
+	};
+
+	/// What to query again after `event`; nothing for events the daemon does not know
+	pub fn for_event(event: &DaemonEvent,) -> Self {
+		match (event.source.as_str(), event.name.as_str(),) {
+			// Focus follows the space or display, and with it the front app
+			(
+				"yabai",
+				"space_changed" | "space_created" | "space_destroyed" | "display_changed"
+				| "application_terminated",
+			)
+			| ("sketchybar", "space_change" | "display_change",) => Self::DESKTOP,
+			("yabai", "display_added" | "display_removed",) => {
+				Self { displays: true, ..Self::DESKTOP }
+			},
+			(
+				"yabai",
+				"window_created" | "window_destroyed" | "window_minimized" | "window_deminimized",
+			) => Self { spaces: true, windows: true, ..Self::NONE },
+			("yabai", "window_focused" | "application_front_switched",)
+			| ("sketchybar", "front_app_switched" | "window_focus",) => Self::FOCUS,
+			("yabai", "window_title_changed",) | ("sketchybar", "window_title",) => {
+				Self { windows: true, ..Self::NONE }
+			},
+			("sketchybar", "power_source_change",) => Self { battery: true, ..Self::NONE },
+			("sketchybar", APPEARANCE_EVENT,) => Self { appearance: true, ..Self::NONE },
+			("yabai" | "sketchybar", "system_woke",) => Self::ALL,
+			_ => Self::NONE,
+		}
+	}
+
+	/// What to query again before rendering an item that depends on `dependencies`
+	pub fn for_dependencies(dependencies: &[StateDependency],) -> Self {
+		let depends_on = |dependency| dependencies.contains(&dependency,);
+		Self {
+			spaces:       depends_on(StateDependency::Spaces,),
+			windows:      depends_on(StateDependency::Windows,),
+			current_app:  depends_on(StateDependency::CurrentApp,),
+			displays:     depends_on(StateDependency::Displays,),
+			battery:      depends_on(StateDependency::Battery,),
+			input_source: depends_on(StateDependency::InputSource,),
+			appearance:   depends_on(StateDependency::Appearance,),
+		}
+	}
+
+	pub fn merge(self, other: Self,) -> Self {
+		Self {
+			spaces:       self.spaces || other.spaces,
+			windows:      self.windows || other.windows,
+			current_app:  self.current_app || other.current_app,
+			displays:     self.displays || other.displays,
+			battery:      self.battery || other.battery,
+			input_source: self.input_source || other.input_source,
+			appearance:   self.appearance || other.appearance,
+		}
+	}
+
+	pub fn is_empty(&self,) -> bool {
+		*self == Self::NONE
+	}
+
+	/// Whether items depending on `dependency` need probing again
+	fn probes(&self, dependency: StateDependency,) -> bool {
+		match dependency {
+			StateDependency::Battery => self.battery,
+			StateDependency::InputSource => self.input_source,
+			StateDependency::Appearance => self.appearance,
+			// The window manager queries cover the rest
+			_ => false,
+		}
+	}
+}
 
 /// Event-driven update system for SketchyBar items
 pub struct EventManager {
 	state:       DaemonState,
-	bars:        Arc<RwLock<HashMap<String, SketchyBar,>,>,>,
+	bars:        BarManager,
+	registry:    ItemRegistry,
 	shutdown_rx: broadcast::Receiver<(),>,
+	wall_clock:  WallClock,
+	stats:       SchedulerStats,
+	policy:      RestartPolicy,
+	statuses:    TaskStatuses,
+	listener:    Option<EventListener,>,
+	sync_period: Duration,
+}
+
+impl Clone for EventManager {
+	fn clone(&self,) -> Self {
+		Self {
+			state:       self.state.clone(),
+			bars:        self.bars.clone(),
+			registry:    self.registry.clone(),
+			shutdown_rx: self.shutdown_rx.resubscribe(),
+			wall_clock:  self.wall_clock.clone(),
+			stats:       self.stats.clone(),
+			policy:      self.policy.clone(),
+			statuses:    self.statuses.clone(),
+			listener:    self.listener.clone(),
+			sync_period: self.sync_period,
+		}
+	}
 }
 
 impl EventManager {
 	pub fn new(
 		state: DaemonState,
-		bars: Arc<RwLock<HashMap<String, SketchyBar,>,>,>,
+		bars: BarManager,
+		registry: ItemRegistry,
 		shutdown_rx: broadcast::Receiver<(),>,
 	) -> Self {
-		Self { state, bars, shutdown_rx, }
+		Self {
+			state,
+			bars,
+			registry,
+			shutdown_rx,
+			wall_clock: system_clock(),
+			stats: SchedulerStats::default(),
+			policy: RestartPolicy::default(),
+			statuses: TaskStatuses::default(),
+			listener: None,
+			sync_period: STATE_SYNC_PERIOD,
+		}
 	}
 
-	/// Start all event-driven update loops
-	pub async fn start_event_loops(&mut self,) -> Result<(),> {
-		info!("🎯 Starting event-driven update system");
+	/// Refresh the state as soon as events arrive on `listener`
+	pub fn with_listener(mut self, listener: EventListener,) -> Self {
+		self.listener = Some(listener,);
+		self
+	}
 
-		// State synchronization task - updates centralized state
-		let state_sync_task = self.spawn_state_sync_task();
-
-		// Item update tasks - react to state changes
-		let clock_task = self.spawn_clock_task();
-		let battery_task = self.spawn_battery_task();
-		let keyboard_task = self.spawn_keyboard_task();
-		let space_task = self.spawn_space_task();
-		let app_task = self.spawn_app_task();
-		let window_task = self.spawn_window_task();
-
-		// Wait for shutdown or task completion
-		tokio::select! {
-			_ = self.shutdown_rx.recv() => {
-				info!("🛑 Event loops shutting down");
-			}
-			result = state_sync_task => {
-				if let Err(e) = result {
-					error!("❌ State sync task error: {}", e);
-				}
-			}
-			result = clock_task => {
-				if let Err(e) = result {
-					error!("❌ Clock task error: {}", e);
-				}
-			}
-			result = battery_task => {
-				if let Err(e) = result {
-					error!("❌ Battery task error: {}", e);
-				}
-			}
-			result = keyboard_task => {
-				if let Err(e) = result {
-					error!("❌ Keyboard task error: {}", e);
-				}
-			}
-			result = space_task => {
-				if let Err(e) = result {
-					error!("❌ Space task error: {}", e);
-				}
-			}
-			result = app_task => {
-				if let Err(e) = result {
-					error!("❌ App task error: {}", e);
-				}
-			}
-			result = window_task => {
-				if let Err(e) = result {
-					error!("❌ Window task error: {}", e);
-				}
-			}
-		}
+	/// Poll the window manager every `period` instead
+	pub fn with_state_sync_period(mut self, period: Duration,) -> Self {
+		self.sync_period = period;
+		self
+	}
 
-		Ok((),)
+	/// Restart failed tasks according to `policy`
+	pub fn with_restart_policy(mut self, policy: RestartPolicy,) -> Self {
+		self.policy = policy;
+		self
 	}
 
-	/// State synchronization task - keeps centralized state up to date
-	fn spawn_state_sync_task(&self,) -> tokio::task::JoinHandle<Result<(),>,> {
-		let state = self.state.clone();
-		let mut shutdown_rx = self.shutdown_rx.resubscribe();
+	/// Record restarts and failures in `statuses`, e.g. the daemon's own
+	pub fn with_task_statuses(mut self, statuses: TaskStatuses,) -> Self {
+		self.statuses = statuses;
+		self
+	}
 
-		tokio::spawn(async move {
-			let mut interval = interval(Duration::from_secs(2,),);
+	/// Align timed renders to `wall_clock` instead of the system clock
+	pub fn with_wall_clock(mut self, wall_clock: WallClock,) -> Self {
+		self.wall_clock = wall_clock;
+		self
+	}
 
-			loop {
-				tokio::select! {
-					_ = interval.tick() => {
-						// Update all state in parallel
-						let (spaces_changed, windows_changed, app_changed) = tokio::join!(
-							state.update_spaces(),
-							state.update_windows(),
-							state.update_current_app()
-						);
-
-						if let Err(e) = spaces_changed {
-							debug!("Spaces update failed: {}", e);
-						}
-						if let Err(e) = windows_changed {
-							debug!("Windows update failed: {}", e);
-						}
-						if let Err(e) = app_changed {
-							debug!("App update failed: {}", e);
-						}
-					}
-					_ = shutdown_rx.recv() => {
-						info!("📊 State sync task shutting down");
-						break;
-					}
-				}
-			}
-			Ok((),)
-		},)
+	/// Wakeups of the scheduler driving timed renders
+	pub fn scheduler_stats(&self,) -> SchedulerStats {
+		self.stats.clone()
 	}
 
-	/// Clock update task (high frequency)
-	fn spawn_clock_task(&self,) -> tokio::task::JoinHandle<Result<(),>,> {
-		let bars = self.bars.clone();
-		let mut shutdown_rx = self.shutdown_rx.resubscribe();
+	/// Restarts and failures of the tasks run by [`Self::start_event_loops`]
+	pub fn task_statuses(&self,) -> TaskStatuses {
+		self.statuses.clone()
+	}
 
-		tokio::spawn(async move {
-			let mut interval = interval(Duration::from_secs(1,),);
+	/// Start all event-driven update loops under a supervisor of their own
+	///
+	/// Returns once shutdown is signalled, or with an error when the restart
+	/// policy gives up on a task.
+	pub async fn start_event_loops(&mut self,) -> Result<(),> {
+		info!("🎯 Starting event-driven update system");
 
-			loop {
-				tokio::select! {
-					_ = interval.tick() => {
-						let bars_guard = bars.read().await;
-						for bar in bars_guard.values() {
-							if let Err(e) = crate::items::clock::update(bar).await {
-								error!("❌ Clock update error: {}", e);
-							}
-						}
-					}
-					_ = shutdown_rx.recv() => {
-						info!("🕐 Clock update task shutting down");
-						break;
-					}
-				}
-			}
-			Ok((),)
-		},)
+		// Items render from fresh state on their first pass
+		self.sync_state().await;
+
+		let mut supervisor = Supervisor::new(self.policy.clone(), self.statuses.clone(),);
+		self.supervise(&mut supervisor,);
+		let result = supervisor.run(self.shutdown_rx.resubscribe(),).await;
+
+		info!("🛑 Event loops shutting down");
+		result
 	}
 
-	/// Battery update task (low frequency)
-	fn spawn_battery_task(&self,) -> tokio::task::JoinHandle<Result<(),>,> {
-		let bars = self.bars.clone();
+	/// Run the event loops for each config `configs` publishes, starting
+	/// them over with its items and intervals whenever it changes
+	///
+	/// `watched` polls at the safety net period, as for a window manager
+	/// that reports its own changes.
+	pub async fn run_reloading(
+		&self,
+		mut configs: watch::Receiver<Arc<ActiveConfig,>,>,
+		watched: bool,
+	) -> Result<(),> {
 		let mut shutdown_rx = self.shutdown_rx.resubscribe();
+		loop {
+			let active = configs.borrow_and_update().clone();
+			let (stop_tx, stop_rx,) = broadcast::channel(1,);
+			let mut generation = Self {
+				registry: active.registry.clone(),
+				shutdown_rx: stop_rx,
+				sync_period: active.config.intervals.sync_period(watched,),
+				..self.clone()
+			};
+			let loops = generation.start_event_loops();
+			tokio::pin!(loops);
 
-		tokio::spawn(async move {
-			let mut interval = interval(Duration::from_secs(30,),);
-
-			loop {
-				tokio::select! {
-					_ = interval.tick() => {
-						let bars_guard = bars.read().await;
-						for bar in bars_guard.values() {
-							if let Err(e) = crate::items::battery::update(bar).await {
-								error!("❌ Battery update error: {}", e);
-							}
-						}
-					}
-					_ = shutdown_rx.recv() => {
-						info!("🔋 Battery update task shutting down");
-						break;
-					}
-				}
+			tokio::select! {
+				result = &mut loops => return result,
+				_ = shutdown_rx.recv() => {
+					let _ = stop_tx.send((),);
+					return loops.await;
+				},
+				Ok(_,) = configs.changed() => {
+					info!("🔄 Restarting event loops for the new config");
+					let _ = stop_tx.send((),);
+					loops.await?;
+				},
 			}
-			Ok((),)
-		},)
+		}
 	}
 
-	/// Keyboard update task (medium frequency)
-	fn spawn_keyboard_task(&self,) -> tokio::task::JoinHandle<Result<(),>,> {
-		let bars = self.bars.clone();
-		let mut shutdown_rx = self.shutdown_rx.resubscribe();
+	/// Refresh the centralized state and what every item probes, once
+	pub async fn sync_state(&self,) {
+		sync_state(&self.state,).await;
+		join_all(self.registry.items().iter().map(|item| refresh(item.as_ref(), &self.state,),),)
+			.await;
+	}
 
-		tokio::spawn(async move {
-			let mut interval = interval(Duration::from_secs(5,),);
+	/// Hand the scheduler, the event listener and one task per registered
+	/// item to `supervisor`
+	pub fn supervise(&self, supervisor: &mut Supervisor,) {
+		// One timer for the state sync and every item with a cadence
+		let manager = self.clone();
+		supervisor.supervise("Scheduler", move || {
+			manager.scheduler().run(manager.shutdown_rx.resubscribe(),)
+		},);
 
-			loop {
-				tokio::select! {
-					_ = interval.tick() => {
-						let bars_guard = bars.read().await;
-						for bar in bars_guard.values() {
-							if let Err(e) = crate::items::keyboard::update(bar).await {
-								error!("❌ Keyboard update error: {}", e);
-							}
-						}
-					}
-					_ = shutdown_rx.recv() => {
-						info!("⌨️  Keyboard update task shutting down");
-						break;
-					}
-				}
-			}
-			Ok((),)
-		},)
+		if let Some(listener,) = &self.listener {
+			let (manager, listener,) = (self.clone(), listener.clone(),);
+			supervisor
+				.supervise("Event listener", move || manager.listener_task(listener.clone(),),);
+		}
+
+		// Item tasks - one per registered item, woken by state changes
+		for item in self.registry.items() {
+			let (manager, item,) = (self.clone(), item.clone(),);
+			supervisor.supervise(item.name(), move || manager.item_task(item.clone(),),);
+		}
 	}
 
-	/// Space update task (state-driven)
-	fn spawn_space_task(&self,) -> tokio::task::JoinHandle<Result<(),>,> {
-		let bars = self.bars.clone();
-		let state = self.state.clone();
-		let mut shutdown_rx = self.shutdown_rx.resubscribe();
+	/// Schedule the state sync and the timed refresh and render of every item
+	fn scheduler(&self,) -> Scheduler {
+		let mut scheduler = Scheduler::new(self.wall_clock.clone(), self.stats.clone(),);
 
-		tokio::spawn(async move {
-			let mut interval = interval(Duration::from_secs(1,),);
+		let state = self.state.clone();
+		scheduler.every("State sync", self.sync_period, move || {
+			let state = state.clone();
+			async move { sync_state(&state,).await }
+		},);
 
-			loop {
-				tokio::select! {
-					_ = interval.tick() => {
-						let bars_guard = bars.read().await;
-						for bar in bars_guard.values() {
-							if let Err(e) = crate::items::space::update_with_state(bar, &state).await {
-								error!("❌ Space update error: {}", e);
-							}
-						}
-					}
-					_ = shutdown_rx.recv() => {
-						info!("🏠 Space update task shutting down");
-						break;
+		for item in self.registry.items() {
+			let Some(period,) = item.cadence() else { continue };
+			let (item, bars, state,) = (item.clone(), self.bars.clone(), self.state.clone(),);
+			scheduler.every(item.name(), period, move || {
+				let (item, bars, state,) = (item.clone(), bars.clone(), state.clone(),);
+				async move {
+					// Probe once; the result fans out to every bar
+					refresh(item.as_ref(), &state,).await;
+					// Items that render from state are redrawn by the change it published
+					if item.dependencies().is_empty() {
+						bars.render(&item,);
 					}
 				}
-			}
-			Ok((),)
-		},)
+			},);
+		}
+
+		scheduler
 	}
 
-	/// App update task (state-driven)
-	fn spawn_app_task(&self,) -> tokio::task::JoinHandle<Result<(),>,> {
-		let bars = self.bars.clone();
+	/// Refresh what each event on `listener` makes stale, gathering bursts
+	/// of events into one refresh
+	fn listener_task(
+		&self,
+		listener: EventListener,
+	) -> impl Future<Output = Result<(),>,> + Send + use<> {
 		let state = self.state.clone();
+		let registry = self.registry.clone();
 		let mut shutdown_rx = self.shutdown_rx.resubscribe();
 
-		tokio::spawn(async move {
-			let mut interval = interval(Duration::from_secs(1,),);
+		async move {
+			let (events_tx, mut events_rx,) = mpsc::channel(64,);
+			let serve = listener.serve(events_tx,);
+			tokio::pin!(serve);
 
 			loop {
 				tokio::select! {
-					_ = interval.tick() => {
-						let bars_guard = bars.read().await;
-						for bar in bars_guard.values() {
-							if let Err(e) = crate::items::current_app::update_with_state(bar, &state).await {
-								error!("❌ Current app update error: {}", e);
-							}
+					result = &mut serve => return result,
+					Some(event) = events_rx.recv() => {
+						debug!("📨 {} {}", event.source, event.name);
+						let mut refresh = Refresh::for_event(&event,);
+						sleep(EVENT_COALESCE,).await;
+						while let Ok(event,) = events_rx.try_recv() {
+							refresh = refresh.merge(Refresh::for_event(&event,),);
+						}
+						if !refresh.is_empty() {
+							let probed = registry.items().iter().filter(|item| {
+								item.dependencies().iter().any(|d| refresh.probes(*d,),)
+							},);
+							tokio::join!(
+								refresh_state(&state, refresh,),
+								join_all(probed.map(|item| self::refresh(item.as_ref(), &state,),),)
+							);
 						}
 					}
-					_ = shutdown_rx.recv() => {
-						info!("📱 Current app update task shutting down");
-						break;
-					}
+					_ = shutdown_rx.recv() => return Ok(()),
 				}
 			}
-			Ok((),)
-		},)
+		}
 	}
 
-	/// Window update task (state-driven)
-	fn spawn_window_task(&self,) -> tokio::task::JoinHandle<Result<(),>,> {
+	/// Have every bar render one item: once at start, then whenever a state
+	/// it depends on changes or its bar is provisioned again
+	fn item_task(
+		&self,
+		item: Arc<dyn BarItem,>,
+	) -> impl Future<Output = Result<(),>,> + Send + use<> {
 		let bars = self.bars.clone();
-		let state = self.state.clone();
+		let mut events_rx = self.state.subscribe();
 		let mut shutdown_rx = self.shutdown_rx.resubscribe();
 
-		tokio::spawn(async move {
-			let mut interval = interval(Duration::from_secs(1,),);
+		async move {
+			bars.render(&item,);
 
 			loop {
 				tokio::select! {
-					_ = interval.tick() => {
-						let bars_guard = bars.read().await;
-						for bar in bars_guard.values() {
-							if let Err(e) = crate::items::window::update_with_state(bar, &state).await {
-								error!("❌ Window update error: {}", e);
-							}
-						}
-					}
+					event = events_rx.recv() => match event {
+						Ok(StateEvent::BarProvisioned(display_id,),) => {
+							bars.render_on(&display_id, &item,);
+						},
+						Ok(event,) if depends_on(item.as_ref(), &event,) => {
+							bars.render(&item,);
+						},
+						Ok(_,) => {},
+						// Missed some changes; a render covers all of them
+						Err(RecvError::Lagged(_,),) => {
+							bars.render(&item,);
+						},
+						Err(RecvError::Closed,) => break,
+					},
 					_ = shutdown_rx.recv() => {
-						info!("🪟 Window update task shutting down");
+						info!("{} update task shutting down", item.name());
 						break;
 					}
 				}
 			}
 			Ok((),)
-		},)
+		}
+	}
+}
+
+/// Run the provider of `item`
+async fn refresh(item: &dyn BarItem, state: &DaemonState,) {
+	if let Err(e,) = item.refresh(state,).await {
+		debug!("{} refresh failed: {}", item.name(), e);
+	}
+}
+
+/// Whether `event` changes data that `item` renders
+fn depends_on(item: &dyn BarItem, event: &StateEvent,) -> bool {
+	event.dependency().is_some_and(|d| item.dependencies().contains(&d,),)
+}
+
+/// Refresh spaces, windows and the focused app; [`DaemonState`] publishes
+/// whatever changed
+async fn sync_state(state: &DaemonState,) {
+	refresh_state(state, Refresh::DESKTOP,).await;
+}
+
+/// Query the window manager for the parts of the state `refresh` names
+///
+/// A query that fails leaves its part of the state as it was.
+pub async fn refresh_state(state: &DaemonState, refresh: Refresh,) {
+	let (spaces_changed, windows_changed, app_changed, displays_changed,) = tokio::join!(
+		async { if refresh.spaces { state.update_spaces().await } else { Ok(false,) } },
+		async { if refresh.windows { state.update_windows().await } else { Ok(false,) } },
+		async { if refresh.current_app { state.update_current_app().await } else { Ok(false,) } },
+		async { if refresh.displays { state.update_displays().await } else { Ok(false,) } }
+	);
+
+	if let Err(e,) = spaces_changed {
+		debug!("Spaces update failed: {}", e);
+	}
+	if let Err(e,) = windows_changed {
+		debug!("Windows update failed: {}", e);
+	}
+	if let Err(e,) = app_changed {
+		debug!("App update failed: {}", e);
+	}
+	if let Err(e,) = displays_changed {
+		debug!("Displays update failed: {}", e);
 	}
 }
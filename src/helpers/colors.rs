@@ -1,4 +1,4 @@
-/// Color palette based on Catppuccin theme (matching your Lua config)
+/// Catppuccin Mocha swatches, which the `mocha` theme is made of
 pub struct Colors;
 
 impl Colors {
@@ -33,23 +33,3 @@ impl Colors {
 	pub const YELLOW: u32 = 0xfff9e2af;
 }
 
-/// Get color based on battery percentage (matching your Lua logic)
-pub fn battery_color(percentage: u8, is_charging: bool,) -> u32 {
-	if is_charging {
-		return Colors::BLUE;
-	}
-
-	match percentage {
-		95..=100 => Colors::BLUE,
-		85..=94 => Colors::SAPPHIRE,
-		75..=84 => Colors::SKY,
-		65..=74 => Colors::TEAL,
-		55..=64 => Colors::GREEN,
-		45..=54 => Colors::YELLOW,
-		35..=44 => Colors::PEACH,
-		25..=34 => Colors::MAROON,
-		15..=24 => Colors::RED,
-		5..=14 => Colors::RED,
-		_ => Colors::RED,
-	}
-}
@@ -1,19 +1,30 @@
 use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
-use std::collections::HashMap;
-use std::process::Command;
+use serde::de::DeserializeOwned;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixStream;
+use tokio::time::Duration;
 use tracing::debug;
-use tracing::warn;
 
-#[derive(Debug, Clone, Serialize, Deserialize,)]
+use crate::runner::DEFAULT_TIMEOUT;
+use crate::runner::Invocation;
+use crate::runner::RunError;
+use crate::runner::Runner;
+use crate::wm::WindowManagerBackend;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize,)]
 pub struct DisplayInfo {
 	pub index:      u32,
 	pub is_builtin: bool,
 	pub frame:      DisplayFrame,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize,)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize,)]
 pub struct DisplayFrame {
 	pub x: f64,
 	pub y: f64,
@@ -21,175 +32,260 @@ pub struct DisplayFrame {
 	pub h: f64,
 }
 
-/// Get all available displays from yabai
-pub async fn get_displays() -> Result<HashMap<String, DisplayInfo,>,> {
-	let output = Command::new("yabai",).args(["-m", "query", "--displays",],).output()?;
-
-	if !output.status.success() {
-		// Fallback if yabai is not available
-		warn!("⚠️  yabai not available, using default display");
-		let mut displays = HashMap::new();
-		displays.insert(
-			"1".to_string(),
-			DisplayInfo {
-				index:      1,
-				is_builtin: true,
-				frame:      DisplayFrame { x: 0.0, y: 0.0, w: 1920.0, h: 1080.0, },
-			},
-		);
-		return Ok(displays,);
-	}
+/// Get the builtin display info
+pub async fn get_builtin_display(
+	backend: &dyn WindowManagerBackend,
+) -> Result<Option<DisplayInfo,>,> {
+	let displays = backend.displays().await?;
+	Ok(displays.values().find(|d| d.is_builtin,).cloned(),)
+}
 
-	let json_str = String::from_utf8(output.stdout,)?;
-	let yabai_displays: Vec<YabaiDisplay,> = serde_json::from_str(&json_str,)?;
+/// Get external display indices
+pub async fn get_external_displays(
+	backend: &dyn WindowManagerBackend,
+) -> Result<Vec<DisplayInfo,>,> {
+	let displays = backend.displays().await?;
+	Ok(displays.values().filter(|d| !d.is_builtin,).cloned().collect(),)
+}
 
-	let mut displays = HashMap::new();
+/// The socket yabai listens on for the current user
+pub fn default_socket_path() -> PathBuf {
+	let user = std::env::var("USER",).unwrap_or_default();
+	PathBuf::from(format!("/tmp/yabai_{}.socket", user),)
+}
 
-	for display in yabai_displays {
-		let display_info = DisplayInfo {
-			index:      display.index,
-			is_builtin: display.label.contains("Built-in",) || display.index == 1,
-			frame:      DisplayFrame {
-				x: display.frame.x,
-				y: display.frame.y,
-				w: display.frame.w,
-				h: display.frame.h,
-			},
-		};
+/// Client for yabai's message socket, falling back to the `yabai` CLI
+///
+/// Sends the same bytes `yabai -m` would, without spawning a process per
+/// query. yabai answers one message per connection and then hangs up, so
+/// each message connects afresh; a socket that is missing or refuses the
+/// connection hands the message to the CLI instead, which also covers yabai
+/// being restarted under the daemon.
+#[derive(Debug, Clone,)]
+pub struct YabaiClient {
+	socket:  PathBuf,
+	runner:  Arc<dyn Runner,>,
+	timeout: Duration,
+}
 
-		displays.insert(display.index.to_string(), display_info,);
+impl YabaiClient {
+	/// Client for the current user's socket
+	pub fn new(runner: Arc<dyn Runner,>,) -> Self {
+		Self::with_socket(default_socket_path(), runner,)
 	}
 
-	Ok(displays,)
-}
+	pub fn with_socket(socket: impl Into<PathBuf,>, runner: Arc<dyn Runner,>,) -> Self {
+		Self { socket: socket.into(), runner, timeout: DEFAULT_TIMEOUT, }
+	}
 
-/// Query all spaces from yabai
-pub async fn query_spaces() -> Result<Vec<crate::state::SpaceInfo,>,> {
-	let output = Command::new("yabai",).args(["-m", "query", "--spaces",],).output()?;
+	/// Give up on a message after `timeout`
+	pub fn timeout(mut self, timeout: Duration,) -> Self {
+		self.timeout = timeout;
+		self
+	}
 
-	if !output.status.success() {
-		return Err(anyhow::anyhow!("yabai spaces query failed"),);
+	pub fn socket(&self,) -> &Path {
+		&self.socket
 	}
 
-	let json_str = String::from_utf8(output.stdout,)?;
-	let yabai_spaces: Vec<YabaiSpace,> = serde_json::from_str(&json_str,)?;
+	/// Send `args`, everything after `yabai -m`, and return the reply
+	pub async fn message(&self, args: &[&str],) -> Result<String, RunError,> {
+		let reply = match tokio::time::timeout(self.timeout, self.exchange(args,),).await {
+			Ok(Ok(reply,),) => reply,
+			Ok(Err(e,),) if is_unavailable(&e,) => {
+				debug!("🔌 yabai socket unavailable ({}), using the CLI", e);
+				let cli = Invocation::new("yabai", &[&["-m",], args,].concat(),);
+				return cli.timeout(self.timeout,).output(self.runner.as_ref(),).await;
+			},
+			Ok(Err(e,),) => {
+				return Err(RunError::Io { program: "yabai".to_string(), message: e.to_string(), },);
+			},
+			Err(_,) => {
+				return Err(RunError::TimedOut {
+					program: "yabai".to_string(),
+					timeout: self.timeout,
+				},);
+			},
+		};
+		decode_reply(reply,)
+	}
 
-	let spaces: Vec<crate::state::SpaceInfo,> = yabai_spaces
-		.into_iter()
-		.map(|s| crate::state::SpaceInfo {
-			index:     s.index,
-			display:   s.display,
-			has_focus: s.has_focus,
-			windows:   s.windows,
-			label:     s.label,
+	/// Send a `query` and deserialize the JSON reply
+	pub async fn query<T: DeserializeOwned,>(&self, args: &[&str],) -> Result<T, RunError,> {
+		let reply = self.message(&[&["query",], args,].concat(),).await?;
+		serde_json::from_str(&reply,).map_err(|e| RunError::Parse {
+			program: "yabai".to_string(),
+			message: e.to_string(),
 		},)
-		.collect();
+	}
+
+	async fn exchange(&self, args: &[&str],) -> std::io::Result<Vec<u8,>,> {
+		let mut stream = UnixStream::connect(&self.socket,).await?;
+		stream.write_all(&encode_message(args,),).await?;
+		stream.shutdown().await?;
+		let mut reply = Vec::new();
+		stream.read_to_end(&mut reply,).await?;
+		Ok(reply,)
+	}
+}
 
-	debug!("📊 Queried {} spaces from yabai", spaces.len());
-	Ok(spaces,)
+/// Whether connecting failed because nothing is listening
+fn is_unavailable(e: &std::io::Error,) -> bool {
+	matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused)
 }
 
-/// Query all windows from yabai
-pub async fn query_windows() -> Result<Vec<crate::state::WindowInfo,>,> {
-	let output = Command::new("yabai",).args(["-m", "query", "--windows",],).output()?;
+/// First byte of a reply that carries an error message instead of output
+const FAILURE_MESSAGE: u8 = 0x07;
 
-	if !output.status.success() {
-		return Err(anyhow::anyhow!("yabai windows query failed"),);
+/// Frame `args` as the yabai client does: the payload length as a native
+/// `int`, then each argument NUL terminated, then one more NUL
+pub fn encode_message(args: &[&str],) -> Vec<u8,> {
+	let payload_len: usize = args.iter().map(|arg| arg.len() + 1,).sum::<usize>() + 1;
+	let mut message = Vec::with_capacity(4 + payload_len,);
+	message.extend_from_slice(&(payload_len as i32).to_ne_bytes(),);
+	for arg in args {
+		message.extend_from_slice(arg.as_bytes(),);
+		message.push(0,);
 	}
+	message.push(0,);
+	message
+}
 
-	let json_str = String::from_utf8(output.stdout,)?;
-	let yabai_windows: Vec<YabaiWindow,> = serde_json::from_str(&json_str,)?;
+/// Output of a reply, or the error yabai sent back
+fn decode_reply(reply: Vec<u8,>,) -> Result<String, RunError,> {
+	if reply.first() == Some(&FAILURE_MESSAGE,) {
+		return Err(RunError::Failed {
+			program: "yabai".to_string(),
+			code:    Some(1,),
+			stderr:  String::from_utf8_lossy(&reply[1..],).trim().to_string(),
+		},);
+	}
+	String::from_utf8(reply,).map_err(|e| RunError::Parse {
+		program: "yabai".to_string(),
+		message: e.to_string(),
+	},)
+}
 
-	let windows: Vec<crate::state::WindowInfo,> = yabai_windows
-		.into_iter()
-		.map(|w| crate::state::WindowInfo {
-			id:        w.id,
-			app:       w.app,
-			title:     w.title,
-			space:     w.space,
-			display:   w.display,
-			has_focus: w.has_focus,
-		},)
-		.collect();
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::FakeRunner;
+	use std::sync::Mutex;
+	use tokio::net::UnixListener;
 
-	debug!("🪟 Queried {} windows from yabai", windows.len());
-	Ok(windows,)
-}
+	/// A socket server answering like yabai, recording each message's args
+	struct StandIn {
+		socket:   PathBuf,
+		messages: Arc<Mutex<Vec<Vec<String,>,>,>,>,
+	}
+
+	impl StandIn {
+		/// Serve `reply` for each message, or hold the connection open on `None`
+		fn spawn(test: &str, reply: fn(&[String],) -> Option<Vec<u8,>,>,) -> Self {
+			let socket = std::env::temp_dir()
+				.join(format!("sbd-yabai-{}-{}.socket", test, std::process::id()),);
+			let _ = std::fs::remove_file(&socket,);
+			let listener = UnixListener::bind(&socket,).unwrap();
+			let messages = Arc::new(Mutex::new(Vec::new(),),);
+			let recorded = messages.clone();
+			tokio::spawn(async move {
+				let mut held = Vec::new();
+				while let Ok((mut stream, _,),) = listener.accept().await {
+					let mut len = [0; 4];
+					stream.read_exact(&mut len,).await.unwrap();
+					let mut payload = vec![0; i32::from_ne_bytes(len,) as usize];
+					stream.read_exact(&mut payload,).await.unwrap();
+					let args: Vec<String,> = payload
+						.split(|b| *b == 0,)
+						.filter(|arg| !arg.is_empty(),)
+						.map(|arg| String::from_utf8_lossy(arg,).into_owned(),)
+						.collect();
+					let answer = reply(&args,);
+					recorded.lock().unwrap().push(args,);
+					match answer {
+						Some(answer,) => stream.write_all(&answer,).await.unwrap(),
+						None => held.push(stream,),
+					}
+				}
+			},);
+			Self { socket, messages, }
+		}
+
+		fn client(&self, runner: &FakeRunner,) -> YabaiClient {
+			YabaiClient::with_socket(&self.socket, Arc::new(runner.clone(),),)
+		}
 
-/// Query focused application
-pub async fn query_focused_app() -> Result<String,> {
-	// Try yabai first
-	if let Ok(output,) =
-		Command::new("yabai",).args(["-m", "query", "--windows", "--window",],).output()
-	{
-		if output.status.success() {
-			let json_str = String::from_utf8(output.stdout,)?;
-			if let Ok(window,) = serde_json::from_str::<YabaiWindow,>(&json_str,) {
-				return Ok(window.app,);
-			}
+		fn messages(&self,) -> Vec<Vec<String,>,> {
+			self.messages.lock().unwrap().clone()
 		}
 	}
 
-	// Fallback to AppleScript
-	let output = Command::new("osascript",)
-		.args([
-			"-e",
-			"tell application \"System Events\" to get name of first application process whose \
-			 frontmost is true",
-		],)
-		.output()?;
+	impl Drop for StandIn {
+		fn drop(&mut self,) {
+			let _ = std::fs::remove_file(&self.socket,);
+		}
+	}
 
-	if !output.status.success() {
-		return Err(anyhow::anyhow!("AppleScript query failed"),);
+	#[test]
+	fn test_message_framing() {
+		let message = encode_message(&["query", "--spaces",],);
+		assert_eq!(message[..4], 16i32.to_ne_bytes());
+		assert_eq!(&message[4..], b"query\0--spaces\0\0");
 	}
 
-	Ok(String::from_utf8(output.stdout,)?.trim().to_string(),)
-}
+	#[tokio::test]
+	async fn test_queries_go_over_the_socket() -> Result<(),> {
+		let stand_in = StandIn::spawn("query", |args| {
+			Some(format!(r#"[{{"asked": {:?}}}]"#, args.join(" ")).into_bytes(),)
+		},);
+		let runner = FakeRunner::new();
+		let client = stand_in.client(&runner,);
 
-/// Get the builtin display info
-pub async fn get_builtin_display() -> Result<Option<DisplayInfo,>,> {
-	let displays = get_displays().await?;
-	Ok(displays.values().find(|d| d.is_builtin,).cloned(),)
-}
+		let spaces: serde_json::Value = client.query(&["--spaces",],).await?;
+		assert_eq!(spaces[0]["asked"], "query --spaces");
+		let window: serde_json::Value = client.query(&["--windows", "--window",],).await?;
+		assert_eq!(window[0]["asked"], "query --windows --window");
 
-/// Get external display indices
-pub async fn get_external_displays() -> Result<Vec<DisplayInfo,>,> {
-	let displays = get_displays().await?;
-	Ok(displays.values().filter(|d| !d.is_builtin,).cloned().collect(),)
-}
+		assert_eq!(stand_in.messages().len(), 2);
+		assert!(runner.calls().is_empty(), "no process should be spawned");
+		Ok((),)
+	}
 
-#[derive(Debug, Deserialize,)]
-struct YabaiDisplay {
-	index: u32,
-	label: String,
-	frame: YabaiFrame,
-}
+	#[tokio::test]
+	async fn test_failure_reply() {
+		let stand_in = StandIn::spawn("failure", |_| {
+			Some(b"\x07could not locate the selected window.\n".to_vec(),)
+		},);
+		let client = stand_in.client(&FakeRunner::new(),);
 
-#[derive(Debug, Deserialize,)]
-struct YabaiFrame {
-	x: f64,
-	y: f64,
-	w: f64,
-	h: f64,
-}
+		let err = client.message(&["query", "--windows", "--window",],).await.unwrap_err();
+		match err {
+			RunError::Failed { stderr, .. } => {
+				assert_eq!(stderr, "could not locate the selected window.")
+			},
+			other => panic!("expected a failure, got {:?}", other),
+		}
+	}
 
-#[derive(Debug, Deserialize,)]
-struct YabaiSpace {
-	index:     u32,
-	display:   u32,
-	#[serde(rename = "has-focus")]
-	has_focus: bool,
-	windows:   Vec<u32,>,
-	label:     String,
-}
+	#[tokio::test]
+	async fn test_silent_socket_times_out() {
+		let stand_in = StandIn::spawn("silent", |_| None,);
+		let client = stand_in.client(&FakeRunner::new(),).timeout(Duration::from_millis(50,),);
+
+		let err = client.message(&["query", "--spaces",],).await.unwrap_err();
+		assert!(matches!(err, RunError::TimedOut { .. }), "{:?}", err);
+	}
+
+	#[tokio::test]
+	async fn test_missing_socket_falls_back_to_cli() -> Result<(),> {
+		let runner = FakeRunner::new();
+		runner.respond("yabai -m query --spaces", "[]",);
+		let client =
+			YabaiClient::with_socket("/nonexistent/yabai.socket", Arc::new(runner.clone(),),);
 
-#[derive(Debug, Deserialize,)]
-struct YabaiWindow {
-	id:        u32,
-	app:       String,
-	title:     String,
-	space:     u32,
-	display:   u32,
-	#[serde(rename = "has-focus")]
-	has_focus: bool,
+		let spaces: Vec<serde_json::Value,> = client.query(&["--spaces",],).await?;
+		assert!(spaces.is_empty());
+		assert_eq!(runner.calls(), ["yabai -m query --spaces"]);
+		Ok((),)
+	}
 }
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::time::Duration;
+
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::BarItem;
+use crate::items::ItemSpec;
+use crate::items::StateDependency;
+use crate::runner::Invocation;
+use crate::runner::RunError;
+use crate::runner::Runner;
+use crate::sketchybar::ItemKind;
+use crate::sketchybar::ItemProperties;
+use crate::sketchybar::SketchyBar;
+use crate::sketchybar::properties::ItemPosition;
+use crate::state::Appearance;
+use crate::state::DaemonState;
+
+/// Hidden item that hears about light and dark mode switches for the daemon
+pub const WATCHER_ITEM: &str = "sketchybar_daemon.appearance";
+
+/// sketchybar event the watcher subscribes to
+pub const APPEARANCE_EVENT: &str = "appearance_change";
+
+/// Distributed notification macOS posts when it switches between light and dark
+pub const APPEARANCE_NOTIFICATION: &str = "AppleInterfaceThemeChangedNotification";
+
+/// Probes the system appearance whenever macOS announces a switch
+///
+/// Draws nothing; only registered while the config follows the system
+/// appearance. The cadence catches switches made while no bar was running.
+#[derive(Debug, Default,)]
+pub struct AppearanceWatcher;
+
+#[async_trait]
+impl BarItem for AppearanceWatcher {
+	fn name(&self,) -> &'static str {
+		"appearance"
+	}
+
+	fn setup(&self, _display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+		let properties = ItemProperties { drawing: Some(false,), ..Default::default() };
+		vec![ItemSpec::new(ItemKind::Item, WATCHER_ITEM, ItemPosition::Left, properties,)]
+	}
+
+	fn subscriptions(&self,) -> &'static [&'static str] {
+		&[APPEARANCE_EVENT,]
+	}
+
+	fn notifications(&self,) -> &'static [(&'static str, &'static str,)] {
+		&[(APPEARANCE_EVENT, APPEARANCE_NOTIFICATION,),]
+	}
+
+	fn dependencies(&self,) -> &'static [StateDependency] {
+		&[StateDependency::Appearance,]
+	}
+
+	fn cadence(&self,) -> Option<Duration,> {
+		Some(Duration::from_secs(60,),)
+	}
+
+	async fn refresh(&self, state: &DaemonState,) -> Result<(),> {
+		state.apply_appearance(probe(state.runner(),).await?,).await;
+		Ok((),)
+	}
+
+	async fn render(&self, _bar: &SketchyBar, _state: &DaemonState,) -> Result<(),> {
+		Ok((),)
+	}
+}
+
+/// Read the system appearance through `defaults`
+///
+/// `AppleInterfaceStyle` is only set in dark mode; in light mode reading it
+/// fails.
+pub async fn probe(runner: &dyn Runner,) -> Result<Appearance,> {
+	let defaults = Invocation::new("defaults", &["read", "-g", "AppleInterfaceStyle",],);
+	match defaults.output(runner,).await {
+		Ok(output,) if output.trim() == "Dark" => Ok(Appearance::Dark,),
+		Ok(_,) | Err(RunError::Failed { .. },) => Ok(Appearance::Light,),
+		Err(e,) => Err(e.into(),),
+	}
+}
@@ -1,87 +1,133 @@
-use crate::helpers::colors::Colors;
-use crate::helpers::colors::{self};
-use crate::helpers::icons::Icons;
-use crate::helpers::icons::{self};
-use crate::helpers::yabai::DisplayInfo;
-use crate::sketchybar::SketchyBar;
 use anyhow::Result;
+use async_trait::async_trait;
 use regex::Regex;
-use std::process::Command;
+use tokio::time::Duration;
 use tracing::debug;
 use tracing::error;
-use tracing::warn;
 
-pub async fn setup(bar: &mut SketchyBar, display_info: &DisplayInfo,) -> Result<(),> {
-	debug!("🔋 Setting up battery item for display {}", display_info.index);
+use crate::helpers::icons::{self};
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::BarItem;
+use crate::items::ItemSpec;
+use crate::items::StateDependency;
+use crate::runner::Invocation;
+use crate::runner::Runner;
+use crate::sketchybar::ItemKind;
+use crate::sketchybar::ItemProperties;
+use crate::sketchybar::SketchyBar;
+use crate::sketchybar::properties::ItemPosition;
+use crate::sketchybar::properties::TextProperties;
+use crate::sketchybar::properties::Width;
+use crate::state::BatteryInfo;
+use crate::state::DaemonState;
+use crate::theme::Theme;
+
+/// Charge and power source; only the builtin display has one
+#[derive(Default,)]
+pub struct Battery {
+	pub theme: Theme,
+}
+
+#[async_trait]
+impl BarItem for Battery {
+	fn name(&self,) -> &'static str {
+		"battery"
+	}
 
-	// Add battery item
-	bar.add("item", "battery", "right",).await?;
+	fn applies_to(&self, display_info: &DisplayInfo,) -> bool {
+		display_info.is_builtin
+	}
 
-	// Configure battery properties
-	bar.set(
-		"battery",
-		&[
-			("width", "dynamic",),
-			("position", "right",),
-			("associated_display", &display_info.index.to_string(),),
-		],
-	)
-	.await?;
+	fn setup(&self, display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+		vec![spec(display_info,)]
+	}
 
-	// Subscribe to events
-	bar.subscribe("battery", &["routine", "power_source_change", "system_woke",],).await?;
+	fn subscriptions(&self,) -> &'static [&'static str] {
+		&["power_source_change", "system_woke",]
+	}
 
-	debug!("✅ Battery item configured for display {}", display_info.index);
-	Ok((),)
-}
+	fn dependencies(&self,) -> &'static [StateDependency] {
+		&[StateDependency::Battery,]
+	}
 
-/// Update battery display with current status
-pub async fn update(bar: &SketchyBar,) -> Result<(),> {
-	// Get battery information using pmset
-	let output = Command::new("pmset",)
-		.args(["-g", "batt",],)
-		.output()
-		.map_err(|e| anyhow::anyhow!("Failed to run pmset: {}", e),)?;
-
-	if !output.status.success() {
-		warn!("pmset command failed with status: {}", output.status);
-		return Ok((),); // Don't fail the entire update loop
+	fn cadence(&self,) -> Option<Duration,> {
+		Some(Duration::from_secs(30,),)
 	}
 
-	let batt_info = String::from_utf8(output.stdout,)
-		.map_err(|e| anyhow::anyhow!("Failed to parse pmset output: {}", e),)?;
-
-	let mut icon = Icons::ERROR;
-	let mut label = "?".to_string();
-	let mut color = Colors::YELLOW;
-
-	// Parse battery percentage
-	let re = Regex::new(r"(\d+)%",)?;
-	if let Some(captures,) = re.captures(&batt_info,) {
-		if let Some(charge_match,) = captures.get(1,) {
-			let charge: u8 = charge_match.as_str().parse().unwrap_or(0,);
-			label = if charge < 10 { format!("0{}", charge) } else { charge.to_string() };
-
-			// Check if charging
-			let is_charging = batt_info.contains("AC Power",);
-
-			// Get appropriate icon and color
-			icon = icons::battery_icon(charge, is_charging,);
-			color = colors::battery_color(charge, is_charging,);
-		}
-	} else {
-		warn!("Could not parse battery percentage from pmset output");
-		return Ok((),); // Don't fail the entire update loop
+	async fn refresh(&self, state: &DaemonState,) -> Result<(),> {
+		state.apply_battery(probe(state.runner(),).await?,).await;
+		Ok((),)
 	}
 
+	async fn render(&self, bar: &SketchyBar, state: &DaemonState,) -> Result<(),> {
+		update_with_state(bar, state, &self.theme,).await
+	}
+}
+
+/// Battery status on the right; only declared for the builtin display
+pub fn spec(display_info: &DisplayInfo,) -> ItemSpec {
+	let properties = ItemProperties {
+		width: Some(Width::Dynamic,),
+		associated_display: Some(display_info.index,),
+		..Default::default()
+	};
+
+	ItemSpec::new(ItemKind::Item, "battery", ItemPosition::Right, properties,)
+}
+
+/// Read the battery through `pmset`
+pub async fn probe(runner: &dyn Runner,) -> Result<BatteryInfo,> {
+	let pmset = Invocation::new("pmset", &["-g", "batt",],);
+	let batt_info = pmset.output(runner,).await?;
+
+	parse_pmset(&batt_info,)
+		.ok_or_else(|| pmset.parse_error("no battery percentage",).into(),)
+}
+
+/// Charge and power source from `pmset -g batt` output
+pub fn parse_pmset(batt_info: &str,) -> Option<BatteryInfo,> {
+	let re = Regex::new(r"(\d+)%",).ok()?;
+	let charge = re.captures(batt_info,)?.get(1,)?.as_str().parse().unwrap_or(0,);
+	Some(BatteryInfo { charge, charging: batt_info.contains("AC Power",), },)
+}
+
+/// Update battery display from the last reading in state
+pub async fn update_with_state(
+	bar: &SketchyBar,
+	state: &DaemonState,
+	theme: &Theme,
+) -> Result<(),> {
+	let battery = state.battery.read().await.clone();
+	match battery {
+		Some(info,) => render_info(bar, &info, theme,).await,
+		// Nothing read yet; keep what the bar shows
+		None => Ok((),),
+	}
+}
+
+async fn render_info(bar: &SketchyBar, info: &BatteryInfo, theme: &Theme,) -> Result<(),> {
+	let label = format!("{:02}", info.charge);
+	let icon = icons::battery_icon(info.charge, info.charging,);
+	let color = theme.battery(info.charge, info.charging,);
+
 	// Update the battery item
-	let cmd = format!(
-		"--set battery icon={} icon.color=0x{:08x} icon.padding_left=10 label={} \
-		 label.color=0x{:08x} label.padding_right=10",
-		icon, color, label, color
-	);
+	let props = ItemProperties {
+		icon: TextProperties {
+			value: Some(icon.to_string(),),
+			color,
+			padding_left: Some(10,),
+			..Default::default()
+		},
+		label: TextProperties {
+			value: Some(label.clone(),),
+			color,
+			padding_right: Some(10,),
+			..Default::default()
+		},
+		..Default::default()
+	};
 
-	if let Err(e,) = bar.message(&cmd,).await {
+	if let Err(e,) = bar.set("battery", props,).await {
 		error!("Failed to update battery: {}", e);
 		return Err(e,);
 	}
@@ -89,7 +135,7 @@ pub async fn update(bar: &SketchyBar,) -> Result<(),> {
 	debug!(
 		"🔋 Battery updated: {}% ({})",
 		label,
-		if batt_info.contains("AC Power") { "charging" } else { "discharging" }
+		if info.charging { "charging" } else { "discharging" }
 	);
 	Ok((),)
 }
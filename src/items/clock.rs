@@ -1,54 +1,77 @@
-use crate::helpers::colors::Colors;
-use crate::helpers::yabai::DisplayInfo;
-use crate::sketchybar::SketchyBar;
 use anyhow::Result;
+use async_trait::async_trait;
 use chrono::Local;
+use tokio::time::Duration;
 use tracing::debug;
 use tracing::error;
 
-pub async fn setup(bar: &mut SketchyBar, display_info: &DisplayInfo,) -> Result<(),> {
-	debug!("🕐 Setting up clock item for display {}", display_info.index);
-
-	// Add clock item
-	bar.add("item", "clock", "right",).await?;
-
-// Configure clock properties
-	bar.set(
-		"clock",
-		&[
-			("update_freq", "1",),
-			("width", "dynamic",),
-			("position", "right",),
-			("label.color", &format!("0x{:08x}", Colors::FLAMINGO),),
-			("background.border_color", &format!("0x{:08x}", Colors::FLAMINGO),),
-		],
-	)
-	.await?;
-
-	// Only show on builtin display
-	if display_info.is_builtin {
-		bar.set("clock", &[("associated_display", &display_info.index.to_string(),),],).await?;
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::BarItem;
+use crate::items::ItemSpec;
+use crate::sketchybar::ItemKind;
+use crate::sketchybar::ItemProperties;
+use crate::sketchybar::SketchyBar;
+use crate::sketchybar::properties::BackgroundProperties;
+use crate::sketchybar::properties::ItemPosition;
+use crate::sketchybar::properties::TextProperties;
+use crate::sketchybar::properties::Width;
+use crate::state::DaemonState;
+use crate::theme::Theme;
+
+/// Date and time, refreshed on the minute
+#[derive(Default,)]
+pub struct Clock {
+	pub theme: Theme,
+}
+
+#[async_trait]
+impl BarItem for Clock {
+	fn name(&self,) -> &'static str {
+		"clock"
 	}
 
-	// Subscribe to events
-	bar.subscribe("clock", &["system_woke", "routine",],).await?;
+	fn setup(&self, display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+		vec![spec(display_info, &self.theme,)]
+	}
 
-	debug!("✅ Clock item configured for display {}", display_info.index);
-	Ok((),)
-}
+	fn subscriptions(&self,) -> &'static [&'static str] {
+		&["system_woke",]
+	}
+
+	/// The label shows minutes; the scheduler lines this up with the minute turning over
+	fn cadence(&self,) -> Option<Duration,> {
+		Some(Duration::from_secs(60,),)
+	}
 
-/// Update clock display with current time
-pub async fn update(bar: &SketchyBar,) -> Result<(),> {
-	let now = Local::now();
-	let time_str = now.format("%y%m%d %H%M %a",).to_string();
+	async fn render(&self, bar: &SketchyBar, _state: &DaemonState,) -> Result<(),> {
+		let now = Local::now();
+		let time_str = now.format("%y%m%d %H%M %a",).to_string();
 
-	let cmd = format!("--set clock label={}", time_str);
+		let props = ItemProperties::label(&time_str,);
 
-	if let Err(e,) = bar.message(&cmd,).await {
-		error!("Failed to update clock: {}", e);
-		return Err(e,);
+		if let Err(e,) = bar.set("clock", props,).await {
+			error!("Failed to update clock: {}", e);
+			return Err(e,);
+		}
+
+		debug!("🕐 Clock updated: {}", time_str);
+		Ok((),)
 	}
+}
+
+/// Clock on the right, tied to the builtin display when there is one
+pub fn spec(display_info: &DisplayInfo, theme: &Theme,) -> ItemSpec {
+	let properties = ItemProperties {
+		width: Some(Width::Dynamic,),
+		// Only show on builtin display
+		associated_display: display_info.is_builtin.then_some(display_info.index,),
+		label: TextProperties { color: theme.accents.clock, ..Default::default() },
+		background: BackgroundProperties {
+			border_color: theme.accents.clock,
+			..Default::default()
+		},
+		..Default::default()
+	};
 
-	debug!("🕐 Clock updated: {}", time_str);
-	Ok((),)
+	ItemSpec::new(ItemKind::Item, "clock", ItemPosition::Right, properties,)
 }
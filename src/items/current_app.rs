@@ -1,62 +1,80 @@
-use crate::helpers::colors::Colors;
-use crate::helpers::icons::Icons;
-use crate::helpers::yabai::DisplayInfo;
-use crate::sketchybar::SketchyBar;
 use anyhow::Result;
-use serde::Deserialize;
-use std::process::Command;
+use async_trait::async_trait;
+use tokio::time::Duration;
 use tracing::debug;
 use tracing::error;
 
-pub async fn setup(bar: &mut SketchyBar, display_info: &DisplayInfo,) -> Result<(),> {
-	debug!("📱 Setting up current app item for display {}", display_info.index);
-
-	// Add current app item
-	bar.add("item", "current_app", "left",).await?;
+use crate::helpers::icons::Icons;
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::BarItem;
+use crate::items::ItemSpec;
+use crate::items::StateDependency;
+use crate::sketchybar::ItemKind;
+use crate::sketchybar::ItemProperties;
+use crate::sketchybar::SketchyBar;
+use crate::sketchybar::properties::BackgroundProperties;
+use crate::sketchybar::properties::ItemPosition;
+use crate::sketchybar::properties::TextProperties;
+use crate::sketchybar::properties::Width;
+use crate::state::DaemonState;
+use crate::theme::Theme;
+
+/// Focused application, redrawn only when the app switches
+#[derive(Default,)]
+pub struct CurrentApp {
+	pub theme: Theme,
+}
 
-	// Configure current app properties
-	bar.set(
-		"current_app",
-		&[
-			("width", "dynamic",),
-			("position", "left",),
-			("icon", Icons::APP,),
-			("icon.color", &format!("0x{:08x}", Colors::MAUVE),),
-			("label", "App",),
-			("label.color", &format!("0x{:08x}", Colors::MAUVE),),
-			("background.border_color", &format!("0x{:08x}", Colors::MAUVE),),
-			("associated_display", &display_info.index.to_string(),),
-		],
-	)
-	.await?;
+#[async_trait]
+impl BarItem for CurrentApp {
+	fn name(&self,) -> &'static str {
+		"current_app"
+	}
 
-	// Subscribe to app change events
-	bar.subscribe("current_app", &["front_app_switched",],).await?;
+	fn setup(&self, display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+		vec![spec(display_info, &self.theme,)]
+	}
 
-	debug!("✅ Current app item configured for display {}", display_info.index);
-	Ok((),)
-}
+	fn subscriptions(&self,) -> &'static [&'static str] {
+		&["front_app_switched",]
+	}
 
-/// Update current app display with the focused application
-pub async fn update(bar: &SketchyBar,) -> Result<(),> {
-	// Try to get current app from yabai first
-	let app_name = if let Ok(yabai_app,) = get_yabai_focused_app().await {
-		yabai_app
-	} else {
-		// Fallback to AppleScript
-		get_applescript_focused_app().await.unwrap_or_else(|_| "Unknown".to_string(),)
-	};
+	fn dependencies(&self,) -> &'static [StateDependency] {
+		&[StateDependency::CurrentApp,]
+	}
 
-	// Update the current app item
-	let cmd = format!("--set current_app label={}", app_name);
+	fn cadence(&self,) -> Option<Duration,> {
+		None
+	}
 
-	if let Err(e,) = bar.message(&cmd,).await {
-		error!("Failed to update current app: {}", e);
-		return Err(e,);
+	async fn render(&self, bar: &SketchyBar, state: &DaemonState,) -> Result<(),> {
+		update_with_state(bar, state,).await
 	}
+}
 
-	debug!("📱 Current app updated: {}", app_name);
-	Ok((),)
+/// Focused application on the left
+pub fn spec(display_info: &DisplayInfo, theme: &Theme,) -> ItemSpec {
+	let properties = ItemProperties {
+		width: Some(Width::Dynamic,),
+		icon: TextProperties {
+			value: Some(Icons::APP.to_string(),),
+			color: theme.accents.current_app,
+			..Default::default()
+		},
+		label: TextProperties {
+			value: Some("App".to_string(),),
+			color: theme.accents.current_app,
+			..Default::default()
+		},
+		background: BackgroundProperties {
+			border_color: theme.accents.current_app,
+			..Default::default()
+		},
+		associated_display: Some(display_info.index,),
+		..Default::default()
+	};
+
+	ItemSpec::new(ItemKind::Item, "current_app", ItemPosition::Left, properties,)
 }
 
 /// Update current app using centralized state (more efficient)
@@ -66,9 +84,9 @@ pub async fn update_with_state(
 	let current_app = state.current_app.read().await;
 
 	if let Some(app_name,) = current_app.as_ref() {
-		let cmd = format!("--set current_app label={}", app_name);
+		let props = ItemProperties::label(app_name,);
 
-		if let Err(e,) = bar.message(&cmd,).await {
+		if let Err(e,) = bar.set("current_app", props,).await {
 			error!("Failed to update current app: {}", e);
 			return Err(e,);
 		}
@@ -78,42 +96,3 @@ pub async fn update_with_state(
 
 	Ok((),)
 }
-
-/// Get focused app from yabai
-async fn get_yabai_focused_app() -> Result<String,> {
-	let output =
-		Command::new("yabai",).args(["-m", "query", "--windows", "--window",],).output()?;
-
-	if !output.status.success() {
-		return Err(anyhow::anyhow!("yabai query failed"),);
-	}
-
-	let json_str = String::from_utf8(output.stdout,)?;
-	let window: YabaiWindow = serde_json::from_str(&json_str,)?;
-
-	Ok(window.app,)
-}
-
-/// Get focused app using AppleScript (fallback)
-async fn get_applescript_focused_app() -> Result<String,> {
-	let output = Command::new("osascript",)
-		.args([
-			"-e",
-			"tell application \"System Events\" to get name of first application process whose \
-			 frontmost is true",
-		],)
-		.output()?;
-
-	if !output.status.success() {
-		return Err(anyhow::anyhow!("AppleScript failed"),);
-	}
-
-	let app_name = String::from_utf8(output.stdout,)?.trim().to_string();
-
-	Ok(app_name,)
-}
-
-#[derive(Debug, Deserialize,)]
-struct YabaiWindow {
-	app: String,
-}
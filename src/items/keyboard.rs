@@ -1,86 +1,137 @@
-use crate::helpers::colors::Colors;
-use crate::helpers::icons::Icons;
-use crate::helpers::yabai::DisplayInfo;
-use crate::sketchybar::SketchyBar;
 use anyhow::Result;
-use std::process::Command;
+use async_trait::async_trait;
+use tokio::time::Duration;
 use tracing::debug;
 use tracing::error;
 use tracing::warn;
 
-pub async fn setup(bar: &mut SketchyBar, display_info: &DisplayInfo,) -> Result<(),> {
-	debug!("⌨️  Setting up keyboard item for display {}", display_info.index);
-
-	// Add keyboard item
-	bar.add("item", "keyboard", "right",).await?;
-
-	// Configure keyboard properties
-	bar.set(
-		"keyboard",
-		&[
-			("width", "dynamic",),
-			("position", "right",),
-			("icon", Icons::KEYBOARD,),
-			("icon.color", &format!("0x{:08x}", Colors::BLUE),),
-			("label", "US",),
-			("label.color", &format!("0x{:08x}", Colors::BLUE),),
-			("background.border_color", &format!("0x{:08x}", Colors::BLUE),),
-		],
-	)
-	.await?;
-
-	// Only show on builtin display
-	if display_info.is_builtin {
-		bar.set("keyboard", &[("associated_display", &display_info.index.to_string(),),],).await?;
+use crate::helpers::icons::Icons;
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::BarItem;
+use crate::items::ItemSpec;
+use crate::items::StateDependency;
+use crate::runner::Invocation;
+use crate::runner::RunError;
+use crate::runner::Runner;
+use crate::sketchybar::ItemKind;
+use crate::sketchybar::ItemProperties;
+use crate::sketchybar::SketchyBar;
+use crate::sketchybar::properties::BackgroundProperties;
+use crate::sketchybar::properties::ItemPosition;
+use crate::sketchybar::properties::TextProperties;
+use crate::sketchybar::properties::Width;
+use crate::state::DaemonState;
+use crate::theme::Theme;
+
+/// Current input source, polled every five seconds
+#[derive(Default,)]
+pub struct Keyboard {
+	pub theme: Theme,
+}
+
+#[async_trait]
+impl BarItem for Keyboard {
+	fn name(&self,) -> &'static str {
+		"keyboard"
 	}
 
-	debug!("✅ Keyboard item configured for display {}", display_info.index);
-	Ok((),)
+	fn setup(&self, display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+		vec![spec(display_info, &self.theme,)]
+	}
+
+	fn dependencies(&self,) -> &'static [StateDependency] {
+		&[StateDependency::InputSource,]
+	}
+
+	fn cadence(&self,) -> Option<Duration,> {
+		Some(Duration::from_secs(5,),)
+	}
+
+	async fn refresh(&self, state: &DaemonState,) -> Result<(),> {
+		state.apply_input_source(probe(state.runner(),).await?,).await;
+		Ok((),)
+	}
+
+	async fn render(&self, bar: &SketchyBar, state: &DaemonState,) -> Result<(),> {
+		update_with_state(bar, state,).await
+	}
 }
 
-/// Update keyboard display with current input source
-pub async fn update(bar: &SketchyBar,) -> Result<(),> {
-	// Get current input source using defaults command
-	let output = Command::new("defaults",)
-		.args([
-			"read",
-			"~/Library/Preferences/com.apple.HIToolbox.plist",
-			"AppleSelectedInputSources",
-		],)
-		.output();
-
-	let input_source = match output {
-		Ok(output,) if output.status.success() => {
-			let output_str = String::from_utf8_lossy(&output.stdout,);
-
-			// Parse the input source from the plist output
-			// This is a simplified parser - in production you might want to use a proper plist
-			// parser
-			if output_str.contains("U.S.",) || output_str.contains("ABC",) {
-				"US"
-			} else if output_str.contains("Dvorak",) {
-				"DV"
-			} else if output_str.contains("Colemak",) {
-				"CM"
-			} else {
-				// Try to extract a short identifier
-				"??"
-			}
+/// Input source indicator on the right
+pub fn spec(display_info: &DisplayInfo, theme: &Theme,) -> ItemSpec {
+	let properties = ItemProperties {
+		width: Some(Width::Dynamic,),
+		// Only show on builtin display
+		associated_display: display_info.is_builtin.then_some(display_info.index,),
+		icon: TextProperties {
+			value: Some(Icons::KEYBOARD.to_string(),),
+			color: theme.accents.keyboard,
+			..Default::default()
 		},
-		Ok(_,) => {
-			warn!("defaults command succeeded but returned non-zero status");
-			"??"
+		label: TextProperties {
+			value: Some("US".to_string(),),
+			color: theme.accents.keyboard,
+			..Default::default()
 		},
-		Err(e,) => {
-			error!("Failed to get input source: {}", e);
-			return Ok((),); // Don't fail the entire update loop
+		background: BackgroundProperties {
+			border_color: theme.accents.keyboard,
+			..Default::default()
 		},
+		..Default::default()
 	};
 
+	ItemSpec::new(ItemKind::Item, "keyboard", ItemPosition::Right, properties,)
+}
+
+/// Read the selected input source through `defaults`
+pub async fn probe(runner: &dyn Runner,) -> Result<String,> {
+	// Get current input source using defaults command
+	let defaults = Invocation::new("defaults", &[
+		"read",
+		"~/Library/Preferences/com.apple.HIToolbox.plist",
+		"AppleSelectedInputSources",
+	],);
+
+	match defaults.output(runner,).await {
+		Ok(output,) => Ok(parse_input_source(&output,).to_string(),),
+		Err(e @ RunError::Failed { .. },) => {
+			warn!("{}", e);
+			Ok("??".to_string(),)
+		},
+		Err(e,) => Err(e.into(),),
+	}
+}
+
+/// Short layout name from the `AppleSelectedInputSources` plist
+pub fn parse_input_source(output_str: &str,) -> &'static str {
+	// This is a simplified parser - in production you might want to use a proper plist parser
+	if output_str.contains("U.S.",) || output_str.contains("ABC",) {
+		"US"
+	} else if output_str.contains("Dvorak",) {
+		"DV"
+	} else if output_str.contains("Colemak",) {
+		"CM"
+	} else {
+		// Try to extract a short identifier
+		"??"
+	}
+}
+
+/// Update keyboard display from the input source in state
+pub async fn update_with_state(bar: &SketchyBar, state: &DaemonState,) -> Result<(),> {
+	let input_source = state.input_source.read().await.clone();
+	match input_source {
+		Some(input_source,) => render_source(bar, &input_source,).await,
+		// Nothing read yet; keep the spec's default
+		None => Ok((),),
+	}
+}
+
+async fn render_source(bar: &SketchyBar, input_source: &str,) -> Result<(),> {
 	// Update the keyboard item
-	let cmd = format!("--set keyboard label={}", input_source);
+	let props = ItemProperties::label(input_source,);
 
-	if let Err(e,) = bar.message(&cmd,).await {
+	if let Err(e,) = bar.set("keyboard", props,).await {
 		error!("Failed to update keyboard: {}", e);
 		return Err(e,);
 	}
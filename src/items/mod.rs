@@ -1,33 +1,139 @@
+pub mod appearance;
 pub mod battery;
 pub mod clock;
 pub mod current_app;
 pub mod keyboard;
+pub mod reconcile;
+pub mod registry;
 pub mod space;
 pub mod window;
 
 #[cfg(test)] mod tests;
 
-use crate::helpers::yabai::DisplayInfo;
-use crate::sketchybar::SketchyBar;
 use anyhow::Result;
+use async_trait::async_trait;
+use tokio::time::Duration;
 use tracing::info;
 
-/// Setup all items for a bar based on display type
-pub async fn setup_all_items(bar: &mut SketchyBar, display_info: &DisplayInfo,) -> Result<(),> {
-	info!("📦 Setting up items for display {}", display_info.index);
+use crate::helpers::yabai::DisplayInfo;
+use crate::sketchybar::ItemKind;
+use crate::sketchybar::ItemProperties;
+use crate::sketchybar::SketchyBar;
+use crate::sketchybar::properties::ItemPosition;
+use crate::state::DaemonState;
+
+pub use crate::state::StateDependency;
+pub use registry::ItemRegistry;
+
+/// Everything the daemon declares about one item on a bar
+#[derive(Debug, Clone, PartialEq,)]
+pub struct ItemSpec {
+	pub name:          String,
+	pub kind:          ItemKind,
+	pub position:      ItemPosition,
+	pub properties:    ItemProperties,
+	pub events:        Vec<String,>,
+	/// Custom events among [`Self::events`] to add to the bar first, each
+	/// with the distributed notification that triggers it
+	pub notifications: Vec<(String, String,),>,
+}
+
+impl ItemSpec {
+	pub fn new(
+		kind: ItemKind,
+		name: &str,
+		position: ItemPosition,
+		properties: ItemProperties,
+	) -> Self {
+		Self {
+			name: name.to_string(),
+			kind,
+			position,
+			properties,
+			events: Vec::new(),
+			notifications: Vec::new(),
+		}
+	}
+
+	/// Events the item subscribes to, builder style
+	pub fn subscribe(mut self, events: &[&str],) -> Self {
+		self.events = events.iter().map(|e| e.to_string(),).collect();
+		self
+	}
+}
+
+/// A bar item the [`EventManager`](crate::events::EventManager) drives
+///
+/// Registering an implementation in the [`ItemRegistry`] is all it takes to
+/// have it provisioned on every bar, rendered once when it starts, and
+/// rendered again on its cadence and whenever a state it depends on changes.
+#[async_trait]
+pub trait BarItem: Send + Sync {
+	/// Unique name, used in logs
+	fn name(&self,) -> &'static str;
 
-	// Always add these items
-	clock::setup(bar, display_info,).await?;
-	keyboard::setup(bar, display_info,).await?;
-	space::setup(bar, display_info,).await?;
-	current_app::setup(bar, display_info,).await?;
-	window::setup(bar, display_info,).await?;
+	/// Whether the item belongs on this display at all
+	fn applies_to(&self, _display_info: &DisplayInfo,) -> bool {
+		true
+	}
+
+	/// The sketchybar items to create for this display
+	fn setup(&self, display_info: &DisplayInfo,) -> Vec<ItemSpec,>;
+
+	/// sketchybar events every declared entry subscribes to
+	fn subscriptions(&self,) -> &'static [&'static str] {
+		&[]
+	}
 
-	// Only add battery to builtin display
-	if display_info.is_builtin {
-		battery::setup(bar, display_info,).await?;
+	/// Custom events among [`Self::subscriptions`], each with the macOS
+	/// distributed notification sketchybar triggers it on
+	fn notifications(&self,) -> &'static [(&'static str, &'static str,)] {
+		&[]
 	}
 
-	info!("✅ All items configured for display {}", display_info.index);
+	/// State the item renders from; it is rendered whenever that state changes
+	fn dependencies(&self,) -> &'static [StateDependency] {
+		&[]
+	}
+
+	/// How often [`Self::render`] runs regardless of state changes; `None`
+	/// for items that only change with the state they depend on. Renders
+	/// land on wall-clock multiples of the period, shared with other items
+	fn cadence(&self,) -> Option<Duration,>;
+
+	/// Probe whatever the item shows and store it in `state`
+	///
+	/// Runs once per cadence tick however many bars there are, so anything
+	/// that shells out belongs here rather than in [`Self::render`]. Items
+	/// that depend on what they store are rendered by the change it publishes.
+	async fn refresh(&self, _state: &DaemonState,) -> Result<(),> {
+		Ok((),)
+	}
+
+	/// Push the current value to `bar`
+	async fn render(&self, bar: &SketchyBar, state: &DaemonState,) -> Result<(),>;
+}
+
+/// Setup all items for a bar based on display type
+///
+/// Safe to call against a bar that is already populated: existing items are
+/// reconciled rather than added again.
+pub async fn setup_all_items(
+	bar: &mut SketchyBar,
+	display_info: &DisplayInfo,
+	registry: &ItemRegistry,
+) -> Result<(),> {
+	info!("📦 Setting up items for display {}", display_info.index);
+
+	let report = reconcile::reconcile(bar, &registry.declared_items(display_info,),).await?;
+
+	info!(
+		"✅ All items configured for display {} ({} added, {} updated, {} replaced, {} removed)",
+		display_info.index,
+		report.added.len(),
+		report.updated.len(),
+		report.replaced.len(),
+		report.removed.len()
+	);
 	Ok((),)
 }
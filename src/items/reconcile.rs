@@ -0,0 +1,231 @@
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use tracing::debug;
+
+use crate::items::ItemSpec;
+use crate::sketchybar::EventInfo;
+use crate::sketchybar::ItemInfo;
+use crate::sketchybar::ItemKind;
+use crate::sketchybar::ItemProperties;
+use crate::sketchybar::PropertyList;
+use crate::sketchybar::SketchyBar;
+use crate::sketchybar::properties::TextProperties;
+
+/// Hidden item whose label lists the items this daemon owns on a bar
+///
+/// Keeping the record inside the bar means it lives and dies with the bar's
+/// own items, so a restarted sketchybar never leaves stale ownership behind.
+pub const MANIFEST_ITEM: &str = "sketchybar_daemon.manifest";
+
+/// What a reconciliation pass did to a bar
+#[derive(Debug, Clone, Default, PartialEq, Eq,)]
+pub struct ReconcileReport {
+	/// Declared items that were missing
+	pub added:     Vec<String,>,
+	/// Existing items that were out of date, and got the properties and
+	/// subscriptions they were missing
+	pub updated:   Vec<String,>,
+	/// Existing items of the wrong kind, removed and added again
+	pub replaced:  Vec<String,>,
+	/// Owned items that are no longer declared
+	pub removed:   Vec<String,>,
+	/// Whether the declared items had to be put back in declared order
+	pub reordered: bool,
+}
+
+/// Bring the items on `bar` in line with `specs`
+///
+/// Items the daemon did not create are left alone; only names recorded in
+/// [`MANIFEST_ITEM`] by an earlier pass are ever removed. Existing items are
+/// queried and only sent the properties and subscriptions they lack. New
+/// items land after the ones already there, so when that breaks the declared
+/// order the declared items are reordered.
+///
+/// Fails without touching the bar when its items or the manifest cannot be
+/// read.
+pub async fn reconcile(bar: &SketchyBar, specs: &[ItemSpec],) -> Result<ReconcileReport,> {
+	let bar_order: Vec<String,> = bar
+		.query_bar()
+		.await
+		.with_context(|| format!("Could not list items on {}", bar.get_bar_name()),)?
+		.items;
+	let existing: HashSet<String,> = bar_order.iter().cloned().collect();
+	let owned = owned_items(bar, &existing,).await?;
+
+	let mut report = ReconcileReport::default();
+	let mut registered = None;
+	for spec in specs {
+		let position = spec.position.to_string();
+
+		let current = if existing.contains(&spec.name,) {
+			Some(bar.query_item(&spec.name,).await?,)
+		} else {
+			None
+		};
+		let current = match current {
+			None => {
+				bar.add(spec.kind, &spec.name, &position,).await?;
+				report.added.push(spec.name.clone(),);
+				None
+			},
+			Some(info,) if !info.kind.is_empty() && info.kind != spec.kind.as_str() => {
+				bar.remove(&spec.name,).await?;
+				bar.add(spec.kind, &spec.name, &position,).await?;
+				report.replaced.push(spec.name.clone(),);
+				None
+			},
+			Some(info,) => Some(info,),
+		};
+
+		let properties =
+			ItemProperties { position: Some(spec.position.clone(),), ..spec.properties.clone() };
+		let properties = match &current {
+			Some(info,) => changed_properties(info, &properties,),
+			None => PropertyList::from(&properties,),
+		};
+		let mut changed = !properties.is_empty();
+		if changed {
+			bar.set(&spec.name, properties,).await?;
+		}
+
+		for (event, notification,) in &spec.notifications {
+			let events = registered_events(bar, &mut registered,).await?;
+			if events.get(event,).and_then(|e| e.notification.as_ref(),) != Some(notification,) {
+				bar.add_event(event, Some(notification,),).await?;
+				let added = EventInfo { bit: 0, notification: Some(notification.clone(),), };
+				events.insert(event.clone(), added,);
+			}
+		}
+		let events: Vec<&str,> = match &current {
+			Some(info,) if !spec.events.is_empty() => {
+				let registered = registered_events(bar, &mut registered,).await?;
+				spec.events
+					.iter()
+					.filter(|event| !is_subscribed(info, registered, event,),)
+					.map(String::as_str,)
+					.collect()
+			},
+			Some(_,) => Vec::new(),
+			None => spec.events.iter().map(String::as_str,).collect(),
+		};
+		if !events.is_empty() {
+			bar.subscribe(&spec.name, &events,).await?;
+			changed = true;
+		}
+
+		if current.is_some() && changed {
+			report.updated.push(spec.name.clone(),);
+		}
+	}
+
+	let declared: HashSet<&str,> = specs.iter().map(|spec| spec.name.as_str(),).collect();
+	for name in &owned {
+		if !declared.contains(name.as_str(),) && existing.contains(name,) {
+			bar.remove(name,).await?;
+			report.removed.push(name.clone(),);
+		}
+	}
+
+	// Kept items stay where they were; added and replaced ones go to the end
+	let appended = |name: &str| report.added.iter().chain(&report.replaced,).any(|n| n == name,);
+	let mut order: Vec<&str,> = bar_order
+		.iter()
+		.map(String::as_str,)
+		.filter(|name| declared.contains(name,) && !appended(name,),)
+		.collect();
+	order.extend(specs.iter().map(|spec| spec.name.as_str(),).filter(|name| appended(name,),),);
+	let declared_order: Vec<&str,> = specs.iter().map(|spec| spec.name.as_str(),).collect();
+	if order != declared_order {
+		bar.reorder(&declared_order,).await?;
+		report.reordered = true;
+	}
+
+	write_manifest(bar, specs, &existing, &owned,).await?;
+
+	debug!(
+		"🔁 Reconciled {}: added {:?}, replaced {:?}, removed {:?}, reordered {}",
+		bar.get_bar_name(),
+		report.added,
+		report.replaced,
+		report.removed,
+		report.reordered
+	);
+	Ok(report,)
+}
+
+/// Names recorded by the previous pass, if any
+///
+/// A manifest that cannot be read fails the pass: going on without it would
+/// overwrite it and leave the items it listed on the bar for good.
+async fn owned_items(bar: &SketchyBar, existing: &HashSet<String,>,) -> Result<Vec<String,>,> {
+	if !existing.contains(MANIFEST_ITEM,) {
+		return Ok(Vec::new(),);
+	}
+
+	let info = bar
+		.query_item(MANIFEST_ITEM,)
+		.await
+		.with_context(|| format!("Could not read item manifest on {}", bar.get_bar_name()),)?;
+	Ok(info
+		.label_text()
+		.split(',',)
+		.filter(|name| !name.is_empty(),)
+		.map(String::from,)
+		.collect(),)
+}
+
+/// The assignments in `properties` that the queried item does not already hold
+fn changed_properties(current: &ItemInfo, properties: &ItemProperties,) -> PropertyList {
+	let current = current.to_property_list();
+	PropertyList::from(properties,)
+		.iter()
+		.filter(|(key, value,)| current.get(key,) != Some(value,),)
+		.collect()
+}
+
+/// Events registered on the bar, queried the first time they are needed
+async fn registered_events<'a,>(
+	bar: &SketchyBar,
+	registered: &'a mut Option<HashMap<String, EventInfo,>,>,
+) -> Result<&'a mut HashMap<String, EventInfo,>,> {
+	if registered.is_none() {
+		*registered = Some(bar.query_events().await?,);
+	}
+	Ok(registered.get_or_insert_default(),)
+}
+
+/// Whether subscribing `item` to `event` would change nothing
+///
+/// sketchybar ignores subscriptions to events it does not know, so those
+/// count as done; events added during this pass have no bit yet and do not.
+fn is_subscribed(item: &ItemInfo, registered: &HashMap<String, EventInfo,>, event: &str,) -> bool {
+	match registered.get(event,) {
+		Some(info,) => info.bit != 0 && item.scripting.update_mask & info.bit != 0,
+		None => true,
+	}
+}
+
+/// Record the declared names in [`MANIFEST_ITEM`], unless it already holds them
+async fn write_manifest(
+	bar: &SketchyBar,
+	specs: &[ItemSpec],
+	existing: &HashSet<String,>,
+	owned: &[String],
+) -> Result<(),> {
+	let names: Vec<&str,> = specs.iter().map(|spec| spec.name.as_str(),).collect();
+	if existing.contains(MANIFEST_ITEM,) && names == owned {
+		return Ok((),);
+	}
+	if !existing.contains(MANIFEST_ITEM,) {
+		bar.add(ItemKind::Item, MANIFEST_ITEM, "left",).await?;
+	}
+
+	let manifest = ItemProperties {
+		drawing: Some(false,),
+		label: TextProperties::new(names.join(",",),),
+		..Default::default()
+	};
+	bar.set(MANIFEST_ITEM, &manifest,).await
+}
@@ -0,0 +1,232 @@
+use anyhow::Result;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+use crate::appearance::Follow;
+use crate::config::BarStyle;
+use crate::config::Config;
+use crate::config::file::ItemConfig;
+use crate::events::Refresh;
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::BarItem;
+use crate::items::ItemSpec;
+use crate::items::StateDependency;
+use crate::items::appearance::AppearanceWatcher;
+use crate::items::battery::Battery;
+use crate::items::clock::Clock;
+use crate::items::current_app::CurrentApp;
+use crate::items::keyboard::Keyboard;
+use crate::items::space::Spaces;
+use crate::items::window::Window;
+use crate::listener::DaemonEvent;
+use crate::listener::forward_script;
+use crate::sketchybar::SketchyBar;
+use crate::state::DaemonState;
+use crate::theme::Theme;
+
+/// Names of the items the daemon ships, in their default bar order
+pub const BUILTIN_ITEMS: &[&str] =
+	&["clock", "keyboard", "spaces", "current_app", "window", "battery",];
+
+/// The shipped item called `name`, colored with `theme`
+pub fn builtin(name: &str, theme: &Theme,) -> Option<Arc<dyn BarItem,>,> {
+	let theme = theme.clone();
+	let item: Arc<dyn BarItem,> = match name {
+		"clock" => Arc::new(Clock { theme, },),
+		"keyboard" => Arc::new(Keyboard { theme, },),
+		"spaces" => Arc::new(Spaces { theme, },),
+		"current_app" => Arc::new(CurrentApp { theme, },),
+		"window" => Arc::new(Window { theme, },),
+		"battery" => Arc::new(Battery { theme, },),
+		_ => return None,
+	};
+	Some(item,)
+}
+
+/// The items the daemon runs, in bar order, and the look of the bars they sit on
+#[derive(Clone, Default,)]
+pub struct ItemRegistry {
+	items:        Vec<Arc<dyn BarItem,>,>,
+	event_socket: Option<PathBuf,>,
+	style:        BarStyle,
+}
+
+impl std::fmt::Debug for ItemRegistry {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		f.debug_list().entries(self.items.iter().map(|item| item.name(),),).finish()
+	}
+}
+
+impl ItemRegistry {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// The built-in items
+	pub fn with_defaults() -> Self {
+		let mut registry = Self::new();
+		registry.register(Clock::default(),);
+		registry.register(Keyboard::default(),);
+		registry.register(Spaces::default(),);
+		registry.register(CurrentApp::default(),);
+		registry.register(Window::default(),);
+		registry.register(Battery::default(),);
+		registry
+	}
+
+	/// The items and bar style of `config`, in its theme, and the hidden
+	/// item that hears about appearance switches when it follows the system
+	pub fn from_config(config: &Config,) -> Result<Self,> {
+		let style = config.style()?;
+		let mut registry = Self::new();
+		for item_config in &config.items {
+			let item = builtin(&item_config.name, &style.theme,)
+				.ok_or_else(|| anyhow!("Unknown item `{}`", item_config.name),)?;
+			registry.register(Configured { item, config: item_config.clone(), },);
+		}
+		if config.appearance.follow == Some(Follow::System,) {
+			registry.register(AppearanceWatcher,);
+		}
+		Ok(registry.with_style(style,),)
+	}
+
+	/// The items and bar style of `config`, reporting events wherever this
+	/// registry does
+	pub fn reconfigured(&self, config: &Config,) -> Result<Self,> {
+		Ok(Self { event_socket: self.event_socket.clone(), ..Self::from_config(config,)? },)
+	}
+
+	/// Lay `style` over the built-in look of every bar
+	pub fn with_style(mut self, style: BarStyle,) -> Self {
+		self.style = style;
+		self
+	}
+
+	pub fn style(&self,) -> &BarStyle {
+		&self.style
+	}
+
+	/// Give every item that subscribes to events the daemon refreshes on a
+	/// script reporting them to the daemon socket at `socket`, unless it
+	/// declares a script of its own
+	pub fn forward_events_to(mut self, socket: &Path,) -> Self {
+		self.event_socket = Some(socket.to_path_buf(),);
+		self
+	}
+
+	/// Add an item after the ones already registered
+	pub fn register(&mut self, item: impl BarItem + 'static,) {
+		self.items.push(Arc::new(item,),);
+	}
+
+	pub fn items(&self,) -> &[Arc<dyn BarItem,>] {
+		&self.items
+	}
+
+	pub fn get(&self, name: &str,) -> Option<&Arc<dyn BarItem,>,> {
+		self.items.iter().find(|item| item.name() == name,)
+	}
+
+	/// Every sketchybar item declared for a display, in bar order
+	pub fn declared_items(&self, display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+		self.items
+			.iter()
+			.filter(|item| item.applies_to(display_info,),)
+			.flat_map(|item| {
+				item.setup(display_info,).into_iter().map(|mut spec| {
+					spec.events.extend(item.subscriptions().iter().map(|e| e.to_string(),),);
+					spec.notifications.extend(item.notifications().iter().map(
+						|(event, notification,)| (event.to_string(), notification.to_string(),),
+					),);
+					if let Some(socket,) = &self.event_socket
+						&& spec.events.iter().any(|event| is_forwarded(event,),)
+						&& spec.properties.script.is_none()
+					{
+						spec.properties.script = Some(forward_script(socket,),);
+					}
+					spec
+				},)
+			},)
+			.collect()
+	}
+
+	/// [`Self::declared_items`] with every property the bar defaults set
+	/// spelled out, for items that were added under other defaults
+	pub fn restyled_items(&self, display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+		let defaults = self.style.default_properties(display_info,);
+		self.declared_items(display_info,)
+			.into_iter()
+			.map(|mut spec| {
+				let mut properties = defaults.clone();
+				properties.overlay(&spec.properties,);
+				spec.properties = properties;
+				spec
+			},)
+			.collect()
+	}
+}
+
+/// Whether the daemon refreshes anything on the sketchybar event `name`;
+/// reporting the rest would only start a shell for nothing
+fn is_forwarded(name: &str,) -> bool {
+	!Refresh::for_event(&DaemonEvent::new("sketchybar", name,),).is_empty()
+}
+
+/// A shipped item with what its `[[items]]` entry changes about it
+struct Configured {
+	item:   Arc<dyn BarItem,>,
+	config: ItemConfig,
+}
+
+#[async_trait]
+impl BarItem for Configured {
+	fn name(&self,) -> &'static str {
+		self.item.name()
+	}
+
+	fn applies_to(&self, display_info: &DisplayInfo,) -> bool {
+		match &self.config.displays {
+			Some(displays,) => displays.matches(display_info,),
+			None => self.item.applies_to(display_info,),
+		}
+	}
+
+	fn setup(&self, display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+		let mut specs = self.item.setup(display_info,);
+		for spec in &mut specs {
+			if let Some(position,) = &self.config.position {
+				spec.position = position.clone();
+			}
+			spec.properties.overlay(&self.config.properties,);
+		}
+		specs
+	}
+
+	fn subscriptions(&self,) -> &'static [&'static str] {
+		self.item.subscriptions()
+	}
+
+	fn notifications(&self,) -> &'static [(&'static str, &'static str,)] {
+		self.item.notifications()
+	}
+
+	fn dependencies(&self,) -> &'static [StateDependency] {
+		self.item.dependencies()
+	}
+
+	fn cadence(&self,) -> Option<Duration,> {
+		self.config.interval.or_else(|| self.item.cadence(),)
+	}
+
+	async fn refresh(&self, state: &DaemonState,) -> Result<(),> {
+		self.item.refresh(state,).await
+	}
+
+	async fn render(&self, bar: &SketchyBar, state: &DaemonState,) -> Result<(),> {
+		self.item.render(bar, state,).await
+	}
+}
@@ -1,100 +1,83 @@
-use crate::helpers::colors::Colors;
-use crate::helpers::yabai::DisplayInfo;
-use crate::sketchybar::SketchyBar;
 use anyhow::Result;
-use serde::Deserialize;
-use std::process::Command;
+use async_trait::async_trait;
+use tokio::time::Duration;
 use tracing::debug;
 use tracing::error;
-use tracing::warn;
-
-pub async fn setup(bar: &mut SketchyBar, display_info: &DisplayInfo,) -> Result<(),> {
-	debug!("🏠 Setting up space items for display {}", display_info.index);
-
-	// Add space items (typically 1-10)
-	for i in 1..=10 {
-		let space_name = format!("space.{}", i);
-
-		// Add space item
-		bar.add("space", &space_name, "left",).await?;
-
-		// Configure space properties
-		bar.set(
-			&space_name,
-			&[
-				("icon", &i.to_string(),),
-				("icon.color", &format!("0x{:08x}", Colors::TEXT),),
-				("background.color", &format!("0x{:08x}", Colors::SURFACE0),),
-				("background.border_color", &format!("0x{:08x}", Colors::OVERLAY0),),
-				("associated_display", &display_info.index.to_string(),),
-			],
-		)
-		.await?;
-
-		// Subscribe to space events
-		bar.subscribe(&space_name, &["space_change", "display_change",],).await?;
-	}
 
-	debug!("✅ Space items configured for display {}", display_info.index);
-	Ok((),)
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::BarItem;
+use crate::items::ItemSpec;
+use crate::items::StateDependency;
+use crate::sketchybar::ItemKind;
+use crate::sketchybar::ItemProperties;
+use crate::sketchybar::SketchyBar;
+use crate::sketchybar::properties::BackgroundProperties;
+use crate::sketchybar::properties::ItemPosition;
+use crate::sketchybar::properties::TextProperties;
+use crate::state::DaemonState;
+use crate::state::SpaceInfo;
+use crate::theme::Theme;
+
+/// Workspace indicators, redrawn only when the synced spaces change
+#[derive(Default,)]
+pub struct Spaces {
+	pub theme: Theme,
 }
 
-/// Update space indicators based on current yabai state
-pub async fn update(bar: &SketchyBar,) -> Result<(),> {
-	// Get current spaces from yabai
-	let output = Command::new("yabai",).args(["-m", "query", "--spaces",],).output();
-
-	let spaces_info = match output {
-		Ok(output,) if output.status.success() => {
-			let json_str = String::from_utf8_lossy(&output.stdout,);
-			match serde_json::from_str::<Vec<YabaiSpace,>,>(&json_str,) {
-				Ok(spaces,) => spaces,
-				Err(e,) => {
-					warn!("Failed to parse yabai spaces JSON: {}", e);
-					return Ok((),); // Don't fail the entire update loop
-				},
-			}
-		},
-		Ok(_,) => {
-			warn!("yabai command succeeded but returned non-zero status");
-			return Ok((),);
-		},
-		Err(_,) => {
-			// yabai not available, skip update
-			return Ok((),);
-		},
-	};
+#[async_trait]
+impl BarItem for Spaces {
+	fn name(&self,) -> &'static str {
+		"spaces"
+	}
 
-	// Update each space indicator
-	for space in spaces_info {
-		let space_name = format!("space.{}", space.index);
+	fn setup(&self, display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+		specs(display_info, &self.theme,)
+	}
 
-		let (bg_color, border_color,) = if space.has_focus {
-			(Colors::BLUE, Colors::BLUE,)
-		} else if space.windows.len() > 0 {
-			(Colors::SURFACE0, Colors::GREEN,)
-		} else {
-			(Colors::SURFACE0, Colors::OVERLAY0,)
-		};
+	fn subscriptions(&self,) -> &'static [&'static str] {
+		&["space_change", "display_change",]
+	}
 
-		let cmd = format!(
-			"--set {} background.color=0x{:08x} background.border_color=0x{:08x}",
-			space_name, bg_color, border_color
-		);
+	fn dependencies(&self,) -> &'static [StateDependency] {
+		&[StateDependency::Spaces,]
+	}
 
-		if let Err(e,) = bar.message(&cmd,).await {
-			error!("Failed to update space {}: {}", space_name, e);
-			// Continue with other spaces
-		}
+	fn cadence(&self,) -> Option<Duration,> {
+		None
 	}
 
-	debug!("🏠 Spaces updated");
-	Ok((),)
+	async fn render(&self, bar: &SketchyBar, state: &DaemonState,) -> Result<(),> {
+		update_with_state(bar, state, &self.theme,).await
+	}
+}
+
+/// One space item per mission control space (typically 1-10)
+pub fn specs(display_info: &DisplayInfo, theme: &Theme,) -> Vec<ItemSpec,> {
+	(1..=10)
+		.map(|i| {
+			let properties = ItemProperties {
+				icon: TextProperties {
+					value: Some(i.to_string(),),
+					color: theme.text,
+					..Default::default()
+				},
+				background: BackgroundProperties {
+					color: theme.item_background,
+					border_color: theme.inactive,
+					..Default::default()
+				},
+				associated_display: Some(display_info.index,),
+				..Default::default()
+			};
+
+			ItemSpec::new(ItemKind::Space, &format!("space.{}", i), ItemPosition::Left, properties,)
+		},)
+		.collect()
 }
 
 /// Update space indicators using centralized state (more efficient)
 pub async fn update_with_state(
-	bar: &SketchyBar, state: &crate::state::DaemonState,
+	bar: &SketchyBar, state: &crate::state::DaemonState, theme: &Theme,
 ) -> Result<(),> {
 	let spaces = state.spaces.read().await;
 
@@ -102,20 +85,9 @@ pub async fn update_with_state(
 	for space in spaces.values() {
 		let space_name = format!("space.{}", space.index);
 
-		let (bg_color, border_color,) = if space.has_focus {
-			(Colors::BLUE, Colors::BLUE,)
-		} else if !space.windows.is_empty() {
-			(Colors::SURFACE0, Colors::GREEN,)
-		} else {
-			(Colors::SURFACE0, Colors::OVERLAY0,)
-		};
-
-		let cmd = format!(
-			"--set {} background.color=0x{:08x} background.border_color=0x{:08x}",
-			space_name, bg_color, border_color
-		);
+		let props = space_properties(space, theme,);
 
-		if let Err(e,) = bar.message(&cmd,).await {
+		if let Err(e,) = bar.set(&space_name, props,).await {
 			error!("Failed to update space {}: {}", space_name, e);
 			// Continue with other spaces
 		}
@@ -125,10 +97,19 @@ pub async fn update_with_state(
 	Ok((),)
 }
 
-#[derive(Debug, Deserialize,)]
-struct YabaiSpace {
-	index:     u32,
-	#[serde(rename = "has-focus")]
-	has_focus: bool,
-	windows:   Vec<u32,>,
+/// Background and border of a space: the focus color when focused, the
+/// spaces accent around one with windows
+fn space_properties(space: &SpaceInfo, theme: &Theme,) -> ItemProperties {
+	let (color, border_color,) = if space.has_focus {
+		(theme.focus, theme.focus,)
+	} else if !space.windows.is_empty() {
+		(theme.item_background, theme.accents.spaces,)
+	} else {
+		(theme.item_background, theme.inactive,)
+	};
+
+	ItemProperties {
+		background: BackgroundProperties { color, border_color, ..Default::default() },
+		..Default::default()
+	}
 }
@@ -1,11 +1,22 @@
 use anyhow::Result;
 use std::process::Command;
+use std::sync::Arc;
 use tokio::time::Duration;
 use tokio::time::sleep;
 
+use crate::bars::BarManager;
 use crate::helpers::yabai::DisplayInfo;
+use crate::items::BarItem;
+use crate::items::ItemRegistry;
+use crate::runner::FakeRunner;
+use crate::runner::RunError;
+use crate::sketchybar::ItemKind;
+use crate::sketchybar::RecordingTransport;
 use crate::sketchybar::SketchyBar;
+use crate::sketchybar::properties::ItemPosition;
 use crate::state::DaemonState;
+use crate::theme::Theme;
+use crate::wm::ScriptedBackend;
 
 /// Test utilities for item testing
 pub mod utils {
@@ -24,12 +35,21 @@ pub mod utils {
 		}
 	}
 
-	pub fn is_sketchybar_running() -> bool {
-		Command::new("pgrep",)
-			.args(["-f", "sketchybar",],)
-			.output()
-			.map(|output| output.status.success(),)
-			.unwrap_or(false,)
+	/// Start an actor for each `(display id, bar)`
+	pub fn bar_manager(state: &DaemonState, bars: Vec<(&str, SketchyBar,),>,) -> BarManager {
+		let manager = BarManager::new(state.clone(),);
+		for (display_id, bar,) in bars {
+			manager.add(display_id, bar,);
+		}
+		manager
+	}
+
+	/// Create a bar named `bar_name` whose messages are captured by the returned transport
+	pub fn recording_bar(bar_name: &str,) -> (SketchyBar, RecordingTransport,) {
+		let transport = RecordingTransport::new();
+		let mut bar = SketchyBar::with_transport(Arc::new(transport.clone(),),);
+		bar.set_bar_name(bar_name,);
+		(bar, transport,)
 	}
 
 	pub fn is_yabai_running() -> bool {
@@ -45,19 +65,17 @@ pub mod utils {
 mod clock_tests {
 	use super::*;
 
-	#[tokio::test]
-	async fn test_clock_setup() {
-		let mut bar = SketchyBar::new();
+	#[test]
+	fn test_clock_spec() {
 		let display = utils::create_test_display();
-
-		// Test clock setup
-		let result = crate::items::clock::setup(&mut bar, &display,).await;
-
-		// Setup should not fail even if sketchybar is not running
-		match result {
-			Ok(_,) => println!("Clock setup succeeded"),
-			Err(e,) => println!("Clock setup failed (expected without sketchybar): {}", e),
-		}
+		let spec = crate::items::clock::spec(&display, &Theme::default(),);
+
+		assert_eq!(spec.name, "clock");
+		assert_eq!(spec.kind, ItemKind::Item);
+		assert_eq!(spec.position, ItemPosition::Right);
+		assert_eq!(spec.properties.associated_display, Some(1));
+		let clock = crate::items::clock::Clock::default();
+		assert_eq!(clock.subscriptions(), ["system_woke"]);
 	}
 
 	#[tokio::test]
@@ -65,7 +83,8 @@ mod clock_tests {
 		let bar = SketchyBar::new();
 
 		// Test clock update
-		let result = crate::items::clock::update(&bar,).await;
+		let clock = crate::items::clock::Clock::default();
+		let result = clock.render(&bar, &DaemonState::new(),).await;
 
 		// Update should not fail even if sketchybar is not running
 		match result {
@@ -96,24 +115,25 @@ mod clock_tests {
 mod battery_tests {
 	use super::*;
 
-	#[tokio::test]
-	async fn test_battery_setup() {
-		let mut bar = SketchyBar::new();
+	#[test]
+	fn test_battery_spec() {
 		let display = utils::create_test_display();
-
-		let result = crate::items::battery::setup(&mut bar, &display,).await;
-
-		match result {
-			Ok(_,) => println!("Battery setup succeeded"),
-			Err(e,) => println!("Battery setup failed (expected without sketchybar): {}", e),
-		}
+		let spec = crate::items::battery::spec(&display,);
+
+		assert_eq!(spec.name, "battery");
+		assert_eq!(spec.position, ItemPosition::Right);
+		let battery = crate::items::battery::Battery::default();
+		assert!(battery.subscriptions().contains(&"power_source_change"));
+		let external = DisplayInfo { is_builtin: false, ..display };
+		assert!(!battery.applies_to(&external));
 	}
 
 	#[tokio::test]
 	async fn test_battery_update() {
 		let bar = SketchyBar::new();
 
-		let result = crate::items::battery::update(&bar,).await;
+		let battery = crate::items::battery::Battery::default();
+		let result = battery.render(&bar, &DaemonState::new(),).await;
 
 		match result {
 			Ok(_,) => println!("Battery update succeeded"),
@@ -121,6 +141,44 @@ mod battery_tests {
 		}
 	}
 
+	#[test]
+	fn test_parse_pmset() {
+		let output = "Now drawing from 'Battery Power'\n \
+		              -InternalBattery-0 (id=4653155)\t7%; discharging; 0:25 remaining";
+		let info = crate::items::battery::parse_pmset(output,).unwrap();
+		assert_eq!(info, crate::state::BatteryInfo { charge: 7, charging: false });
+
+		let output = "Now drawing from 'AC Power'\n -InternalBattery-0\t100%; charged;";
+		assert!(crate::items::battery::parse_pmset(output).unwrap().charging);
+		assert_eq!(crate::items::battery::parse_pmset("No batteries available"), None);
+	}
+
+	#[tokio::test]
+	async fn test_battery_probe_reports_unreadable_output() {
+		let runner = FakeRunner::new();
+		runner.respond("pmset -g batt", "Now drawing from 'AC Power'\n -InternalBattery-0\t42%;",);
+		let info = crate::items::battery::probe(&runner,).await.unwrap();
+		assert_eq!(info, crate::state::BatteryInfo { charge: 42, charging: true });
+
+		runner.respond("pmset -g batt", "No batteries available",);
+		let err = crate::items::battery::probe(&runner,).await.unwrap_err();
+		assert!(matches!(err.downcast_ref(), Some(RunError::Parse { .. })));
+	}
+
+	#[tokio::test]
+	async fn test_battery_renders_from_state() {
+		let (bar, transport,) = utils::recording_bar("sketchybar",);
+		let state = DaemonState::new();
+
+		// Nothing probed yet: nothing sent
+		crate::items::battery::update_with_state(&bar, &state, &Theme::default(),).await.unwrap();
+		assert!(transport.sent().is_empty());
+
+		state.apply_battery(crate::state::BatteryInfo { charge: 7, charging: false, },).await;
+		crate::items::battery::update_with_state(&bar, &state, &Theme::default(),).await.unwrap();
+		assert!(transport.sent_to("sketchybar")[0].contains("label=07"));
+	}
+
 	#[test]
 	fn test_battery_info_parsing() {
 		// Test battery percentage parsing
@@ -141,9 +199,7 @@ mod battery_tests {
 		fn get_battery_color(percentage: u32, is_charging: bool,) -> u32 {
 			use crate::helpers::colors::Colors;
 
-			if is_charging {
-				Colors::GREEN
-			} else if percentage > 50 {
+			if is_charging || percentage > 50 {
 				Colors::GREEN
 			} else if percentage > 20 {
 				Colors::YELLOW
@@ -164,29 +220,43 @@ mod battery_tests {
 mod space_tests {
 	use super::*;
 
-	#[tokio::test]
-	async fn test_space_setup() {
-		let mut bar = SketchyBar::new();
+	#[test]
+	fn test_space_specs() {
 		let display = utils::create_test_display();
+		let specs = crate::items::space::specs(&display, &Theme::default(),);
 
-		let result = crate::items::space::setup(&mut bar, &display,).await;
-
-		match result {
-			Ok(_,) => println!("Space setup succeeded"),
-			Err(e,) => println!("Space setup failed (expected without sketchybar): {}", e),
-		}
+		assert_eq!(specs.len(), 10);
+		assert_eq!(specs[0].name, "space.1");
+		assert_eq!(specs[9].name, "space.10");
+		assert!(specs.iter().all(|spec| spec.kind == ItemKind::Space));
 	}
 
 	#[tokio::test]
 	async fn test_space_update() {
-		let bar = SketchyBar::new();
-
-		let result = crate::items::space::update(&bar,).await;
-
-		match result {
-			Ok(_,) => println!("Space update succeeded"),
-			Err(e,) => println!("Space update failed (expected without yabai): {}", e),
-		}
+		let (bar, transport,) = utils::recording_bar("sketchybar",);
+		let backend = ScriptedBackend::new();
+		backend.set_spaces(vec![crate::state::SpaceInfo {
+			index:     3,
+			display:   1,
+			has_focus: true,
+			windows:   vec![],
+			label:     String::new(),
+		}],);
+		let state = DaemonState::new().with_backend(Arc::new(backend.clone(),),);
+		let spaces = crate::items::space::Spaces::default();
+
+		state.update_spaces().await.unwrap();
+		spaces.render(&bar, &state,).await.unwrap();
+		let sent = transport.sent_to("sketchybar",);
+		assert_eq!(sent.len(), 1);
+		assert!(sent[0].starts_with("--set space.3 "));
+
+		// A window manager that is not answering keeps the spaces as they were
+		transport.clear();
+		backend.set_unavailable(true,);
+		assert!(state.update_spaces().await.is_err());
+		spaces.render(&bar, &state,).await.unwrap();
+		assert_eq!(transport.sent_to("sketchybar"), sent);
 	}
 
 	#[tokio::test]
@@ -202,7 +272,7 @@ mod space_tests {
 		// Update state first
 		let _ = state.update_spaces().await;
 
-		let result = crate::items::space::update_with_state(&bar, &state,).await;
+		let result = crate::items::space::update_with_state(&bar, &state, &Theme::default(),).await;
 
 		match result {
 			Ok(_,) => println!("Space state update succeeded"),
@@ -243,17 +313,15 @@ mod space_tests {
 mod app_tests {
 	use super::*;
 
-	#[tokio::test]
-	async fn test_current_app_setup() {
-		let mut bar = SketchyBar::new();
+	#[test]
+	fn test_current_app_spec() {
 		let display = utils::create_test_display();
+		let spec = crate::items::current_app::spec(&display, &Theme::default(),);
 
-		let result = crate::items::current_app::setup(&mut bar, &display,).await;
-
-		match result {
-			Ok(_,) => println!("Current app setup succeeded"),
-			Err(e,) => println!("Current app setup failed (expected without sketchybar): {}", e),
-		}
+		assert_eq!(spec.name, "current_app");
+		assert_eq!(spec.position, ItemPosition::Left);
+		let current_app = crate::items::current_app::CurrentApp::default();
+		assert_eq!(current_app.subscriptions(), ["front_app_switched"]);
 	}
 
 	#[tokio::test]
@@ -295,17 +363,15 @@ mod app_tests {
 mod window_tests {
 	use super::*;
 
-	#[tokio::test]
-	async fn test_window_setup() {
-		let mut bar = SketchyBar::new();
+	#[test]
+	fn test_window_spec() {
 		let display = utils::create_test_display();
+		let spec = crate::items::window::spec(&display, &Theme::default(),);
 
-		let result = crate::items::window::setup(&mut bar, &display,).await;
-
-		match result {
-			Ok(_,) => println!("Window setup succeeded"),
-			Err(e,) => println!("Window setup failed (expected without sketchybar): {}", e),
-		}
+		assert_eq!(spec.name, "window");
+		assert_eq!(spec.position, ItemPosition::Left);
+		let window = crate::items::window::Window::default();
+		assert_eq!(window.subscriptions(), ["window_focus", "window_title"]);
 	}
 
 	#[tokio::test]
@@ -399,24 +465,22 @@ mod window_tests {
 mod keyboard_tests {
 	use super::*;
 
-	#[tokio::test]
-	async fn test_keyboard_setup() {
-		let mut bar = SketchyBar::new();
+	#[test]
+	fn test_keyboard_spec() {
 		let display = utils::create_test_display();
+		let spec = crate::items::keyboard::spec(&display, &Theme::default(),);
 
-		let result = crate::items::keyboard::setup(&mut bar, &display,).await;
-
-		match result {
-			Ok(_,) => println!("Keyboard setup succeeded"),
-			Err(e,) => println!("Keyboard setup failed (expected without sketchybar): {}", e),
-		}
+		assert_eq!(spec.name, "keyboard");
+		assert_eq!(spec.properties.label.value.as_deref(), Some("US"));
+		assert!(spec.events.is_empty());
 	}
 
 	#[tokio::test]
 	async fn test_keyboard_update() {
 		let bar = SketchyBar::new();
 
-		let result = crate::items::keyboard::update(&bar,).await;
+		let keyboard = crate::items::keyboard::Keyboard::default();
+		let result = keyboard.render(&bar, &DaemonState::new(),).await;
 
 		match result {
 			Ok(_,) => println!("Keyboard update succeeded"),
@@ -424,6 +488,66 @@ mod keyboard_tests {
 		}
 	}
 
+	#[test]
+	fn test_parse_input_source() {
+		let output = r#"(
+    {
+        InputSourceKind = "Keyboard Layout";
+        "KeyboardLayout Name" = Dvorak;
+    }
+)"#;
+		assert_eq!(crate::items::keyboard::parse_input_source(output), "DV");
+		let output = "\"KeyboardLayout Name\" = ABC;";
+		assert_eq!(crate::items::keyboard::parse_input_source(output), "US");
+		assert_eq!(crate::items::keyboard::parse_input_source(""), "??");
+	}
+
+	#[tokio::test]
+	async fn test_keyboard_probe_survives_defaults_failing() {
+		let runner = FakeRunner::new();
+		let defaults = "defaults read ~/Library/Preferences/com.apple.HIToolbox.plist \
+		                AppleSelectedInputSources";
+		runner.respond(defaults, "\"KeyboardLayout Name\" = Colemak;",);
+		assert_eq!(crate::items::keyboard::probe(&runner).await.unwrap(), "CM");
+
+		runner.fail(defaults, RunError::Failed {
+			program: "defaults".to_string(),
+			code:    Some(1,),
+			stderr:  "Domain does not exist".to_string(),
+		},);
+		assert_eq!(crate::items::keyboard::probe(&runner).await.unwrap(), "??");
+
+		// Nothing to show for a probe that never answered
+		runner.hang(defaults,);
+		tokio::time::pause();
+		assert!(crate::items::keyboard::probe(&runner).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn test_appearance_probe_reads_the_interface_style() {
+		use crate::items::appearance::probe;
+		use crate::state::Appearance;
+
+		let runner = FakeRunner::new();
+		let defaults = "defaults read -g AppleInterfaceStyle";
+		runner.respond(defaults, "Dark\n",);
+		assert_eq!(probe(&runner).await.unwrap(), Appearance::Dark);
+
+		// Light mode leaves the key unset
+		runner.fail(defaults, RunError::Failed {
+			program: "defaults".to_string(),
+			code:    Some(1,),
+			stderr:  "The domain/default pair of (kCFPreferencesAnyApplication, \
+			          AppleInterfaceStyle) does not exist"
+				.to_string(),
+		},);
+		assert_eq!(probe(&runner).await.unwrap(), Appearance::Light);
+
+		runner.hang(defaults,);
+		tokio::time::pause();
+		assert!(probe(&runner).await.is_err());
+	}
+
 	#[test]
 	fn test_keyboard_layout_parsing() {
 		// Test keyboard layout name parsing
@@ -452,7 +576,8 @@ mod integration_tests {
 		let display = utils::create_test_display();
 
 		// Test setting up all items
-		let result = crate::items::setup_all_items(&mut bar, &display,).await;
+		let registry = ItemRegistry::with_defaults();
+		let result = crate::items::setup_all_items(&mut bar, &display, &registry,).await;
 
 		match result {
 			Ok(_,) => println!("All items setup succeeded"),
@@ -476,11 +601,11 @@ mod integration_tests {
 		let _ = state.update_current_app().await;
 
 		// Test updating all items in sequence (not parallel due to type differences)
-		let results = vec![
-			crate::items::clock::update(&bar,).await,
-			crate::items::battery::update(&bar,).await,
-			crate::items::keyboard::update(&bar,).await,
-			crate::items::space::update_with_state(&bar, &state,).await,
+		let results = [
+			crate::items::clock::Clock::default().render(&bar, &state,).await,
+			crate::items::battery::update_with_state(&bar, &state, &Theme::default(),).await,
+			crate::items::keyboard::update_with_state(&bar, &state,).await,
+			crate::items::space::update_with_state(&bar, &state, &Theme::default(),).await,
 			crate::items::current_app::update_with_state(&bar, &state,).await,
 			crate::items::window::update_with_state(&bar, &state,).await,
 		];
@@ -529,3 +654,789 @@ mod integration_tests {
 		}
 	}
 }
+
+/// Deterministic tests against the recording transport
+#[cfg(test)]
+mod recording_tests {
+	use super::*;
+	use crate::items::reconcile;
+	use crate::state::SpaceInfo;
+	use crate::state::WindowInfo;
+
+	fn space(index: u32, has_focus: bool, windows: Vec<u32,>,) -> SpaceInfo {
+		SpaceInfo { index, display: 2, has_focus, windows, label: String::new(), }
+	}
+
+	#[tokio::test]
+	async fn test_clock_setup_commands() -> Result<(),> {
+		let (mut bar, transport,) = utils::recording_bar("sketchybar",);
+		let display = utils::create_test_display();
+
+		crate::items::setup_all_items(&mut bar, &display, &ItemRegistry::with_defaults(),).await?;
+
+		let sent = transport.sent_to("sketchybar",);
+		assert_eq!(sent[0], "--query bar");
+		assert_eq!(sent[1], "--add item clock right");
+		assert!(sent[2].starts_with("--set clock position=right associated_display=1"));
+		assert_eq!(sent[3], "--subscribe clock system_woke");
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_restart_does_not_re_add_items() -> Result<(),> {
+		let (mut bar, transport,) = utils::recording_bar("sketchybar",);
+		let display = utils::create_test_display();
+		crate::items::setup_all_items(&mut bar, &display, &ItemRegistry::with_defaults(),).await?;
+		let items = bar.query_bar().await?.items;
+
+		// A restarted daemon talks to the same, still populated bar
+		transport.clear();
+		let mut restarted = SketchyBar::with_transport(Arc::new(transport.clone(),),);
+		restarted.set_bar_name("sketchybar",);
+		let specs = ItemRegistry::with_defaults().declared_items(&display,);
+		let report = reconcile::reconcile(&restarted, &specs,).await?;
+
+		assert_eq!(report, reconcile::ReconcileReport::default());
+		// Everything is already in place, so the pass only looks
+		let sent = transport.sent_to("sketchybar",);
+		assert!(sent.iter().all(|m| m.starts_with("--query ")), "{:?}", sent);
+		assert_eq!(restarted.query_bar().await?.items, items);
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_reconcile_sends_only_what_changed() -> Result<(),> {
+		use crate::sketchybar::properties::Color;
+
+		let (mut bar, transport,) = utils::recording_bar("sketchybar",);
+		let display = utils::create_test_display();
+		crate::items::setup_all_items(&mut bar, &display, &ItemRegistry::with_defaults(),).await?;
+
+		let mut specs = ItemRegistry::with_defaults().declared_items(&display,);
+		specs[0].properties.label.color = Some(Color(0xffa6e3a1,),);
+		specs[0].events.push("power_source_change".to_string(),);
+		transport.clear();
+		let report = reconcile::reconcile(&bar, &specs,).await?;
+
+		assert_eq!(report.updated, vec!["clock"]);
+		let sent: Vec<String,> = transport
+			.sent_to("sketchybar",)
+			.into_iter()
+			.filter(|m| !m.starts_with("--query "),)
+			.collect();
+		assert_eq!(sent, vec![
+			"--set clock label.color=0xffa6e3a1",
+			"--subscribe clock power_source_change"
+		]);
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_reconcile_fails_when_the_manifest_cannot_be_read() -> Result<(),> {
+		let (mut bar, transport,) = utils::recording_bar("sketchybar",);
+		let mut display = utils::create_test_display();
+		crate::items::setup_all_items(&mut bar, &display, &ItemRegistry::with_defaults(),).await?;
+
+		// Without the manifest the battery would stay on the bar for good
+		display.is_builtin = false;
+		transport.respond(&format!("--query {}", reconcile::MANIFEST_ITEM), "",);
+		transport.clear();
+		let specs = ItemRegistry::with_defaults().declared_items(&display,);
+		let err = reconcile::reconcile(&bar, &specs,).await.unwrap_err();
+
+		assert!(err.to_string().contains("manifest"));
+		let sent = transport.sent_to("sketchybar",);
+		assert!(sent.iter().all(|m| m.starts_with("--query ")), "{:?}", sent);
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_reconcile_fails_when_the_bar_cannot_be_listed() -> Result<(),> {
+		let (bar, transport,) = utils::recording_bar("sketchybar",);
+		transport.respond("--query bar", "",);
+		let specs = ItemRegistry::with_defaults().declared_items(&utils::create_test_display(),);
+
+		let err = reconcile::reconcile(&bar, &specs,).await.unwrap_err();
+		assert!(err.to_string().contains("sketchybar"));
+		assert_eq!(transport.sent_to("sketchybar"), vec!["--query bar"]);
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_reconcile_removes_only_owned_items() -> Result<(),> {
+		let (mut bar, transport,) = utils::recording_bar("sketchybar",);
+		let mut display = utils::create_test_display();
+		bar.add(ItemKind::Item, "foreign", "right",).await?;
+		crate::items::setup_all_items(&mut bar, &display, &ItemRegistry::with_defaults(),).await?;
+
+		// The display no longer declares a battery
+		display.is_builtin = false;
+		transport.clear();
+		let specs = ItemRegistry::with_defaults().declared_items(&display,);
+		let report = reconcile::reconcile(&bar, &specs,).await?;
+
+		assert_eq!(report.removed, vec!["battery"]);
+		assert!(transport.received("sketchybar", "--remove battery"));
+		let items = bar.query_bar().await?.items;
+		assert!(items.contains(&"foreign".to_string()));
+		assert!(!items.contains(&"battery".to_string()));
+		assert_eq!(
+			bar.query_item(reconcile::MANIFEST_ITEM).await?.label_text().split(',').count(),
+			14
+		);
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_reconcile_replaces_items_of_the_wrong_kind() -> Result<(),> {
+		let (mut bar, transport,) = utils::recording_bar("sketchybar",);
+		let display = utils::create_test_display();
+		bar.add(ItemKind::Item, "space.1", "left",).await?;
+
+		crate::items::setup_all_items(&mut bar, &display, &ItemRegistry::with_defaults(),).await?;
+
+		let sent = transport.sent_to("sketchybar",);
+		let removed = sent.iter().position(|m| m == "--remove space.1",).unwrap();
+		assert_eq!(sent[removed + 1], "--add space space.1 left");
+		assert_eq!(bar.query_item("space.1").await?.kind, "space");
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_reconcile_restores_declared_order() -> Result<(),> {
+		let (mut bar, transport,) = utils::recording_bar("sketchybar",);
+		let display = utils::create_test_display();
+		crate::items::setup_all_items(&mut bar, &display, &ItemRegistry::with_defaults(),).await?;
+		assert!(!transport.sent_to("sketchybar").iter().any(|m| m.starts_with("--reorder")));
+
+		// The clock moves from first to last
+		let mut specs = ItemRegistry::with_defaults().declared_items(&display,);
+		let clock = specs.remove(0,);
+		specs.push(clock,);
+		let report = reconcile::reconcile(&bar, &specs,).await?;
+
+		assert!(report.reordered);
+		let declared: Vec<String,> = specs.iter().map(|spec| spec.name.clone(),).collect();
+		let mut items = bar.query_bar().await?.items;
+		items.retain(|name| declared.contains(name,),);
+		assert_eq!(items, declared);
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_battery_only_on_builtin_display() -> Result<(),> {
+		let (mut bar, transport,) = utils::recording_bar("external_2",);
+		let mut display = utils::create_test_display();
+		display.index = 2;
+		display.is_builtin = false;
+
+		crate::items::setup_all_items(&mut bar, &display, &ItemRegistry::with_defaults(),).await?;
+
+		assert!(!transport.sent_to("external_2").iter().any(|m| m.contains("battery")));
+		assert!(transport.received("external_2", "--add item window left"));
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_space_focus_update() -> Result<(),> {
+		let (bar, transport,) = utils::recording_bar("external_2",);
+		let state = DaemonState::new();
+		{
+			let mut spaces = state.spaces.write().await;
+			spaces.insert(2, space(2, false, vec![7],),);
+			spaces.insert(3, space(3, true, vec![],),);
+		}
+
+		crate::items::space::update_with_state(&bar, &state, &Theme::default(),).await?;
+
+		assert!(transport.received(
+			"external_2",
+			"--set space.3 background.color=0xff89b4fa background.border_color=0xff89b4fa"
+		));
+		assert!(transport.received(
+			"external_2",
+			"--set space.2 background.color=0xff313244 background.border_color=0xffa6e3a1"
+		));
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_current_app_and_window_update() -> Result<(),> {
+		let (bar, transport,) = utils::recording_bar("sketchybar",);
+		let state = DaemonState::new();
+		*state.current_app.write().await = Some("Terminal".to_string(),);
+		state.windows.write().await.insert(
+			1,
+			WindowInfo {
+				id:        1,
+				app:       "Terminal".to_string(),
+				title:     "bash".to_string(),
+				space:     1,
+				display:   1,
+				has_focus: true,
+			},
+		);
+
+		crate::items::current_app::update_with_state(&bar, &state,).await?;
+		crate::items::window::update_with_state(&bar, &state,).await?;
+
+		assert_eq!(transport.sent_to("sketchybar"), vec![
+			"--set current_app label=Terminal".to_string(),
+			"--set window label=bash".to_string(),
+		]);
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_hostile_app_and_window_names() -> Result<(),> {
+		let (bar, transport,) = utils::recording_bar("sketchybar",);
+		let state = DaemonState::new();
+		*state.current_app.write().await = Some("Visual Studio Code".to_string(),);
+		state.windows.write().await.insert(
+			1,
+			WindowInfo {
+				id:        1,
+				app:       "Code".to_string(),
+				title:     "\"main.rs\" --set clock drawing=off".to_string(),
+				space:     1,
+				display:   1,
+				has_focus: true,
+			},
+		);
+
+		crate::items::current_app::update_with_state(&bar, &state,).await?;
+		crate::items::window::update_with_state(&bar, &state,).await?;
+
+		let args: Vec<Vec<String,>,> = transport
+			.sent_to("sketchybar",)
+			.iter()
+			.map(|m| crate::sketchybar::command::split_message(m,),)
+			.collect();
+		assert_eq!(args[0], vec!["--set", "current_app", "label=Visual Studio Code"]);
+		assert_eq!(args[1], vec![
+			"--set",
+			"window",
+			"label=\u{201d}main.rs\u{201d} --set clock drawing=off"
+		]);
+		Ok((),)
+	}
+
+	#[test]
+	fn test_window_title_truncation_multibyte() {
+		let title = "日本語".repeat(20,);
+		let truncated = crate::items::window::truncate_title(&title,);
+		assert_eq!(truncated.chars().count(), 50);
+		assert!(truncated.ends_with("..."));
+	}
+}
+
+/// Items plugged in through the registry alone
+#[cfg(test)]
+mod registry_tests {
+	use super::*;
+	use crate::events::EventManager;
+	use crate::items::ItemSpec;
+	use crate::scheduler::WallClock;
+	use crate::sketchybar::ItemProperties;
+	use async_trait::async_trait;
+	use std::sync::atomic::AtomicUsize;
+	use std::sync::atomic::Ordering;
+	use tokio::sync::broadcast;
+
+	/// Counts renders and only belongs on external displays
+	struct Counter {
+		renders: Arc<AtomicUsize,>,
+	}
+
+	#[async_trait]
+	impl BarItem for Counter {
+		fn name(&self,) -> &'static str {
+			"counter"
+		}
+
+		fn applies_to(&self, display_info: &DisplayInfo,) -> bool {
+			!display_info.is_builtin
+		}
+
+		fn setup(&self, _display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+			vec![ItemSpec::new(
+				ItemKind::Item,
+				"counter",
+				ItemPosition::Right,
+				ItemProperties::default(),
+			)]
+		}
+
+		fn subscriptions(&self,) -> &'static [&'static str] {
+			&["system_woke",]
+		}
+
+		fn cadence(&self,) -> Option<Duration,> {
+			Some(Duration::from_millis(10,),)
+		}
+
+		async fn render(&self, bar: &SketchyBar, _state: &DaemonState,) -> Result<(),> {
+			let n = self.renders.fetch_add(1, Ordering::SeqCst,) + 1;
+			bar.set("counter", ItemProperties::label(n.to_string(),),).await
+		}
+	}
+
+	/// Only listens to sketchybar's own timer, which the daemon ignores
+	struct Ticker;
+
+	#[async_trait]
+	impl BarItem for Ticker {
+		fn name(&self,) -> &'static str {
+			"ticker"
+		}
+
+		fn setup(&self, _display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+			vec![ItemSpec::new(
+				ItemKind::Item,
+				"ticker",
+				ItemPosition::Right,
+				ItemProperties::default(),
+			)]
+		}
+
+		fn subscriptions(&self,) -> &'static [&'static str] {
+			&["routine",]
+		}
+
+		fn cadence(&self,) -> Option<Duration,> {
+			None
+		}
+
+		async fn render(&self, _bar: &SketchyBar, _state: &DaemonState,) -> Result<(),> {
+			Ok((),)
+		}
+	}
+
+	/// Panics on every render
+	struct Faulty;
+
+	#[async_trait]
+	impl BarItem for Faulty {
+		fn name(&self,) -> &'static str {
+			"faulty"
+		}
+
+		fn setup(&self, _display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+			Vec::new()
+		}
+
+		fn cadence(&self,) -> Option<Duration,> {
+			Some(Duration::from_millis(10,),)
+		}
+
+		async fn render(&self, _bar: &SketchyBar, _state: &DaemonState,) -> Result<(),> {
+			panic!("faulty item");
+		}
+	}
+
+	#[test]
+	fn test_default_registry_order() {
+		let registry = ItemRegistry::with_defaults();
+		let names: Vec<&str,> = registry.items().iter().map(|item| item.name(),).collect();
+		assert_eq!(names, ["clock", "keyboard", "spaces", "current_app", "window", "battery"]);
+		assert!(registry.get("battery").is_some());
+
+		let specs = registry.declared_items(&utils::create_test_display(),);
+		assert_eq!(specs.first().map(|s| s.events.clone()), Some(vec!["system_woke".to_string()]));
+		assert_eq!(specs.last().map(|s| s.name.as_str()), Some("battery"));
+	}
+
+	#[test]
+	fn test_registered_item_is_declared() {
+		let mut registry = ItemRegistry::new();
+		registry.register(Counter { renders: Arc::new(AtomicUsize::new(0,),), },);
+
+		let mut external = utils::create_test_display();
+		external.is_builtin = false;
+		let specs = registry.declared_items(&external,);
+		assert_eq!(specs.len(), 1);
+		assert_eq!(specs[0].events, vec!["system_woke"]);
+		assert!(registry.declared_items(&utils::create_test_display()).is_empty());
+	}
+
+	#[test]
+	fn test_subscribed_items_forward_events() {
+		let socket = std::path::Path::new("/tmp/sketchybar-daemon_test.socket",);
+		let registry = ItemRegistry::with_defaults().forward_events_to(socket,);
+		let specs = registry.declared_items(&utils::create_test_display(),);
+
+		let script = crate::listener::forward_script(socket,);
+		let battery = specs.iter().find(|s| s.name == "battery",).unwrap();
+		assert_eq!(battery.properties.script.as_deref(), Some(script.as_str()));
+		// Space items subscribe too, so each one reports
+		let spaces: Vec<&ItemSpec,> =
+			specs.iter().filter(|s| s.name.starts_with("space."),).collect();
+		assert!(!spaces.is_empty());
+		assert!(spaces.iter().all(|s| s.properties.script.is_some()));
+
+		// Events the daemon does nothing with start no shell
+		let mut registry = ItemRegistry::new().forward_events_to(socket,);
+		registry.register(Ticker,);
+		let specs = registry.declared_items(&utils::create_test_display(),);
+		assert_eq!(specs[0].events, ["routine"]);
+		assert_eq!(specs[0].properties.script, None);
+
+		// Not forwarded unless asked
+		let specs = ItemRegistry::with_defaults().declared_items(&utils::create_test_display(),);
+		assert!(specs.iter().all(|s| s.properties.script.is_none()));
+	}
+
+	#[test]
+	fn test_config_changes_items() -> Result<(),> {
+		use crate::config::Config;
+		use crate::sketchybar::properties::Color;
+		use crate::sketchybar::properties::ItemPosition;
+		use crate::sketchybar::properties::Width;
+
+		let config = Config::parse(
+			r#"
+			[[items]]
+			name = "battery"
+			displays = "all"
+			interval = 120
+
+			[[items]]
+			name = "clock"
+			position = "left"
+			properties.label.color = "0xffa6e3a1"
+			"#,
+		)?;
+		let registry = ItemRegistry::from_config(&config,)?;
+		let names: Vec<&str,> = registry.items().iter().map(|item| item.name(),).collect();
+		assert_eq!(names, ["battery", "clock"]);
+		assert_eq!(registry.get("battery").unwrap().cadence(), Some(Duration::from_secs(120)));
+		assert_eq!(registry.get("clock").unwrap().cadence(), Some(Duration::from_secs(60)));
+
+		// The battery is no longer kept to the builtin display
+		let mut external = utils::create_test_display();
+		external.is_builtin = false;
+		let specs = registry.declared_items(&external,);
+		assert_eq!(specs[0].name, "battery");
+
+		let clock = &specs[1];
+		assert_eq!(clock.position, ItemPosition::Left);
+		assert_eq!(clock.properties.label.color, Some(Color(0xffa6e3a1)));
+		// What the config leaves alone stays as the item declares it
+		assert_eq!(clock.properties.width, Some(Width::Dynamic));
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_following_the_system_watches_for_switches() -> Result<(),> {
+		use crate::config::Config;
+		use crate::items::appearance::WATCHER_ITEM;
+
+		let config =
+			Config::parse("[[items]]\nname = \"clock\"\n\n[appearance]\nfollow = \"system\"\n",)?;
+		let registry = ItemRegistry::from_config(&config,)?;
+		let names: Vec<&str,> = registry.items().iter().map(|item| item.name(),).collect();
+		assert_eq!(names, ["clock", "appearance"]);
+
+		// The event is added before the hidden item subscribes to it
+		let (mut bar, transport,) = utils::recording_bar("sketchybar",);
+		crate::items::setup_all_items(&mut bar, &utils::create_test_display(), &registry,).await?;
+		let sent = transport.sent_to("sketchybar",);
+		let position = |msg: &str| sent.iter().position(|m| m == msg,);
+		let add_event =
+			position("--add event appearance_change AppleInterfaceThemeChangedNotification",);
+		let subscribe = position(&format!("--subscribe {} appearance_change", WATCHER_ITEM),);
+		assert!(add_event.is_some() && add_event < subscribe, "{:?}", sent);
+		let set = format!("--set {} ", WATCHER_ITEM);
+		assert!(sent.iter().any(|m| m.starts_with(&set) && m.contains("drawing=false")));
+
+		// Clocks need no watching
+		let sun = "[appearance]\nfollow = \"sun\"\nlatitude = 0\nlongitude = 0\n";
+		assert!(ItemRegistry::from_config(&Config::parse(sun,)?,)?.get("appearance").is_none());
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_power_source_change_probes_battery_at_once() -> Result<(),> {
+		use crate::events::SAFETY_NET_SYNC_PERIOD;
+		use crate::listener::EventListener;
+		use crate::wm::ScriptedBackend;
+		use tokio::io::AsyncWriteExt;
+		use tokio::net::UnixStream;
+
+		let runner = FakeRunner::new();
+		let on_battery = "Now drawing from 'Battery Power'\n -InternalBattery-0\t42%;";
+		runner.respond("pmset -g batt", on_battery,);
+		let state = DaemonState::with_runner(Arc::new(runner.clone(),),)
+			.with_backend(Arc::new(ScriptedBackend::new(),),);
+		let mut registry = ItemRegistry::new();
+		registry.register(crate::items::battery::Battery::default(),);
+
+		let socket = std::env::temp_dir()
+			.join(format!("sbd-power-source-{}.socket", std::process::id()),);
+		let listener = EventListener::bind(&socket,)?;
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let bars = utils::bar_manager(&state, vec![],);
+		let mut manager = EventManager::new(state.clone(), bars, registry, shutdown_rx,)
+			.with_listener(listener.clone(),)
+			.with_state_sync_period(SAFETY_NET_SYNC_PERIOD,);
+		let task = tokio::spawn(async move { manager.start_event_loops().await },);
+		sleep(Duration::from_millis(50,),).await;
+		assert_eq!(state.battery.read().await.as_ref().map(|b| b.charging), Some(false));
+
+		// Plugged in, long before the next 30 second probe
+		runner.respond("pmset -g batt", "Now drawing from 'AC Power'\n -InternalBattery-0\t42%;",);
+		let mut events_rx = state.subscribe();
+		let mut stream = UnixStream::connect(&socket,).await?;
+		stream.write_all(b"sketchybar power_source_change battery AC\n",).await?;
+
+		let event = tokio::time::timeout(Duration::from_secs(1,), events_rx.recv(),).await??;
+		let charging = crate::state::BatteryInfo { charge: 42, charging: true, };
+		assert_eq!(event, crate::state::StateEvent::BatteryChanged(charging));
+
+		shutdown_tx.send((),)?;
+		task.await??;
+		listener.close();
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_event_manager_drives_registered_items() {
+		let renders = Arc::new(AtomicUsize::new(0,),);
+		let mut registry = ItemRegistry::new();
+		registry.register(Counter { renders: renders.clone(), },);
+
+		let (builtin, builtin_transport,) = utils::recording_bar("sketchybar",);
+		let (external, external_transport,) = utils::recording_bar("external_2",);
+		let state = DaemonState::new();
+		let bars = utils::bar_manager(&state, vec![
+			("1", builtin,),
+			("2", external,),
+		],);
+
+		let mut display = utils::create_test_display();
+		state.displays.write().await.insert("1".to_string(), display.clone(),);
+		display.index = 2;
+		display.is_builtin = false;
+		state.displays.write().await.insert("2".to_string(), display,);
+
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let mut manager = EventManager::new(state, bars, registry, shutdown_rx,);
+		let task = tokio::spawn(async move { manager.start_event_loops().await },);
+
+		sleep(Duration::from_millis(100,),).await;
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+
+		assert!(renders.load(Ordering::SeqCst) >= 2);
+		assert!(external_transport.received("external_2", "--set counter label=1"));
+		assert!(builtin_transport.sent_to("sketchybar").is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_idle_desktop_sends_nothing() {
+		let mut registry = ItemRegistry::new();
+		registry.register(crate::items::space::Spaces::default(),);
+		registry.register(crate::items::current_app::CurrentApp::default(),);
+		registry.register(crate::items::window::Window::default(),);
+
+		let (bar, transport,) = utils::recording_bar("sketchybar",);
+		let backend = ScriptedBackend::new();
+		backend.set_focused_app("Finder",);
+		let state = DaemonState::new().with_backend(Arc::new(backend.clone(),),);
+		let bars = utils::bar_manager(&state, vec![("1", bar,)],);
+		// Already in sync with the desktop, so the state sync sees no change
+		*state.current_app.write().await = Some("Finder".to_string(),);
+
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let mut manager = EventManager::new(state.clone(), bars, registry, shutdown_rx,);
+		let task = tokio::spawn(async move { manager.start_event_loops().await },);
+
+		// Initial render, then silence
+		sleep(Duration::from_millis(50,),).await;
+		assert!(transport.received("sketchybar", "--set current_app label=Finder"));
+		transport.clear();
+		sleep(Duration::from_millis(200,),).await;
+		assert!(transport.sent().is_empty());
+
+		// A change reaches only the items that depend on it
+		backend.set_focused_app("Safari",);
+		state.apply_current_app("Safari".to_string(),).await;
+		sleep(Duration::from_millis(50,),).await;
+		assert_eq!(transport.sent_to("sketchybar"), vec!["--set current_app label=Safari"]);
+
+		// A re-provisioned bar gets every item again
+		transport.clear();
+		state.mark_provisioned("1",);
+		sleep(Duration::from_millis(50,),).await;
+		assert!(transport.received("sketchybar", "--set current_app label=Safari"));
+
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_default_items_share_scheduler_wakeups() {
+		let (bar, transport,) = utils::recording_bar("sketchybar",);
+		let state = DaemonState::new();
+		let bars = utils::bar_manager(&state, vec![("1", bar,)],);
+		state.displays.write().await.insert("1".to_string(), utils::create_test_display(),);
+
+		// A wall clock that stands still with tokio's
+		let origin = tokio::time::Instant::now();
+		let wall_clock: WallClock =
+			Arc::new(move || Duration::from_secs(1_000_000,) + origin.elapsed(),);
+
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let mut manager =
+			EventManager::new(state, bars, ItemRegistry::with_defaults(), shutdown_rx,)
+				.with_wall_clock(wall_clock,);
+		let stats = manager.scheduler_stats();
+		let task = tokio::spawn(async move { manager.start_event_loops().await },);
+
+		sleep(Duration::from_millis(300_500,),).await;
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+
+		// State sync every 2s and keyboard every 5s; battery and clock ride along
+		assert_eq!(stats.wakeups_per_minute(), 36);
+		// Once at start, then on each of the five minutes that turned over
+		let clock_sets = transport
+			.sent_to("sketchybar",)
+			.iter()
+			.filter(|m| m.starts_with("--set clock label=",),)
+			.count();
+		assert_eq!(clock_sets, 6);
+	}
+
+	#[tokio::test]
+	async fn test_panicking_item_leaves_others_running() {
+		let renders = Arc::new(AtomicUsize::new(0,),);
+		let mut registry = ItemRegistry::new();
+		registry.register(Faulty,);
+		registry.register(Counter { renders: renders.clone(), },);
+
+		let (bar, transport,) = utils::recording_bar("external_2",);
+		let state = DaemonState::new();
+		let bars = utils::bar_manager(&state, vec![("2", bar,)],);
+
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let mut manager = EventManager::new(state, bars, registry, shutdown_rx,);
+		let statuses = manager.task_statuses();
+		let task = tokio::spawn(async move { manager.start_event_loops().await },);
+
+		sleep(Duration::from_millis(100,),).await;
+		let renders_so_far = renders.load(Ordering::SeqCst,);
+		sleep(Duration::from_millis(100,),).await;
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+
+		// The bar actor caught every panic, so neither task ever went down
+		let faulty = statuses.get("faulty",).unwrap();
+		assert_eq!(faulty.failures, 0);
+		assert_eq!(statuses.get("Scheduler").unwrap().failures, 0);
+		assert!(renders.load(Ordering::SeqCst) > renders_so_far);
+		assert!(transport.received("external_2", "--set counter label=2"));
+	}
+
+	/// Counts how often it probes and shows the count on every bar
+	struct Probed {
+		probes:  Arc<AtomicUsize,>,
+		renders: Arc<AtomicUsize,>,
+	}
+
+	#[async_trait]
+	impl BarItem for Probed {
+		fn name(&self,) -> &'static str {
+			"probed"
+		}
+
+		fn setup(&self, _display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+			Vec::new()
+		}
+
+		fn cadence(&self,) -> Option<Duration,> {
+			Some(Duration::from_secs(1,),)
+		}
+
+		async fn refresh(&self, _state: &DaemonState,) -> Result<(),> {
+			self.probes.fetch_add(1, Ordering::SeqCst,);
+			Ok((),)
+		}
+
+		async fn render(&self, bar: &SketchyBar, _state: &DaemonState,) -> Result<(),> {
+			self.renders.fetch_add(1, Ordering::SeqCst,);
+			let probes = self.probes.load(Ordering::SeqCst,);
+			bar.set("probed", ItemProperties::label(probes.to_string(),),).await
+		}
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_probes_run_once_per_tick_for_all_bars() {
+		let probes = Arc::new(AtomicUsize::new(0,),);
+		let renders = Arc::new(AtomicUsize::new(0,),);
+		let mut registry = ItemRegistry::new();
+		registry.register(Probed { probes: probes.clone(), renders: renders.clone(), },);
+
+		let (builtin, builtin_transport,) = utils::recording_bar("sketchybar",);
+		let (external, external_transport,) = utils::recording_bar("external_2",);
+		let state = DaemonState::new();
+		let bars = utils::bar_manager(&state, vec![
+			("1", builtin,),
+			("2", external,),
+		],);
+
+		let origin = tokio::time::Instant::now();
+		let wall_clock: WallClock =
+			Arc::new(move || Duration::from_millis(1_000_000_500,) + origin.elapsed(),);
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let mut manager = EventManager::new(state, bars, registry, shutdown_rx,)
+			.with_wall_clock(wall_clock,);
+		let task = tokio::spawn(async move { manager.start_event_loops().await },);
+
+		// Startup, then ticks at +0.5s, +1.5s and +2.5s
+		sleep(Duration::from_secs(3,),).await;
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+
+		assert_eq!(probes.load(Ordering::SeqCst), 4);
+		assert_eq!(renders.load(Ordering::SeqCst), 8);
+		assert!(builtin_transport.received("sketchybar", "--set probed label=4"));
+		assert!(external_transport.received("external_2", "--set probed label=4"));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_state_backed_items_fan_out_changes() {
+		let mut registry = ItemRegistry::new();
+		registry.register(crate::items::keyboard::Keyboard::default(),);
+
+		let (builtin, builtin_transport,) = utils::recording_bar("sketchybar",);
+		let (external, external_transport,) = utils::recording_bar("external_2",);
+		let state = DaemonState::new();
+		let bars = utils::bar_manager(&state, vec![
+			("1", builtin,),
+			("2", external,),
+		],);
+
+		// Well clear of the next 5 second tick
+		let origin = tokio::time::Instant::now();
+		let wall_clock: WallClock =
+			Arc::new(move || Duration::from_millis(1_000_000_500,) + origin.elapsed(),);
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let mut manager = EventManager::new(state.clone(), bars, registry, shutdown_rx,)
+			.with_wall_clock(wall_clock,);
+		let task = tokio::spawn(async move { manager.start_event_loops().await },);
+		sleep(Duration::from_millis(50,),).await;
+		builtin_transport.clear();
+		external_transport.clear();
+
+		state.apply_input_source("XX".to_string(),).await;
+		// Reading the same layout again changes nothing
+		state.apply_input_source("XX".to_string(),).await;
+		sleep(Duration::from_millis(50,),).await;
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+
+		assert_eq!(builtin_transport.sent_to("sketchybar"), vec!["--set keyboard label=XX"]);
+		assert_eq!(external_transport.sent_to("external_2"), vec!["--set keyboard label=XX"]);
+	}
+}
@@ -1,69 +1,80 @@
-use crate::helpers::colors::Colors;
-use crate::helpers::icons::Icons;
-use crate::helpers::yabai::DisplayInfo;
-use crate::sketchybar::SketchyBar;
 use anyhow::Result;
-use serde::Deserialize;
-use std::process::Command;
+use async_trait::async_trait;
+use tokio::time::Duration;
 use tracing::debug;
 use tracing::error;
 
-pub async fn setup(bar: &mut SketchyBar, display_info: &DisplayInfo,) -> Result<(),> {
-	debug!("🪟 Setting up window item for display {}", display_info.index);
-
-	// Add window item
-	bar.add("item", "window", "left",).await?;
-
-	// Configure window properties
-	bar.set(
-		"window",
-		&[
-			("width", "dynamic",),
-			("position", "left",),
-			("icon", Icons::WINDOW,),
-			("icon.color", &format!("0x{:08x}", Colors::GREEN),),
-			("label", "Window",),
-			("label.color", &format!("0x{:08x}", Colors::GREEN),),
-			("background.border_color", &format!("0x{:08x}", Colors::GREEN),),
-			("associated_display", &display_info.index.to_string(),),
-		],
-	)
-	.await?;
-
-	// Subscribe to window events
-	bar.subscribe("window", &["window_focus", "window_title",],).await?;
-
-	debug!("✅ Window item configured for display {}", display_info.index);
-	Ok((),)
+use crate::helpers::icons::Icons;
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::BarItem;
+use crate::items::ItemSpec;
+use crate::items::StateDependency;
+use crate::sketchybar::ItemKind;
+use crate::sketchybar::ItemProperties;
+use crate::sketchybar::SketchyBar;
+use crate::sketchybar::properties::BackgroundProperties;
+use crate::sketchybar::properties::ItemPosition;
+use crate::sketchybar::properties::TextProperties;
+use crate::sketchybar::properties::Width;
+use crate::state::DaemonState;
+use crate::theme::Theme;
+
+/// Focused window title, redrawn only when the windows change
+#[derive(Default,)]
+pub struct Window {
+	pub theme: Theme,
 }
 
-/// Update window display with current window title
-pub async fn update(bar: &SketchyBar,) -> Result<(),> {
-	// Try to get current window from yabai first
-	let window_title = if let Ok(yabai_title,) = get_yabai_focused_window().await {
-		yabai_title
-	} else {
-		// Fallback to AppleScript
-		get_applescript_focused_window().await.unwrap_or_else(|_| "No Window".to_string(),)
-	};
+#[async_trait]
+impl BarItem for Window {
+	fn name(&self,) -> &'static str {
+		"window"
+	}
 
-	// Truncate long titles
-	let display_title = if window_title.len() > 50 {
-		format!("{}...", &window_title[..47])
-	} else {
-		window_title.clone()
-	};
+	fn setup(&self, display_info: &DisplayInfo,) -> Vec<ItemSpec,> {
+		vec![spec(display_info, &self.theme,)]
+	}
 
-	// Update the window item
-	let cmd = format!("--set window label=\"{}\"", display_title);
+	fn subscriptions(&self,) -> &'static [&'static str] {
+		&["window_focus", "window_title",]
+	}
 
-	if let Err(e,) = bar.message(&cmd,).await {
-		error!("Failed to update window: {}", e);
-		return Err(e,);
+	fn dependencies(&self,) -> &'static [StateDependency] {
+		&[StateDependency::Windows,]
 	}
 
-	debug!("🪟 Window updated: {}", display_title);
-	Ok((),)
+	fn cadence(&self,) -> Option<Duration,> {
+		None
+	}
+
+	async fn render(&self, bar: &SketchyBar, state: &DaemonState,) -> Result<(),> {
+		update_with_state(bar, state,).await
+	}
+}
+
+/// Focused window title on the left
+pub fn spec(display_info: &DisplayInfo, theme: &Theme,) -> ItemSpec {
+	let properties = ItemProperties {
+		width: Some(Width::Dynamic,),
+		icon: TextProperties {
+			value: Some(Icons::WINDOW.to_string(),),
+			color: theme.accents.window,
+			..Default::default()
+		},
+		label: TextProperties {
+			value: Some("Window".to_string(),),
+			color: theme.accents.window,
+			..Default::default()
+		},
+		background: BackgroundProperties {
+			border_color: theme.accents.window,
+			..Default::default()
+		},
+		associated_display: Some(display_info.index,),
+		..Default::default()
+	};
+
+	ItemSpec::new(ItemKind::Item, "window", ItemPosition::Left, properties,)
 }
 
 /// Update window using centralized state (more efficient)
@@ -74,15 +85,11 @@ pub async fn update_with_state(
 
 	// Find the focused window
 	if let Some(focused_window,) = windows.values().find(|w| w.has_focus,) {
-		let display_title = if focused_window.title.len() > 50 {
-			format!("{}...", &focused_window.title[..47])
-		} else {
-			focused_window.title.clone()
-		};
+		let display_title = truncate_title(&focused_window.title,);
 
-		let cmd = format!("--set window label=\"{}\"", display_title);
+		let props = ItemProperties::label(&display_title,);
 
-		if let Err(e,) = bar.message(&cmd,).await {
+		if let Err(e,) = bar.set("window", props,).await {
 			error!("Failed to update window: {}", e);
 			return Err(e,);
 		}
@@ -90,8 +97,8 @@ pub async fn update_with_state(
 		debug!("🪟 Window updated from state: {}", display_title);
 	} else {
 		// No focused window
-		let cmd = "--set window label=\"No Window\"";
-		if let Err(e,) = bar.message(cmd,).await {
+		let props = ItemProperties::label("No Window",);
+		if let Err(e,) = bar.set("window", props,).await {
 			error!("Failed to update window: {}", e);
 			return Err(e,);
 		}
@@ -100,41 +107,11 @@ pub async fn update_with_state(
 	Ok((),)
 }
 
-/// Get focused window title from yabai
-async fn get_yabai_focused_window() -> Result<String,> {
-	let output =
-		Command::new("yabai",).args(["-m", "query", "--windows", "--window",],).output()?;
-
-	if !output.status.success() {
-		return Err(anyhow::anyhow!("yabai query failed"),);
-	}
-
-	let json_str = String::from_utf8(output.stdout,)?;
-	let window: YabaiWindow = serde_json::from_str(&json_str,)?;
-
-	Ok(window.title,)
-}
-
-/// Get focused window title using AppleScript (fallback)
-async fn get_applescript_focused_window() -> Result<String,> {
-	let output = Command::new("osascript",)
-		.args([
-			"-e",
-			"tell application \"System Events\" to get name of window 1 of (first application \
-			 process whose frontmost is true)",
-		],)
-		.output()?;
-
-	if !output.status.success() {
-		return Err(anyhow::anyhow!("AppleScript failed"),);
+/// Shorten titles over 50 characters, cutting on a character boundary
+pub fn truncate_title(title: &str,) -> String {
+	if title.chars().count() > 50 {
+		format!("{}...", title.chars().take(47,).collect::<String>())
+	} else {
+		title.to_string()
 	}
-
-	let window_title = String::from_utf8(output.stdout,)?.trim().to_string();
-
-	Ok(window_title,)
-}
-
-#[derive(Debug, Deserialize,)]
-struct YabaiWindow {
-	title: String,
 }
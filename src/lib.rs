@@ -1,9 +1,22 @@
+pub mod appearance;
+pub mod bars;
 pub mod config;
+pub mod control;
 pub mod events;
 pub mod helpers;
 pub mod items;
+pub mod listener;
+pub mod processes;
+pub mod provision;
+pub mod reload;
+pub mod render;
+pub mod runner;
+pub mod scheduler;
 pub mod sketchybar;
 pub mod state;
+pub mod supervisor;
+pub mod theme;
+pub mod wm;
 
 #[cfg(test)] mod tests;
 
@@ -0,0 +1,295 @@
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::BufReader;
+use tokio::net::UnixListener;
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use tokio::time::Duration;
+use tokio::time::timeout;
+use tracing::debug;
+use tracing::warn;
+
+/// How long a sender may keep its connection open before it is dropped
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(2,);
+
+/// The daemon socket for the current user, unless `SKETCHYBAR_DAEMON_SOCKET` names one
+pub fn default_socket_path() -> PathBuf {
+	if let Ok(path,) = std::env::var("SKETCHYBAR_DAEMON_SOCKET",) {
+		return PathBuf::from(path,);
+	}
+	let user = std::env::var("USER",).unwrap_or_default();
+	PathBuf::from(format!("/tmp/sketchybar-daemon_{}.socket", user),)
+}
+
+/// Item script that reports each sketchybar event it receives to `socket`
+/// as `sketchybar <sender> <item> <info>`, with `$INFO` on one line
+///
+/// The script holds no quotes: the sketchybar-rs client takes every `"` and
+/// `'` as a quote toggle, and [`encode_arg`](crate::sketchybar::command::encode_arg)
+/// replaces them in values. The here-document keeps the line from being
+/// split or globbed, and `set -f` does the same for the words of `$INFO`.
+pub fn forward_script(socket: &Path,) -> String {
+	format!(
+		"set -f\nnc -U -w 1 {} <<EOF\nsketchybar $SENDER $NAME $(printf %s\\  $INFO)\nEOF\n",
+		shell_escape(&socket.display().to_string(),)
+	)
+}
+
+/// `text` as one shell word, with a backslash before anything but letters,
+/// digits and `/._-`
+fn shell_escape(text: &str,) -> String {
+	let mut escaped = String::new();
+	for c in text.chars() {
+		if !(c.is_ascii_alphanumeric() || "/._-".contains(c,)) {
+			escaped.push('\\',);
+		}
+		escaped.push(c,);
+	}
+	escaped
+}
+
+/// One line written to the daemon socket: `<source> <name> [detail]`
+///
+/// e.g. `yabai space_changed`, as sent by the signals the daemon registers, or
+/// `sketchybar power_source_change battery AC` from a [`forward_script`].
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct DaemonEvent {
+	/// Who sent the event, e.g. `yabai`
+	pub source: String,
+	pub name:   String,
+	/// Rest of the line, if any
+	pub detail: String,
+}
+
+impl DaemonEvent {
+	pub fn new(source: &str, name: &str,) -> Self {
+		Self { source: source.to_string(), name: name.to_string(), detail: String::new(), }
+	}
+
+	/// Parse one line, `None` when it lacks a source or a name
+	pub fn parse(line: &str,) -> Option<Self,> {
+		let mut parts = line.trim().splitn(3, ' ',);
+		let source = parts.next().filter(|s| !s.is_empty(),)?;
+		let name = parts.next().filter(|s| !s.is_empty(),)?;
+		let detail = parts.next().unwrap_or_default().trim();
+		Some(Self {
+			source: source.to_string(),
+			name:   name.to_string(),
+			detail: detail.to_string(),
+		},)
+	}
+}
+
+/// Unix socket that window manager signals and scripts report events to
+///
+/// Any number of events may be written per connection, one per line.
+/// Clones share the bound socket, so a restarted task keeps listening on it.
+#[derive(Debug, Clone,)]
+pub struct EventListener {
+	path:     PathBuf,
+	listener: Arc<UnixListener,>,
+}
+
+impl EventListener {
+	/// Listen on `path`, replacing a socket left behind by an earlier daemon
+	pub fn bind(path: impl Into<PathBuf,>,) -> Result<Self,> {
+		let path = path.into();
+		let listener = bind_socket(&path,)?;
+		debug!("👂 Listening for events on {}", path.display());
+		Ok(Self { path, listener: Arc::new(listener,), },)
+	}
+
+	pub fn path(&self,) -> &Path {
+		&self.path
+	}
+
+	/// Accept connections until the socket fails, sending every event to `events`
+	pub async fn serve(&self, events: mpsc::Sender<DaemonEvent,>,) -> Result<(),> {
+		loop {
+			let (stream, _,) = self.listener.accept().await.context("Event socket failed",)?;
+			tokio::spawn(read_events(stream, events.clone(),),);
+		}
+	}
+
+	/// Remove the socket file
+	pub fn close(&self,) {
+		remove_socket(&self.path,);
+	}
+}
+
+/// Listen on the Unix socket at `path`, replacing one left behind by an
+/// earlier daemon. Fails if a running daemon still answers on it.
+pub fn bind_socket(path: &Path,) -> Result<UnixListener,> {
+	match std::os::unix::net::UnixStream::connect(path,) {
+		Ok(_,) => bail!("A daemon is already running on {}", path.display()),
+		Err(e,) if e.kind() == ErrorKind::NotFound => {},
+		Err(e,) if e.kind() == ErrorKind::ConnectionRefused => {
+			std::fs::remove_file(path,)
+				.with_context(|| format!("Failed to remove stale socket {}", path.display()),)?;
+		},
+		Err(e,) => {
+			return Err(e,).with_context(|| format!("Failed to check {}", path.display()),);
+		},
+	}
+	UnixListener::bind(path,).with_context(|| format!("Failed to listen on {}", path.display()),)
+}
+
+pub fn remove_socket(path: &Path,) {
+	if let Err(e,) = std::fs::remove_file(path,) {
+		debug!("Could not remove {}: {}", path.display(), e);
+	}
+}
+
+async fn read_events(stream: UnixStream, events: mpsc::Sender<DaemonEvent,>,) {
+	let mut lines = BufReader::new(stream,).lines();
+	loop {
+		let line = match timeout(CONNECTION_TIMEOUT, lines.next_line(),).await {
+			Ok(Ok(Some(line,),),) => line,
+			Ok(Ok(None,),) => return,
+			Ok(Err(e,),) => {
+				debug!("Event connection failed: {}", e);
+				return;
+			},
+			Err(_,) => {
+				debug!("Dropping an event connection that stayed open");
+				return;
+			},
+		};
+		match DaemonEvent::parse(&line,) {
+			Some(event,) => {
+				if events.send(event,).await.is_err() {
+					return;
+				}
+			},
+			None if line.trim().is_empty() => {},
+			None => warn!("⚠️  Ignoring malformed event {:?}", line),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::io::AsyncWriteExt;
+
+	fn socket(test: &str,) -> PathBuf {
+		std::env::temp_dir().join(format!("sbd-events-{}-{}.socket", test, std::process::id()),)
+	}
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(DaemonEvent::parse("yabai space_changed\n"), Some(DaemonEvent::new(
+			"yabai",
+			"space_changed"
+		)));
+		let event = DaemonEvent::parse("yabai window_focused 4242",).unwrap();
+		assert_eq!(event.detail, "4242");
+		assert_eq!(DaemonEvent::parse("yabai"), None);
+		assert_eq!(DaemonEvent::parse("   "), None);
+	}
+
+	#[tokio::test]
+	async fn test_events_are_read_per_line() -> Result<(),> {
+		let listener = EventListener::bind(socket("lines",),)?;
+		let (tx, mut rx,) = mpsc::channel(8,);
+		let serving = listener.clone();
+		let task = tokio::spawn(async move { serving.serve(tx,).await },);
+
+		let mut stream = UnixStream::connect(listener.path(),).await?;
+		stream.write_all(b"yabai space_changed\nnonsense\n\nyabai display_added\n",).await?;
+		drop(stream,);
+		// A second sender on its own connection
+		UnixStream::connect(listener.path(),).await?.write_all(b"yabai window_focused",).await?;
+
+		let mut received = Vec::new();
+		for _ in 0..3 {
+			received.push(rx.recv().await.unwrap().name,);
+		}
+		received.sort();
+		assert_eq!(received, ["display_added", "space_changed", "window_focused"]);
+
+		task.abort();
+		listener.close();
+		assert!(!listener.path().exists());
+		Ok((),)
+	}
+
+	#[test]
+	fn test_forward_script_reports_one_line() {
+		use crate::sketchybar::Command;
+		use crate::sketchybar::PropertyList;
+		use crate::sketchybar::command::split_message;
+		use std::os::unix::fs::PermissionsExt;
+
+		// A stand-in nc that records what it would send and where
+		let dir = std::env::temp_dir().join(format!("sbd-forward-{}", std::process::id()),);
+		std::fs::create_dir_all(&dir,).unwrap();
+		let sent = dir.join("sent",);
+		let nc = dir.join("nc",);
+		let stub = format!("#!/bin/sh\necho \"$@\" > '{0}'\ncat >> '{0}'\n", sent.display());
+		std::fs::write(&nc, stub,).unwrap();
+		std::fs::set_permissions(&nc, std::fs::Permissions::from_mode(0o755,),).unwrap();
+
+		// The script as sketchybar gets it, through the client's tokenizer
+		let script = forward_script(Path::new("/tmp/daemon sockets/d.socket",),);
+		let set = Command::set("current_app", PropertyList::new().with("script", &script,),);
+		let args = split_message(&set.to_message(),);
+		assert_eq!(args[2], format!("script={}", script));
+
+		let status = std::process::Command::new("/bin/sh",)
+			.args(["-c", args[2].trim_start_matches("script=",),],)
+			.env("PATH", format!("{}:/usr/bin:/bin", dir.display()),)
+			.env("SENDER", "front_app_switched",)
+			.env("NAME", "current_app",)
+			.env("INFO", "Safari *\nTechnology  Preview",)
+			.status()
+			.unwrap();
+		let recorded = std::fs::read_to_string(&sent,);
+		std::fs::remove_dir_all(&dir,).unwrap();
+		assert!(status.success());
+
+		let recorded = recorded.unwrap();
+		let (args, line,) = recorded.split_once('\n',).unwrap();
+		assert_eq!(args, "-U -w 1 /tmp/daemon sockets/d.socket");
+		let event = DaemonEvent::parse(line,).unwrap();
+		assert_eq!(event.source, "sketchybar");
+		assert_eq!(event.name, "front_app_switched");
+		assert_eq!(event.detail, "current_app Safari * Technology Preview");
+		assert_eq!(line.trim_end().lines().count(), 1);
+	}
+
+	#[tokio::test]
+	async fn test_stale_socket_is_replaced() -> Result<(),> {
+		let path = socket("stale",);
+		drop(EventListener::bind(&path,)?,);
+		assert!(path.exists());
+
+		let listener = EventListener::bind(&path,)?;
+		listener.close();
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_live_socket_is_kept() -> Result<(),> {
+		let listener = EventListener::bind(socket("live",),)?;
+		let error = EventListener::bind(listener.path(),).unwrap_err();
+		assert!(format!("{:#}", error).contains("already running"));
+
+		// The running daemon still gets its events
+		let (tx, mut rx,) = mpsc::channel(1,);
+		let serving = listener.clone();
+		let task = tokio::spawn(async move { serving.serve(tx,).await },);
+		UnixStream::connect(listener.path(),).await?.write_all(b"yabai space_changed",).await?;
+		assert_eq!(rx.recv().await.unwrap().name, "space_changed");
+
+		task.abort();
+		listener.close();
+		Ok((),)
+	}
+}
@@ -1,16 +1,16 @@
-mod config;
-mod events;
-mod helpers;
-mod items;
-mod sketchybar;
-mod state;
-
+use anyhow::Context;
 use anyhow::Result;
+use anyhow::anyhow;
+use anyhow::bail;
 use futures::stream::StreamExt;
+use signal_hook::consts::SIGHUP;
 use signal_hook::consts::SIGTERM;
 use signal_hook_tokio::Signals;
 use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
+use tokio::sync::Mutex;
 use tokio::sync::RwLock;
 use tokio::sync::broadcast;
 use tokio::time::Duration;
@@ -19,42 +19,137 @@ use tracing::error;
 use tracing::info;
 use tracing::warn;
 
-use helpers::yabai::DisplayInfo;
-use sketchybar::SketchyBar;
-use state::DaemonState;
+use sketchybar_daemon::appearance::AppearanceFollower;
+use sketchybar_daemon::bars;
+use sketchybar_daemon::bars::BarManager;
+use sketchybar_daemon::config;
+use sketchybar_daemon::config::Config;
+use sketchybar_daemon::control;
+use sketchybar_daemon::control::ControlRequest;
+use sketchybar_daemon::control::ControlServer;
+use sketchybar_daemon::control::Controller;
+use sketchybar_daemon::control::LogLevelSetter;
+use sketchybar_daemon::events;
+use sketchybar_daemon::helpers::yabai::DisplayInfo;
+use sketchybar_daemon::items::ItemRegistry;
+use sketchybar_daemon::listener;
+use sketchybar_daemon::listener::EventListener;
+use sketchybar_daemon::processes::BarProcesses;
+use sketchybar_daemon::processes::LaunchConfig;
+use sketchybar_daemon::reload;
+use sketchybar_daemon::reload::ConfigReloader;
+use sketchybar_daemon::render;
+use sketchybar_daemon::render::RenderFixture;
+use sketchybar_daemon::runner;
+use sketchybar_daemon::sketchybar::CliTransport;
+use sketchybar_daemon::sketchybar::SketchyBar;
+use sketchybar_daemon::state::DaemonState;
+use sketchybar_daemon::state::StateEvent;
+use sketchybar_daemon::supervisor::RestartPolicy;
+use sketchybar_daemon::supervisor::Supervisor;
+use sketchybar_daemon::supervisor::TaskStatuses;
+use sketchybar_daemon::wm::WindowManager;
 
 /// Main daemon state
-#[derive(Debug,)]
 pub struct SketchyBarDaemon {
 	displays:    Arc<RwLock<HashMap<String, DisplayInfo,>,>,>,
-	bars:        Arc<RwLock<HashMap<String, SketchyBar,>,>,>,
+	bars:        BarManager,
+	processes:   Arc<Mutex<BarProcesses,>,>,
+	reloader:    ConfigReloader,
 	state:       DaemonState,
+	policy:      RestartPolicy,
+	log_level:   Option<LogLevelSetter,>,
 	shutdown_tx: broadcast::Sender<(),>,
 }
 
+impl std::fmt::Debug for SketchyBarDaemon {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		f.debug_struct("SketchyBarDaemon",)
+			.field("displays", &self.displays,)
+			.field("bars", &self.bars,)
+			.field("reloader", &self.reloader,)
+			.field("policy", &self.policy,)
+			.finish_non_exhaustive()
+	}
+}
+
+impl Default for SketchyBarDaemon {
+	fn default() -> Self {
+		Self::new(LaunchConfig::default(), RestartPolicy::default(), WindowManager::default(),)
+	}
+}
+
 impl SketchyBarDaemon {
-	pub fn new() -> Self {
+	pub fn new(
+		launch_config: LaunchConfig,
+		policy: RestartPolicy,
+		window_manager: WindowManager,
+	) -> Self {
 		let (shutdown_tx, _,) = broadcast::channel(1,);
+		let state = DaemonState::new().with_backend(window_manager.backend(runner::system(),),);
+		let bars = BarManager::new(state.clone(),);
+		let registry =
+			ItemRegistry::with_defaults().forward_events_to(&listener::default_socket_path(),);
 
 		Self {
 			displays: Arc::new(RwLock::new(HashMap::new(),),),
-			bars: Arc::new(RwLock::new(HashMap::new(),),),
-			state: DaemonState::new(),
+			bars: bars.clone(),
+			processes: Arc::new(Mutex::new(BarProcesses::new(launch_config,),),),
+			reloader: ConfigReloader::new(
+				config::default_path(),
+				Config::default(),
+				registry,
+				state.clone(),
+				bars,
+			),
+			state,
+			policy,
+			log_level: None,
 			shutdown_tx,
 		}
 	}
 
+	/// Draw the items and bars of `config`, and poll at its intervals, until
+	/// the config file changes
+	pub fn with_config(mut self, config: &Config,) -> Result<Self,> {
+		self.reloader = ConfigReloader::new(
+			self.reloader.path(),
+			config.clone(),
+			item_registry(config,)?,
+			self.state.clone(),
+			self.bars.clone(),
+		);
+		Ok(self,)
+	}
+
+	/// Let `ctl set-log-level` replace the log filter through `setter`
+	pub fn with_log_level_setter(mut self, setter: LogLevelSetter,) -> Self {
+		self.log_level = Some(setter,);
+		self
+	}
+
 	/// Start the daemon
-	pub async fn run(&mut self,) -> Result<(),> {
+	pub async fn run(&self,) -> Result<(),> {
 		info!("🦀 Starting SketchyBar Daemon v0.2.0");
+		info!("🪟 Following {}", self.state.backend().name());
 
 		// Setup signal handling
-		let mut signals = Signals::new(&[SIGTERM,],)?;
+		let mut signals = Signals::new([SIGTERM, SIGHUP,],)?;
 		let shutdown_tx = self.shutdown_tx.clone();
+		let reloader = self.reloader.clone();
 
 		tokio::spawn(async move {
 			while let Some(signal,) = signals.next().await {
 				match signal {
+					SIGHUP => {
+						info!("📡 Received SIGHUP, reloading the config");
+						let reloader = reloader.clone();
+						tokio::spawn(async move {
+							if let Err(e,) = reloader.reload().await {
+								error!("❌ Keeping the running config: {:#}", e);
+							}
+						},);
+					},
 					SIGTERM => {
 						info!("📡 Received SIGTERM, shutting down gracefully");
 						let _ = shutdown_tx.send((),);
@@ -68,116 +163,224 @@ impl SketchyBarDaemon {
 		// Initial display detection and bar setup
 		self.detect_and_setup_displays().await?;
 
-		// Start display monitoring
-		let daemon_clone = Arc::new(RwLock::new(self.clone(),),);
-		let monitor_task = tokio::spawn(Self::monitor_displays(daemon_clone,),);
+		// Display monitoring and every update loop restart on their own when
+		// they fail; only the restart policy can bring the daemon down
+		let statuses = TaskStatuses::default();
+		let mut supervisor = Supervisor::new(self.policy.clone(), statuses.clone(),);
+		let daemon = self.clone();
+		supervisor.supervise("Display monitor", move || Self::monitor_displays(daemon.clone(),),);
+
+		// Edits to the config file apply to the running bars
+		let (reloader, shutdown_tx,) = (self.reloader.clone(), self.shutdown_tx.clone(),);
+		supervisor.supervise("Config watcher", move || {
+			let reloader = reloader.clone();
+			let mut shutdown_rx = shutdown_tx.subscribe();
+			async move {
+				tokio::select! {
+					result = reloader.watch(reload::CONFIG_POLL_PERIOD,) => result,
+					_ = shutdown_rx.recv() => Ok(()),
+				}
+			}
+		},);
+
+		// The theme follows light and dark mode, or the clock, as the config says
+		let follower = AppearanceFollower::new(self.reloader.clone(), self.state.clone(),);
+		let shutdown_tx = self.shutdown_tx.clone();
+		supervisor.supervise("Appearance follower", move || {
+			let follower = follower.clone();
+			let mut shutdown_rx = shutdown_tx.subscribe();
+			async move {
+				tokio::select! {
+					result = follower.run() => result,
+					_ = shutdown_rx.recv() => Ok(()),
+				}
+			}
+		},);
+
+		// `sketchybar-daemon ctl` talks to the daemon through the control socket
+		let control_server = match ControlServer::bind(control::default_socket_path(),) {
+			Ok(server,) => Some(server,),
+			Err(e,) => {
+				warn!("⚠️  No control socket, ctl will not reach this daemon: {:#}", e);
+				None
+			},
+		};
+		if let Some(server,) = &control_server {
+			let mut controller = Controller::new(
+				self.state.clone(),
+				self.bars.clone(),
+				self.reloader.clone(),
+				statuses.clone(),
+				self.shutdown_tx.clone(),
+			);
+			if let Some(setter,) = &self.log_level {
+				controller = controller.with_log_level_setter(setter.clone(),);
+			}
+			let (server, shutdown_tx,) = (server.clone(), self.shutdown_tx.clone(),);
+			supervisor.supervise("Control server", move || {
+				let (server, controller,) = (server.clone(), controller.clone(),);
+				let mut shutdown_rx = shutdown_tx.subscribe();
+				async move {
+					tokio::select! {
+						result = server.serve(controller,) => result,
+						_ = shutdown_rx.recv() => Ok(()),
+					}
+				}
+			},);
+		}
 
-		// Start update loops for all items using event system
 		let mut event_manager = events::EventManager::new(
 			self.state.clone(),
 			self.bars.clone(),
+			self.reloader.registry(),
 			self.shutdown_tx.subscribe(),
-		);
-		let update_task = tokio::spawn(async move { event_manager.start_event_loops().await },);
+		)
+		.with_restart_policy(self.policy.clone(),)
+		.with_task_statuses(statuses,);
+
+		// Changes reported to the daemon socket refresh the state at once,
+		// leaving polling as a safety net
+		let listener = match EventListener::bind(listener::default_socket_path(),) {
+			Ok(listener,) => Some(listener,),
+			Err(e,) => {
+				warn!("⚠️  No event socket, polling only: {:#}", e);
+				None
+			},
+		};
+		let mut watched = false;
+		if let Some(listener,) = &listener {
+			watched = match self.state.backend().watch(listener.path(),).await {
+				Ok(watched,) => watched,
+				Err(e,) => {
+					warn!("⚠️  {} will not report changes: {}", self.state.backend().name(), e);
+					false
+				},
+			};
+			event_manager = event_manager.with_listener(listener.clone(),);
+		}
+		if watched {
+			info!("📡 {} reports changes, polling as a safety net", self.state.backend().name());
+		}
 
-		// Wait for shutdown signal or task completion
-		let mut shutdown_rx = self.shutdown_tx.subscribe();
-		tokio::select! {
-			_ = shutdown_rx.recv() => {
-				info!("🛑 Shutdown signal received");
-			}
-			result = monitor_task => {
-				if let Err(e) = result {
-					error!("❌ Display monitor task failed: {}", e);
-				}
-			}
-			result = update_task => {
-				if let Err(e) = result {
-					error!("❌ Update task failed: {}", e);
-				}
-			}
+		// Item tasks start over on the items and intervals of every config applied
+		let reloader = self.reloader.clone();
+		supervisor.supervise("Event loops", move || {
+			let (event_manager, configs,) = (event_manager.clone(), reloader.subscribe(),);
+			async move { event_manager.run_reloading(configs, watched,).await }
+		},);
+
+		// Runs until the shutdown signal, or until a task fails too often
+		match supervisor.run(self.shutdown_tx.subscribe(),).await {
+			Ok(_,) => info!("🛑 Shutdown signal received"),
+			Err(e,) => {
+				error!("🚨 Shutting down: {}", e);
+				let _ = self.shutdown_tx.send((),);
+			},
+		}
+
+		// Leave no signals pointing at a socket that is gone
+		if watched && let Err(e,) = self.state.backend().unwatch().await {
+			warn!("⚠️  Failed to remove {} signals: {}", self.state.backend().name(), e);
+		}
+		if let Some(listener,) = &listener {
+			listener.close();
 		}
+		if let Some(server,) = &control_server {
+			server.close();
+		}
+
+		// Tear down the sketchybar instances we started
+		self.processes.lock().await.stop_all().await;
 
 		info!("✅ SketchyBar Daemon shutdown complete");
 		Ok((),)
 	}
 
 	/// Detect displays and setup bars
-	async fn detect_and_setup_displays(&mut self,) -> Result<(),> {
-		let new_displays = helpers::yabai::get_displays().await?;
-		let mut displays = self.displays.write().await;
-		let mut bars = self.bars.write().await;
-
+	///
+	/// Setup runs on each bar's own actor, so a bar that is slow to answer
+	/// never holds up detection or the other bars.
+	async fn detect_and_setup_displays(&self,) -> Result<(),> {
+		let new_displays = self.state.backend().displays().await?;
 		info!("📺 Detected {} displays", new_displays.len());
 
-		// Remove bars for displays that no longer exist
-		let mut to_remove = Vec::new();
-		for display_id in displays.keys() {
-			if !new_displays.contains_key(display_id,) {
-				to_remove.push(display_id.clone(),);
-			}
-		}
+		// Work out what changed without holding the lock over any bar or process work
+		let (removed, added,) = {
+			let mut displays = self.displays.write().await;
+			let removed: Vec<String,> =
+				displays.keys().filter(|id| !new_displays.contains_key(*id,),).cloned().collect();
+			let added: Vec<(String, DisplayInfo,),> = new_displays
+				.iter()
+				.filter(|(id, _,)| !displays.contains_key(*id,),)
+				.map(|(id, info,)| (id.clone(), info.clone(),),)
+				.collect();
+			*displays = new_displays.clone();
+			(removed, added,)
+		};
+		self.state.apply_displays(new_displays,).await;
 
-		for display_id in to_remove {
+		// Remove bars for displays that no longer exist
+		for display_id in removed {
 			info!("🗑️  Removing bar for disconnected display {}", display_id);
-			displays.remove(&display_id,);
-			bars.remove(&display_id,);
+			if let Some(handle,) = self.bars.remove(&display_id,) {
+				self.processes.lock().await.stop(handle.bar_name(),).await;
+			}
 		}
 
 		// Add bars for new displays
-		for (display_id, display_info,) in new_displays.iter() {
-			if !displays.contains_key(display_id,) {
-				info!("🚀 Setting up bar for new display {}", display_id);
-
-				let bar_name = if display_info.is_builtin {
-					"sketchybar".to_string()
+		for (display_id, display_info,) in added {
+			info!("🚀 Setting up bar for new display {}", display_id);
+
+			let bar_name = bars::bar_name(&display_info,);
+
+			// Bars with a daemon-managed instance are addressed by name through the CLI
+			let mut bar = {
+				let mut processes = self.processes.lock().await;
+				if processes.config().spawns(&display_info,) {
+					processes.ensure(&bar_name, &display_info,);
+					let binary = processes.config().binary.clone();
+					SketchyBar::with_transport(Arc::new(CliTransport::new(binary,),),)
 				} else {
-					format!("external_{}", display_info.index)
-				};
-
-				let mut bar = SketchyBar::new();
-				bar.set_bar_name(&bar_name,);
-
-				// Configure the bar
-				if let Err(e,) = config::setup_bar(&mut bar, &bar_name, display_info,).await {
-					error!("❌ Failed to configure bar {}: {}", bar_name, e);
-					continue;
+					SketchyBar::new()
 				}
-
-				// Add all items
-				if let Err(e,) = items::setup_all_items(&mut bar, display_info,).await {
-					error!("❌ Failed to setup items for bar {}: {}", bar_name, e);
-					continue;
-				}
-
-				// Enable hotloading
-				if let Err(e,) = bar.hotload(true,).await {
-					warn!("⚠️  Failed to enable hotloading for bar {}: {}", bar_name, e);
-				}
-
-				bars.insert(display_id.clone(), bar,);
-				info!("✅ Bar '{}' configured for display {}", bar_name, display_id);
-			}
+			};
+			bar.set_bar_name(&bar_name,);
+
+			// The actor fills the bar in and announces it; if setup fails the
+			// heartbeat provisions it once it answers. Nothing waits on the
+			// outcome, so a hung bar cannot hold up detection.
+			let handle = self.bars.add(&display_id, bar,);
+			drop(handle.provision(display_info, self.reloader.registry(),),);
 		}
 
-		// Update state with new displays
-		*displays = new_displays.clone();
-		*self.state.displays.write().await = new_displays;
-
 		Ok((),)
 	}
 
-	/// Monitor displays for changes
-	async fn monitor_displays(daemon: Arc<RwLock<Self,>,>,) -> Result<(),> {
+	/// Monitor displays for changes and bars for sketchybar restarts
+	///
+	/// Runs every few seconds, and at once when the state sees a display come or go.
+	async fn monitor_displays(daemon: Self,) -> Result<(),> {
 		let mut interval = interval(Duration::from_secs(5,),);
+		let mut shutdown_rx = daemon.shutdown_tx.subscribe();
+		let mut events_rx = daemon.state.subscribe();
 
 		loop {
-			interval.tick().await;
+			tokio::select! {
+				_ = interval.tick() => {}
+				event = events_rx.recv() => match event {
+					Ok(StateEvent::DisplayAdded(_,) | StateEvent::DisplayRemoved(_,),) => {},
+					_ => continue,
+				},
+				_ = shutdown_rx.recv() => return Ok(()),
+			}
 
-			let mut daemon_guard = daemon.write().await;
-			if let Err(e,) = daemon_guard.detect_and_setup_displays().await {
+			if let Err(e,) = daemon.detect_and_setup_displays().await {
 				error!("❌ Display detection failed: {}", e);
 			}
+
+			// Each bar checks itself and re-provisions if sketchybar came back empty
+			let displays = daemon.displays.read().await.clone();
+			daemon.bars.heartbeat(&displays, &daemon.reloader.registry(),);
 		}
 	}
 }
@@ -187,23 +390,143 @@ impl Clone for SketchyBarDaemon {
 		Self {
 			displays:    self.displays.clone(),
 			bars:        self.bars.clone(),
+			processes:   self.processes.clone(),
+			reloader:    self.reloader.clone(),
 			state:       self.state.clone(),
+			policy:      self.policy.clone(),
+			log_level:   self.log_level.clone(),
 			shutdown_tx: self.shutdown_tx.clone(),
 		}
 	}
 }
 
+const USAGE: &str = "\
+Usage: sketchybar-daemon [render [fixture.json | -] | check-config [config.toml]
+                         | config init [config.toml | -] | config schema | ctl <command>]
+
+Without arguments, runs the daemon. With render (or --dry-run), prints the
+commands the daemon would send for a desktop described in JSON, without
+running sketchybar or a window manager. With check-config, reports what is
+wrong with a config file, and fails if anything is. With config init, writes
+a commented config with the built-in values; config schema prints the JSON
+Schema of the config file. With ctl, sends one command to a running daemon:
+";
+
+/// The items of `config`, reporting the sketchybar events they subscribe to
+/// back to the daemon
+fn item_registry(config: &Config,) -> Result<ItemRegistry,> {
+	Ok(ItemRegistry::from_config(config,)?.forward_events_to(&listener::default_socket_path(),),)
+}
+
 #[tokio::main]
 async fn main() -> Result<(),> {
-	// Initialize tracing
-	tracing_subscriber::fmt()
+	let args: Vec<String,> = std::env::args().skip(1,).collect();
+	match args.first().map(String::as_str,) {
+		None => run_daemon().await,
+		Some("render" | "--dry-run",) => render(args.get(1,),).await,
+		Some("check-config",) => check_config(args.get(1,),),
+		Some("config",) => config(&args[1..],),
+		Some("ctl",) => ctl(&args[1..],).await,
+		Some("-h" | "--help" | "help",) => {
+			print!("{}{}", USAGE, control::CTL_USAGE);
+			Ok((),)
+		},
+		Some(other,) => {
+			bail!("Unknown argument {:?}\n\n{}{}", other, USAGE, control::CTL_USAGE.trim_end())
+		},
+	}
+}
+
+async fn run_daemon() -> Result<(),> {
+	// Initialize tracing, with a filter `ctl set-log-level` can replace
+	let builder = tracing_subscriber::fmt()
 		.with_env_filter(
 			tracing_subscriber::EnvFilter::try_from_default_env()
 				.unwrap_or_else(|_| "info".into(),),
 		)
-		.init();
+		.with_filter_reloading();
+	let filter = builder.reload_handle();
+	builder.init();
+	let log_level: LogLevelSetter = Arc::new(move |level| {
+		filter.reload(tracing_subscriber::EnvFilter::try_new(level,)?,)?;
+		Ok((),)
+	},);
 
 	// Create and run daemon
-	let mut daemon = SketchyBarDaemon::new();
+	let config = Config::load_or_default(&config::default_path(),)?;
+	let daemon = SketchyBarDaemon::new(
+		LaunchConfig::from_env()?,
+		RestartPolicy::from_env()?,
+		WindowManager::from_env()?,
+	)
+	.with_config(&config,)?
+	.with_log_level_setter(log_level,);
 	daemon.run().await
 }
+
+/// Print the command stream for the fixture at `path`, or for one empty
+/// builtin display without one
+async fn render(path: Option<&String,>,) -> Result<(),> {
+	let fixture = match path {
+		Some(path,) => RenderFixture::load(Path::new(path,),)?,
+		None => RenderFixture::default(),
+	};
+	let config = Config::load_or_default(&config::default_path(),)?;
+	for sent in render::render(&fixture, &item_registry(&config,)?,).await? {
+		println!("sketchybar --name {} {}", sent.bar_name, sent.message);
+	}
+	Ok((),)
+}
+
+/// Load the config at `path`, or the default one, and fail with its problems
+fn check_config(path: Option<&String,>,) -> Result<(),> {
+	let path = path.map_or_else(config::default_path, PathBuf::from,);
+	let problems = config::check::problems(&Config::load(&path,)?,);
+	if !problems.is_empty() {
+		bail!("{} has {} problem(s):\n  {}", path.display(), problems.len(), problems.join("\n  "));
+	}
+	println!("✅ {} is valid", path.display());
+	Ok((),)
+}
+
+/// Write a config file with the built-in values, or print its schema
+fn config(args: &[String],) -> Result<(),> {
+	match (args.first().map(String::as_str,), args.get(1,),) {
+		(Some("init",), Some(path,),) if path == "-" => {
+			print!("{}", Config::template());
+			Ok((),)
+		},
+		(Some("init",), path,) => {
+			let path = path.map_or_else(config::default_path, PathBuf::from,);
+			if path.exists() {
+				bail!("{} already exists, not overwriting it", path.display());
+			}
+			if let Some(parent,) = path.parent() {
+				std::fs::create_dir_all(parent,)
+					.with_context(|| format!("Failed to create {}", parent.display()),)?;
+			}
+			std::fs::write(&path, Config::template(),)
+				.with_context(|| format!("Failed to write {}", path.display()),)?;
+			println!("📝 Wrote {}", path.display());
+			Ok((),)
+		},
+		(Some("schema",), None,) => {
+			println!("{}", serde_json::to_string_pretty(&Config::schema(),)?);
+			Ok((),)
+		},
+		_ => bail!("Usage: sketchybar-daemon config init [config.toml | -] | config schema"),
+	}
+}
+
+/// Send one command to the running daemon and print its answer
+async fn ctl(args: &[String],) -> Result<(),> {
+	let request = ControlRequest::from_args(args,)?;
+	let response = control::request(&control::default_socket_path(), &request,).await?;
+	if !response.ok {
+		return Err(anyhow!(response.error.unwrap_or_else(|| "Request failed".to_string())),);
+	}
+	if !response.result.is_null() {
+		println!("{}", serde_json::to_string_pretty(&response.result,)?);
+	}
+	Ok((),)
+}
@@ -0,0 +1,390 @@
+use anyhow::Result;
+use anyhow::bail;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::process::Command;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tokio::time::sleep;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+use crate::helpers::yabai::DisplayInfo;
+use crate::supervisor::RestartPolicy;
+
+/// Which bars get a sketchybar process managed by the daemon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default,)]
+pub enum SpawnPolicy {
+	/// Every bar is started by hand or by `brew services`
+	#[default]
+	Never,
+	/// Only `external_{index}` bars, leaving the builtin one to `brew services`
+	External,
+	/// Every bar, including the builtin one
+	All,
+}
+
+impl FromStr for SpawnPolicy {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str,) -> Result<Self,> {
+		match s {
+			"never" | "" => Ok(SpawnPolicy::Never,),
+			"external" => Ok(SpawnPolicy::External,),
+			"all" => Ok(SpawnPolicy::All,),
+			other => bail!("Unknown spawn policy {:?}, expected never, external or all", other),
+		}
+	}
+}
+
+/// How sketchybar instances are launched
+#[derive(Debug, Clone, PartialEq,)]
+pub struct LaunchConfig {
+	pub policy:        SpawnPolicy,
+	/// The sketchybar binary, also used as the client by
+	/// [`CliTransport`](crate::sketchybar::CliTransport)
+	pub binary:        PathBuf,
+	/// Extra arguments after `--name <bar>`, e.g. `--config <file>`
+	pub args:          Vec<String,>,
+	/// Backoff before restarting an instance that exited; its escalation
+	/// limit is not used, instances are restarted until stopped
+	pub restart:       RestartPolicy,
+}
+
+impl Default for LaunchConfig {
+	fn default() -> Self {
+		Self {
+			policy:        SpawnPolicy::Never,
+			binary:        PathBuf::from("sketchybar",),
+			args:          Vec::new(),
+			restart:       RestartPolicy {
+				initial_backoff: Duration::from_secs(2,),
+				..Default::default()
+			},
+		}
+	}
+}
+
+impl LaunchConfig {
+	/// Read `SKETCHYBAR_DAEMON_SPAWN`, `SKETCHYBAR_DAEMON_BINARY` and
+	/// `SKETCHYBAR_DAEMON_ARGS` (whitespace separated)
+	pub fn from_env() -> Result<Self,> {
+		Self::from_vars(|key| std::env::var(key,).ok(),)
+	}
+
+	fn from_vars(var: impl Fn(&str,) -> Option<String,>,) -> Result<Self,> {
+		let mut config = Self::default();
+		if let Some(policy,) = var("SKETCHYBAR_DAEMON_SPAWN",) {
+			config.policy = policy.parse()?;
+		}
+		if let Some(binary,) = var("SKETCHYBAR_DAEMON_BINARY",) {
+			config.binary = PathBuf::from(binary,);
+		}
+		if let Some(args,) = var("SKETCHYBAR_DAEMON_ARGS",) {
+			config.args = args.split_whitespace().map(String::from,).collect();
+		}
+		Ok(config,)
+	}
+
+	/// Whether the daemon owns the sketchybar process for this display
+	pub fn spawns(&self, display_info: &DisplayInfo,) -> bool {
+		match self.policy {
+			SpawnPolicy::Never => false,
+			SpawnPolicy::External => !display_info.is_builtin,
+			SpawnPolicy::All => true,
+		}
+	}
+
+	/// The command that starts the instance for `bar_name`
+	pub fn command(&self, bar_name: &str,) -> Command {
+		let mut command = Command::new(&self.binary,);
+		command.arg("--name",).arg(bar_name,).args(&self.args,).kill_on_drop(true,);
+		command
+	}
+}
+
+/// What the supervisor knows about one instance
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq,)]
+pub struct InstanceStatus {
+	/// Process id while the instance is running
+	pub pid:      Option<u32,>,
+	/// How many times the instance exited and was started again
+	pub restarts: u32,
+}
+
+#[derive(Debug,)]
+struct Instance {
+	stop_tx: oneshot::Sender<(),>,
+	task:    JoinHandle<(),>,
+	status:  Arc<Mutex<InstanceStatus,>,>,
+}
+
+/// Spawns, supervises and tears down one sketchybar process per bar
+#[derive(Debug, Default,)]
+pub struct BarProcesses {
+	config:    LaunchConfig,
+	instances: HashMap<String, Instance,>,
+}
+
+impl BarProcesses {
+	pub fn new(config: LaunchConfig,) -> Self {
+		Self { config, instances: HashMap::new(), }
+	}
+
+	pub fn config(&self,) -> &LaunchConfig {
+		&self.config
+	}
+
+	/// Start supervising `bar_name` if the policy covers its display
+	///
+	/// Returns whether a new instance was started.
+	pub fn ensure(&mut self, bar_name: &str, display_info: &DisplayInfo,) -> bool {
+		if !self.config.spawns(display_info,) || self.instances.contains_key(bar_name,) {
+			return false;
+		}
+
+		let (stop_tx, stop_rx,) = oneshot::channel();
+		let status = Arc::new(Mutex::new(InstanceStatus::default(),),);
+		let task = tokio::spawn(supervise(
+			bar_name.to_string(),
+			self.config.clone(),
+			status.clone(),
+			stop_rx,
+		),);
+
+		self.instances.insert(bar_name.to_string(), Instance { stop_tx, task, status, },);
+		true
+	}
+
+	/// Kill the instance for `bar_name` and stop restarting it
+	pub async fn stop(&mut self, bar_name: &str,) {
+		let Some(instance,) = self.instances.remove(bar_name,) else { return };
+		let _ = instance.stop_tx.send((),);
+		if let Err(e,) = instance.task.await {
+			error!("❌ Supervisor for bar '{}' failed: {}", bar_name, e);
+		}
+	}
+
+	/// Kill every instance, e.g. on shutdown
+	pub async fn stop_all(&mut self,) {
+		let names: Vec<String,> = self.instances.keys().cloned().collect();
+		for name in names {
+			self.stop(&name,).await;
+		}
+	}
+
+	pub fn status(&self, bar_name: &str,) -> Option<InstanceStatus,> {
+		self.instances.get(bar_name,).map(|instance| *instance.status.lock().unwrap(),)
+	}
+}
+
+/// Keep one instance running until told to stop
+async fn supervise(
+	bar_name: String,
+	config: LaunchConfig,
+	status: Arc<Mutex<InstanceStatus,>,>,
+	mut stop_rx: oneshot::Receiver<(),>,
+) {
+	let mut failures = 0;
+	loop {
+		let started = Instant::now();
+		match config.command(&bar_name,).spawn() {
+			Ok(mut child,) => {
+				status.lock().unwrap().pid = child.id();
+				info!("🚀 Started sketchybar '{}' (pid {:?})", bar_name, child.id());
+
+				tokio::select! {
+					result = child.wait() => match result {
+						Ok(exit) => warn!("💥 sketchybar '{}' exited with {}", bar_name, exit),
+						Err(e) => error!("❌ Lost track of sketchybar '{}': {}", bar_name, e),
+					},
+					_ = &mut stop_rx => {
+						if let Err(e) = child.kill().await {
+							warn!("⚠️  Failed to kill sketchybar '{}': {}", bar_name, e);
+						}
+						info!("🛑 Stopped sketchybar '{}'", bar_name);
+						return;
+					}
+				}
+			},
+			Err(e,) => {
+				let binary = config.binary.display();
+				error!("❌ Failed to start {} for bar '{}': {}", binary, bar_name, e)
+			},
+		}
+
+		status.lock().unwrap().pid = None;
+		if started.elapsed() >= config.restart.healthy_after {
+			failures = 0;
+		}
+		failures += 1;
+
+		let backoff = config.restart.backoff(failures,);
+		warn!("🔁 Restarting sketchybar '{}' in {:?}", bar_name, backoff);
+		tokio::select! {
+			_ = sleep(backoff) => {}
+			_ = &mut stop_rx => return,
+		}
+		status.lock().unwrap().restarts += 1;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::helpers::yabai::DisplayFrame;
+	use std::os::unix::fs::PermissionsExt;
+	use std::path::Path;
+
+	fn display(index: u32, is_builtin: bool,) -> DisplayInfo {
+		let frame = DisplayFrame { x: 0.0, y: 0.0, w: 1920.0, h: 1080.0, };
+		DisplayInfo { index, is_builtin, frame, }
+	}
+
+	/// Write an executable stand-in for sketchybar that logs its arguments
+	fn stand_in(test: &str, body: &str,) -> (PathBuf, PathBuf,) {
+		let dir = std::env::temp_dir().join(format!("sbd-{}-{}", test, std::process::id()),);
+		std::fs::create_dir_all(&dir,).unwrap();
+		let log = dir.join("log",);
+		let _ = std::fs::remove_file(&log,);
+
+		let binary = dir.join("sketchybar",);
+		let script = format!("#!/bin/sh\necho \"$$ $@\" >> '{}'\n{}\n", log.display(), body);
+		std::fs::write(&binary, script,).unwrap();
+		std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755,),).unwrap();
+		(binary, log,)
+	}
+
+	async fn wait_for_lines(log: &Path, lines: usize,) -> Vec<String,> {
+		for _ in 0..200 {
+			let content = std::fs::read_to_string(log,).unwrap_or_default();
+			if content.lines().count() >= lines {
+				return content.lines().map(String::from,).collect();
+			}
+			sleep(Duration::from_millis(25,),).await;
+		}
+		panic!("stand-in logged fewer than {} lines", lines);
+	}
+
+	/// Restart after `initial`, doubling with every exit
+	fn backoff(initial: Duration,) -> RestartPolicy {
+		RestartPolicy { initial_backoff: initial, ..Default::default() }
+	}
+
+	fn is_alive(pid: &str,) -> bool {
+		std::process::Command::new("kill",)
+			.args(["-0", pid,],)
+			.status()
+			.map(|status| status.success(),)
+			.unwrap_or(false,)
+	}
+
+	#[test]
+	fn test_launch_config_from_vars() {
+		let vars = HashMap::from([
+			("SKETCHYBAR_DAEMON_SPAWN", "external",),
+			("SKETCHYBAR_DAEMON_BINARY", "/opt/homebrew/bin/sketchybar",),
+			("SKETCHYBAR_DAEMON_ARGS", "--config  /tmp/rc",),
+		],);
+		let config =
+			LaunchConfig::from_vars(|key| vars.get(key,).map(|v| v.to_string(),),).unwrap();
+
+		assert_eq!(config.policy, SpawnPolicy::External);
+		assert_eq!(config.binary, PathBuf::from("/opt/homebrew/bin/sketchybar"));
+		assert_eq!(config.args, vec!["--config", "/tmp/rc"]);
+		assert!(config.spawns(&display(2, false)));
+		assert!(!config.spawns(&display(1, true)));
+
+		assert_eq!(LaunchConfig::from_vars(|_| None).unwrap(), LaunchConfig::default());
+		assert!(LaunchConfig::from_vars(|_| Some("sometimes".to_string())).is_err());
+	}
+
+	#[tokio::test]
+	async fn test_spawns_only_covered_bars() {
+		let (binary, log,) = stand_in("spawn", "exec sleep 30",);
+		let config = LaunchConfig {
+			policy: SpawnPolicy::External,
+			binary,
+			args: vec!["--config".to_string(), "/tmp/rc".to_string()],
+			..Default::default()
+		};
+		let mut processes = BarProcesses::new(config,);
+
+		assert!(!processes.ensure("sketchybar", &display(1, true)));
+		assert!(processes.ensure("external_2", &display(2, false)));
+		assert!(!processes.ensure("external_2", &display(2, false)));
+
+		let lines = wait_for_lines(&log, 1,).await;
+		assert!(lines[0].ends_with("--name external_2 --config /tmp/rc"));
+		assert!(processes.status("sketchybar").is_none());
+
+		processes.stop_all().await;
+	}
+
+	#[tokio::test]
+	async fn test_restarts_exited_instance() {
+		let (binary, log,) = stand_in("restart", "exit 1",);
+		let config = LaunchConfig {
+			policy: SpawnPolicy::All,
+			binary,
+			restart: backoff(Duration::from_millis(10,),),
+			..Default::default()
+		};
+		let mut processes = BarProcesses::new(config,);
+		processes.ensure("sketchybar", &display(1, true),);
+
+		wait_for_lines(&log, 3,).await;
+		assert!(processes.status("sketchybar").unwrap().restarts >= 2);
+
+		processes.stop("sketchybar",).await;
+		assert!(processes.status("sketchybar").is_none());
+	}
+
+	#[tokio::test]
+	async fn test_restarts_back_off() {
+		let (binary, log,) = stand_in("backoff", "exit 1",);
+		let config = LaunchConfig {
+			policy: SpawnPolicy::All,
+			binary,
+			restart: backoff(Duration::from_millis(100,),),
+			..Default::default()
+		};
+		let mut processes = BarProcesses::new(config,);
+		processes.ensure("sketchybar", &display(1, true),);
+
+		// When each start shows up in the log
+		let mut starts = Vec::new();
+		for lines in 1..=4 {
+			wait_for_lines(&log, lines,).await;
+			starts.push(Instant::now(),);
+		}
+		processes.stop("sketchybar",).await;
+
+		let gaps: Vec<Duration,> = starts.windows(2,).map(|pair| pair[1] - pair[0],).collect();
+		assert!(gaps[0] < gaps[1] && gaps[1] < gaps[2], "restarts did not back off: {:?}", gaps);
+		assert!(gaps[2] >= Duration::from_millis(350,), "{:?}", gaps);
+	}
+
+	#[tokio::test]
+	async fn test_stop_kills_instance() {
+		let (binary, log,) = stand_in("stop", "exec sleep 30",);
+		let config = LaunchConfig { policy: SpawnPolicy::All, binary, ..Default::default() };
+		let mut processes = BarProcesses::new(config,);
+		processes.ensure("external_3", &display(3, false),);
+
+		let lines = wait_for_lines(&log, 1,).await;
+		let pid = lines[0].split(' ',).next().unwrap().to_string();
+		assert!(is_alive(&pid));
+		let status = processes.status("external_3",).unwrap();
+		assert_eq!(status.pid.map(|p| p.to_string()), Some(pid.clone()));
+
+		processes.stop("external_3",).await;
+		assert!(!is_alive(&pid));
+	}
+}
@@ -0,0 +1,164 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use tracing::info;
+use tracing::warn;
+
+use crate::config;
+use crate::helpers::yabai::DisplayInfo;
+use crate::items;
+use crate::items::ItemRegistry;
+use crate::items::reconcile;
+use crate::items::reconcile::MANIFEST_ITEM;
+use crate::sketchybar::ItemInfo;
+use crate::sketchybar::QueryTarget;
+use crate::sketchybar::SketchyBar;
+use crate::sketchybar::query;
+
+/// Run the full setup for one bar: bar and default properties, then every
+/// registered item
+///
+/// The daemon watches its own config, so sketchybar's `--hotload` is left
+/// off: it only reloads `sketchybarrc`, which never runs under the daemon.
+pub async fn provision_bar(
+	bar: &mut SketchyBar,
+	display_info: &DisplayInfo,
+	registry: &ItemRegistry,
+) -> Result<(),> {
+	let bar_name = bar.get_bar_name().to_string();
+
+	// Configure the bar
+	config::setup_bar(bar, &bar_name, display_info, registry.style(),).await?;
+
+	// Add all items
+	items::setup_all_items(bar, display_info, registry,).await?;
+
+	Ok((),)
+}
+
+/// [`provision_bar`] after the default properties changed
+///
+/// `--default` only reaches items added after it, so the items already on
+/// the bar are set to the new defaults explicitly.
+pub async fn restyle_bar(
+	bar: &mut SketchyBar,
+	display_info: &DisplayInfo,
+	registry: &ItemRegistry,
+) -> Result<(),> {
+	let bar_name = bar.get_bar_name().to_string();
+	config::setup_bar(bar, &bar_name, display_info, registry.style(),).await?;
+
+	let report = reconcile::reconcile(bar, &registry.restyled_items(display_info,),).await?;
+	info!("🎨 Restyled {} items on bar '{}'", report.updated.len(), bar_name);
+	Ok((),)
+}
+
+/// What a heartbeat found on a bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum BarStatus {
+	/// The bar answers and still holds our items
+	Provisioned,
+	/// The bar answers but our items are gone: sketchybar restarted or reloaded
+	Missing,
+	/// Messages to the bar fail: sketchybar is not running
+	Unreachable,
+}
+
+/// Heartbeat a bar by querying the item manifest written on every setup
+///
+/// A restarted or reloaded sketchybar comes back empty, so a missing
+/// manifest is enough to tell it apart from the instance we provisioned,
+/// without tracking process ids.
+pub async fn probe(bar: &SketchyBar,) -> BarStatus {
+	let target = QueryTarget::Item(MANIFEST_ITEM.to_string(),);
+	match bar.query(target,).await {
+		Err(_,) => BarStatus::Unreachable,
+		Ok(response,) => match query::parse::<ItemInfo,>(MANIFEST_ITEM, &response,) {
+			Ok(info,) if info.name == MANIFEST_ITEM => BarStatus::Provisioned,
+			_ => BarStatus::Missing,
+		},
+	}
+}
+
+/// Remembers the last heartbeat of every bar and decides when to re-provision
+#[derive(Debug, Default,)]
+pub struct BarWatchdog {
+	statuses: HashMap<String, BarStatus,>,
+}
+
+impl BarWatchdog {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Last status seen for `bar_name`
+	pub fn status(&self, bar_name: &str,) -> Option<BarStatus,> {
+		self.statuses.get(bar_name,).copied()
+	}
+
+	/// Heartbeat `bar`, returning whether it needs a full setup
+	pub async fn needs_provisioning(&mut self, bar: &SketchyBar,) -> bool {
+		let bar_name = bar.get_bar_name().to_string();
+		let status = probe(bar,).await;
+		let previous = self.statuses.insert(bar_name.clone(), status,);
+
+		match status {
+			BarStatus::Provisioned => {
+				if previous.is_some_and(|p| p != BarStatus::Provisioned,) {
+					info!("💚 Bar '{}' is healthy again", bar_name);
+				}
+				false
+			},
+			BarStatus::Missing => {
+				info!("🔄 Bar '{}' lost its items, re-provisioning", bar_name);
+				true
+			},
+			BarStatus::Unreachable => {
+				if previous != Some(BarStatus::Unreachable,) {
+					warn!("💔 Bar '{}' is unreachable, waiting for it to come back", bar_name);
+				}
+				false
+			},
+		}
+	}
+
+	/// Remember `status` for `bar_name`, e.g. right after provisioning it
+	pub fn record(&mut self, bar_name: &str, status: BarStatus,) {
+		self.statuses.insert(bar_name.to_string(), status,);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::helpers::yabai::DisplayFrame;
+	use crate::sketchybar::RecordingTransport;
+	use std::sync::Arc;
+
+	fn display() -> DisplayInfo {
+		DisplayInfo {
+			index:      1,
+			is_builtin: true,
+			frame:      DisplayFrame { x: 0.0, y: 0.0, w: 1920.0, h: 1080.0, },
+		}
+	}
+
+	fn recording_bar() -> (SketchyBar, RecordingTransport,) {
+		let transport = RecordingTransport::new();
+		let mut bar = SketchyBar::with_transport(Arc::new(transport.clone(),),);
+		bar.set_bar_name("sketchybar",);
+		(bar, transport,)
+	}
+
+	#[tokio::test]
+	async fn test_probe_reports_bar_status() -> Result<(),> {
+		let (mut bar, transport,) = recording_bar();
+
+		assert_eq!(probe(&bar).await, BarStatus::Missing);
+		provision_bar(&mut bar, &display(), &ItemRegistry::with_defaults(),).await?;
+		assert_eq!(probe(&bar).await, BarStatus::Provisioned);
+
+		transport.set_unreachable("sketchybar", true,);
+		assert_eq!(probe(&bar).await, BarStatus::Unreachable);
+		Ok((),)
+	}
+}
@@ -0,0 +1,498 @@
+use anyhow::Result;
+use anyhow::bail;
+use futures::future::join_all;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::sync::watch;
+use tokio::time::Duration;
+use tokio::time::interval;
+use tokio::time::sleep;
+use tokio::time::timeout;
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+
+use crate::bars::BarManager;
+use crate::config::Config;
+use crate::config::check;
+use crate::config::file;
+use crate::items::ItemRegistry;
+use crate::state::DaemonState;
+use crate::theme::Theme;
+
+/// How often the config file is checked for changes
+pub const CONFIG_POLL_PERIOD: Duration = Duration::from_secs(2,);
+
+/// How long a changed file must stay the same before it is read, so an
+/// editor saving in several writes is not caught halfway
+const CONFIG_SETTLE: Duration = Duration::from_millis(100,);
+
+/// How long a reload waits on one bar
+const BAR_TIMEOUT: Duration = Duration::from_secs(10,);
+
+/// The config the daemon runs on, and the items built from it
+#[derive(Debug, Clone,)]
+pub struct ActiveConfig {
+	pub config:   Config,
+	pub registry: ItemRegistry,
+}
+
+/// What changed from one config to the next
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize,)]
+pub struct ConfigDiff {
+	/// Polling intervals
+	pub intervals:  bool,
+	/// `--bar` properties
+	pub bar:        bool,
+	/// `--default` properties, which items already on a bar do not take up
+	/// by themselves
+	pub defaults:   bool,
+	/// Colors of the theme, which go into the bar and default properties as
+	/// well as every item
+	pub theme:      bool,
+	/// How the theme follows light and dark mode
+	pub appearance: bool,
+	/// Items only the new config lists
+	pub added:      Vec<String,>,
+	/// Items only the old config lists
+	pub removed:    Vec<String,>,
+	/// Items both list, configured differently
+	pub changed:    Vec<String,>,
+	/// Whether the items both list come in a different order
+	pub reordered:  bool,
+}
+
+impl ConfigDiff {
+	pub fn between(old: &Config, new: &Config,) -> Self {
+		let find = |config: &Config, name: &str| {
+			config.items.iter().find(|item| item.name == name,).cloned()
+		};
+		let only_in = |config: &Config, other: &Config| -> Vec<String,> {
+			config
+				.items
+				.iter()
+				.filter(|item| find(other, &item.name,).is_none(),)
+				.map(|item| item.name.clone(),)
+				.collect()
+		};
+		let kept = |config: &Config, other: &Config| -> Vec<String,> {
+			config
+				.items
+				.iter()
+				.filter(|item| find(other, &item.name,).is_some(),)
+				.map(|item| item.name.clone(),)
+				.collect()
+		};
+
+		Self {
+			intervals:  old.intervals != new.intervals,
+			bar:        old.bar != new.bar,
+			defaults:   old.defaults != new.defaults,
+			theme:      old.theme().ok() != new.theme().ok(),
+			appearance: old.appearance != new.appearance,
+			added:      only_in(new, old,),
+			removed:    only_in(old, new,),
+			changed:    new
+				.items
+				.iter()
+				.filter(|item| find(old, &item.name,).is_some_and(|old_item| old_item != **item,),)
+				.map(|item| item.name.clone(),)
+				.collect(),
+			reordered:  kept(old, new,) != kept(new, old,),
+		}
+	}
+
+	pub fn is_empty(&self,) -> bool {
+		*self == Self::default()
+	}
+}
+
+/// What a reload changed, and how each bar took it
+#[derive(Debug, Clone, PartialEq, Serialize,)]
+pub struct Reloaded {
+	pub changes: ConfigDiff,
+	/// Outcome by bar name, e.g. `reloaded` or `timed out`
+	pub bars:    BTreeMap<String, String,>,
+}
+
+/// A theme picked with [`ConfigReloader::switch_theme`], over the one the
+/// config file named at the time
+#[derive(Debug, Clone, PartialEq, Eq,)]
+struct ThemeSwitch {
+	file:   String,
+	chosen: String,
+}
+
+impl ThemeSwitch {
+	/// Put `config`, freshly read from the file, in the chosen theme, unless
+	/// the file names another theme by now or no longer has the chosen one
+	fn keep(switch: &mut Option<Self,>, config: &mut Config,) {
+		let Some(theme_switch,) = switch else {
+			return;
+		};
+		if theme_switch.file == config.theme
+			&& Theme::resolve(&theme_switch.chosen, &config.themes,).is_ok()
+		{
+			config.theme = theme_switch.chosen.clone();
+		} else {
+			*switch = None;
+		}
+	}
+}
+
+/// Applies the config file to the running daemon whenever it changes
+///
+/// Bars are set up again in place and [`DaemonState`] is kept; whatever runs
+/// the items follows [`Self::subscribe`]. A file that fails to load changes
+/// nothing, so the daemon keeps running on the last good config.
+#[derive(Debug, Clone,)]
+pub struct ConfigReloader {
+	path:      PathBuf,
+	state:     DaemonState,
+	bars:      BarManager,
+	active:    Arc<watch::Sender<Arc<ActiveConfig,>,>,>,
+	/// Held for the length of a reload, so two never interleave, along with
+	/// the theme switched to since the file was read
+	reloading: Arc<Mutex<Option<ThemeSwitch,>,>,>,
+}
+
+impl ConfigReloader {
+	/// Reload from `path`, starting out on `config` drawn by `registry`
+	pub fn new(
+		path: impl Into<PathBuf,>,
+		config: Config,
+		registry: ItemRegistry,
+		state: DaemonState,
+		bars: BarManager,
+	) -> Self {
+		let (active, _,) = watch::channel(Arc::new(ActiveConfig { config, registry, },),);
+		Self {
+			path: path.into(),
+			state,
+			bars,
+			active: Arc::new(active,),
+			reloading: Arc::new(Mutex::new(None,),),
+		}
+	}
+
+	pub fn path(&self,) -> &Path {
+		&self.path
+	}
+
+	pub fn active(&self,) -> Arc<ActiveConfig,> {
+		self.active.borrow().clone()
+	}
+
+	/// The items of the active config
+	pub fn registry(&self,) -> ItemRegistry {
+		self.active().registry.clone()
+	}
+
+	/// Sees every config applied from now on
+	pub fn subscribe(&self,) -> watch::Receiver<Arc<ActiveConfig,>,> {
+		self.active.subscribe()
+	}
+
+	/// Read the config file, apply what changed, and set every bar up again
+	/// even if nothing did
+	pub async fn reload(&self,) -> Result<Reloaded,> {
+		let mut switch = self.reloading.lock().await;
+		let mut config = Config::load_or_default(&self.path,)?;
+		ThemeSwitch::keep(&mut switch, &mut config,);
+		self.apply(config, true,).await
+	}
+
+	/// Color every bar with the theme called `name` until the config file
+	/// names another one
+	pub async fn switch_theme(&self, name: &str,) -> Result<Reloaded,> {
+		let mut switch = self.reloading.lock().await;
+		let mut config = self.active().config.clone();
+		let file = match switch.as_ref() {
+			Some(theme_switch,) => theme_switch.file.clone(),
+			None => config.theme.clone(),
+		};
+		config.theme = name.to_string();
+		let reloaded = self.apply(config, false,).await?;
+		*switch = (name != file).then(|| ThemeSwitch { file, chosen: name.to_string(), },);
+		Ok(reloaded,)
+	}
+
+	/// Check the config file every `period`, applying it whenever its
+	/// content changes
+	pub async fn watch(&self, period: Duration,) -> Result<(),> {
+		let mut last = self.read();
+		let mut ticks = interval(period,);
+		loop {
+			ticks.tick().await;
+			let current = self.read();
+			if current == last {
+				continue;
+			}
+			sleep(CONFIG_SETTLE,).await;
+			if self.read() != current {
+				continue;
+			}
+			last = current;
+
+			info!("📝 {} changed", self.path.display());
+			let mut switch = self.reloading.lock().await;
+			let applied = match Config::load_or_default(&self.path,) {
+				Ok(mut config,) => {
+					ThemeSwitch::keep(&mut switch, &mut config,);
+					self.apply(config, false,).await
+				},
+				Err(e,) => Err(e,),
+			};
+			if let Err(e,) = applied {
+				error!("❌ Keeping the running config: {:#}", e);
+			}
+		}
+	}
+
+	/// The config file and the theme files next to it
+	fn read(&self,) -> Option<String,> {
+		let mut sources = std::fs::read_to_string(&self.path,).ok()?;
+		for path in file::theme_files(&self.path,) {
+			sources.push_str(&std::fs::read_to_string(path,).unwrap_or_default(),);
+		}
+		Some(sources,)
+	}
+
+	/// Switch to `config` and set up the bars for it; `provision_all` sets
+	/// them up even when nothing changed
+	///
+	/// A config with any of the problems `check-config` reports is turned
+	/// down, keeping the running one.
+	async fn apply(&self, config: Config, provision_all: bool,) -> Result<Reloaded,> {
+		let problems = check::problems(&config,);
+		if !problems.is_empty() {
+			bail!("{}", problems.join("; ",));
+		}
+
+		let active = self.active();
+		let changes = ConfigDiff::between(&active.config, &config,);
+		if changes.is_empty() && !provision_all {
+			debug!("Config unchanged, nothing to apply");
+			return Ok(Reloaded { changes, bars: BTreeMap::new(), },);
+		}
+
+		// Everything that can fail on the new config fails before any of it is applied
+		let registry = if changes.is_empty() {
+			active.registry.clone()
+		} else {
+			active.registry.reconfigured(&config,)?
+		};
+
+		// Setup is queued before the new config goes out, so it runs ahead
+		// of the first render of any new item in each bar's mailbox
+		let displays = self.state.displays.read().await.clone();
+		let setups: Vec<_,> = self
+			.bars
+			.handles()
+			.into_iter()
+			.filter_map(|handle| {
+				let display_info = displays.get(handle.display_id(),)?.clone();
+				let done = if changes.defaults || changes.theme {
+					handle.restyle(display_info, registry.clone(),)
+				} else {
+					handle.provision(display_info, registry.clone(),)
+				};
+				Some((handle.bar_name().to_string(), done,),)
+			},)
+			.collect();
+
+		if !changes.is_empty() {
+			info!("📝 Applying config changes: {:?}", changes);
+			self.active.send_replace(Arc::new(ActiveConfig { config, registry, },),);
+		}
+
+		let outcomes = join_all(setups.into_iter().map(|(bar_name, done,)| async move {
+			let outcome = match timeout(BAR_TIMEOUT, done,).await {
+				Ok(Ok(Ok(_,),),) => "reloaded".to_string(),
+				Ok(Ok(Err(e,),),) => format!("failed: {}", e),
+				Ok(Err(_,),) => "not taking commands".to_string(),
+				Err(_,) => "timed out".to_string(),
+			};
+			(bar_name, outcome,)
+		},),)
+		.await;
+		let bars: BTreeMap<String, String,> = outcomes.into_iter().collect();
+		info!("🔄 Reloaded {} bars", bars.len());
+		Ok(Reloaded { changes, bars, },)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sketchybar::RecordingTransport;
+	use crate::sketchybar::SketchyBar;
+	use crate::wm::ScriptedBackend;
+
+	fn config_path(test: &str,) -> PathBuf {
+		std::env::temp_dir().join(format!("sbd-config-{}-{}.toml", test, std::process::id()),)
+	}
+
+	fn item_names(registry: &ItemRegistry,) -> Vec<&'static str,> {
+		registry.items().iter().map(|item| item.name(),).collect()
+	}
+
+	/// A reloader for `path` over one provisioned recording bar
+	async fn reloader(path: &Path,) -> Result<(ConfigReloader, RecordingTransport,),> {
+		let state = DaemonState::new().with_backend(Arc::new(ScriptedBackend::new(),),);
+		state.apply_displays(crate::wm::default_displays(),).await;
+		let transport = RecordingTransport::new();
+		let mut bar = SketchyBar::with_transport(Arc::new(transport.clone(),),);
+		bar.set_bar_name("sketchybar",);
+		let bars = BarManager::new(state.clone(),);
+
+		let config = Config::default();
+		let registry = ItemRegistry::from_config(&config,)?;
+		let display_info = crate::wm::default_displays()["1"].clone();
+		bars.add("1", bar,).provision(display_info, registry.clone(),).await??;
+		transport.clear();
+		Ok((ConfigReloader::new(path, config, registry, state, bars,), transport,),)
+	}
+
+	#[test]
+	fn test_diff_names_what_changed() -> Result<(),> {
+		let new = Config::parse(
+			r#"
+			[intervals]
+			state_sync = 5
+
+			[[items]]
+			name = "battery"
+
+			[[items]]
+			name = "clock"
+			properties.label.color = 0xffa6e3a1
+			"#,
+		)?;
+		let diff = ConfigDiff::between(&Config::default(), &new,);
+
+		assert!(diff.intervals);
+		assert!(!diff.bar && !diff.defaults);
+		assert!(diff.added.is_empty());
+		assert_eq!(diff.removed, ["keyboard", "spaces", "current_app", "window"]);
+		assert_eq!(diff.changed, ["clock"]);
+		assert!(diff.reordered);
+		assert!(ConfigDiff::between(&new, &new.clone()).is_empty());
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_reload_applies_changes_to_every_bar() -> Result<(),> {
+		let path = config_path("apply",);
+		std::fs::write(
+			&path,
+			"[defaults.builtin]\nblur_radius = 12\n\n[[items]]\nname = \"clock\"\n\n\
+			 [[items]]\nname = \"battery\"\n",
+		)?;
+		let (reloader, transport,) = reloader(&path,).await?;
+		let mut configs = reloader.subscribe();
+
+		let reloaded = reloader.reload().await?;
+		std::fs::remove_file(&path,)?;
+
+		assert_eq!(reloaded.bars["sketchybar"], "reloaded");
+		assert!(reloaded.changes.defaults);
+		assert_eq!(reloaded.changes.removed.len(), 4);
+		assert!(configs.has_changed()?);
+		assert_eq!(item_names(&configs.borrow_and_update().registry), ["clock", "battery"]);
+
+		// Removed items go, kept ones are restyled in place rather than added again
+		let sent = transport.sent_to("sketchybar",);
+		assert!(transport.received("sketchybar", "--remove current_app"));
+		assert!(!sent.iter().any(|m| m.starts_with("--add item clock")));
+		assert!(sent.iter().any(|m| m.starts_with("--set clock") && m.contains("blur_radius=12")));
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_invalid_config_keeps_the_running_one() -> Result<(),> {
+		let path = config_path("invalid",);
+		std::fs::write(&path, "[[items]]\nname = \"clock\"\n\n[bar.builtin]\nheigth = 30\n",)?;
+		let (reloader, transport,) = reloader(&path,).await?;
+		let configs = reloader.subscribe();
+
+		let error = format!("{:#}", reloader.reload().await.unwrap_err());
+		std::fs::remove_file(&path,)?;
+
+		assert!(error.contains("unknown field `heigth`"), "{}", error);
+		assert!(!configs.has_changed()?);
+		assert_eq!(reloader.active().config, Config::default());
+		assert!(transport.sent_to("sketchybar").is_empty());
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_config_with_problems_keeps_the_running_one() -> Result<(),> {
+		let path = config_path("problems",);
+		std::fs::write(&path, "[intervals]\nstate_sync = 0\n",)?;
+		let (reloader, transport,) = reloader(&path,).await?;
+		let configs = reloader.subscribe();
+
+		let error = format!("{:#}", reloader.reload().await.unwrap_err());
+		std::fs::remove_file(&path,)?;
+
+		assert_eq!(error, "intervals.state_sync: must be at least 1 second");
+		assert!(!configs.has_changed()?);
+		assert_eq!(reloader.active().config, Config::default());
+		assert!(transport.sent_to("sketchybar").is_empty());
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_switched_theme_lasts_until_the_file_names_another() -> Result<(),> {
+		let path = config_path("theme",);
+		std::fs::write(&path, "[[items]]\nname = \"clock\"\n",)?;
+		let (reloader, transport,) = reloader(&path,).await?;
+
+		let switched = reloader.switch_theme("latte",).await?;
+		assert!(switched.changes.theme);
+		assert_eq!(switched.bars["sketchybar"], "reloaded");
+		// Items already on the bar take up the new background and their accent
+		let sent = transport.sent_to("sketchybar",);
+		assert!(sent.iter().any(|m| m.starts_with("--default") && m.contains("color=0xffccd0da")));
+		assert!(sent.iter().any(|m| m.starts_with("--set clock") && m.contains("0xffdd7878")));
+
+		let error = format!("{:#}", reloader.switch_theme("solarized",).await.unwrap_err());
+		assert!(error.contains("unknown theme `solarized`"), "{}", error);
+
+		// Editing the file keeps the switched theme, naming another in it does not
+		reloader.reload().await?;
+		assert_eq!(reloader.active().config.theme, "latte");
+		std::fs::write(&path, "theme = \"gruvbox\"\n",)?;
+		reloader.reload().await?;
+		assert_eq!(reloader.active().config.theme, "gruvbox");
+		std::fs::write(&path, "",)?;
+		reloader.reload().await?;
+		std::fs::remove_file(&path,)?;
+		assert_eq!(reloader.active().config.theme, "mocha");
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_watch_applies_edits() -> Result<(),> {
+		let path = config_path("watch",);
+		let (reloader, _transport,) = reloader(&path,).await?;
+		let mut configs = reloader.subscribe();
+		let watching = reloader.clone();
+		let task = tokio::spawn(async move { watching.watch(Duration::from_millis(20,),).await },);
+		// The watcher takes the missing file as its starting point
+		sleep(Duration::from_millis(50,),).await;
+
+		std::fs::write(&path, "[[items]]\nname = \"clock\"\n",)?;
+		timeout(Duration::from_secs(2,), configs.changed(),).await??;
+		std::fs::remove_file(&path,)?;
+
+		assert_eq!(item_names(&configs.borrow().registry), ["clock"]);
+		task.abort();
+		Ok((),)
+	}
+}
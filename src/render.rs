@@ -0,0 +1,167 @@
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::bars;
+use crate::helpers::yabai::DisplayInfo;
+use crate::items::ItemRegistry;
+use crate::provision;
+use crate::runner::FakeRunner;
+use crate::sketchybar::RecordingTransport;
+use crate::sketchybar::SentMessage;
+use crate::sketchybar::SketchyBar;
+use crate::state::BatteryInfo;
+use crate::state::DaemonState;
+use crate::state::SpaceInfo;
+use crate::state::WindowInfo;
+use crate::wm::ScriptedBackend;
+
+/// The desktop to render against, in the shapes the daemon syncs
+///
+/// Every field is optional; without displays there is one builtin display.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize,)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderFixture {
+	pub displays:     Vec<DisplayInfo,>,
+	pub spaces:       Vec<SpaceInfo,>,
+	pub windows:      Vec<WindowInfo,>,
+	/// Focused app; follows the focused window when left out
+	pub current_app:  Option<String,>,
+	pub battery:      Option<BatteryInfo,>,
+	pub input_source: Option<String,>,
+}
+
+impl RenderFixture {
+	pub fn from_json(json: &str,) -> Result<Self,> {
+		serde_json::from_str(json,).context("Invalid render fixture",)
+	}
+
+	/// Read a fixture from `path`, or from stdin for `-`
+	pub fn load(path: &Path,) -> Result<Self,> {
+		let json = if path == Path::new("-",) {
+			std::io::read_to_string(std::io::stdin(),).context("Failed to read stdin",)?
+		} else {
+			std::fs::read_to_string(path,)
+				.with_context(|| format!("Failed to read {}", path.display()),)?
+		};
+		Self::from_json(&json,)
+	}
+
+	/// State synced from the fixture the same way the daemon syncs from a
+	/// window manager; probes find no commands to run
+	async fn state(&self,) -> Result<DaemonState,> {
+		let backend = ScriptedBackend::new();
+		if !self.displays.is_empty() {
+			backend.set_displays(self.displays.clone(),);
+		}
+		backend.set_spaces(self.spaces.clone(),);
+		backend.set_windows(self.windows.clone(),);
+		if let Some(app,) = &self.current_app {
+			backend.set_focused_app(app,);
+		}
+
+		let state = DaemonState::with_runner(Arc::new(FakeRunner::new(),),)
+			.with_backend(Arc::new(backend,),);
+		state.update_displays().await?;
+		state.update_spaces().await?;
+		state.update_windows().await?;
+		// Nothing focused leaves the app unset, as it does in the daemon
+		if self.current_app.is_some() || self.windows.iter().any(|w| w.has_focus,) {
+			state.update_current_app().await?;
+		}
+		if let Some(battery,) = &self.battery {
+			state.apply_battery(battery.clone(),).await;
+		}
+		if let Some(source,) = &self.input_source {
+			state.apply_input_source(source.clone(),).await;
+		}
+		Ok(state,)
+	}
+}
+
+/// Everything the daemon would send for `fixture`: each bar's setup, then
+/// one render of every item, bar by bar in display order
+pub async fn render(
+	fixture: &RenderFixture,
+	registry: &ItemRegistry,
+) -> Result<Vec<SentMessage,>,> {
+	let state = fixture.state().await?;
+	let mut displays: Vec<DisplayInfo,> = state.displays.read().await.values().cloned().collect();
+	displays.sort_by_key(|d| d.index,);
+
+	let transport = RecordingTransport::new();
+	for display_info in &displays {
+		let mut bar = SketchyBar::with_transport(Arc::new(transport.clone(),),);
+		bar.set_bar_name(&bars::bar_name(display_info,),);
+
+		provision::provision_bar(&mut bar, display_info, registry,).await?;
+		for item in registry.items().iter().filter(|item| item.applies_to(display_info,),) {
+			item.render(&bar, &state,).await?;
+		}
+	}
+	Ok(transport.sent(),)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn messages(sent: &[SentMessage], bar_name: &str,) -> Vec<String,> {
+		sent.iter().filter(|m| m.bar_name == bar_name,).map(|m| m.message.clone(),).collect()
+	}
+
+	#[tokio::test]
+	async fn test_default_fixture_renders_one_bar() -> Result<(),> {
+		let sent = render(&RenderFixture::default(), &ItemRegistry::with_defaults(),).await?;
+
+		let builtin = messages(&sent, "sketchybar",);
+		assert_eq!(builtin.len(), sent.len());
+		assert!(builtin[0].starts_with("--bar "));
+		assert!(builtin.iter().any(|m| m == "--add item clock right"));
+		// Nothing is focused, so the app keeps its placeholder
+		assert!(!builtin.iter().any(|m| m.starts_with("--set current_app label=")));
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_fixture_drives_every_bar() -> Result<(),> {
+		let fixture = RenderFixture::from_json(
+			r#"{
+				"displays": [
+					{"index": 2, "is_builtin": false,
+					 "frame": {"x": 1920.0, "y": 0.0, "w": 2560.0, "h": 1440.0}},
+					{"index": 1, "is_builtin": true,
+					 "frame": {"x": 0.0, "y": 0.0, "w": 1920.0, "h": 1080.0}}
+				],
+				"spaces": [
+					{"index": 1, "display": 1, "has_focus": true, "windows": [7], "label": ""}
+				],
+				"windows": [
+					{"id": 7, "app": "Safari", "title": "Docs", "space": 1, "display": 1,
+					 "has_focus": true}
+				],
+				"battery": {"charge": 80, "charging": false},
+				"input_source": "US"
+			}"#,
+		)?;
+		let sent = render(&fixture, &ItemRegistry::with_defaults(),).await?;
+
+		// Bars are set up in display order, each before the next
+		assert_eq!(sent.first().map(|m| m.bar_name.as_str()), Some("sketchybar"));
+		assert_eq!(sent.last().map(|m| m.bar_name.as_str()), Some("external_2"));
+
+		let builtin = messages(&sent, "sketchybar",);
+		assert!(builtin.iter().any(|m| m.starts_with("--set battery") && m.contains("label=80")));
+		assert!(builtin.iter().any(|m| m.starts_with("--set current_app label=Safari")));
+		assert!(!messages(&sent, "external_2").iter().any(|m| m.contains("battery")));
+		Ok((),)
+	}
+
+	#[test]
+	fn test_fixture_rejects_unknown_keys() {
+		let err = RenderFixture::from_json(r#"{"display": []}"#,).unwrap_err();
+		assert!(format!("{:#}", err).contains("unknown field `display`"));
+	}
+}
@@ -0,0 +1,359 @@
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tokio::time::Duration;
+use tokio::time::sleep;
+use tokio::time::timeout;
+use tracing::debug;
+
+/// How long an external command may take before it is killed
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5,);
+
+/// External commands the daemon runs at once; the rest wait their turn
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Why an external command gave no usable output
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub enum RunError {
+	/// The program is not installed, or not on the `PATH`
+	NotFound { program: String, },
+	/// The program ran but exited unsuccessfully
+	Failed { program: String, code: Option<i32,>, stderr: String, },
+	/// The program did not finish in time and was killed
+	TimedOut { program: String, timeout: Duration, },
+	/// The program's output could not be understood
+	Parse { program: String, message: String, },
+	/// The program could not be started for any other reason
+	Io { program: String, message: String, },
+}
+
+impl Display for RunError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		match self {
+			RunError::NotFound { program, } => write!(f, "{} is not installed", program),
+			RunError::Failed { program, code: Some(code,), stderr, } => {
+				write!(f, "{} exited with status {}: {}", program, code, stderr)
+			},
+			RunError::Failed { program, code: None, stderr, } => {
+				write!(f, "{} was killed by a signal: {}", program, stderr)
+			},
+			RunError::TimedOut { program, timeout, } => {
+				write!(f, "{} did not finish within {:?}", program, timeout)
+			},
+			RunError::Parse { program, message, } => {
+				write!(f, "Could not parse {} output: {}", program, message)
+			},
+			RunError::Io { program, message, } => {
+				write!(f, "Failed to run {}: {}", program, message)
+			},
+		}
+	}
+}
+
+impl std::error::Error for RunError {}
+
+/// An external command, with its arguments and how long it may run
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct Invocation {
+	pub program: String,
+	pub args:    Vec<String,>,
+	pub timeout: Duration,
+}
+
+impl Invocation {
+	pub fn new(program: &str, args: &[&str],) -> Self {
+		Self {
+			program: program.to_string(),
+			args:    args.iter().map(|arg| arg.to_string(),).collect(),
+			timeout: DEFAULT_TIMEOUT,
+		}
+	}
+
+	/// Kill the command if it runs longer than `timeout`
+	pub fn timeout(mut self, timeout: Duration,) -> Self {
+		self.timeout = timeout;
+		self
+	}
+
+	/// Program and arguments separated by spaces, as [`FakeRunner`] matches them
+	pub fn command_line(&self,) -> String {
+		std::iter::once(&self.program,).chain(&self.args,).cloned().collect::<Vec<_,>>().join(" ",)
+	}
+
+	/// A [`RunError::Parse`] for this command
+	pub fn parse_error(&self, message: impl Display,) -> RunError {
+		RunError::Parse { program: self.program.clone(), message: message.to_string(), }
+	}
+
+	/// Run through `runner` and return stdout
+	pub async fn output(&self, runner: &dyn Runner,) -> Result<String, RunError,> {
+		runner.run(self,).await
+	}
+
+	/// Run through `runner` and deserialize stdout as JSON
+	pub async fn json<T: DeserializeOwned,>(&self, runner: &dyn Runner,) -> Result<T, RunError,> {
+		let stdout = runner.run(self,).await?;
+		serde_json::from_str(&stdout,).map_err(|e| self.parse_error(e,),)
+	}
+}
+
+/// Runs external commands for the daemon's probes
+#[async_trait]
+pub trait Runner: Debug + Send + Sync {
+	/// Run `invocation` to completion and return its stdout
+	async fn run(&self, invocation: &Invocation,) -> Result<String, RunError,>;
+}
+
+/// Runner backed by real processes on the tokio runtime
+///
+/// Clones share one concurrency limit. The timeout covers waiting for a turn
+/// as well as running, so callers get an answer within it either way.
+#[derive(Debug, Clone,)]
+pub struct ProcessRunner {
+	permits: Arc<Semaphore,>,
+}
+
+impl Default for ProcessRunner {
+	fn default() -> Self {
+		Self::new(DEFAULT_CONCURRENCY,)
+	}
+}
+
+impl ProcessRunner {
+	pub fn new(concurrency: usize,) -> Self {
+		Self { permits: Arc::new(Semaphore::new(concurrency.max(1,),),), }
+	}
+
+	async fn output(&self, invocation: &Invocation,) -> Result<String, RunError,> {
+		let program = invocation.program.clone();
+		let _permit = self.permits.acquire().await.map_err(|e| RunError::Io {
+			program: program.clone(),
+			message: e.to_string(),
+		},)?;
+
+		let output = Command::new(&invocation.program,)
+			.args(&invocation.args,)
+			.stdin(Stdio::null(),)
+			.kill_on_drop(true,)
+			.output()
+			.await
+			.map_err(|e| match e.kind() {
+				std::io::ErrorKind::NotFound => RunError::NotFound { program: program.clone(), },
+				_ => RunError::Io { program: program.clone(), message: e.to_string(), },
+			},)?;
+
+		if !output.status.success() {
+			return Err(RunError::Failed {
+				program,
+				code: output.status.code(),
+				stderr: String::from_utf8_lossy(&output.stderr,).trim().to_string(),
+			},);
+		}
+		String::from_utf8(output.stdout,).map_err(|e| invocation.parse_error(e,),)
+	}
+}
+
+#[async_trait]
+impl Runner for ProcessRunner {
+	async fn run(&self, invocation: &Invocation,) -> Result<String, RunError,> {
+		debug!("🏃 Running {}", invocation.command_line());
+		// Dropping the output future kills the child
+		timeout(invocation.timeout, self.output(invocation,),).await.unwrap_or_else(|_| {
+			Err(RunError::TimedOut {
+				program: invocation.program.clone(),
+				timeout: invocation.timeout,
+			},)
+		},)
+	}
+}
+
+/// The runner shared by the whole daemon, so its concurrency limit holds across probes
+pub fn system() -> Arc<dyn Runner,> {
+	static SYSTEM: OnceLock<Arc<dyn Runner,>,> = OnceLock::new();
+	SYSTEM.get_or_init(|| Arc::new(ProcessRunner::default(),),).clone()
+}
+
+/// In-memory runner that records every invocation and replays canned results
+///
+/// Clones share the same recording. Commands without a canned result fail
+/// with [`RunError::NotFound`], as on a machine without the tool.
+#[derive(Debug, Clone, Default,)]
+pub struct FakeRunner {
+	inner: Arc<Mutex<FakeRuns,>,>,
+}
+
+#[derive(Debug, Default,)]
+struct FakeRuns {
+	calls:   Vec<String,>,
+	results: HashMap<String, FakeResult,>,
+}
+
+#[derive(Debug, Clone,)]
+enum FakeResult {
+	Output(String,),
+	Error(RunError,),
+	Hang,
+}
+
+impl FakeRunner {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Answer `command_line` with `stdout`
+	pub fn respond(&self, command_line: &str, stdout: &str,) {
+		self.set(command_line, FakeResult::Output(stdout.to_string(),),);
+	}
+
+	/// Fail `command_line` with `error`
+	pub fn fail(&self, command_line: &str, error: RunError,) {
+		self.set(command_line, FakeResult::Error(error,),);
+	}
+
+	/// Let `command_line` run until its timeout
+	pub fn hang(&self, command_line: &str,) {
+		self.set(command_line, FakeResult::Hang,);
+	}
+
+	/// Command lines run so far, in order
+	pub fn calls(&self,) -> Vec<String,> {
+		self.inner.lock().unwrap().calls.clone()
+	}
+
+	fn set(&self, command_line: &str, result: FakeResult,) {
+		self.inner.lock().unwrap().results.insert(command_line.to_string(), result,);
+	}
+}
+
+#[async_trait]
+impl Runner for FakeRunner {
+	async fn run(&self, invocation: &Invocation,) -> Result<String, RunError,> {
+		let command_line = invocation.command_line();
+		let result = {
+			let mut inner = self.inner.lock().unwrap();
+			inner.calls.push(command_line.clone(),);
+			inner.results.get(&command_line,).cloned()
+		};
+
+		match result {
+			Some(FakeResult::Output(stdout,),) => Ok(stdout,),
+			Some(FakeResult::Error(error,),) => Err(error,),
+			Some(FakeResult::Hang,) => {
+				sleep(invocation.timeout,).await;
+				Err(RunError::TimedOut {
+					program: invocation.program.clone(),
+					timeout: invocation.timeout,
+				},)
+			},
+			None => Err(RunError::NotFound { program: invocation.program.clone(), },),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde::Deserialize;
+	use tokio::time::Instant;
+
+	fn sh(script: &str,) -> Invocation {
+		Invocation::new("/bin/sh", &["-c", script,],)
+	}
+
+	#[tokio::test]
+	async fn test_process_runner_reports_each_failure() {
+		let runner = ProcessRunner::default();
+
+		assert_eq!(sh("echo hello",).output(&runner).await, Ok("hello\n".to_string()));
+		assert_eq!(
+			sh("echo oops >&2; exit 3",).output(&runner).await,
+			Err(RunError::Failed {
+				program: "/bin/sh".to_string(),
+				code:    Some(3),
+				stderr:  "oops".to_string(),
+			})
+		);
+		assert_eq!(
+			Invocation::new("/nonexistent/yabai", &[],).output(&runner).await,
+			Err(RunError::NotFound { program: "/nonexistent/yabai".to_string() })
+		);
+		assert!(matches!(
+			sh("echo '{'",).json::<serde_json::Value>(&runner).await,
+			Err(RunError::Parse { .. })
+		));
+	}
+
+	#[tokio::test]
+	async fn test_process_runner_kills_slow_commands() {
+		let runner = ProcessRunner::default();
+		let started = Instant::now();
+
+		let result = sh("sleep 5",).timeout(Duration::from_millis(100,),).output(&runner,).await;
+		assert_eq!(
+			result,
+			Err(RunError::TimedOut {
+				program: "/bin/sh".to_string(),
+				timeout: Duration::from_millis(100),
+			})
+		);
+		assert!(started.elapsed() < Duration::from_secs(2));
+	}
+
+	#[tokio::test]
+	async fn test_process_runner_limits_concurrency() {
+		let runner = ProcessRunner::new(1,);
+		let slow = sh("sleep 5",).timeout(Duration::from_secs(1,),);
+		let quick = sh("echo done",).timeout(Duration::from_millis(200,),);
+
+		// The only permit is taken, so the quick command times out waiting for it
+		let (slow, quick,) = tokio::join!(slow.output(&runner), async {
+			sleep(Duration::from_millis(50,),).await;
+			quick.output(&runner,).await
+		});
+		assert!(matches!(slow, Err(RunError::TimedOut { .. })));
+		assert!(matches!(quick, Err(RunError::TimedOut { .. })));
+		assert_eq!(sh("echo done",).output(&runner).await, Ok("done\n".to_string()));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_fake_runner_replays_results() {
+		#[derive(Debug, Deserialize, PartialEq,)]
+		struct Space {
+			index: u32,
+		}
+
+		let runner = FakeRunner::new();
+		runner.respond("yabai -m query --spaces", r#"[{"index": 1}]"#,);
+		runner.fail("pmset -g batt", RunError::Failed {
+			program: "pmset".to_string(),
+			code:    Some(1,),
+			stderr:  String::new(),
+		},);
+		runner.hang("osascript -e beep",);
+
+		let spaces = Invocation::new("yabai", &["-m", "query", "--spaces",],);
+		assert_eq!(spaces.json::<Vec<Space>>(&runner).await, Ok(vec![Space { index: 1 }]));
+		let battery = Invocation::new("pmset", &["-g", "batt",],).output(&runner,).await;
+		assert!(matches!(battery, Err(RunError::Failed { code: Some(1), .. })));
+		let beep = Invocation::new("osascript", &["-e", "beep",],);
+		let beep = beep.timeout(Duration::from_secs(1,),);
+		assert!(matches!(beep.output(&runner).await, Err(RunError::TimedOut { .. })));
+		let missing = Invocation::new("defaults", &["read",],).output(&runner,).await;
+		assert_eq!(missing, Err(RunError::NotFound { program: "defaults".to_string() }));
+
+		assert_eq!(runner.calls(), [
+			"yabai -m query --spaces",
+			"pmset -g batt",
+			"osascript -e beep",
+			"defaults read",
+		]);
+	}
+}
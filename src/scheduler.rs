@@ -0,0 +1,313 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tokio::time::sleep;
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+/// Time since the Unix epoch, used to align jobs to wall-clock boundaries
+pub type WallClock = Arc<dyn Fn() -> Duration + Send + Sync,>;
+
+/// The system clock
+pub fn system_clock() -> WallClock {
+	Arc::new(|| SystemTime::now().duration_since(UNIX_EPOCH,).unwrap_or_default(),)
+}
+
+/// Scheduler wakeups, shared with whoever wants to report them
+#[derive(Debug, Clone, Default,)]
+pub struct SchedulerStats {
+	wakeups: Arc<Mutex<VecDeque<Instant,>,>,>,
+}
+
+impl SchedulerStats {
+	fn record(&self, at: Instant,) {
+		let mut wakeups = self.wakeups.lock().unwrap();
+		wakeups.push_back(at,);
+		while wakeups.front().is_some_and(|t| at.duration_since(*t,) >= Duration::from_secs(60,),) {
+			wakeups.pop_front();
+		}
+	}
+
+	/// Wakeups during the last minute
+	pub fn wakeups_per_minute(&self,) -> usize {
+		let now = Instant::now();
+		let wakeups = self.wakeups.lock().unwrap();
+		wakeups.iter().filter(|t| now.duration_since(**t,) < Duration::from_secs(60,),).count()
+	}
+}
+
+type Action = Arc<dyn Fn() -> BoxFuture<'static, (),> + Send + Sync,>;
+
+struct Job {
+	name:   String,
+	period: Duration,
+	/// Next run, as time since the Unix epoch
+	next:   Duration,
+	action: Action,
+	/// Set while a run is in flight, so a slow run is not piled on
+	busy:   Arc<AtomicBool,>,
+}
+
+/// Marks a job idle again once its run ends, panicking or not
+struct InFlight {
+	name: String,
+	busy: Arc<AtomicBool,>,
+}
+
+impl Drop for InFlight {
+	fn drop(&mut self,) {
+		if std::thread::panicking() {
+			error!("❌ {} job panicked", self.name);
+		}
+		self.busy.store(false, Ordering::Release,);
+	}
+}
+
+/// One timer for every periodic job in the daemon
+///
+/// Each job runs on wall-clock multiples of its period: a 60 second job
+/// runs on the minute, a 5 second one on :00, :05, ... Jobs whose
+/// boundaries coincide share a wakeup, so a 2 and a 30 second job wake
+/// the daemon 30 times a minute rather than 32, and the clock label turns
+/// over with the minute instead of up to a period late.
+pub struct Scheduler {
+	jobs:       Vec<Job,>,
+	wall_clock: WallClock,
+	stats:      SchedulerStats,
+}
+
+impl Default for Scheduler {
+	fn default() -> Self {
+		Self::new(system_clock(), SchedulerStats::default(),)
+	}
+}
+
+impl Scheduler {
+	pub fn new(wall_clock: WallClock, stats: SchedulerStats,) -> Self {
+		Self { jobs: Vec::new(), wall_clock, stats, }
+	}
+
+	/// Run `job` every `period`, aligned to the wall clock
+	pub fn every<F, Fut,>(&mut self, name: &str, period: Duration, job: F,)
+	where
+		F: Fn() -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = (),> + Send + 'static,
+	{
+		if period.is_zero() {
+			warn!("⚠️  Ignoring job '{}' with a zero period", name);
+			return;
+		}
+		let action: Action = Arc::new(move || Box::pin(job(),) as BoxFuture<'static, (),>,);
+		let next = next_boundary((self.wall_clock)(), period,);
+		let busy = Arc::new(AtomicBool::new(false,),);
+		self.jobs.push(Job { name: name.to_string(), period, next, action, busy, },);
+	}
+
+	/// Names of the scheduled jobs, in order of registration
+	pub fn jobs(&self,) -> Vec<&str,> {
+		self.jobs.iter().map(|job| job.name.as_str(),).collect()
+	}
+
+	pub fn stats(&self,) -> SchedulerStats {
+		self.stats.clone()
+	}
+
+	/// Run due jobs until shutdown
+	///
+	/// Each due job runs detached in its own task, so a slow or panicking
+	/// job never holds up the others. A job still running at its next
+	/// boundary skips that run; missed boundaries are skipped, not caught up
+	/// on, which also covers the machine sleeping. Runs in flight at
+	/// shutdown are left to finish.
+	pub async fn run(mut self, mut shutdown_rx: broadcast::Receiver<(),>,) -> Result<(),> {
+		info!("⏰ Scheduler running {} jobs", self.jobs.len());
+
+		loop {
+			let now = (self.wall_clock)();
+			let wait = match self.jobs.iter().map(|job| job.next,).min() {
+				Some(next,) => next.saturating_sub(now,),
+				None => Duration::MAX,
+			};
+
+			tokio::select! {
+				_ = sleep_for(wait) => {}
+				_ = shutdown_rx.recv() => {
+					info!("⏰ Scheduler shutting down");
+					break;
+				}
+			}
+
+			self.stats.record(Instant::now(),);
+			let now = (self.wall_clock)();
+			for job in self.jobs.iter_mut().filter(|job| job.next <= now,) {
+				job.next = next_boundary(now, job.period,);
+				if job.busy.swap(true, Ordering::AcqRel,) {
+					debug!("⏰ Skipping {}, still running", job.name);
+					continue;
+				}
+				debug!("⏰ Running {}", job.name);
+				let in_flight = InFlight { name: job.name.clone(), busy: job.busy.clone(), };
+				let run = (job.action)();
+				tokio::spawn(async move {
+					let _in_flight = in_flight;
+					run.await;
+				},);
+			}
+		}
+
+		Ok((),)
+	}
+}
+
+/// Sleep for `wait`, or forever when there is nothing to wait for
+async fn sleep_for(wait: Duration,) {
+	if wait == Duration::MAX {
+		std::future::pending::<(),>().await;
+	}
+	sleep(wait,).await;
+}
+
+/// The first multiple of `period` strictly after `now`
+fn next_boundary(now: Duration, period: Duration,) -> Duration {
+	let period_ns = period.as_nanos();
+	let next_ns = (now.as_nanos() / period_ns + 1) * period_ns;
+	Duration::new((next_ns / 1_000_000_000) as u64, (next_ns % 1_000_000_000) as u32,)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A wall clock that follows tokio's, so pausing tokio pauses it too
+	fn tokio_clock(start: Duration,) -> WallClock {
+		let origin = Instant::now();
+		Arc::new(move || start + origin.elapsed(),)
+	}
+
+	fn recorder(
+		scheduler: &mut Scheduler,
+		name: &str,
+		period: Duration,
+		wall_clock: &WallClock,
+	) -> Arc<Mutex<Vec<Duration,>,>,> {
+		let runs = Arc::new(Mutex::new(Vec::new(),),);
+		let (job_runs, wall_clock,) = (runs.clone(), wall_clock.clone(),);
+		scheduler.every(name, period, move || {
+			job_runs.lock().unwrap().push(wall_clock(),);
+			async {}
+		},);
+		runs
+	}
+
+	#[test]
+	fn test_next_boundary() {
+		let minute = Duration::from_secs(60,);
+		assert_eq!(next_boundary(Duration::from_millis(125_300), minute), Duration::from_secs(180));
+		assert_eq!(next_boundary(Duration::from_secs(120), minute), Duration::from_secs(180));
+		assert_eq!(
+			next_boundary(Duration::from_millis(1_250), Duration::from_millis(500)),
+			Duration::from_millis(1_500)
+		);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_jobs_run_on_wall_clock_boundaries() {
+		// 7.7 seconds before the minute
+		let wall_clock = tokio_clock(Duration::from_millis(1_000_012_300,),);
+		let mut scheduler = Scheduler::new(wall_clock.clone(), SchedulerStats::default(),);
+		let minutes = recorder(&mut scheduler, "clock", Duration::from_secs(60,), &wall_clock,);
+		let seconds = recorder(&mut scheduler, "fast", Duration::from_secs(1,), &wall_clock,);
+
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let task = tokio::spawn(scheduler.run(shutdown_rx,),);
+		sleep(Duration::from_secs(120,),).await;
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+
+		let minutes = minutes.lock().unwrap().clone();
+		assert_eq!(minutes, vec![Duration::from_secs(1_000_020), Duration::from_secs(1_000_080)]);
+		let seconds = seconds.lock().unwrap().clone();
+		assert_eq!(seconds.len(), 120);
+		assert!(seconds.iter().all(|t| t.subsec_nanos() == 0));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_coinciding_jobs_share_wakeups() {
+		let wall_clock = tokio_clock(Duration::from_secs(1_000_000,),);
+		let mut scheduler = Scheduler::new(wall_clock.clone(), SchedulerStats::default(),);
+		let stats = scheduler.stats();
+		let sync = recorder(&mut scheduler, "sync", Duration::from_secs(2,), &wall_clock,);
+		recorder(&mut scheduler, "keyboard", Duration::from_secs(5,), &wall_clock,);
+		recorder(&mut scheduler, "battery", Duration::from_secs(30,), &wall_clock,);
+		recorder(&mut scheduler, "clock", Duration::from_secs(60,), &wall_clock,);
+		assert_eq!(scheduler.jobs(), ["sync", "keyboard", "battery", "clock"]);
+
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let task = tokio::spawn(scheduler.run(shutdown_rx,),);
+		sleep(Duration::from_secs(300,) + Duration::from_millis(500,),).await;
+
+		// Multiples of 2 or 5 seconds in a minute; the 30 and 60 second jobs ride along
+		assert_eq!(stats.wakeups_per_minute(), 36);
+		assert_eq!(sync.lock().unwrap().len(), 150);
+
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_missed_boundaries_are_skipped() {
+		let wall_clock = tokio_clock(Duration::from_secs(1_000_000,),);
+		let mut scheduler = Scheduler::new(wall_clock.clone(), SchedulerStats::default(),);
+		let stats = scheduler.stats();
+		let (job_clock, slow,) = (wall_clock.clone(), Arc::new(Mutex::new(Vec::new(),),),);
+		let runs = slow.clone();
+		scheduler.every("slow", Duration::from_secs(1,), move || {
+			runs.lock().unwrap().push(job_clock(),);
+			sleep(Duration::from_millis(2_500,),)
+		},);
+
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let task = tokio::spawn(scheduler.run(shutdown_rx,),);
+		sleep(Duration::from_millis(9_900,),).await;
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+
+		// Each run takes 2.5s, so the two boundaries after a run find it still going
+		let runs = slow.lock().unwrap().clone();
+		let offsets: Vec<u64,> = runs.iter().map(|t| t.as_secs() - 1_000_000,).collect();
+		assert_eq!(offsets, vec![1, 4, 7]);
+		assert_eq!(stats.wakeups_per_minute(), 9);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_slow_job_does_not_hold_up_others() {
+		let wall_clock = tokio_clock(Duration::from_secs(1_000_000,),);
+		let mut scheduler = Scheduler::new(wall_clock.clone(), SchedulerStats::default(),);
+		scheduler.every("slow", Duration::from_secs(2,), || sleep(Duration::from_secs(7,),),);
+		let fast = recorder(&mut scheduler, "fast", Duration::from_secs(1,), &wall_clock,);
+
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let task = tokio::spawn(scheduler.run(shutdown_rx,),);
+		sleep(Duration::from_millis(10_500,),).await;
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+
+		// Still on every boundary while the slow job sleeps through its own
+		let runs = fast.lock().unwrap().clone();
+		let offsets: Vec<u64,> = runs.iter().map(|t| t.as_secs() - 1_000_000,).collect();
+		assert_eq!(offsets, (1..=10).collect::<Vec<u64,>>());
+		assert!(runs.iter().all(|t| t.subsec_nanos() == 0));
+	}
+}
@@ -0,0 +1,411 @@
+use std::fmt;
+
+/// Ordered `key=value` assignments for `--bar`, `--default` and `--set`
+#[derive(Debug, Clone, Default, PartialEq, Eq,)]
+pub struct PropertyList(Vec<(String, String,),>,);
+
+impl PropertyList {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Append an assignment, builder style
+	pub fn with(mut self, key: &str, value: impl ToString,) -> Self {
+		self.push(key, value,);
+		self
+	}
+
+	/// Append an assignment
+	pub fn push(&mut self, key: &str, value: impl ToString,) {
+		self.0.push((key.to_string(), value.to_string(),),);
+	}
+
+	/// Append every assignment from `other`
+	pub fn extend(&mut self, other: PropertyList,) {
+		self.0.extend(other.0,);
+	}
+
+	pub fn is_empty(&self,) -> bool {
+		self.0.is_empty()
+	}
+
+	pub fn len(&self,) -> usize {
+		self.0.len()
+	}
+
+	pub fn iter(&self,) -> impl Iterator<Item = (&str, &str,),> {
+		self.0.iter().map(|(k, v,)| (k.as_str(), v.as_str(),),)
+	}
+
+	/// Value of the last assignment to `key`
+	pub fn get(&self, key: &str,) -> Option<&str,> {
+		self.0.iter().rev().find(|(k, _,)| k == key,).map(|(_, v,)| v.as_str(),)
+	}
+
+	fn args(&self,) -> impl Iterator<Item = String,> + '_ {
+		self.0.iter().map(|(k, v,)| format!("{}={}", k, v),)
+	}
+}
+
+impl<K: ToString, V: ToString,> FromIterator<(K, V,),> for PropertyList {
+	fn from_iter<I: IntoIterator<Item = (K, V,),>,>(iter: I,) -> Self {
+		Self(iter.into_iter().map(|(k, v,)| (k.to_string(), v.to_string(),),).collect(),)
+	}
+}
+
+/// Kind of entity created by `--add`
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum ItemKind {
+	Item,
+	Space,
+	Alias,
+}
+
+impl ItemKind {
+	pub fn as_str(&self,) -> &'static str {
+		match self {
+			ItemKind::Item => "item",
+			ItemKind::Space => "space",
+			ItemKind::Alias => "alias",
+		}
+	}
+}
+
+/// Target of a `--query`
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub enum QueryTarget {
+	Bar,
+	Defaults,
+	Events,
+	DefaultMenuItems,
+	Item(String,),
+}
+
+impl QueryTarget {
+	pub fn as_str(&self,) -> &str {
+		match self {
+			QueryTarget::Bar => "bar",
+			QueryTarget::Defaults => "defaults",
+			QueryTarget::Events => "events",
+			QueryTarget::DefaultMenuItems => "default_menu_items",
+			QueryTarget::Item(name,) => name,
+		}
+	}
+}
+
+/// Interpolation curve for `--animate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum AnimationCurve {
+	Linear,
+	Quadratic,
+	Tanh,
+	Sin,
+	Exp,
+	Circ,
+}
+
+impl AnimationCurve {
+	pub fn as_str(&self,) -> &'static str {
+		match self {
+			AnimationCurve::Linear => "linear",
+			AnimationCurve::Quadratic => "quadratic",
+			AnimationCurve::Tanh => "tanh",
+			AnimationCurve::Sin => "sin",
+			AnimationCurve::Exp => "exp",
+			AnimationCurve::Circ => "circ",
+		}
+	}
+}
+
+/// Placement used by `--move`
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum MoveRelation {
+	Before,
+	After,
+}
+
+impl MoveRelation {
+	pub fn as_str(&self,) -> &'static str {
+		match self {
+			MoveRelation::Before => "before",
+			MoveRelation::After => "after",
+		}
+	}
+}
+
+/// A single sketchybar domain command
+///
+/// Several commands can be sent in one message; `Animate` only makes sense
+/// when followed by the `Set` commands it applies to.
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub enum Command {
+	Bar(PropertyList,),
+	Default(PropertyList,),
+	Add { kind: ItemKind, name: String, position: String, },
+	AddBracket { name: String, members: Vec<String,>, },
+	AddEvent { name: String, notification: Option<String,>, },
+	Set { item: String, properties: PropertyList, },
+	Subscribe { item: String, events: Vec<String,>, },
+	Query(QueryTarget,),
+	Trigger { event: String, env: PropertyList, },
+	Remove(String,),
+	Animate { curve: AnimationCurve, duration: u32, },
+	Move { item: String, relation: MoveRelation, reference: String, },
+	Reorder(Vec<String,>,),
+	Hotload(bool,),
+	Update,
+	Reload,
+}
+
+impl Command {
+	pub fn add(kind: ItemKind, name: &str, position: &str,) -> Self {
+		Command::Add { kind, name: name.to_string(), position: position.to_string(), }
+	}
+
+	pub fn set(item: &str, properties: PropertyList,) -> Self {
+		Command::Set { item: item.to_string(), properties, }
+	}
+
+	pub fn subscribe(item: &str, events: &[&str],) -> Self {
+		Command::Subscribe {
+			item:   item.to_string(),
+			events: events.iter().map(|e| e.to_string(),).collect(),
+		}
+	}
+
+	/// The raw argument vector, before any transport encoding
+	pub fn args(&self,) -> Vec<String,> {
+		let mut args = Vec::new();
+		match self {
+			Command::Bar(properties,) => {
+				args.push("--bar".to_string(),);
+				args.extend(properties.args(),);
+			},
+			Command::Default(properties,) => {
+				args.push("--default".to_string(),);
+				args.extend(properties.args(),);
+			},
+			Command::Add { kind, name, position, } => {
+				args.extend(["--add", kind.as_str(), name, position,].map(String::from,),);
+			},
+			Command::AddBracket { name, members, } => {
+				args.extend(["--add", "bracket", name,].map(String::from,),);
+				args.extend(members.iter().cloned(),);
+			},
+			Command::AddEvent { name, notification, } => {
+				args.extend(["--add", "event", name,].map(String::from,),);
+				args.extend(notification.iter().cloned(),);
+			},
+			Command::Set { item, properties, } => {
+				args.extend(["--set", item,].map(String::from,),);
+				args.extend(properties.args(),);
+			},
+			Command::Subscribe { item, events, } => {
+				args.extend(["--subscribe", item,].map(String::from,),);
+				args.extend(events.iter().cloned(),);
+			},
+			Command::Query(target,) => {
+				args.extend(["--query", target.as_str(),].map(String::from,),);
+			},
+			Command::Trigger { event, env, } => {
+				args.extend(["--trigger", event,].map(String::from,),);
+				args.extend(env.args(),);
+			},
+			Command::Remove(item,) => {
+				args.extend(["--remove", item,].map(String::from,),);
+			},
+			Command::Animate { curve, duration, } => {
+				args.extend(["--animate", curve.as_str(),].map(String::from,),);
+				args.push(duration.to_string(),);
+			},
+			Command::Move { item, relation, reference, } => {
+				args.extend(["--move", item, relation.as_str(), reference,].map(String::from,),);
+			},
+			Command::Reorder(items,) => {
+				args.push("--reorder".to_string(),);
+				args.extend(items.iter().cloned(),);
+			},
+			Command::Hotload(enabled,) => {
+				args.push("--hotload".to_string(),);
+				args.push(enabled.to_string(),);
+			},
+			Command::Update => args.push("--update".to_string(),),
+			Command::Reload => args.push("--reload".to_string(),),
+		}
+		args
+	}
+
+	/// Encode for the sketchybar-rs client, which splits on unquoted spaces
+	pub fn to_message(&self,) -> String {
+		self.args().iter().map(|arg| encode_arg(arg,),).collect::<Vec<_,>>().join(" ",)
+	}
+}
+
+impl fmt::Display for Command {
+	fn fmt(&self, f: &mut fmt::Formatter<'_,>,) -> fmt::Result {
+		f.write_str(&self.to_message(),)
+	}
+}
+
+/// Encode several commands into one message
+pub fn batch_message(commands: &[Command],) -> String {
+	commands.iter().map(Command::to_message,).collect::<Vec<_,>>().join(" ",)
+}
+
+/// Make one argument survive the sketchybar-rs tokenizer intact
+///
+/// The client treats `"` and `'` purely as quote toggles and has no escape
+/// character, so quotes inside values are replaced by their typographic
+/// look-alikes; the value after the first `=` is then quoted if it contains
+/// a space.
+pub fn encode_arg(arg: &str,) -> String {
+	let sanitized: String = arg
+		.chars()
+		.filter(|c| *c != '\0',)
+		.map(|c| match c {
+			'"' => '\u{201d}',
+			'\'' => '\u{2019}',
+			c => c,
+		},)
+		.collect();
+
+	if sanitized.is_empty() {
+		return "\"\"".to_string();
+	}
+	if !sanitized.contains(' ',) {
+		return sanitized;
+	}
+
+	match sanitized.split_once('=',) {
+		Some((key, value,),) if !key.contains(' ',) => format!("{}=\"{}\"", key, value),
+		_ => format!("\"{}\"", sanitized),
+	}
+}
+
+/// Split a message the way the sketchybar-rs client does before sending it
+pub fn split_message(msg: &str,) -> Vec<String,> {
+	let mut args = Vec::new();
+	let mut current = String::new();
+	let mut quote = None;
+
+	for c in msg.chars() {
+		if c == '"' || c == '\'' {
+			quote = if quote == Some(c,) { None } else { Some(c,) };
+			continue;
+		}
+		if c == ' ' && quote.is_none() {
+			args.push(std::mem::take(&mut current,),);
+			continue;
+		}
+		current.push(c,);
+	}
+
+	if !current.is_empty() || args.is_empty() || quote.is_some() || msg.ends_with(['"', '\'',],) {
+		args.push(current,);
+	}
+	args
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn label(title: &str,) -> Command {
+		Command::set("window", PropertyList::new().with("label", title,),)
+	}
+
+	#[test]
+	fn test_simple_commands_serialise_unchanged() {
+		assert_eq!(
+			Command::add(ItemKind::Item, "clock", "right").to_message(),
+			"--add item clock right"
+		);
+		assert_eq!(
+			Command::subscribe("clock", &["system_woke", "routine"]).to_message(),
+			"--subscribe clock system_woke routine"
+		);
+		assert_eq!(
+			Command::Query(QueryTarget::DefaultMenuItems).to_message(),
+			"--query default_menu_items"
+		);
+		assert_eq!(
+			Command::Move {
+				item:      "clock".to_string(),
+				relation:  MoveRelation::Before,
+				reference: "battery".to_string(),
+			}
+			.to_message(),
+			"--move clock before battery"
+		);
+		assert_eq!(
+			Command::Reorder(vec!["space.1".to_string(), "clock".to_string()]).to_message(),
+			"--reorder space.1 clock"
+		);
+		assert_eq!(
+			batch_message(&[
+				Command::Animate { curve: AnimationCurve::Tanh, duration: 30 },
+				Command::set("space.1", PropertyList::new().with("background.color", "0xff89b4fa")),
+			]),
+			"--animate tanh 30 --set space.1 background.color=0xff89b4fa"
+		);
+	}
+
+	#[test]
+	fn test_spaces_stay_in_one_argument() {
+		let cmd = label("Visual Studio Code",);
+		assert_eq!(cmd.to_message(), "--set window label=\"Visual Studio Code\"");
+		assert_eq!(split_message(&cmd.to_message()), cmd.args());
+	}
+
+	#[test]
+	fn test_hostile_titles_round_trip() {
+		let titles = [
+			"--set clock drawing=off",
+			"x --remove clock",
+			"  leading and trailing  ",
+			"a=b=c d",
+			"",
+			"tab\tand\nnewline",
+			"日本語 タイトル",
+		];
+
+		for title in titles {
+			let cmd = label(title,);
+			let args = split_message(&cmd.to_message(),);
+			assert_eq!(args, cmd.args(), "title {:?} did not round-trip", title);
+			assert_eq!(args.len(), 3);
+		}
+	}
+
+	#[test]
+	fn test_quotes_are_replaced_not_injected() {
+		let titles = [
+			(
+				"say \"hi\" --set clock drawing=off",
+				"say \u{201d}hi\u{201d} --set clock drawing=off",
+			),
+			("it's mine", "it\u{2019}s mine",),
+			("\"", "\u{201d}",),
+			("'unbalanced \" mix", "\u{2019}unbalanced \u{201d} mix",),
+			("nul\0byte", "nulbyte",),
+		];
+
+		for (title, shown,) in titles {
+			let args = split_message(&label(title,).to_message(),);
+			assert_eq!(args, vec![
+				"--set".to_string(),
+				"window".to_string(),
+				format!("label={}", shown),
+			]);
+		}
+	}
+
+	#[test]
+	fn test_property_list_lookup() {
+		let props: PropertyList =
+			[("label", "a",), ("icon", "b",), ("label", "c",),].into_iter().collect();
+		assert_eq!(props.len(), 3);
+		assert_eq!(props.get("label"), Some("c"));
+		assert_eq!(props.get("width"), None);
+	}
+}
@@ -0,0 +1,246 @@
+pub mod command;
+pub mod properties;
+pub mod query;
+pub mod transport;
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::debug;
+
+pub use command::AnimationCurve;
+pub use command::Command;
+pub use command::ItemKind;
+pub use command::MoveRelation;
+pub use command::PropertyList;
+pub use command::QueryTarget;
+pub use properties::ItemProperties;
+pub use query::BarInfo;
+pub use query::EventInfo;
+pub use query::ItemInfo;
+pub use transport::BarTransport;
+pub use transport::CliTransport;
+pub use transport::MachTransport;
+pub use transport::RecordingTransport;
+pub use transport::SentMessage;
+
+/// High-level wrapper around a [`BarTransport`] addressed to one bar
+#[derive(Clone, Debug,)]
+pub struct SketchyBar {
+	bar_name:  String,
+	transport: Arc<dyn BarTransport,>,
+}
+
+impl Default for SketchyBar {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl SketchyBar {
+	pub fn new() -> Self {
+		Self::with_transport(Arc::new(MachTransport,),)
+	}
+
+	/// Create a bar handle that talks through the given transport
+	pub fn with_transport(transport: Arc<dyn BarTransport,>,) -> Self {
+		Self { bar_name: "sketchybar".to_string(), transport, }
+	}
+
+	pub fn set_bar_name(&mut self, name: &str,) {
+		self.bar_name = name.to_string();
+	}
+
+	pub fn get_bar_name(&self,) -> &str {
+		&self.bar_name
+	}
+
+	/// Send a raw message to sketchybar
+	pub async fn message(&self, msg: &str,) -> Result<String,> {
+		debug!("Sending message to {}: {}", self.bar_name, msg);
+
+		self.transport.send(&self.bar_name, msg,).await
+	}
+
+	/// Send a single command
+	pub async fn send(&self, command: &Command,) -> Result<String,> {
+		self.message(&command.to_message(),).await
+	}
+
+	/// Send several commands in one message
+	pub async fn send_batch(&self, commands: &[Command],) -> Result<String,> {
+		if commands.is_empty() {
+			return Ok(String::new(),);
+		}
+		self.message(&command::batch_message(commands,),).await
+	}
+
+	/// Configure bar properties
+	pub async fn bar(&self, properties: impl Into<PropertyList,>,) -> Result<(),> {
+		self.send(&Command::Bar(properties.into(),),).await?;
+		Ok((),)
+	}
+
+	/// Set default properties for items
+	pub async fn default(&self, properties: impl Into<PropertyList,>,) -> Result<(),> {
+		self.send(&Command::Default(properties.into(),),).await?;
+		Ok((),)
+	}
+
+	/// Add an item to the bar
+	pub async fn add(&self, kind: ItemKind, name: &str, position: &str,) -> Result<(),> {
+		self.send(&Command::add(kind, name, position,),).await?;
+		Ok((),)
+	}
+
+	/// Add a custom event items can subscribe to, triggered by the macOS
+	/// distributed `notification` if there is one
+	pub async fn add_event(&self, name: &str, notification: Option<&str,>,) -> Result<(),> {
+		let notification = notification.map(str::to_string,);
+		self.send(&Command::AddEvent { name: name.to_string(), notification, },).await?;
+		Ok((),)
+	}
+
+	/// Set properties for an item
+	pub async fn set(&self, item_name: &str, properties: impl Into<PropertyList,>,) -> Result<(),> {
+		self.send(&Command::set(item_name, properties.into(),),).await?;
+		Ok((),)
+	}
+
+	/// Subscribe an item to events
+	pub async fn subscribe(&self, item_name: &str, events: &[&str],) -> Result<(),> {
+		self.send(&Command::subscribe(item_name, events,),).await?;
+		Ok((),)
+	}
+
+	/// Put `item_names` in this order, left to right
+	pub async fn reorder(&self, item_names: &[&str],) -> Result<(),> {
+		let items = item_names.iter().map(|name| name.to_string(),).collect();
+		self.send(&Command::Reorder(items,),).await?;
+		Ok((),)
+	}
+
+	/// Enable or disable hotloading
+	pub async fn hotload(&self, enabled: bool,) -> Result<(),> {
+		self.send(&Command::Hotload(enabled,),).await?;
+		Ok((),)
+	}
+
+	/// Remove an item from the bar
+	pub async fn remove(&self, item_name: &str,) -> Result<(),> {
+		self.send(&Command::Remove(item_name.to_string(),),).await?;
+		Ok((),)
+	}
+
+	/// Query information from sketchybar
+	pub async fn query(&self, target: QueryTarget,) -> Result<String,> {
+		self.send(&Command::Query(target,),).await
+	}
+
+	/// Query and parse the bar's own properties and item list
+	pub async fn query_bar(&self,) -> Result<BarInfo,> {
+		let response = self.query(QueryTarget::Bar,).await?;
+		query::parse("bar", &response,)
+	}
+
+	/// Query and parse the current state of one item
+	pub async fn query_item(&self, item_name: &str,) -> Result<ItemInfo,> {
+		let response = self.query(QueryTarget::Item(item_name.to_string(),),).await?;
+		query::parse(item_name, &response,)
+	}
+
+	/// Query and parse the properties new items inherit
+	pub async fn query_defaults(&self,) -> Result<ItemInfo,> {
+		let response = self.query(QueryTarget::Defaults,).await?;
+		query::parse("defaults", &response,)
+	}
+
+	/// Query and parse the registered events, keyed by name
+	pub async fn query_events(&self,) -> Result<HashMap<String, EventInfo,>,> {
+		let response = self.query(QueryTarget::Events,).await?;
+		query::parse("events", &response,)
+	}
+
+	/// Query the menu bar entries that can be used as aliases
+	pub async fn query_default_menu_items(&self,) -> Result<Vec<String,>,> {
+		let response = self.query(QueryTarget::DefaultMenuItems,).await?;
+		query::parse("default_menu_items", &response,)
+	}
+
+	/// Trigger an event
+	pub async fn trigger(&self, event_name: &str,) -> Result<(),> {
+		self.send(&Command::Trigger { event: event_name.to_string(), env: PropertyList::new(), },)
+			.await?;
+		Ok((),)
+	}
+
+	/// Update the bar (force refresh)
+	pub async fn update(&self,) -> Result<(),> {
+		self.send(&Command::Update,).await?;
+		Ok((),)
+	}
+
+	/// Reload the entire bar configuration
+	pub async fn reload(&self,) -> Result<(),> {
+		self.send(&Command::Reload,).await?;
+		Ok((),)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_sketchybar_rs_behavior() -> Result<(),> {
+		if !crate::tests::utils::is_sketchybar_available() {
+			println!("Skipping sketchybar-rs test - sketchybar not available");
+			return Ok((),);
+		}
+
+		let query = sketchybar_rs::message("--query bar", None,)?;
+		println!("{query}");
+		assert!(!query.is_empty());
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_messages_go_through_transport() -> Result<(),> {
+		let transport = RecordingTransport::new();
+		transport.respond("--query bar", "{}",);
+
+		let mut bar = SketchyBar::with_transport(Arc::new(transport.clone(),),);
+		bar.set_bar_name("external_2",);
+
+		bar.set("space.3", PropertyList::new().with("background.color", "0xff89b4fa",),).await?;
+		assert_eq!(bar.query(QueryTarget::Bar,).await?, "{}");
+
+		assert_eq!(transport.sent_to("external_2"), vec![
+			"--set space.3 background.color=0xff89b4fa".to_string(),
+			"--query bar".to_string(),
+		]);
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_typed_queries_through_transport() -> Result<(),> {
+		let transport = RecordingTransport::new();
+		transport.respond("--query bar", r#"{"position": "bottom", "items": ["clock"]}"#,);
+		transport.respond(
+			"--query clock",
+			r#"{"name": "clock", "geometry": {"drawing": "off"}, "label": {"value": "12:00"}}"#,
+		);
+		transport.respond("--query default_menu_items", r#"["Control Center,Battery"]"#,);
+
+		let bar = SketchyBar::with_transport(Arc::new(transport.clone(),),);
+
+		assert_eq!(bar.query_bar().await?.items, vec!["clock"]);
+		let clock = bar.query_item("clock",).await?;
+		assert!(!clock.is_drawing());
+		assert_eq!(clock.label_text(), "12:00");
+		assert_eq!(bar.query_default_menu_items().await?, vec!["Control Center,Battery"]);
+		transport.respond("--query events", "",);
+		assert!(bar.query_events().await.is_err());
+		Ok((),)
+	}
+}
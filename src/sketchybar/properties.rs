@@ -0,0 +1,1180 @@
+use schemars::JsonSchema;
+use schemars::Schema;
+use schemars::SchemaGenerator;
+use schemars::json_schema;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::de;
+use std::borrow::Cow;
+use std::fmt;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use super::command::PropertyList;
+
+/// ARGB color, serialised as `0xAARRGGBB`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default,)]
+pub struct Color(pub u32,);
+
+impl From<u32,> for Color {
+	fn from(argb: u32,) -> Self {
+		Color(argb,)
+	}
+}
+
+impl Display for Color {
+	fn fmt(&self, f: &mut fmt::Formatter<'_,>,) -> fmt::Result {
+		write!(f, "0x{:08x}", self.0)
+	}
+}
+
+/// Screen edge the bar is attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum BarPosition {
+	Top,
+	Bottom,
+}
+
+/// Where an item sits in the bar
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub enum ItemPosition {
+	Left,
+	Right,
+	Center,
+	/// Left of the notch
+	Q,
+	/// Right of the notch
+	E,
+	/// Inside the popup of the named item
+	Popup(String,),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum Align {
+	Left,
+	Center,
+	Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum Width {
+	Dynamic,
+	Fixed(u32,),
+}
+
+/// When an item's script runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum Updates {
+	On,
+	Off,
+	WhenShown,
+}
+
+impl Display for BarPosition {
+	fn fmt(&self, f: &mut fmt::Formatter<'_,>,) -> fmt::Result {
+		f.write_str(match self {
+			BarPosition::Top => "top",
+			BarPosition::Bottom => "bottom",
+		},)
+	}
+}
+
+impl Display for ItemPosition {
+	fn fmt(&self, f: &mut fmt::Formatter<'_,>,) -> fmt::Result {
+		match self {
+			ItemPosition::Left => f.write_str("left",),
+			ItemPosition::Right => f.write_str("right",),
+			ItemPosition::Center => f.write_str("center",),
+			ItemPosition::Q => f.write_str("q",),
+			ItemPosition::E => f.write_str("e",),
+			ItemPosition::Popup(parent,) => write!(f, "popup.{}", parent),
+		}
+	}
+}
+
+impl Display for Align {
+	fn fmt(&self, f: &mut fmt::Formatter<'_,>,) -> fmt::Result {
+		f.write_str(match self {
+			Align::Left => "left",
+			Align::Center => "center",
+			Align::Right => "right",
+		},)
+	}
+}
+
+impl Display for Width {
+	fn fmt(&self, f: &mut fmt::Formatter<'_,>,) -> fmt::Result {
+		match self {
+			Width::Dynamic => f.write_str("dynamic",),
+			Width::Fixed(width,) => write!(f, "{}", width),
+		}
+	}
+}
+
+impl Display for Updates {
+	fn fmt(&self, f: &mut fmt::Formatter<'_,>,) -> fmt::Result {
+		f.write_str(match self {
+			Updates::On => "on",
+			Updates::Off => "off",
+			Updates::WhenShown => "when_shown",
+		},)
+	}
+}
+
+impl FromStr for Color {
+	type Err = String;
+
+	/// `0xAARRGGBB`, or `#RRGGBB` / `#AARRGGBB` with an opaque default alpha
+	fn from_str(s: &str,) -> Result<Self, Self::Err,> {
+		let invalid = || format!("invalid color {:?}, expected 0xAARRGGBB or #RRGGBB", s);
+		let (digits, opaque,) = match (s.strip_prefix("0x",), s.strip_prefix('#',),) {
+			(Some(digits,), _,) if digits.len() == 8 => (digits, false,),
+			(_, Some(digits,),) if digits.len() == 6 => (digits, true,),
+			(_, Some(digits,),) if digits.len() == 8 => (digits, false,),
+			_ => return Err(invalid(),),
+		};
+		let argb = u32::from_str_radix(digits, 16,).map_err(|_| invalid(),)?;
+		Ok(Color(if opaque { 0xff000000 | argb } else { argb },),)
+	}
+}
+
+impl FromStr for BarPosition {
+	type Err = String;
+
+	fn from_str(s: &str,) -> Result<Self, Self::Err,> {
+		match s {
+			"top" => Ok(BarPosition::Top,),
+			"bottom" => Ok(BarPosition::Bottom,),
+			_ => Err(format!("invalid bar position {:?}, expected top or bottom", s),),
+		}
+	}
+}
+
+impl FromStr for ItemPosition {
+	type Err = String;
+
+	fn from_str(s: &str,) -> Result<Self, Self::Err,> {
+		match s {
+			"left" => Ok(ItemPosition::Left,),
+			"right" => Ok(ItemPosition::Right,),
+			"center" => Ok(ItemPosition::Center,),
+			"q" => Ok(ItemPosition::Q,),
+			"e" => Ok(ItemPosition::E,),
+			_ => match s.strip_prefix("popup.",) {
+				Some(parent,) if !parent.is_empty() => {
+					Ok(ItemPosition::Popup(parent.to_string(),),)
+				},
+				_ => Err(format!(
+					"invalid item position {:?}, expected left, right, center, q, e or \
+					 popup.<item>",
+					s
+				),),
+			},
+		}
+	}
+}
+
+impl FromStr for Align {
+	type Err = String;
+
+	fn from_str(s: &str,) -> Result<Self, Self::Err,> {
+		match s {
+			"left" => Ok(Align::Left,),
+			"center" => Ok(Align::Center,),
+			"right" => Ok(Align::Right,),
+			_ => Err(format!("invalid alignment {:?}, expected left, center or right", s),),
+		}
+	}
+}
+
+impl FromStr for Width {
+	type Err = String;
+
+	fn from_str(s: &str,) -> Result<Self, Self::Err,> {
+		match s {
+			"dynamic" => Ok(Width::Dynamic,),
+			_ => s.parse().map(Width::Fixed,).map_err(|_| {
+				format!("invalid width {:?}, expected dynamic or a number of pixels", s)
+			},),
+		}
+	}
+}
+
+impl FromStr for Updates {
+	type Err = String;
+
+	fn from_str(s: &str,) -> Result<Self, Self::Err,> {
+		match s {
+			"on" => Ok(Updates::On,),
+			"off" => Ok(Updates::Off,),
+			"when_shown" => Ok(Updates::WhenShown,),
+			_ => Err(format!("invalid updates {:?}, expected on, off or when_shown", s),),
+		}
+	}
+}
+
+/// Values read from config files as sketchybar spells them
+trait Spelled: FromStr<Err = String,> {
+	const EXPECTING: &'static str;
+
+	/// The value a bare integer stands for, if the type takes one
+	fn from_integer(_n: u64,) -> Option<Self,> {
+		None
+	}
+}
+
+impl Spelled for Color {
+	const EXPECTING: &'static str = "a color such as \"0xff313244\" or \"#313244\"";
+
+	fn from_integer(n: u64,) -> Option<Self,> {
+		u32::try_from(n,).ok().map(Color,)
+	}
+}
+
+impl Spelled for BarPosition {
+	const EXPECTING: &'static str = "\"top\" or \"bottom\"";
+}
+
+impl Spelled for ItemPosition {
+	const EXPECTING: &'static str = "an item position such as \"left\" or \"popup.clock\"";
+}
+
+impl Spelled for Align {
+	const EXPECTING: &'static str = "\"left\", \"center\" or \"right\"";
+}
+
+impl Spelled for Width {
+	const EXPECTING: &'static str = "\"dynamic\" or a number of pixels";
+
+	fn from_integer(n: u64,) -> Option<Self,> {
+		u32::try_from(n,).ok().map(Width::Fixed,)
+	}
+}
+
+impl Spelled for Updates {
+	const EXPECTING: &'static str = "\"on\", \"off\" or \"when_shown\"";
+}
+
+struct SpelledVisitor<T,>(PhantomData<T,>,);
+
+impl<T: Spelled,> de::Visitor<'_,> for SpelledVisitor<T,> {
+	type Value = T;
+
+	fn expecting(&self, f: &mut fmt::Formatter<'_,>,) -> fmt::Result {
+		f.write_str(T::EXPECTING,)
+	}
+
+	fn visit_str<E: de::Error,>(self, v: &str,) -> Result<T, E,> {
+		v.parse().map_err(E::custom,)
+	}
+
+	fn visit_u64<E: de::Error,>(self, v: u64,) -> Result<T, E,> {
+		T::from_integer(v,).ok_or_else(|| E::invalid_value(de::Unexpected::Unsigned(v,), &self,),)
+	}
+
+	fn visit_i64<E: de::Error,>(self, v: i64,) -> Result<T, E,> {
+		match u64::try_from(v,) {
+			Ok(v,) => self.visit_u64(v,),
+			Err(_,) => Err(E::invalid_value(de::Unexpected::Signed(v,), &self,),),
+		}
+	}
+}
+
+fn deserialize_spelled<'de, D: Deserializer<'de,>, T: Spelled,>(d: D,) -> Result<T, D::Error,> {
+	d.deserialize_any(SpelledVisitor(PhantomData,),)
+}
+
+impl<'de,> Deserialize<'de,> for Color {
+	fn deserialize<D: Deserializer<'de,>,>(d: D,) -> Result<Self, D::Error,> {
+		deserialize_spelled(d,)
+	}
+}
+
+impl<'de,> Deserialize<'de,> for BarPosition {
+	fn deserialize<D: Deserializer<'de,>,>(d: D,) -> Result<Self, D::Error,> {
+		deserialize_spelled(d,)
+	}
+}
+
+impl<'de,> Deserialize<'de,> for ItemPosition {
+	fn deserialize<D: Deserializer<'de,>,>(d: D,) -> Result<Self, D::Error,> {
+		deserialize_spelled(d,)
+	}
+}
+
+impl<'de,> Deserialize<'de,> for Align {
+	fn deserialize<D: Deserializer<'de,>,>(d: D,) -> Result<Self, D::Error,> {
+		deserialize_spelled(d,)
+	}
+}
+
+impl<'de,> Deserialize<'de,> for Width {
+	fn deserialize<D: Deserializer<'de,>,>(d: D,) -> Result<Self, D::Error,> {
+		deserialize_spelled(d,)
+	}
+}
+
+impl<'de,> Deserialize<'de,> for Updates {
+	fn deserialize<D: Deserializer<'de,>,>(d: D,) -> Result<Self, D::Error,> {
+		deserialize_spelled(d,)
+	}
+}
+
+/// `schema`, described the way [`Spelled::EXPECTING`] puts it
+fn spelled_schema<T: Spelled,>(mut schema: Schema,) -> Schema {
+	schema.insert("description".to_string(), T::EXPECTING.into(),);
+	schema
+}
+
+impl JsonSchema for Color {
+	fn schema_name() -> Cow<'static, str,> {
+		"Color".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator,) -> Schema {
+		spelled_schema::<Self,>(json_schema!({
+			"anyOf": [
+				{
+					"type": "string",
+					"pattern": "^(0x[0-9a-fA-F]{8}|#[0-9a-fA-F]{6}|#[0-9a-fA-F]{8})$"
+				},
+				{ "type": "integer", "minimum": 0, "maximum": u32::MAX }
+			]
+		}),)
+	}
+}
+
+impl JsonSchema for BarPosition {
+	fn schema_name() -> Cow<'static, str,> {
+		"BarPosition".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator,) -> Schema {
+		spelled_schema::<Self,>(json_schema!({ "enum": ["top", "bottom"] }),)
+	}
+}
+
+impl JsonSchema for ItemPosition {
+	fn schema_name() -> Cow<'static, str,> {
+		"ItemPosition".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator,) -> Schema {
+		spelled_schema::<Self,>(json_schema!({
+			"type": "string",
+			"pattern": "^(left|right|center|q|e|popup\\..+)$"
+		}),)
+	}
+}
+
+impl JsonSchema for Align {
+	fn schema_name() -> Cow<'static, str,> {
+		"Align".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator,) -> Schema {
+		spelled_schema::<Self,>(json_schema!({ "enum": ["left", "center", "right"] }),)
+	}
+}
+
+impl JsonSchema for Width {
+	fn schema_name() -> Cow<'static, str,> {
+		"Width".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator,) -> Schema {
+		spelled_schema::<Self,>(json_schema!({
+			"anyOf": [{ "const": "dynamic" }, { "type": "integer", "minimum": 0 }]
+		}),)
+	}
+}
+
+impl JsonSchema for Updates {
+	fn schema_name() -> Cow<'static, str,> {
+		"Updates".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator,) -> Schema {
+		spelled_schema::<Self,>(json_schema!({ "enum": ["on", "off", "when_shown"] }),)
+	}
+}
+
+/// Take `value` over what `field` holds, if it is set
+fn set<T: Clone,>(field: &mut Option<T,>, value: &Option<T,>,) {
+	if value.is_some() {
+		field.clone_from(value,);
+	}
+}
+
+/// Writes `Some` values under a dotted key prefix
+struct Writer<'a,> {
+	out:    &'a mut PropertyList,
+	prefix: String,
+}
+
+impl Writer<'_,> {
+	fn key(&self, key: &str,) -> String {
+		match (self.prefix.is_empty(), key.is_empty(),) {
+			(true, _,) => key.to_string(),
+			(false, true,) => self.prefix.clone(),
+			(false, false,) => format!("{}.{}", self.prefix, key),
+		}
+	}
+
+	fn put<T: Display,>(&mut self, key: &str, value: &Option<T,>,) {
+		if let Some(value,) = value {
+			let key = self.key(key,);
+			self.out.push(&key, value,);
+		}
+	}
+
+	fn nested(&mut self, key: &str, properties: &impl WriteProperties,) {
+		let prefix = self.key(key,);
+		properties.write(&mut Writer { out: self.out, prefix, },);
+	}
+}
+
+/// Serialisation into dotted sketchybar keys
+///
+/// Implementations destructure `self` exhaustively, so a field that is not
+/// written out fails to compile.
+trait WriteProperties {
+	fn write(&self, w: &mut Writer<'_,>,);
+
+	fn to_property_list(&self,) -> PropertyList {
+		let mut out = PropertyList::new();
+		self.write(&mut Writer { out: &mut out, prefix: String::new(), },);
+		out
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontProperties {
+	pub family: Option<String,>,
+	pub style:  Option<String,>,
+	pub size:   Option<f32,>,
+}
+
+impl FontProperties {
+	/// Set every property `other` sets, keeping the rest
+	pub fn overlay(&mut self, other: &Self,) {
+		let Self { family, style, size, } = other;
+		set(&mut self.family, family,);
+		set(&mut self.style, style,);
+		set(&mut self.size, size,);
+	}
+}
+
+impl WriteProperties for FontProperties {
+	fn write(&self, w: &mut Writer<'_,>,) {
+		let Self { family, style, size, } = self;
+		w.put("family", family,);
+		w.put("style", style,);
+		w.put("size", size,);
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShadowProperties {
+	pub drawing:  Option<bool,>,
+	pub color:    Option<Color,>,
+	pub angle:    Option<u32,>,
+	pub distance: Option<u32,>,
+}
+
+impl ShadowProperties {
+	/// Set every property `other` sets, keeping the rest
+	pub fn overlay(&mut self, other: &Self,) {
+		let Self { drawing, color, angle, distance, } = other;
+		set(&mut self.drawing, drawing,);
+		set(&mut self.color, color,);
+		set(&mut self.angle, angle,);
+		set(&mut self.distance, distance,);
+	}
+}
+
+impl WriteProperties for ShadowProperties {
+	fn write(&self, w: &mut Writer<'_,>,) {
+		let Self { drawing, color, angle, distance, } = self;
+		w.put("drawing", drawing,);
+		w.put("color", color,);
+		w.put("angle", angle,);
+		w.put("distance", distance,);
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageProperties {
+	/// Image source; serialised as the bare `image` key
+	pub value:         Option<String,>,
+	pub drawing:       Option<bool,>,
+	pub scale:         Option<f32,>,
+	pub border_color:  Option<Color,>,
+	pub border_width:  Option<u32,>,
+	pub corner_radius: Option<u32,>,
+	pub padding_left:  Option<i32,>,
+	pub padding_right: Option<i32,>,
+	pub y_offset:      Option<i32,>,
+}
+
+impl ImageProperties {
+	/// Set every property `other` sets, keeping the rest
+	pub fn overlay(&mut self, other: &Self,) {
+		let Self {
+			value,
+			drawing,
+			scale,
+			border_color,
+			border_width,
+			corner_radius,
+			padding_left,
+			padding_right,
+			y_offset,
+		} = other;
+		set(&mut self.value, value,);
+		set(&mut self.drawing, drawing,);
+		set(&mut self.scale, scale,);
+		set(&mut self.border_color, border_color,);
+		set(&mut self.border_width, border_width,);
+		set(&mut self.corner_radius, corner_radius,);
+		set(&mut self.padding_left, padding_left,);
+		set(&mut self.padding_right, padding_right,);
+		set(&mut self.y_offset, y_offset,);
+	}
+}
+
+impl WriteProperties for ImageProperties {
+	fn write(&self, w: &mut Writer<'_,>,) {
+		let Self {
+			value,
+			drawing,
+			scale,
+			border_color,
+			border_width,
+			corner_radius,
+			padding_left,
+			padding_right,
+			y_offset,
+		} = self;
+		w.put("", value,);
+		w.put("drawing", drawing,);
+		w.put("scale", scale,);
+		w.put("border_color", border_color,);
+		w.put("border_width", border_width,);
+		w.put("corner_radius", corner_radius,);
+		w.put("padding_left", padding_left,);
+		w.put("padding_right", padding_right,);
+		w.put("y_offset", y_offset,);
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackgroundProperties {
+	pub drawing:       Option<bool,>,
+	pub color:         Option<Color,>,
+	pub border_color:  Option<Color,>,
+	pub border_width:  Option<u32,>,
+	pub height:        Option<u32,>,
+	pub corner_radius: Option<u32,>,
+	pub padding_left:  Option<i32,>,
+	pub padding_right: Option<i32,>,
+	pub x_offset:      Option<i32,>,
+	pub y_offset:      Option<i32,>,
+	pub clip:          Option<f32,>,
+	pub image:         ImageProperties,
+	pub shadow:        ShadowProperties,
+}
+
+impl BackgroundProperties {
+	/// Set every property `other` sets, keeping the rest
+	pub fn overlay(&mut self, other: &Self,) {
+		let Self {
+			drawing,
+			color,
+			border_color,
+			border_width,
+			height,
+			corner_radius,
+			padding_left,
+			padding_right,
+			x_offset,
+			y_offset,
+			clip,
+			image,
+			shadow,
+		} = other;
+		set(&mut self.drawing, drawing,);
+		set(&mut self.color, color,);
+		set(&mut self.border_color, border_color,);
+		set(&mut self.border_width, border_width,);
+		set(&mut self.height, height,);
+		set(&mut self.corner_radius, corner_radius,);
+		set(&mut self.padding_left, padding_left,);
+		set(&mut self.padding_right, padding_right,);
+		set(&mut self.x_offset, x_offset,);
+		set(&mut self.y_offset, y_offset,);
+		set(&mut self.clip, clip,);
+		self.image.overlay(image,);
+		self.shadow.overlay(shadow,);
+	}
+}
+
+impl WriteProperties for BackgroundProperties {
+	fn write(&self, w: &mut Writer<'_,>,) {
+		let Self {
+			drawing,
+			color,
+			border_color,
+			border_width,
+			height,
+			corner_radius,
+			padding_left,
+			padding_right,
+			x_offset,
+			y_offset,
+			clip,
+			image,
+			shadow,
+		} = self;
+		w.put("drawing", drawing,);
+		w.put("color", color,);
+		w.put("border_color", border_color,);
+		w.put("border_width", border_width,);
+		w.put("height", height,);
+		w.put("corner_radius", corner_radius,);
+		w.put("padding_left", padding_left,);
+		w.put("padding_right", padding_right,);
+		w.put("x_offset", x_offset,);
+		w.put("y_offset", y_offset,);
+		w.put("clip", clip,);
+		w.nested("image", image,);
+		w.nested("shadow", shadow,);
+	}
+}
+
+/// Properties shared by `icon` and `label`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct TextProperties {
+	/// Displayed text; serialised as the bare `icon`/`label` key
+	pub value:           Option<String,>,
+	pub drawing:         Option<bool,>,
+	pub color:           Option<Color,>,
+	pub highlight:       Option<bool,>,
+	pub highlight_color: Option<Color,>,
+	pub padding_left:    Option<i32,>,
+	pub padding_right:   Option<i32,>,
+	pub y_offset:        Option<i32,>,
+	pub width:           Option<Width,>,
+	pub align:           Option<Align,>,
+	pub max_chars:       Option<u32,>,
+	pub font:            FontProperties,
+	pub background:      BackgroundProperties,
+	pub shadow:          ShadowProperties,
+}
+
+impl TextProperties {
+	/// Text properties that only set the displayed value
+	pub fn new(value: impl Into<String,>,) -> Self {
+		Self { value: Some(value.into(),), ..Default::default() }
+	}
+
+	/// Set every property `other` sets, keeping the rest
+	pub fn overlay(&mut self, other: &Self,) {
+		let Self {
+			value,
+			drawing,
+			color,
+			highlight,
+			highlight_color,
+			padding_left,
+			padding_right,
+			y_offset,
+			width,
+			align,
+			max_chars,
+			font,
+			background,
+			shadow,
+		} = other;
+		set(&mut self.value, value,);
+		set(&mut self.drawing, drawing,);
+		set(&mut self.color, color,);
+		set(&mut self.highlight, highlight,);
+		set(&mut self.highlight_color, highlight_color,);
+		set(&mut self.padding_left, padding_left,);
+		set(&mut self.padding_right, padding_right,);
+		set(&mut self.y_offset, y_offset,);
+		set(&mut self.width, width,);
+		set(&mut self.align, align,);
+		set(&mut self.max_chars, max_chars,);
+		self.font.overlay(font,);
+		self.background.overlay(background,);
+		self.shadow.overlay(shadow,);
+	}
+}
+
+impl WriteProperties for TextProperties {
+	fn write(&self, w: &mut Writer<'_,>,) {
+		let Self {
+			value,
+			drawing,
+			color,
+			highlight,
+			highlight_color,
+			padding_left,
+			padding_right,
+			y_offset,
+			width,
+			align,
+			max_chars,
+			font,
+			background,
+			shadow,
+		} = self;
+		w.put("", value,);
+		w.put("drawing", drawing,);
+		w.put("color", color,);
+		w.put("highlight", highlight,);
+		w.put("highlight_color", highlight_color,);
+		w.put("padding_left", padding_left,);
+		w.put("padding_right", padding_right,);
+		w.put("y_offset", y_offset,);
+		w.put("width", width,);
+		w.put("align", align,);
+		w.put("max_chars", max_chars,);
+		w.nested("font", font,);
+		w.nested("background", background,);
+		w.nested("shadow", shadow,);
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct PopupProperties {
+	pub drawing:     Option<bool,>,
+	pub horizontal:  Option<bool,>,
+	pub topmost:     Option<bool,>,
+	pub height:      Option<u32,>,
+	pub blur_radius: Option<u32,>,
+	pub y_offset:    Option<i32,>,
+	pub align:       Option<Align,>,
+	pub background:  BackgroundProperties,
+}
+
+impl PopupProperties {
+	/// Set every property `other` sets, keeping the rest
+	pub fn overlay(&mut self, other: &Self,) {
+		let Self {
+			drawing,
+			horizontal,
+			topmost,
+			height,
+			blur_radius,
+			y_offset,
+			align,
+			background,
+		} = other;
+		set(&mut self.drawing, drawing,);
+		set(&mut self.horizontal, horizontal,);
+		set(&mut self.topmost, topmost,);
+		set(&mut self.height, height,);
+		set(&mut self.blur_radius, blur_radius,);
+		set(&mut self.y_offset, y_offset,);
+		set(&mut self.align, align,);
+		self.background.overlay(background,);
+	}
+}
+
+impl WriteProperties for PopupProperties {
+	fn write(&self, w: &mut Writer<'_,>,) {
+		let Self {
+			drawing,
+			horizontal,
+			topmost,
+			height,
+			blur_radius,
+			y_offset,
+			align,
+			background,
+		} = self;
+		w.put("drawing", drawing,);
+		w.put("horizontal", horizontal,);
+		w.put("topmost", topmost,);
+		w.put("height", height,);
+		w.put("blur_radius", blur_radius,);
+		w.put("y_offset", y_offset,);
+		w.put("align", align,);
+		w.nested("background", background,);
+	}
+}
+
+/// Properties accepted by `--set` and `--default`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct ItemProperties {
+	pub drawing:            Option<bool,>,
+	pub position:           Option<ItemPosition,>,
+	pub associated_display: Option<u32,>,
+	pub associated_space:   Option<u32,>,
+	pub ignore_association: Option<bool,>,
+	pub y_offset:           Option<i32,>,
+	pub padding_left:       Option<i32,>,
+	pub padding_right:      Option<i32,>,
+	pub width:              Option<Width,>,
+	pub scroll_texts:       Option<bool,>,
+	pub blur_radius:        Option<u32,>,
+	pub updates:            Option<Updates,>,
+	pub update_freq:        Option<u32,>,
+	pub script:             Option<String,>,
+	pub click_script:       Option<String,>,
+	pub icon:               TextProperties,
+	pub label:              TextProperties,
+	pub background:         BackgroundProperties,
+	pub popup:              PopupProperties,
+}
+
+impl ItemProperties {
+	/// Item properties that only set the label text
+	pub fn label(value: impl Into<String,>,) -> Self {
+		Self { label: TextProperties::new(value,), ..Default::default() }
+	}
+
+	/// Set every property `other` sets, keeping the rest
+	pub fn overlay(&mut self, other: &Self,) {
+		let Self {
+			drawing,
+			position,
+			associated_display,
+			associated_space,
+			ignore_association,
+			y_offset,
+			padding_left,
+			padding_right,
+			width,
+			scroll_texts,
+			blur_radius,
+			updates,
+			update_freq,
+			script,
+			click_script,
+			icon,
+			label,
+			background,
+			popup,
+		} = other;
+		set(&mut self.drawing, drawing,);
+		set(&mut self.position, position,);
+		set(&mut self.associated_display, associated_display,);
+		set(&mut self.associated_space, associated_space,);
+		set(&mut self.ignore_association, ignore_association,);
+		set(&mut self.y_offset, y_offset,);
+		set(&mut self.padding_left, padding_left,);
+		set(&mut self.padding_right, padding_right,);
+		set(&mut self.width, width,);
+		set(&mut self.scroll_texts, scroll_texts,);
+		set(&mut self.blur_radius, blur_radius,);
+		set(&mut self.updates, updates,);
+		set(&mut self.update_freq, update_freq,);
+		set(&mut self.script, script,);
+		set(&mut self.click_script, click_script,);
+		self.icon.overlay(icon,);
+		self.label.overlay(label,);
+		self.background.overlay(background,);
+		self.popup.overlay(popup,);
+	}
+}
+
+impl WriteProperties for ItemProperties {
+	fn write(&self, w: &mut Writer<'_,>,) {
+		let Self {
+			drawing,
+			position,
+			associated_display,
+			associated_space,
+			ignore_association,
+			y_offset,
+			padding_left,
+			padding_right,
+			width,
+			scroll_texts,
+			blur_radius,
+			updates,
+			update_freq,
+			script,
+			click_script,
+			icon,
+			label,
+			background,
+			popup,
+		} = self;
+		w.put("drawing", drawing,);
+		w.put("position", position,);
+		w.put("associated_display", associated_display,);
+		w.put("associated_space", associated_space,);
+		w.put("ignore_association", ignore_association,);
+		w.put("y_offset", y_offset,);
+		w.put("padding_left", padding_left,);
+		w.put("padding_right", padding_right,);
+		w.put("width", width,);
+		w.put("scroll_texts", scroll_texts,);
+		w.put("blur_radius", blur_radius,);
+		w.put("updates", updates,);
+		w.put("update_freq", update_freq,);
+		w.put("script", script,);
+		w.put("click_script", click_script,);
+		w.nested("icon", icon,);
+		w.nested("label", label,);
+		w.nested("background", background,);
+		w.nested("popup", popup,);
+	}
+}
+
+/// Properties accepted by `--bar`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct BarProperties {
+	pub position:           Option<BarPosition,>,
+	pub height:             Option<u32,>,
+	pub sticky:             Option<bool,>,
+	pub shadow:             Option<bool,>,
+	pub font_smoothing:     Option<bool,>,
+	pub show_in_fullscreen: Option<bool,>,
+	pub margin:             Option<u32,>,
+	pub color:              Option<Color,>,
+	pub border_color:       Option<Color,>,
+	pub border_width:       Option<u32,>,
+	pub corner_radius:      Option<u32,>,
+	pub blur_radius:        Option<u32,>,
+	pub y_offset:           Option<i32,>,
+	pub padding_left:       Option<u32,>,
+	pub padding_right:      Option<u32,>,
+	pub notch_width:        Option<u32,>,
+	pub display:            Option<u32,>,
+	pub hidden:             Option<bool,>,
+	pub topmost:            Option<bool,>,
+}
+
+impl BarProperties {
+	/// Set every property `other` sets, keeping the rest
+	pub fn overlay(&mut self, other: &Self,) {
+		let Self {
+			position,
+			height,
+			sticky,
+			shadow,
+			font_smoothing,
+			show_in_fullscreen,
+			margin,
+			color,
+			border_color,
+			border_width,
+			corner_radius,
+			blur_radius,
+			y_offset,
+			padding_left,
+			padding_right,
+			notch_width,
+			display,
+			hidden,
+			topmost,
+		} = other;
+		set(&mut self.position, position,);
+		set(&mut self.height, height,);
+		set(&mut self.sticky, sticky,);
+		set(&mut self.shadow, shadow,);
+		set(&mut self.font_smoothing, font_smoothing,);
+		set(&mut self.show_in_fullscreen, show_in_fullscreen,);
+		set(&mut self.margin, margin,);
+		set(&mut self.color, color,);
+		set(&mut self.border_color, border_color,);
+		set(&mut self.border_width, border_width,);
+		set(&mut self.corner_radius, corner_radius,);
+		set(&mut self.blur_radius, blur_radius,);
+		set(&mut self.y_offset, y_offset,);
+		set(&mut self.padding_left, padding_left,);
+		set(&mut self.padding_right, padding_right,);
+		set(&mut self.notch_width, notch_width,);
+		set(&mut self.display, display,);
+		set(&mut self.hidden, hidden,);
+		set(&mut self.topmost, topmost,);
+	}
+}
+
+impl WriteProperties for BarProperties {
+	fn write(&self, w: &mut Writer<'_,>,) {
+		let Self {
+			position,
+			height,
+			sticky,
+			shadow,
+			font_smoothing,
+			show_in_fullscreen,
+			margin,
+			color,
+			border_color,
+			border_width,
+			corner_radius,
+			blur_radius,
+			y_offset,
+			padding_left,
+			padding_right,
+			notch_width,
+			display,
+			hidden,
+			topmost,
+		} = self;
+		w.put("position", position,);
+		w.put("height", height,);
+		w.put("sticky", sticky,);
+		w.put("shadow", shadow,);
+		w.put("font_smoothing", font_smoothing,);
+		w.put("show_in_fullscreen", show_in_fullscreen,);
+		w.put("margin", margin,);
+		w.put("color", color,);
+		w.put("border_color", border_color,);
+		w.put("border_width", border_width,);
+		w.put("corner_radius", corner_radius,);
+		w.put("blur_radius", blur_radius,);
+		w.put("y_offset", y_offset,);
+		w.put("padding_left", padding_left,);
+		w.put("padding_right", padding_right,);
+		w.put("notch_width", notch_width,);
+		w.put("display", display,);
+		w.put("hidden", hidden,);
+		w.put("topmost", topmost,);
+	}
+}
+
+impl From<&ItemProperties,> for PropertyList {
+	fn from(properties: &ItemProperties,) -> Self {
+		properties.to_property_list()
+	}
+}
+
+impl From<ItemProperties,> for PropertyList {
+	fn from(properties: ItemProperties,) -> Self {
+		properties.to_property_list()
+	}
+}
+
+impl From<&BarProperties,> for PropertyList {
+	fn from(properties: &BarProperties,) -> Self {
+		properties.to_property_list()
+	}
+}
+
+impl From<BarProperties,> for PropertyList {
+	fn from(properties: BarProperties,) -> Self {
+		properties.to_property_list()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_empty_properties_serialise_to_nothing() {
+		assert!(PropertyList::from(ItemProperties::default()).is_empty());
+		assert!(PropertyList::from(BarProperties::default()).is_empty());
+	}
+
+	#[test]
+	fn test_nested_keys_are_dotted() {
+		let props = ItemProperties {
+			position: Some(ItemPosition::Popup("clock".to_string(),),),
+			width: Some(Width::Fixed(120,),),
+			updates: Some(Updates::WhenShown,),
+			icon: TextProperties {
+				value: Some("X".to_string(),),
+				font: FontProperties { size: Some(14.5,), ..Default::default() },
+				..Default::default()
+			},
+			label: TextProperties::new("Visual Studio Code",),
+			background: BackgroundProperties {
+				color: Some(Color(0xff313244,),),
+				image: ImageProperties {
+					value: Some("app.Safari".to_string(),),
+					..Default::default()
+				},
+				shadow: ShadowProperties { distance: Some(3,), ..Default::default() },
+				..Default::default()
+			},
+			..Default::default()
+		};
+
+		let list = PropertyList::from(&props,);
+		let pairs: Vec<(&str, &str,),> = list.iter().collect();
+		assert_eq!(pairs, vec![
+			("position", "popup.clock"),
+			("width", "120"),
+			("updates", "when_shown"),
+			("icon", "X"),
+			("icon.font.size", "14.5"),
+			("label", "Visual Studio Code"),
+			("background.color", "0xff313244"),
+			("background.image", "app.Safari"),
+			("background.shadow.distance", "3"),
+		]);
+	}
+
+	#[test]
+	fn test_values_parse_as_sketchybar_spells_them() {
+		let popup = ItemPosition::Popup("clock".to_string(),);
+		for position in [ItemPosition::Left, ItemPosition::Q, popup,] {
+			assert_eq!(position.to_string().parse(), Ok(position));
+		}
+		assert_eq!("when_shown".parse(), Ok(Updates::WhenShown));
+		assert_eq!("dynamic".parse(), Ok(Width::Dynamic));
+		assert_eq!("0xff313244".parse(), Ok(Color(0xff313244)));
+		assert_eq!("#313244".parse(), Ok(Color(0xff313244)));
+		assert_eq!("#80313244".parse(), Ok(Color(0x80313244)));
+		assert!("313244".parse::<Color>().is_err());
+		assert!("popup.".parse::<ItemPosition>().is_err());
+	}
+
+	#[test]
+	fn test_overlay_keeps_unset_properties() {
+		let mut props = ItemProperties {
+			width: Some(Width::Dynamic,),
+			label: TextProperties {
+				color: Some(Color(1,),),
+				font: FontProperties { size: Some(14.0,), ..Default::default() },
+				..Default::default()
+			},
+			..Default::default()
+		};
+		props.overlay(&ItemProperties {
+			label: TextProperties {
+				font: FontProperties { size: Some(16.0,), ..Default::default() },
+				..Default::default()
+			},
+			..Default::default()
+		},);
+
+		assert_eq!(props.width, Some(Width::Dynamic));
+		assert_eq!(props.label.color, Some(Color(1)));
+		assert_eq!(props.label.font.size, Some(16.0));
+	}
+
+	#[test]
+	fn test_bar_properties_include_every_set_field() {
+		let props = BarProperties {
+			position: Some(BarPosition::Bottom,),
+			show_in_fullscreen: Some(true,),
+			color: Some(Color(0,),),
+			..Default::default()
+		};
+
+		let list = PropertyList::from(props,);
+		assert_eq!(list.get("position"), Some("bottom"));
+		assert_eq!(list.get("show_in_fullscreen"), Some("true"));
+		assert_eq!(list.get("color"), Some("0x00000000"));
+	}
+}
@@ -0,0 +1,473 @@
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::de::DeserializeOwned;
+use serde::de::Error;
+use std::collections::HashMap;
+
+use super::command::PropertyList;
+use super::properties::Color;
+
+/// Response to `--query bar`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize,)]
+#[serde(default)]
+pub struct BarInfo {
+	pub position:       String,
+	#[serde(deserialize_with = "on_off")]
+	pub topmost:        bool,
+	#[serde(deserialize_with = "on_off")]
+	pub sticky:         bool,
+	#[serde(deserialize_with = "on_off")]
+	pub hidden:         bool,
+	#[serde(deserialize_with = "on_off")]
+	pub shadow:         bool,
+	#[serde(deserialize_with = "on_off")]
+	pub font_smoothing: bool,
+	pub blur_radius:    u32,
+	pub margin:         i32,
+	#[serde(deserialize_with = "color")]
+	pub color:          Color,
+	#[serde(deserialize_with = "color")]
+	pub border_color:   Color,
+	pub border_width:   u32,
+	pub corner_radius:  u32,
+	pub height:         u32,
+	pub notch_width:    u32,
+	pub y_offset:       i32,
+	pub padding_left:   i32,
+	pub padding_right:  i32,
+	#[serde(deserialize_with = "string_or_number")]
+	pub display:        String,
+	pub items:          Vec<String,>,
+}
+
+/// Response to `--query <item>`; `--query defaults` shares the shape with `name` and
+/// `kind` left empty
+#[derive(Debug, Clone, Default, PartialEq, Deserialize,)]
+#[serde(default)]
+pub struct ItemInfo {
+	pub name:           String,
+	#[serde(rename = "type")]
+	pub kind:           String,
+	pub geometry:       GeometryInfo,
+	pub icon:           TextInfo,
+	pub label:          TextInfo,
+	pub scripting:      ScriptingInfo,
+	pub bounding_rects: HashMap<String, RectInfo,>,
+}
+
+impl ItemInfo {
+	/// Whether the item is drawn at all
+	pub fn is_drawing(&self,) -> bool {
+		self.geometry.drawing
+	}
+
+	/// Current label text
+	pub fn label_text(&self,) -> &str {
+		&self.label.value
+	}
+
+	/// Current icon text
+	pub fn icon_text(&self,) -> &str {
+		&self.icon.value
+	}
+
+	/// The item's state under the keys `--set` takes, spelled the way
+	/// [`ItemProperties`](super::ItemProperties) writes them
+	///
+	/// What the query does not report, such as `blur_radius`, `max_chars` or
+	/// the popup, is left out, as is an association with several displays or
+	/// spaces.
+	pub fn to_property_list(&self,) -> PropertyList {
+		let GeometryInfo {
+			drawing,
+			position,
+			associated_space_mask,
+			associated_display_mask,
+			ignore_association,
+			y_offset,
+			padding_left,
+			padding_right,
+			scroll_texts,
+			width,
+			background,
+		} = &self.geometry;
+		let ScriptingInfo { script, click_script, update_freq, update_mask: _, updates, } =
+			&self.scripting;
+
+		let mut out = PropertyList::new();
+		out.push("drawing", drawing,);
+		out.push("position", position,);
+		if let Some(display,) = single_bit(*associated_display_mask,) {
+			out.push("associated_display", display,);
+		}
+		if let Some(space,) = single_bit(*associated_space_mask,) {
+			out.push("associated_space", space,);
+		}
+		out.push("ignore_association", ignore_association,);
+		out.push("y_offset", y_offset,);
+		out.push("padding_left", padding_left,);
+		out.push("padding_right", padding_right,);
+		out.push("width", width,);
+		out.push("scroll_texts", scroll_texts,);
+		out.push("updates", updates,);
+		out.push("update_freq", update_freq,);
+		out.push("script", script,);
+		out.push("click_script", click_script,);
+		self.icon.write("icon", &mut out,);
+		self.label.write("label", &mut out,);
+		background.write("background", &mut out,);
+		out
+	}
+}
+
+/// The one bit set in `mask`, as sketchybar numbers displays and spaces
+fn single_bit(mask: u64,) -> Option<u32,> {
+	(mask.count_ones() == 1).then(|| mask.trailing_zeros(),)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize,)]
+#[serde(default)]
+pub struct GeometryInfo {
+	#[serde(deserialize_with = "on_off")]
+	pub drawing:                 bool,
+	pub position:                String,
+	pub associated_space_mask:   u64,
+	pub associated_display_mask: u64,
+	#[serde(deserialize_with = "on_off")]
+	pub ignore_association:      bool,
+	pub y_offset:                i32,
+	pub padding_left:            i32,
+	pub padding_right:           i32,
+	#[serde(deserialize_with = "on_off")]
+	pub scroll_texts:            bool,
+	#[serde(deserialize_with = "string_or_number")]
+	pub width:                   String,
+	pub background:              BackgroundInfo,
+}
+
+/// State of an `icon` or `label`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize,)]
+#[serde(default)]
+pub struct TextInfo {
+	pub value:           String,
+	#[serde(deserialize_with = "on_off")]
+	pub drawing:         bool,
+	#[serde(deserialize_with = "on_off")]
+	pub highlight:       bool,
+	#[serde(deserialize_with = "color")]
+	pub color:           Color,
+	#[serde(deserialize_with = "color")]
+	pub highlight_color: Color,
+	pub padding_left:    i32,
+	pub padding_right:   i32,
+	pub y_offset:        i32,
+	/// `Family:Style:Size`
+	pub font:            String,
+	#[serde(deserialize_with = "string_or_number")]
+	pub width:           String,
+	pub align:           String,
+	pub background:      BackgroundInfo,
+	pub shadow:          ShadowInfo,
+}
+
+impl TextInfo {
+	fn write(&self, prefix: &str, out: &mut PropertyList,) {
+		let key = |name: &str| format!("{}.{}", prefix, name);
+		out.push(prefix, &self.value,);
+		out.push(&key("drawing",), self.drawing,);
+		out.push(&key("color",), self.color,);
+		out.push(&key("highlight",), self.highlight,);
+		out.push(&key("highlight_color",), self.highlight_color,);
+		out.push(&key("padding_left",), self.padding_left,);
+		out.push(&key("padding_right",), self.padding_right,);
+		out.push(&key("y_offset",), self.y_offset,);
+		out.push(&key("width",), &self.width,);
+		out.push(&key("align",), &self.align,);
+		if let [family, style, size,] = self.font.split(':',).collect::<Vec<&str,>>()[..] {
+			out.push(&key("font.family",), family,);
+			out.push(&key("font.style",), style,);
+			if let Ok(size,) = size.parse::<f32>() {
+				out.push(&key("font.size",), size,);
+			}
+		}
+		self.background.write(&key("background",), out,);
+		self.shadow.write(&key("shadow",), out,);
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize,)]
+#[serde(default)]
+pub struct BackgroundInfo {
+	#[serde(deserialize_with = "on_off")]
+	pub drawing:       bool,
+	#[serde(deserialize_with = "color")]
+	pub color:         Color,
+	#[serde(deserialize_with = "color")]
+	pub border_color:  Color,
+	pub border_width:  u32,
+	pub height:        u32,
+	pub corner_radius: u32,
+	pub padding_left:  i32,
+	pub padding_right: i32,
+	pub x_offset:      i32,
+	pub y_offset:      i32,
+	pub clip:          f32,
+	pub image:         ImageInfo,
+	pub shadow:        ShadowInfo,
+}
+
+impl BackgroundInfo {
+	fn write(&self, prefix: &str, out: &mut PropertyList,) {
+		let key = |name: &str| format!("{}.{}", prefix, name);
+		out.push(&key("drawing",), self.drawing,);
+		out.push(&key("color",), self.color,);
+		out.push(&key("border_color",), self.border_color,);
+		out.push(&key("border_width",), self.border_width,);
+		out.push(&key("height",), self.height,);
+		out.push(&key("corner_radius",), self.corner_radius,);
+		out.push(&key("padding_left",), self.padding_left,);
+		out.push(&key("padding_right",), self.padding_right,);
+		out.push(&key("x_offset",), self.x_offset,);
+		out.push(&key("y_offset",), self.y_offset,);
+		out.push(&key("clip",), self.clip,);
+		out.push(&key("image",), &self.image.value,);
+		out.push(&key("image.drawing",), self.image.drawing,);
+		out.push(&key("image.scale",), self.image.scale,);
+		self.shadow.write(&key("shadow",), out,);
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize,)]
+#[serde(default)]
+pub struct ImageInfo {
+	pub value:   String,
+	#[serde(deserialize_with = "on_off")]
+	pub drawing: bool,
+	pub scale:   f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize,)]
+#[serde(default)]
+pub struct ShadowInfo {
+	#[serde(deserialize_with = "on_off")]
+	pub drawing:  bool,
+	#[serde(deserialize_with = "color")]
+	pub color:    Color,
+	pub angle:    u32,
+	pub distance: u32,
+}
+
+impl ShadowInfo {
+	fn write(&self, prefix: &str, out: &mut PropertyList,) {
+		let key = |name: &str| format!("{}.{}", prefix, name);
+		out.push(&key("drawing",), self.drawing,);
+		out.push(&key("color",), self.color,);
+		out.push(&key("angle",), self.angle,);
+		out.push(&key("distance",), self.distance,);
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize,)]
+#[serde(default)]
+pub struct ScriptingInfo {
+	pub script:       String,
+	pub click_script: String,
+	pub update_freq:  u32,
+	pub update_mask:  u64,
+	pub updates:      String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize,)]
+#[serde(default)]
+pub struct RectInfo {
+	pub origin: [f64; 2],
+	pub size:   [f64; 2],
+}
+
+/// One entry of the `--query events` map
+#[derive(Debug, Clone, Default, PartialEq, Deserialize,)]
+#[serde(default)]
+pub struct EventInfo {
+	pub bit:          u64,
+	pub notification: Option<String,>,
+}
+
+/// Parse a query response, naming the query in the error
+pub fn parse<T: DeserializeOwned,>(query: &str, response: &str,) -> Result<T,> {
+	serde_json::from_str(response,)
+		.with_context(|| format!("Failed to parse response to --query {}: {:?}", query, response),)
+}
+
+#[derive(Deserialize,)]
+#[serde(untagged)]
+enum Loose {
+	Bool(bool,),
+	Number(f64,),
+	Text(String,),
+}
+
+/// sketchybar reports booleans as `on`/`off`
+fn on_off<'de, D: Deserializer<'de,>,>(deserializer: D,) -> Result<bool, D::Error,> {
+	match Loose::deserialize(deserializer,)? {
+		Loose::Bool(value,) => Ok(value,),
+		Loose::Number(value,) => Ok(value != 0.0,),
+		Loose::Text(text,) => Ok(!matches!(text.as_str(), "off" | "false" | "no" | ""),),
+	}
+}
+
+/// sketchybar reports colors as `0xAARRGGBB` strings
+fn color<'de, D: Deserializer<'de,>,>(deserializer: D,) -> Result<Color, D::Error,> {
+	match Loose::deserialize(deserializer,)? {
+		Loose::Number(value,) => Ok(Color(value as u32,),),
+		Loose::Text(text,) => {
+			let hex = text.trim_start_matches("0x",).trim_start_matches("0X",);
+			u32::from_str_radix(hex, 16,)
+				.map(Color,)
+				.map_err(|_| D::Error::custom(format!("invalid color {:?}", text),),)
+		},
+		Loose::Bool(_,) => Err(D::Error::custom("expected a color, found a boolean",),),
+	}
+}
+
+/// Fields such as `width` and `display` are either a keyword or a number
+fn string_or_number<'de, D: Deserializer<'de,>,>(deserializer: D,) -> Result<String, D::Error,> {
+	match Loose::deserialize(deserializer,)? {
+		Loose::Text(text,) => Ok(text,),
+		Loose::Number(value,) => Ok(value.to_string(),),
+		Loose::Bool(value,) => Ok(value.to_string(),),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const BAR: &str = r#"{
+		"position": "top",
+		"topmost": "window",
+		"sticky": "on",
+		"hidden": "off",
+		"shadow": "off",
+		"font_smoothing": "off",
+		"blur_radius": 0,
+		"margin": 0,
+		"color": "0x00000000",
+		"border_color": "0xffff0000",
+		"border_width": 0,
+		"corner_radius": 0,
+		"height": 56,
+		"notch_width": 200,
+		"y_offset": 8,
+		"padding_left": 2,
+		"padding_right": 2,
+		"display": "all",
+		"items": ["space.1", "clock"]
+	}"#;
+
+	const ITEM: &str = r#"{
+		"name": "clock",
+		"type": "item",
+		"geometry": {
+			"drawing": "on",
+			"position": "right",
+			"associated_space_mask": 0,
+			"associated_display_mask": 2,
+			"ignore_association": "off",
+			"y_offset": 0,
+			"padding_left": 4,
+			"padding_right": 4,
+			"scroll_texts": "on",
+			"width": "dynamic",
+			"background": { "drawing": "on", "color": "0xff313244", "height": 40 }
+		},
+		"icon": { "value": "", "drawing": "on", "font": "MesloLGL Nerd Font:Regular:16.00" },
+		"label": { "value": "250101 1200 Wed", "color": "0xfff2cdcd", "width": 120 },
+		"scripting": { "script": "", "update_freq": 1, "updates": "when_shown" },
+		"bounding_rects": { "display-1": { "origin": [1700.0, 8.0], "size": [150.0, 40.0] } },
+		"some_future_field": { "x": 1 }
+	}"#;
+
+	#[test]
+	fn test_parse_bar() {
+		let bar: BarInfo = parse("bar", BAR,).unwrap();
+		assert_eq!(bar.position, "top");
+		assert!(bar.topmost);
+		assert!(bar.sticky);
+		assert!(!bar.hidden);
+		assert_eq!(bar.border_color, Color(0xffff0000));
+		assert_eq!(bar.display, "all");
+		assert_eq!(bar.items, vec!["space.1", "clock"]);
+	}
+
+	#[test]
+	fn test_parse_item() {
+		let item: ItemInfo = parse("clock", ITEM,).unwrap();
+		assert_eq!(item.name, "clock");
+		assert_eq!(item.kind, "item");
+		assert!(item.is_drawing());
+		assert_eq!(item.geometry.associated_display_mask, 2);
+		assert_eq!(item.geometry.background.color, Color(0xff313244));
+		assert_eq!(item.label_text(), "250101 1200 Wed");
+		assert_eq!(item.label.width, "120");
+		assert_eq!(item.scripting.updates, "when_shown");
+		assert_eq!(item.bounding_rects["display-1"].size, [150.0, 40.0]);
+	}
+
+	#[test]
+	fn test_item_state_as_set_keys() {
+		use crate::sketchybar::ItemProperties;
+		use crate::sketchybar::properties::Width;
+
+		let item: ItemInfo = parse("clock", ITEM,).unwrap();
+		let state = item.to_property_list();
+		assert_eq!(state.get("drawing"), Some("true"));
+		assert_eq!(state.get("associated_display"), Some("1"));
+		assert_eq!(state.get("associated_space"), None);
+		assert_eq!(state.get("width"), Some("dynamic"));
+		assert_eq!(state.get("label"), Some("250101 1200 Wed"));
+		assert_eq!(state.get("label.color"), Some("0xfff2cdcd"));
+		assert_eq!(state.get("icon.font.family"), Some("MesloLGL Nerd Font"));
+		assert_eq!(state.get("icon.font.size"), Some("16"));
+		assert_eq!(state.get("background.color"), Some("0xff313244"));
+		assert_eq!(state.get("update_freq"), Some("1"));
+
+		// Spelled the way the properties that set it are
+		let set = ItemProperties {
+			associated_display: Some(1,),
+			width: Some(Width::Dynamic,),
+			..ItemProperties::label("250101 1200 Wed",)
+		};
+		let set = PropertyList::from(&set,);
+		assert!(set.iter().all(|(key, value,)| state.get(key,) == Some(value,)));
+	}
+
+	#[test]
+	fn test_parse_events_and_menu_items() {
+		let events: HashMap<String, EventInfo,> = parse(
+			"events",
+			r#"{"front_app_switched": {"bit": 1}, "theme": {"bit": 64, "notification": "AppleInterfaceThemeChangedNotification"}}"#,
+		)
+		.unwrap();
+		assert_eq!(events["front_app_switched"].bit, 1);
+		assert_eq!(
+			events["theme"].notification.as_deref(),
+			Some("AppleInterfaceThemeChangedNotification")
+		);
+
+		let menu: Vec<String,> =
+			parse("default_menu_items", r#"["Control Center,Battery", "Control Center,WiFi"]"#,)
+				.unwrap();
+		assert_eq!(menu.len(), 2);
+	}
+
+	#[test]
+	fn test_parse_errors_name_the_query() {
+		let err = parse::<BarInfo,>("bar", "",).unwrap_err();
+		assert!(err.to_string().contains("--query bar"));
+
+		let err = parse::<ItemInfo,>("clock", r#"{"label": {"color": "blue"}}"#,).unwrap_err();
+		assert!(format!("{:#}", err).contains("invalid color"));
+	}
+}
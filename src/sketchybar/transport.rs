@@ -0,0 +1,504 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::Value;
+use serde_json::json;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::task::spawn_blocking;
+use tokio::time::Duration;
+use tokio::time::timeout;
+
+use super::command::split_message;
+use crate::runner;
+use crate::runner::Invocation;
+use crate::runner::RunError;
+use crate::runner::Runner;
+
+/// How long a bar may take to answer one message
+pub const MESSAGE_TIMEOUT: Duration = Duration::from_secs(2,);
+
+/// Delivery mechanism for messages addressed to a sketchybar instance
+#[async_trait]
+pub trait BarTransport: Debug + Send + Sync {
+	/// Deliver `msg` to the bar registered as `bar_name` and return its response
+	async fn send(&self, bar_name: &str, msg: &str,) -> Result<String,>;
+}
+
+/// Transport backed by the sketchybar-rs mach client
+///
+/// The client caches the mach port of the first bar it reaches for the whole
+/// process, so it can only address one bar reliably; use [`CliTransport`]
+/// when the daemon drives several named instances.
+///
+/// The client blocks, and does not return at all when the bar never answers,
+/// so each message runs on the blocking pool and is abandoned after
+/// [`MESSAGE_TIMEOUT`].
+#[derive(Debug, Default, Clone, Copy,)]
+pub struct MachTransport;
+
+#[async_trait]
+impl BarTransport for MachTransport {
+	async fn send(&self, bar_name: &str, msg: &str,) -> Result<String,> {
+		let (name, message,) = (bar_name.to_string(), msg.to_string(),);
+		let reply = spawn_blocking(move || sketchybar_rs::message(&message, Some(&name,),),);
+		match timeout(MESSAGE_TIMEOUT, reply,).await {
+			Ok(Ok(Ok(response,),),) => Ok(response,),
+			Ok(Ok(Err(e,),),) => anyhow::bail!("SketchyBar error for '{}': {}", bar_name, e),
+			Ok(Err(e,),) => anyhow::bail!("SketchyBar client for '{}' failed: {}", bar_name, e),
+			Err(_,) => anyhow::bail!(
+				"SketchyBar error for '{}': no answer within {:?}",
+				bar_name,
+				MESSAGE_TIMEOUT
+			),
+		}
+	}
+}
+
+/// Transport that runs the sketchybar binary as a client, addressing the bar
+/// with `--name`
+///
+/// Arguments are passed as a real argv, split exactly as the mach client would.
+#[derive(Debug, Clone,)]
+pub struct CliTransport {
+	binary: PathBuf,
+	runner: Arc<dyn Runner,>,
+}
+
+impl CliTransport {
+	pub fn new(binary: impl Into<PathBuf,>,) -> Self {
+		Self::with_runner(binary, runner::system(),)
+	}
+
+	/// Run the client through `runner` instead of the shared one
+	pub fn with_runner(binary: impl Into<PathBuf,>, runner: Arc<dyn Runner,>,) -> Self {
+		Self { binary: binary.into(), runner, }
+	}
+}
+
+#[async_trait]
+impl BarTransport for CliTransport {
+	async fn send(&self, bar_name: &str, msg: &str,) -> Result<String,> {
+		let binary = self.binary.to_string_lossy();
+		let args = split_message(msg,);
+		let argv: Vec<&str,> =
+			["--name", bar_name,].into_iter().chain(args.iter().map(String::as_str,),).collect();
+
+		let client = Invocation::new(&binary, &argv,).timeout(MESSAGE_TIMEOUT,);
+		match client.output(self.runner.as_ref(),).await {
+			Ok(response,) => Ok(response,),
+			Err(RunError::Failed { stderr, .. },) => {
+				anyhow::bail!("SketchyBar error for '{}': {}", bar_name, stderr)
+			},
+			Err(e,) => anyhow::bail!("SketchyBar error for '{}': {}", bar_name, e),
+		}
+	}
+}
+
+/// A message captured by [`RecordingTransport`]
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct SentMessage {
+	pub bar_name: String,
+	pub message:  String,
+}
+
+/// In-memory transport that records every message and replays canned responses
+///
+/// Clones share the same recording, so a test can keep one handle while the
+/// bars under test own the others. Each bar also keeps a minimal model of the
+/// items and events it has been told to `--add`, `--set`, `--subscribe` and
+/// `--remove`, which answers `--query bar`, `--query events` and
+/// `--query <item>` when no canned response is set up.
+#[derive(Debug, Clone, Default,)]
+pub struct RecordingTransport {
+	inner: Arc<Mutex<Recording,>,>,
+}
+
+#[derive(Debug, Default,)]
+struct Recording {
+	sent:          Vec<SentMessage,>,
+	responses:     HashMap<String, String,>,
+	bar_responses: HashMap<(String, String,), String,>,
+	bars:          HashMap<String, SimulatedBar,>,
+	unreachable:   HashSet<String,>,
+}
+
+/// Events every sketchybar knows, in the order they take mask bits
+const BUILTIN_EVENTS: &[&str] = &[
+	"front_app_switched",
+	"space_change",
+	"display_change",
+	"volume_change",
+	"brightness_change",
+	"power_source_change",
+	"wifi_change",
+	"media_change",
+	"system_will_sleep",
+	"system_woke",
+	"mouse.entered",
+	"mouse.exited",
+	"mouse.clicked",
+	"mouse.scrolled",
+];
+
+/// Items a real bar would hold after the messages it has received
+#[derive(Debug, Default,)]
+struct SimulatedBar {
+	items:  Vec<SimulatedItem,>,
+	/// Events added with `--add event`, after [`BUILTIN_EVENTS`]
+	events: Vec<(String, Option<String,>,),>,
+}
+
+#[derive(Debug,)]
+struct SimulatedItem {
+	name:        String,
+	kind:        String,
+	position:    String,
+	properties:  HashMap<String, String,>,
+	update_mask: u64,
+}
+
+impl SimulatedBar {
+	/// Mask bit of a registered event
+	fn event_bit(&self, name: &str,) -> Option<u64,> {
+		let custom = self.events.iter().map(|(event, _,)| event.as_str(),);
+		let mut events = BUILTIN_EVENTS.iter().copied().chain(custom,);
+		events.position(|event| event == name,).map(|i| 1 << i,)
+	}
+
+	fn item_mut(&mut self, name: &str,) -> Option<&mut SimulatedItem,> {
+		self.items.iter_mut().find(|item| item.name == name,)
+	}
+
+	fn apply(&mut self, msg: &str,) {
+		let args = split_message(msg,);
+		let starts: Vec<usize,> =
+			(0..args.len()).filter(|i| args[*i].starts_with("--",),).collect();
+
+		for (n, start,) in starts.iter().enumerate() {
+			let end = starts.get(n + 1,).copied().unwrap_or(args.len(),);
+			match &args[*start..end] {
+				[op, kind, name, position, ..]
+					if op == "--add"
+						&& matches!(kind.as_str(), "item" | "space" | "alias")
+						&& self.items.iter().all(|item| item.name != *name,) =>
+				{
+					self.items.push(SimulatedItem {
+						name:        name.clone(),
+						kind:        kind.clone(),
+						position:    position.clone(),
+						properties:  HashMap::new(),
+						update_mask: 0,
+					},);
+				},
+				[op, kind, name, notification @ ..]
+					if op == "--add" && kind == "event" && self.event_bit(name,).is_none() =>
+				{
+					self.events.push((name.clone(), notification.first().cloned(),),);
+				},
+				[op, name, events @ ..] if op == "--subscribe" => {
+					let bits = events.iter().filter_map(|event| self.event_bit(event,),);
+					let mask = bits.fold(0, |mask, bit| mask | bit,);
+					if let Some(item,) = self.item_mut(name,) {
+						item.update_mask |= mask;
+					}
+				},
+				[op, name] if op == "--remove" => self.items.retain(|item| item.name != *name,),
+				[op, names @ ..] if op == "--reorder" => self.reorder(names,),
+				[op, name, properties @ ..] if op == "--set" => {
+					let Some(item,) = self.item_mut(name,) else { continue };
+					for (key, value,) in properties.iter().filter_map(|p| p.split_once('=',),) {
+						if key == "position" {
+							item.position = value.to_string();
+						} else {
+							item.properties.insert(key.to_string(), value.to_string(),);
+						}
+					}
+				},
+				_ => {},
+			}
+		}
+	}
+
+	/// The listed items take the slots they already hold, in the listed order
+	fn reorder(&mut self, names: &[String],) {
+		let slots: Vec<usize,> = (0..self.items.len())
+			.filter(|i| names.contains(&self.items[*i].name,),)
+			.collect();
+		let mut listed: Vec<SimulatedItem,> = Vec::new();
+		for name in names {
+			if let Some(i,) = self.items.iter().position(|item| item.name == *name,) {
+				listed.push(self.items.remove(i,),);
+			}
+		}
+		for (slot, item,) in slots.into_iter().zip(listed,) {
+			self.items.insert(slot, item,);
+		}
+	}
+
+	fn query(&self, msg: &str,) -> Option<String,> {
+		let args = split_message(msg,);
+		let [op, target,] = args.as_slice() else { return None };
+		if op != "--query" {
+			return None;
+		}
+
+		if target == "bar" {
+			let names: Vec<&str,> = self.items.iter().map(|item| item.name.as_str(),).collect();
+			return Some(json!({ "items": names }).to_string(),);
+		}
+		if target == "events" {
+			let custom =
+				self.events.iter().map(|(name, notification,)| (name.as_str(), notification,),);
+			let events: serde_json::Map<String, Value,> = BUILTIN_EVENTS
+				.iter()
+				.map(|name| (*name, &None,),)
+				.chain(custom,)
+				.enumerate()
+				.map(|(i, (name, notification,),)| {
+					let bit = 1_u64 << i;
+					(name.to_string(), json!({ "bit": bit, "notification": notification }),)
+				},)
+				.collect();
+			return Some(Value::Object(events,).to_string(),);
+		}
+
+		let item = self.items.iter().find(|item| item.name == *target,)?;
+		Some(item.to_json().to_string(),)
+	}
+}
+
+impl SimulatedItem {
+	/// What `--query <item>` reports, nested and spelled the way sketchybar
+	/// spells it
+	fn to_json(&self,) -> Value {
+		let mut item = json!({
+			"name": self.name,
+			"type": self.kind,
+			"geometry": { "drawing": "on", "position": self.position },
+			"icon": {},
+			"label": {},
+			"scripting": { "update_mask": self.update_mask },
+		});
+		for (key, value,) in &self.properties {
+			if key.contains(".font.",) {
+				continue;
+			}
+			let mut path: Vec<&str,> = key.split('.',).collect();
+			match path[0] {
+				"icon" | "label" if path.len() == 1 => path.push("value",),
+				"icon" | "label" => {},
+				"script" | "click_script" | "update_freq" | "updates" => {
+					path.insert(0, "scripting",)
+				},
+				_ => path.insert(0, "geometry",),
+			}
+			if path.last() == Some(&"image",) {
+				path.push("value",);
+			}
+			let value = match path.as_slice() {
+				["geometry", "associated_display" | "associated_space",] => {
+					path[1] = if path[1] == "associated_display" {
+						"associated_display_mask"
+					} else {
+						"associated_space_mask"
+					};
+					json!(value.parse::<u32>().map_or(0, |n| 1_u64 << n))
+				},
+				// Text, even when it reads as a number
+				[.., "value" | "script" | "click_script" | "position" | "updates",]
+				| [.., "width" | "align",] => json!(value),
+				_ => match value.as_str() {
+					"true" => json!("on"),
+					"false" => json!("off"),
+					_ => value.parse::<f64>().map_or_else(|_| json!(value), |n| json!(n)),
+				},
+			};
+			insert(&mut item, &path, value,);
+		}
+
+		// `icon.font.family` and friends come back as one `Family:Style:Size`
+		for text in ["icon", "label",] {
+			let font = |part: &str| self.properties.get(&format!("{}.font.{}", text, part),);
+			if let (None, None, None,) = (font("family",), font("style",), font("size",),) {
+				continue;
+			}
+			let size = font("size",).and_then(|size| size.parse::<f32>().ok(),).unwrap_or_default();
+			let family = font("family",).map_or("", String::as_str,);
+			let style = font("style",).map_or("", String::as_str,);
+			item[text]["font"] = json!(format!("{}:{}:{:.2}", family, style, size));
+		}
+		item
+	}
+}
+
+/// Set `value` at `path` in `root`, making objects along the way
+fn insert(root: &mut Value, path: &[&str], value: Value,) {
+	let mut node = root;
+	for part in path {
+		if !node.is_object() {
+			*node = json!({});
+		}
+		node = node.as_object_mut().unwrap().entry(*part,).or_insert(Value::Null,);
+	}
+	*node = value;
+}
+
+impl RecordingTransport {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Serve `response` whenever any bar receives exactly `msg`
+	pub fn respond(&self, msg: &str, response: &str,) {
+		let mut inner = self.inner.lock().unwrap();
+		inner.responses.insert(msg.to_string(), response.to_string(),);
+	}
+
+	/// Serve `response` when `bar_name` receives exactly `msg`, overriding [`Self::respond`]
+	pub fn respond_for(&self, bar_name: &str, msg: &str, response: &str,) {
+		let mut inner = self.inner.lock().unwrap();
+		inner.bar_responses.insert((bar_name.to_string(), msg.to_string(),), response.to_string(),);
+	}
+
+	/// All messages sent so far, in order
+	pub fn sent(&self,) -> Vec<SentMessage,> {
+		self.inner.lock().unwrap().sent.clone()
+	}
+
+	/// Messages sent to a single bar, in order
+	pub fn sent_to(&self, bar_name: &str,) -> Vec<String,> {
+		let inner = self.inner.lock().unwrap();
+		inner.sent.iter().filter(|m| m.bar_name == bar_name,).map(|m| m.message.clone(),).collect()
+	}
+
+	/// Whether `bar_name` has received exactly `msg`
+	pub fn received(&self, bar_name: &str, msg: &str,) -> bool {
+		let inner = self.inner.lock().unwrap();
+		inner.sent.iter().any(|m| m.bar_name == bar_name && m.message == msg,)
+	}
+
+	/// Forget recorded messages, keeping canned responses
+	pub fn clear(&self,) {
+		self.inner.lock().unwrap().sent.clear();
+	}
+
+	/// Make every message to `bar_name` fail, as if sketchybar had exited
+	pub fn set_unreachable(&self, bar_name: &str, unreachable: bool,) {
+		let mut inner = self.inner.lock().unwrap();
+		if unreachable {
+			inner.unreachable.insert(bar_name.to_string(),);
+		} else {
+			inner.unreachable.remove(bar_name,);
+		}
+	}
+
+	/// Drop every simulated item on `bar_name`, as a sketchybar restart would
+	pub fn restart(&self, bar_name: &str,) {
+		self.inner.lock().unwrap().bars.remove(bar_name,);
+	}
+}
+
+#[async_trait]
+impl BarTransport for RecordingTransport {
+	async fn send(&self, bar_name: &str, msg: &str,) -> Result<String,> {
+		let mut inner = self.inner.lock().unwrap();
+		inner.sent.push(SentMessage { bar_name: bar_name.to_string(), message: msg.to_string(), },);
+		if inner.unreachable.contains(bar_name,) {
+			anyhow::bail!("SketchyBar error for '{}': bar is not running", bar_name);
+		}
+
+		let simulated = inner.bars.entry(bar_name.to_string(),).or_default();
+		simulated.apply(msg,);
+		let simulated = simulated.query(msg,);
+
+		let response = inner
+			.bar_responses
+			.get(&(bar_name.to_string(), msg.to_string(),),)
+			.or_else(|| inner.responses.get(msg,),)
+			.cloned()
+			.or(simulated,)
+			.unwrap_or_default();
+
+		Ok(response,)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	async fn send(transport: &impl BarTransport, bar_name: &str, msg: &str,) -> String {
+		transport.send(bar_name, msg,).await.unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_recording_transport_captures_messages() {
+		let transport = RecordingTransport::new();
+		let handle = transport.clone();
+
+		send(&transport, "sketchybar", "--set clock label=now",).await;
+		send(&transport, "external_2", "--set space.3 drawing=on",).await;
+
+		assert_eq!(handle.sent().len(), 2);
+		assert_eq!(handle.sent_to("external_2"), vec!["--set space.3 drawing=on".to_string()]);
+		assert!(handle.received("sketchybar", "--set clock label=now"));
+		assert!(!handle.received("external_2", "--set clock label=now"));
+
+		handle.clear();
+		assert!(handle.sent().is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_recording_transport_replays_responses() {
+		let transport = RecordingTransport::new();
+		transport.respond("--query bar", r#"{"position":"top"}"#,);
+		transport.respond_for("external_2", "--query bar", r#"{"position":"bottom"}"#,);
+
+		assert_eq!(send(&transport, "sketchybar", "--query bar").await, r#"{"position":"top"}"#);
+		assert_eq!(send(&transport, "external_2", "--query bar").await, r#"{"position":"bottom"}"#);
+		assert_eq!(send(&transport, "sketchybar", "--update").await, "");
+	}
+
+	#[tokio::test]
+	async fn test_cli_transport_passes_argv() {
+		let transport = CliTransport::new("echo",);
+		let response = send(&transport, "external_2", "--set window label=\"a  b\"",).await;
+		assert_eq!(response, "--name external_2 --set window label=a  b\n");
+
+		let err = CliTransport::new("false",).send("external_2", "--update",).await.unwrap_err();
+		assert!(err.to_string().contains("external_2"));
+	}
+
+	#[tokio::test]
+	async fn test_recording_transport_simulates_items() {
+		let transport = RecordingTransport::new();
+		send(&transport, "sketchybar", "--add item clock right --add space space.1 left",).await;
+		send(&transport, "sketchybar", "--set clock label=\"12 00\" drawing=off",).await;
+		send(&transport, "sketchybar", "--set space.1 position=right --remove space.1",).await;
+		send(&transport, "external_2", "--add item window left",).await;
+
+		assert_eq!(send(&transport, "sketchybar", "--query bar").await, r#"{"items":["clock"]}"#);
+		assert_eq!(send(&transport, "external_2", "--query bar").await, r#"{"items":["window"]}"#);
+
+		let clock = send(&transport, "sketchybar", "--query clock",).await;
+		let clock: serde_json::Value = serde_json::from_str(&clock,).unwrap();
+		assert_eq!(clock["type"], "item");
+		assert_eq!(clock["label"]["value"], "12 00");
+		assert_eq!(clock["geometry"]["drawing"], "off");
+		assert_eq!(send(&transport, "sketchybar", "--query space.1").await, "");
+
+		// Subscriptions show up as the registered events' bits
+		let subscribe = "--add event theme Notification --subscribe clock theme";
+		send(&transport, "sketchybar", subscribe,).await;
+		let events = send(&transport, "sketchybar", "--query events",).await;
+		let events: serde_json::Value = serde_json::from_str(&events,).unwrap();
+		assert_eq!(events["theme"]["notification"], "Notification");
+		let clock = send(&transport, "sketchybar", "--query clock",).await;
+		let clock: serde_json::Value = serde_json::from_str(&clock,).unwrap();
+		assert_eq!(clock["scripting"]["update_mask"], events["theme"]["bit"]);
+	}
+}
@@ -1,102 +1,328 @@
 use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio::sync::broadcast;
 use tracing::debug;
 
+use crate::helpers::yabai::DisplayInfo;
+use crate::runner;
+use crate::runner::Runner;
+use crate::wm::WindowManagerBackend;
+use crate::wm::YabaiBackend;
+
+/// Parts of [`DaemonState`] an item can depend on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash,)]
+pub enum StateDependency {
+	Spaces,
+	Windows,
+	CurrentApp,
+	Displays,
+	Battery,
+	InputSource,
+	Appearance,
+}
+
+/// A change to the synced desktop state, published by [`DaemonState`]
+#[derive(Debug, Clone, PartialEq,)]
+pub enum StateEvent {
+	SpaceAdded(u32,),
+	SpaceRemoved(u32,),
+	/// Focus moved between spaces; either side is `None` when no space had focus
+	SpaceFocusChanged { from: Option<u32,>, to: Option<u32,>, },
+	/// The set of windows on a space changed
+	SpaceWindowsChanged(u32,),
+	/// A space moved to another display, was relabelled, or gained or lost
+	/// focus without the focused space changing
+	SpaceChanged(u32,),
+	WindowOpened(u32,),
+	WindowClosed(u32,),
+	WindowTitleChanged { id: u32, title: String, },
+	/// A window moved to another space or display, now reports a different
+	/// app, or gained or lost focus without the focused window changing
+	WindowChanged(u32,),
+	WindowFocusChanged { from: Option<u32,>, to: Option<u32,>, },
+	AppSwitched { from: Option<String,>, to: String, },
+	DisplayAdded(String,),
+	DisplayRemoved(String,),
+	BatteryChanged(BatteryInfo,),
+	InputSourceChanged(String,),
+	AppearanceChanged(Appearance,),
+	/// The bar for this display id was (re)provisioned and holds default values
+	BarProvisioned(String,),
+}
+
+impl StateEvent {
+	/// The part of the state this event is about; `None` for events every item cares about
+	pub fn dependency(&self,) -> Option<StateDependency,> {
+		match self {
+			StateEvent::SpaceAdded(_,)
+			| StateEvent::SpaceRemoved(_,)
+			| StateEvent::SpaceFocusChanged { .. }
+			| StateEvent::SpaceWindowsChanged(_,)
+			| StateEvent::SpaceChanged(_,) => Some(StateDependency::Spaces,),
+			StateEvent::WindowOpened(_,)
+			| StateEvent::WindowClosed(_,)
+			| StateEvent::WindowTitleChanged { .. }
+			| StateEvent::WindowChanged(_,)
+			| StateEvent::WindowFocusChanged { .. } => Some(StateDependency::Windows,),
+			StateEvent::AppSwitched { .. } => Some(StateDependency::CurrentApp,),
+			StateEvent::DisplayAdded(_,) | StateEvent::DisplayRemoved(_,) => {
+				Some(StateDependency::Displays,)
+			},
+			StateEvent::BatteryChanged(_,) => Some(StateDependency::Battery,),
+			StateEvent::InputSourceChanged(_,) => Some(StateDependency::InputSource,),
+			StateEvent::AppearanceChanged(_,) => Some(StateDependency::Appearance,),
+			StateEvent::BarProvisioned(_,) => None,
+		}
+	}
+}
+
 /// Centralized state management for the SketchyBar daemon
+///
+/// Providers write here once per refresh; every bar renders from it.
 #[derive(Debug, Clone,)]
 pub struct DaemonState {
-	pub spaces:      Arc<RwLock<HashMap<u32, SpaceInfo,>,>,>,
-	pub windows:     Arc<RwLock<HashMap<u32, WindowInfo,>,>,>,
-	pub current_app: Arc<RwLock<Option<String,>,>,>,
-	pub displays:    Arc<RwLock<HashMap<String, crate::helpers::yabai::DisplayInfo,>,>,>,
+	pub spaces:       Arc<RwLock<HashMap<u32, SpaceInfo,>,>,>,
+	pub windows:      Arc<RwLock<HashMap<u32, WindowInfo,>,>,>,
+	pub current_app:  Arc<RwLock<Option<String,>,>,>,
+	pub displays:     Arc<RwLock<HashMap<String, DisplayInfo,>,>,>,
+	pub battery:      Arc<RwLock<Option<BatteryInfo,>,>,>,
+	/// Short name of the selected keyboard layout, e.g. `US`
+	pub input_source: Arc<RwLock<Option<String,>,>,>,
+	/// Whether macOS is in light or dark mode
+	pub appearance:   Arc<RwLock<Option<Appearance,>,>,>,
+	events:           broadcast::Sender<StateEvent,>,
+	runner:           Arc<dyn Runner,>,
+	backend:          Arc<dyn WindowManagerBackend,>,
+}
+
+impl Default for DaemonState {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl DaemonState {
 	pub fn new() -> Self {
+		Self::with_runner(runner::system(),)
+	}
+
+	/// State whose providers run their external commands through `runner`,
+	/// syncing the desktop from yabai
+	pub fn with_runner(runner: Arc<dyn Runner,>,) -> Self {
+		let (events, _,) = broadcast::channel(64,);
 		Self {
-			spaces:      Arc::new(RwLock::new(HashMap::new(),),),
-			windows:     Arc::new(RwLock::new(HashMap::new(),),),
+			spaces: Arc::new(RwLock::new(HashMap::new(),),),
+			windows: Arc::new(RwLock::new(HashMap::new(),),),
 			current_app: Arc::new(RwLock::new(None,),),
-			displays:    Arc::new(RwLock::new(HashMap::new(),),),
+			displays: Arc::new(RwLock::new(HashMap::new(),),),
+			battery: Arc::new(RwLock::new(None,),),
+			input_source: Arc::new(RwLock::new(None,),),
+			appearance: Arc::new(RwLock::new(None,),),
+			events,
+			backend: Arc::new(YabaiBackend::new(runner.clone(),),),
+			runner,
+		}
+	}
+
+	/// Sync displays, spaces and windows from `backend` instead
+	pub fn with_backend(mut self, backend: Arc<dyn WindowManagerBackend,>,) -> Self {
+		self.backend = backend;
+		self
+	}
+
+	/// Runner for the external commands providers query
+	pub fn runner(&self,) -> &dyn Runner {
+		self.runner.as_ref()
+	}
+
+	/// The window manager the desktop state is synced from
+	pub fn backend(&self,) -> &dyn WindowManagerBackend {
+		self.backend.as_ref()
+	}
+
+	/// Receive every change published from now on
+	pub fn subscribe(&self,) -> broadcast::Receiver<StateEvent,> {
+		self.events.subscribe()
+	}
+
+	fn publish(&self, events: &[StateEvent],) {
+		for event in events {
+			// Nobody listening is fine
+			let _ = self.events.send(event.clone(),);
 		}
 	}
 
-	/// Update spaces state from yabai query
+	/// Update spaces state from the window manager
 	pub async fn update_spaces(&self,) -> Result<bool,> {
-		let spaces_data = crate::helpers::yabai::query_spaces().await?;
+		let spaces_data = self.backend.spaces().await?;
+		Ok(!self.apply_spaces(spaces_data,).await.is_empty(),)
+	}
+
+	/// Replace the spaces, publishing what changed
+	pub async fn apply_spaces(&self, spaces_data: Vec<SpaceInfo,>,) -> Vec<StateEvent,> {
 		let mut spaces = self.spaces.write().await;
+		let new: HashMap<u32, SpaceInfo,> =
+			spaces_data.into_iter().map(|s| (s.index, s,),).collect();
+		let events = diff_spaces(&spaces, &new,);
+		*spaces = new;
+		drop(spaces,);
 
-		let mut changed = false;
-
-		// Check for changes
-		for space in &spaces_data {
-			if let Some(existing,) = spaces.get(&space.index,) {
-				if existing.has_focus != space.has_focus
-					|| existing.windows.len() != space.windows.len()
-				{
-					changed = true;
-				}
-			} else {
-				changed = true;
-			}
+		if !events.is_empty() {
+			debug!("🏠 Spaces state updated ({} changes)", events.len());
 		}
+		self.publish(&events,);
+		events
+	}
+
+	/// Update windows state from the window manager
+	pub async fn update_windows(&self,) -> Result<bool,> {
+		let windows_data = self.backend.windows().await?;
+		Ok(!self.apply_windows(windows_data,).await.is_empty(),)
+	}
+
+	/// Replace the windows, publishing what changed
+	pub async fn apply_windows(&self, windows_data: Vec<WindowInfo,>,) -> Vec<StateEvent,> {
+		let mut windows = self.windows.write().await;
+		let new: HashMap<u32, WindowInfo,> =
+			windows_data.into_iter().map(|w| (w.id, w,),).collect();
+		let events = diff_windows(&windows, &new,);
+		*windows = new;
+		drop(windows,);
 
-		// Update state
-		spaces.clear();
-		for space in spaces_data {
-			spaces.insert(space.index, space,);
+		if !events.is_empty() {
+			debug!("🪟 Windows state updated ({} changes)", events.len());
 		}
+		self.publish(&events,);
+		events
+	}
+
+	/// Update current app state
+	///
+	/// When the window manager cannot name the focused app, as on an empty
+	/// desktop, the last one stays current.
+	pub async fn update_current_app(&self,) -> Result<bool,> {
+		let new_app = self.backend.focused_app().await?;
+		Ok(!self.apply_current_app(new_app,).await.is_empty(),)
+	}
 
-		if changed {
-			debug!("🏠 Spaces state updated ({} spaces)", spaces.len());
+	/// Record the focused app, publishing a switch if it changed
+	pub async fn apply_current_app(&self, new_app: String,) -> Vec<StateEvent,> {
+		let mut current_app = self.current_app.write().await;
+		if current_app.as_ref() == Some(&new_app,) {
+			return Vec::new();
 		}
 
-		Ok(changed,)
+		debug!("📱 Current app updated: {}", new_app);
+		let from = current_app.replace(new_app.clone(),);
+		drop(current_app,);
+
+		let events = vec![StateEvent::AppSwitched { from, to: new_app, }];
+		self.publish(&events,);
+		events
 	}
 
-	/// Update windows state from yabai query
-	pub async fn update_windows(&self,) -> Result<bool,> {
-		let windows_data = crate::helpers::yabai::query_windows().await?;
-		let mut windows = self.windows.write().await;
+	/// Update displays from the window manager
+	pub async fn update_displays(&self,) -> Result<bool,> {
+		let displays = self.backend.displays().await?;
+		Ok(!self.apply_displays(displays,).await.is_empty(),)
+	}
+
+	/// Replace the displays, publishing which were added or removed
+	pub async fn apply_displays(&self, new: HashMap<String, DisplayInfo,>,) -> Vec<StateEvent,> {
+		let mut displays = self.displays.write().await;
+		let mut events: Vec<StateEvent,> = displays
+			.keys()
+			.filter(|id| !new.contains_key(*id,),)
+			.map(|id| StateEvent::DisplayRemoved(id.clone(),),)
+			.collect();
+		events.extend(
+			new.keys()
+				.filter(|id| !displays.contains_key(*id,),)
+				.map(|id| StateEvent::DisplayAdded(id.clone(),),),
+		);
+		*displays = new;
+		drop(displays,);
 
-		eprintln!("reached here");
+		self.publish(&events,);
+		events
+	}
+
+	/// Store a battery reading, publishing it if it changed
+	pub async fn apply_battery(&self, info: BatteryInfo,) -> Vec<StateEvent,> {
+		let mut battery = self.battery.write().await;
+		if battery.as_ref() == Some(&info,) {
+			return Vec::new();
+		}
 
-		let mut changed = false;
-		let old_count = windows.len();
+		debug!("🔋 Battery state updated: {}%", info.charge);
+		*battery = Some(info.clone(),);
+		drop(battery,);
 
-		// Update state
-		windows.clear();
-		for window in windows_data {
-			windows.insert(window.id, window,);
+		let events = vec![StateEvent::BatteryChanged(info,)];
+		self.publish(&events,);
+		events
+	}
+
+	/// Store the selected input source, publishing it if it changed
+	pub async fn apply_input_source(&self, source: String,) -> Vec<StateEvent,> {
+		let mut input_source = self.input_source.write().await;
+		if input_source.as_ref() == Some(&source,) {
+			return Vec::new();
 		}
 
-		if windows.len() != old_count {
-			changed = true;
-			debug!("🪟 Windows state updated ({} windows)", windows.len());
+		debug!("⌨️  Input source updated: {}", source);
+		*input_source = Some(source.clone(),);
+		drop(input_source,);
+
+		let events = vec![StateEvent::InputSourceChanged(source,)];
+		self.publish(&events,);
+		events
+	}
+
+	/// Store the system appearance, publishing it if it changed
+	pub async fn apply_appearance(&self, appearance: Appearance,) -> Vec<StateEvent,> {
+		let mut current = self.appearance.write().await;
+		if *current == Some(appearance,) {
+			return Vec::new();
 		}
 
-		Ok(changed,)
+		debug!("🌗 Appearance updated: {}", appearance);
+		*current = Some(appearance,);
+		drop(current,);
+
+		let events = vec![StateEvent::AppearanceChanged(appearance,)];
+		self.publish(&events,);
+		events
 	}
 
-	/// Update current app state
-	pub async fn update_current_app(&self,) -> Result<bool,> {
-		let new_app = crate::helpers::yabai::query_focused_app()
-			.await
-			.unwrap_or_else(|_| "Unknown".to_string(),);
+	/// Announce that the bar for `display_id` was set up from scratch and
+	/// needs every item rendered again
+	pub fn mark_provisioned(&self, display_id: &str,) {
+		self.publish(&[StateEvent::BarProvisioned(display_id.to_string(),),],);
+	}
 
-		let mut current_app = self.current_app.write().await;
-		let changed = current_app.as_ref() != Some(&new_app,);
+	/// Everything the state holds right now
+	pub async fn snapshot(&self,) -> StateSnapshot {
+		let mut spaces: Vec<SpaceInfo,> = self.spaces.read().await.values().cloned().collect();
+		spaces.sort_by_key(|s| s.index,);
+		let mut windows: Vec<WindowInfo,> = self.windows.read().await.values().cloned().collect();
+		windows.sort_by_key(|w| w.id,);
 
-		if changed {
-			*current_app = Some(new_app.clone(),);
-			debug!("📱 Current app updated: {}", new_app);
+		StateSnapshot {
+			spaces,
+			windows,
+			current_app: self.current_app.read().await.clone(),
+			displays: self.displays.read().await.clone().into_iter().collect(),
+			battery: self.battery.read().await.clone(),
+			input_source: self.input_source.read().await.clone(),
+			appearance: *self.appearance.read().await,
 		}
-
-		Ok(changed,)
 	}
 
 	/// Get current focused space
@@ -112,7 +338,19 @@ impl DaemonState {
 	}
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize,)]
+/// [`DaemonState`] at one moment, sorted for reading, as `ctl dump-state` prints it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize,)]
+pub struct StateSnapshot {
+	pub spaces:       Vec<SpaceInfo,>,
+	pub windows:      Vec<WindowInfo,>,
+	pub current_app:  Option<String,>,
+	pub displays:     BTreeMap<String, DisplayInfo,>,
+	pub battery:      Option<BatteryInfo,>,
+	pub input_source: Option<String,>,
+	pub appearance:   Option<Appearance,>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize,)]
 pub struct SpaceInfo {
 	pub index:     u32,
 	pub display:   u32,
@@ -121,7 +359,31 @@ pub struct SpaceInfo {
 	pub label:     String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize,)]
+/// Battery charge as reported by `pmset -g batt`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize,)]
+pub struct BatteryInfo {
+	pub charge:   u8,
+	pub charging: bool,
+}
+
+/// Light or dark mode, as `AppleInterfaceStyle` tells them apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize,)]
+#[serde(rename_all = "snake_case")]
+pub enum Appearance {
+	Light,
+	Dark,
+}
+
+impl std::fmt::Display for Appearance {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		match self {
+			Appearance::Light => write!(f, "light"),
+			Appearance::Dark => write!(f, "dark"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize,)]
 pub struct WindowInfo {
 	pub id:        u32,
 	pub app:       String,
@@ -130,3 +392,83 @@ pub struct WindowInfo {
 	pub display:   u32,
 	pub has_focus: bool,
 }
+
+fn focused<T,>(items: &HashMap<u32, T,>, has_focus: impl Fn(&T,) -> bool,) -> Option<u32,> {
+	let mut ids: Vec<u32,> =
+		items.iter().filter(|(_, v,)| has_focus(v,),).map(|(id, _,)| *id,).collect();
+	ids.sort_unstable();
+	ids.first().copied()
+}
+
+fn sorted_keys<T,>(items: &HashMap<u32, T,>,) -> BTreeSet<u32,> {
+	items.keys().copied().collect()
+}
+
+/// Structural diff of two space maps keyed by space index
+///
+/// Every field is compared; the order windows are listed in is not.
+pub fn diff_spaces(
+	old: &HashMap<u32, SpaceInfo,>,
+	new: &HashMap<u32, SpaceInfo,>,
+) -> Vec<StateEvent,> {
+	let (old_ids, new_ids,) = (sorted_keys(old,), sorted_keys(new,),);
+	let mut events: Vec<StateEvent,> =
+		old_ids.difference(&new_ids,).map(|id| StateEvent::SpaceRemoved(*id,),).collect();
+	events.extend(new_ids.difference(&old_ids,).map(|id| StateEvent::SpaceAdded(*id,),),);
+
+	let (from, to,) = (focused(old, |s| s.has_focus,), focused(new, |s| s.has_focus,),);
+	for id in old_ids.intersection(&new_ids,) {
+		let (before, after,) = (&old[id], &new[id],);
+		let mut before_windows = before.windows.clone();
+		let mut after_windows = after.windows.clone();
+		before_windows.sort_unstable();
+		after_windows.sort_unstable();
+		if before_windows != after_windows {
+			events.push(StateEvent::SpaceWindowsChanged(*id,),);
+		}
+		if before.display != after.display
+			|| before.label != after.label
+			|| (before.has_focus != after.has_focus && from == to)
+		{
+			events.push(StateEvent::SpaceChanged(*id,),);
+		}
+	}
+
+	if from != to {
+		events.push(StateEvent::SpaceFocusChanged { from, to, },);
+	}
+	events
+}
+
+/// Structural diff of two window maps keyed by window id
+///
+/// Every field is compared.
+pub fn diff_windows(
+	old: &HashMap<u32, WindowInfo,>,
+	new: &HashMap<u32, WindowInfo,>,
+) -> Vec<StateEvent,> {
+	let (old_ids, new_ids,) = (sorted_keys(old,), sorted_keys(new,),);
+	let mut events: Vec<StateEvent,> =
+		old_ids.difference(&new_ids,).map(|id| StateEvent::WindowClosed(*id,),).collect();
+	events.extend(new_ids.difference(&old_ids,).map(|id| StateEvent::WindowOpened(*id,),),);
+
+	let (from, to,) = (focused(old, |w| w.has_focus,), focused(new, |w| w.has_focus,),);
+	for id in old_ids.intersection(&new_ids,) {
+		let (before, after,) = (&old[id], &new[id],);
+		if before.title != after.title {
+			events.push(StateEvent::WindowTitleChanged { id: *id, title: after.title.clone(), },);
+		}
+		if before.app != after.app
+			|| before.space != after.space
+			|| before.display != after.display
+			|| (before.has_focus != after.has_focus && from == to)
+		{
+			events.push(StateEvent::WindowChanged(*id,),);
+		}
+	}
+
+	if from != to {
+		events.push(StateEvent::WindowFocusChanged { from, to, },);
+	}
+	events
+}
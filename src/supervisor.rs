@@ -0,0 +1,371 @@
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::task::JoinError;
+use tokio::task::JoinSet;
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tokio::time::sleep;
+use tokio::time::timeout;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+/// How long a task gets to finish on its own after shutdown before it is aborted
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(1,);
+
+/// When to restart a task that failed, and when to give up on the daemon
+#[derive(Debug, Clone, PartialEq,)]
+pub struct RestartPolicy {
+	/// Wait before the first restart; doubles with every failure in a row
+	pub initial_backoff: Duration,
+	pub max_backoff:     Duration,
+	/// A task that ran this long before failing starts its streak over
+	pub healthy_after:   Duration,
+	/// Shut the daemon down once a task fails this many times in a row;
+	/// `None` keeps restarting forever
+	pub escalate_after:  Option<u32,>,
+}
+
+impl Default for RestartPolicy {
+	fn default() -> Self {
+		Self {
+			initial_backoff: Duration::from_secs(1,),
+			max_backoff:     Duration::from_secs(60,),
+			healthy_after:   Duration::from_secs(60,),
+			escalate_after:  None,
+		}
+	}
+}
+
+impl RestartPolicy {
+	/// Read `SKETCHYBAR_DAEMON_ESCALATE_AFTER`
+	pub fn from_env() -> Result<Self,> {
+		Self::from_vars(|key| std::env::var(key,).ok(),)
+	}
+
+	fn from_vars(var: impl Fn(&str,) -> Option<String,>,) -> Result<Self,> {
+		let mut policy = Self::default();
+		if let Some(failures,) = var("SKETCHYBAR_DAEMON_ESCALATE_AFTER",) {
+			let failures = failures
+				.parse()
+				.with_context(|| format!("SKETCHYBAR_DAEMON_ESCALATE_AFTER={}", failures),)?;
+			policy.escalate_after = Some(failures,).filter(|&n| n > 0,);
+		}
+		Ok(policy,)
+	}
+
+	/// Wait before restarting after `failures` failures in a row
+	pub fn backoff(&self, failures: u32,) -> Duration {
+		let doublings = failures.saturating_sub(1,).min(16,);
+		self.initial_backoff.saturating_mul(1 << doublings,).min(self.max_backoff,)
+	}
+}
+
+/// What the supervisor knows about one task
+#[derive(Debug, Clone, Default, PartialEq,)]
+pub struct TaskStatus {
+	pub running:              bool,
+	pub restarts:             u32,
+	/// Failures over the daemon's lifetime
+	pub failures:             u32,
+	/// Failures since the task last ran healthy
+	pub consecutive_failures: u32,
+	pub last_error:           Option<String,>,
+}
+
+/// Status of every supervised task, shared with whoever wants to report it
+#[derive(Debug, Clone, Default,)]
+pub struct TaskStatuses {
+	tasks: Arc<Mutex<BTreeMap<String, TaskStatus,>,>,>,
+}
+
+impl TaskStatuses {
+	pub fn get(&self, name: &str,) -> Option<TaskStatus,> {
+		self.tasks.lock().unwrap().get(name,).cloned()
+	}
+
+	/// Every task by name
+	pub fn snapshot(&self,) -> BTreeMap<String, TaskStatus,> {
+		self.tasks.lock().unwrap().clone()
+	}
+
+	fn update(&self, name: &str, update: impl FnOnce(&mut TaskStatus,),) {
+		update(self.tasks.lock().unwrap().entry(name.to_string(),).or_default(),);
+	}
+}
+
+type TaskFactory = Arc<dyn Fn() -> BoxFuture<'static, Result<(),>,> + Send + Sync,>;
+
+/// Keeps long-running tasks alive
+///
+/// Every task runs in its own tokio task, so a panic or error in one is
+/// seen only by the supervisor, which starts a fresh copy after a backoff.
+/// The daemon only goes down when the [`RestartPolicy`] escalates.
+pub struct Supervisor {
+	policy:   RestartPolicy,
+	tasks:    Vec<(String, TaskFactory,),>,
+	statuses: TaskStatuses,
+}
+
+impl Supervisor {
+	pub fn new(policy: RestartPolicy, statuses: TaskStatuses,) -> Self {
+		Self { policy, tasks: Vec::new(), statuses, }
+	}
+
+	/// Keep the future made by `task` running, making a new one on every restart
+	pub fn supervise<F, Fut,>(&mut self, name: &str, task: F,)
+	where
+		F: Fn() -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = Result<(),>,> + Send + 'static,
+	{
+		let factory: TaskFactory =
+			Arc::new(move || Box::pin(task(),) as BoxFuture<'static, Result<(),>,>,);
+		self.tasks.push((name.to_string(), factory,),);
+	}
+
+	/// Names of the supervised tasks, in order of registration
+	pub fn tasks(&self,) -> Vec<&str,> {
+		self.tasks.iter().map(|(name, _,)| name.as_str(),).collect()
+	}
+
+	pub fn statuses(&self,) -> TaskStatuses {
+		self.statuses.clone()
+	}
+
+	/// Run every task until shutdown; errors when the policy escalates
+	pub async fn run(self, shutdown_rx: broadcast::Receiver<(),>,) -> Result<(),> {
+		let mut keepers = JoinSet::new();
+		for (name, factory,) in self.tasks {
+			let policy = self.policy.clone();
+			let statuses = self.statuses.clone();
+			let shutdown_rx = shutdown_rx.resubscribe();
+			keepers.spawn(keep(name, factory, policy, statuses, shutdown_rx,),);
+		}
+
+		while let Some(result,) = keepers.join_next().await {
+			// Dropping the set aborts the remaining keepers and their tasks
+			result??;
+		}
+		Ok((),)
+	}
+}
+
+/// Run one task, restarting it until shutdown or escalation
+async fn keep(
+	name: String,
+	factory: TaskFactory,
+	policy: RestartPolicy,
+	statuses: TaskStatuses,
+	mut shutdown_rx: broadcast::Receiver<(),>,
+) -> Result<(),> {
+	loop {
+		let started = Instant::now();
+		let mut handle = AbortOnDrop(tokio::spawn(factory(),),);
+		statuses.update(&name, |status| status.running = true,);
+
+		let result = tokio::select! {
+			result = &mut handle.0 => result,
+			_ = shutdown_rx.recv() => {
+				if timeout(SHUTDOWN_GRACE, &mut handle.0).await.is_err() {
+					warn!("⚠️  {} task did not stop in time, aborting it", name);
+				}
+				statuses.update(&name, |status| status.running = false,);
+				return Ok(());
+			}
+		};
+		statuses.update(&name, |status| status.running = false,);
+		if shutting_down(&mut shutdown_rx,) {
+			return Ok((),);
+		}
+
+		let reason = match result {
+			Ok(Ok(_,),) => "exited unexpectedly".to_string(),
+			Ok(Err(e,),) => e.to_string(),
+			Err(e,) => panic_message(e,),
+		};
+
+		let healthy = started.elapsed() >= policy.healthy_after;
+		let mut failures = 0;
+		statuses.update(&name, |status| {
+			if healthy {
+				status.consecutive_failures = 0;
+			}
+			status.failures += 1;
+			status.consecutive_failures += 1;
+			status.last_error = Some(reason.clone(),);
+			failures = status.consecutive_failures;
+		},);
+
+		if policy.escalate_after.is_some_and(|limit| failures >= limit,) {
+			error!("🚨 {} task failed {} times in a row: {}", name, failures, reason);
+			return Err(anyhow!("{} task failed {} times in a row: {}", name, failures, reason),);
+		}
+
+		let backoff = policy.backoff(failures,);
+		warn!("🔁 {} task failed ({}), restarting in {:?}", name, reason, backoff);
+		tokio::select! {
+			_ = sleep(backoff) => {}
+			_ = shutdown_rx.recv() => return Ok(()),
+		}
+		statuses.update(&name, |status| status.restarts += 1,);
+		info!("🔁 Restarted {} task", name);
+	}
+}
+
+/// Aborts the task when the keeper goes away, e.g. on escalation
+struct AbortOnDrop<T,>(tokio::task::JoinHandle<T,>,);
+
+impl<T,> Drop for AbortOnDrop<T,> {
+	fn drop(&mut self,) {
+		self.0.abort();
+	}
+}
+
+/// Whether shutdown was signalled while the task was finishing
+fn shutting_down(shutdown_rx: &mut broadcast::Receiver<(),>,) -> bool {
+	!matches!(shutdown_rx.try_recv(), Err(TryRecvError::Empty))
+}
+
+/// What a crashed task panicked with
+fn panic_message(error: JoinError,) -> String {
+	if !error.is_panic() {
+		return error.to_string();
+	}
+	let payload: Box<dyn Any + Send,> = error.into_panic();
+	let message = payload
+		.downcast_ref::<&str,>()
+		.map(|s| s.to_string(),)
+		.or_else(|| payload.downcast_ref::<String,>().cloned(),)
+		.unwrap_or_else(|| "unknown panic".to_string(),);
+	format!("panicked: {}", message)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::atomic::AtomicU32;
+	use std::sync::atomic::Ordering;
+
+	fn policy() -> RestartPolicy {
+		RestartPolicy {
+			initial_backoff: Duration::from_secs(1,),
+			max_backoff:     Duration::from_secs(8,),
+			healthy_after:   Duration::from_secs(60,),
+			escalate_after:  None,
+		}
+	}
+
+	#[test]
+	fn test_backoff_doubles_up_to_the_cap() {
+		let backoffs: Vec<u64,> = (1..=6).map(|n| policy().backoff(n,).as_secs(),).collect();
+		assert_eq!(backoffs, vec![1, 2, 4, 8, 8, 8]);
+		assert_eq!(policy().backoff(u32::MAX), Duration::from_secs(8));
+	}
+
+	#[test]
+	fn test_policy_from_vars() {
+		let policy = RestartPolicy::from_vars(|_| None,).unwrap();
+		assert_eq!(policy, RestartPolicy::default());
+
+		let policy = RestartPolicy::from_vars(|_| Some("3".to_string(),),).unwrap();
+		assert_eq!(policy.escalate_after, Some(3));
+		let policy = RestartPolicy::from_vars(|_| Some("0".to_string(),),).unwrap();
+		assert_eq!(policy.escalate_after, None);
+		assert!(RestartPolicy::from_vars(|_| Some("often".to_string(),),).is_err());
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_panicking_task_restarts_without_touching_others() {
+		let mut supervisor = Supervisor::new(policy(), TaskStatuses::default(),);
+		let statuses = supervisor.statuses();
+
+		let starts = Arc::new(AtomicU32::new(0,),);
+		let battery_starts = starts.clone();
+		supervisor.supervise("battery", move || {
+			let n = battery_starts.fetch_add(1, Ordering::SeqCst,);
+			async move {
+				if n < 3 {
+					panic!("battery exploded");
+				}
+				std::future::pending().await
+			}
+		},);
+		let ticks = Arc::new(AtomicU32::new(0,),);
+		let clock_ticks = ticks.clone();
+		supervisor.supervise("clock", move || {
+			let ticks = clock_ticks.clone();
+			async move {
+				loop {
+					sleep(Duration::from_secs(1,),).await;
+					ticks.fetch_add(1, Ordering::SeqCst,);
+				}
+			}
+		},);
+		assert_eq!(supervisor.tasks(), ["battery", "clock"]);
+
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let task = tokio::spawn(supervisor.run(shutdown_rx,),);
+
+		// Three panics: restarts after 1s, 2s and 4s
+		sleep(Duration::from_millis(6_500,),).await;
+		assert_eq!(starts.load(Ordering::SeqCst), 3);
+		sleep(Duration::from_secs(1,),).await;
+		assert_eq!(starts.load(Ordering::SeqCst), 4);
+
+		let battery = statuses.get("battery",).unwrap();
+		assert!(battery.running);
+		assert_eq!((battery.restarts, battery.failures, battery.consecutive_failures), (3, 3, 3));
+		assert_eq!(battery.last_error.as_deref(), Some("panicked: battery exploded"));
+
+		let clock = statuses.get("clock",).unwrap();
+		assert_eq!((clock.restarts, clock.failures), (0, 0));
+		assert_eq!(ticks.load(Ordering::SeqCst), 7);
+
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+		assert!(statuses.snapshot().values().all(|status| !status.running));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_policy_escalates_after_failures_in_a_row() {
+		let policy = RestartPolicy { escalate_after: Some(3,), ..policy() };
+		let mut supervisor = Supervisor::new(policy, TaskStatuses::default(),);
+		let statuses = supervisor.statuses();
+		supervisor.supervise("keyboard", || async { Err(anyhow!("no input source"),) },);
+		supervisor.supervise("clock", std::future::pending,);
+
+		let (_shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let error = supervisor.run(shutdown_rx,).await.unwrap_err();
+		assert_eq!(error.to_string(), "keyboard task failed 3 times in a row: no input source");
+		assert_eq!(statuses.get("keyboard").unwrap().restarts, 2);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_healthy_run_resets_the_streak() {
+		let policy = RestartPolicy { escalate_after: Some(2,), ..policy() };
+		let mut supervisor = Supervisor::new(policy, TaskStatuses::default(),);
+		let statuses = supervisor.statuses();
+		supervisor.supervise("spaces", || async {
+			sleep(Duration::from_secs(90,),).await;
+			Err(anyhow!("yabai went away"),)
+		},);
+
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let task = tokio::spawn(supervisor.run(shutdown_rx,),);
+		sleep(Duration::from_secs(300,),).await;
+
+		let spaces = statuses.get("spaces",).unwrap();
+		assert_eq!((spaces.failures, spaces.consecutive_failures), (3, 1));
+		shutdown_tx.send((),).unwrap();
+		task.await.unwrap().unwrap();
+	}
+}
@@ -4,6 +4,9 @@ use std::time::Duration;
 use tokio::time::sleep;
 
 use crate::helpers::yabai;
+use crate::runner;
+use crate::wm::WindowManagerBackend;
+use crate::wm::YabaiBackend;
 use crate::sketchybar::SketchyBar;
 use crate::state::DaemonState;
 use crate::state::SpaceInfo;
@@ -13,6 +16,11 @@ use crate::state::WindowInfo;
 pub mod utils {
 	use super::*;
 
+	/// yabai, run through the shared runner
+	pub fn yabai() -> YabaiBackend {
+		YabaiBackend::new(runner::system(),)
+	}
+
 	/// Check if yabai is available and running
 	pub fn is_yabai_available() -> bool {
 		Command::new("yabai",)
@@ -163,6 +171,29 @@ mod unit_tests {
 		}
 	}
 
+	#[tokio::test]
+	async fn test_setup_bar_sends_every_property() -> Result<(),> {
+		let transport = crate::sketchybar::RecordingTransport::new();
+		let mut bar = SketchyBar::with_transport(std::sync::Arc::new(transport.clone(),),);
+		let display = yabai::DisplayInfo {
+			index:      2,
+			is_builtin: false,
+			frame:      yabai::DisplayFrame { x: 0.0, y: 0.0, w: 2560.0, h: 1440.0, },
+		};
+
+		crate::config::setup_bar(&mut bar, "sketchybar", &display, &Default::default(),).await?;
+
+		let sent = transport.sent_to("sketchybar",);
+		assert_eq!(sent.len(), 2);
+		assert!(sent[0].starts_with("--bar position=bottom height=26"));
+		assert!(sent[0].contains("show_in_fullscreen=true"));
+		assert!(sent[0].contains("color=0x00000000"));
+		assert!(sent[1].starts_with("--default position=left ignore_association=false"));
+		assert!(sent[1].contains("updates=when_shown"));
+		assert!(sent[1].contains("label.font.family=\"MesloLGL Nerd Font\""));
+		Ok((),)
+	}
+
 	#[test]
 	fn test_display_frame_calculations() {
 		let frame = yabai::DisplayFrame { x: 100.0, y: 200.0, w: 1920.0, h: 1080.0, };
@@ -189,7 +220,7 @@ mod integration_tests {
 			return;
 		}
 
-		let displays = yabai::get_displays().await;
+		let displays = utils::yabai().displays().await;
 		match displays {
 			Ok(displays,) => {
 				assert!(!displays.is_empty(), "Should have at least one display");
@@ -225,7 +256,7 @@ mod integration_tests {
 			return;
 		}
 
-		let spaces = yabai::query_spaces().await;
+		let spaces = utils::yabai().spaces().await;
 		match spaces {
 			Ok(spaces,) => {
 				assert!(!spaces.is_empty(), "Should have at least one space");
@@ -260,7 +291,7 @@ mod integration_tests {
 			return;
 		}
 
-		let windows = yabai::query_windows().await;
+		let windows = utils::yabai().windows().await;
 		match windows {
 			Ok(windows,) => {
 				// Windows might be empty, that's okay
@@ -291,7 +322,7 @@ mod integration_tests {
 			return;
 		}
 
-		let focused_app = yabai::query_focused_app().await;
+		let focused_app = utils::yabai().focused_app().await;
 		match focused_app {
 			Ok(app,) => {
 				assert!(!app.is_empty(), "Focused app should not be empty");
@@ -343,30 +374,64 @@ mod integration_tests {
 #[cfg(test)]
 mod edge_case_tests {
 	use super::*;
+	use crate::helpers::yabai::YabaiClient;
+	use crate::runner::FakeRunner;
+	use crate::runner::RunError;
+	use std::sync::Arc;
+
+	/// yabai through `runner` alone, whether or not a real yabai is listening
+	fn cli_yabai(runner: &FakeRunner,) -> Arc<YabaiBackend,> {
+		let runner: Arc<FakeRunner,> = Arc::new(runner.clone(),);
+		let client = YabaiClient::with_socket("/nonexistent/yabai.socket", runner.clone(),);
+		Arc::new(YabaiBackend::with_client(client, runner,),)
+	}
 
 	#[tokio::test]
 	async fn test_yabai_not_available() {
-		// Temporarily rename yabai to simulate it not being available
-		let original_path = std::env::var("PATH",).unwrap_or_default();
-		unsafe { std::env::set_var("PATH", "/nonexistent",) };
+		// A runner that knows no commands, as on a machine without yabai
+		let runner = FakeRunner::new();
+
+		// Should fall back to default display
+		let displays = cli_yabai(&runner,).displays().await.unwrap();
+		assert_eq!(displays.len(), 1);
+		let default_display = displays.get("1",).unwrap();
+		assert_eq!(default_display.index, 1);
+		assert!(default_display.is_builtin);
+	}
 
-		let displays = yabai::get_displays().await;
-		match displays {
-			Ok(displays,) => {
-				// Should fall back to default display
-				assert_eq!(displays.len(), 1);
-				let default_display = displays.get("1",).unwrap();
-				assert_eq!(default_display.index, 1);
-				assert!(default_display.is_builtin);
-			},
-			Err(_,) => {
-				// This is also acceptable behavior
-				println!("yabai fallback handled gracefully");
-			},
-		}
+	#[tokio::test(start_paused = true)]
+	async fn test_slow_yabai_is_an_error() {
+		let runner = FakeRunner::new();
+		runner.hang("yabai -m query --displays",);
+		runner.hang("yabai -m query --spaces",);
+
+		// Falling back here would tear down the bars on every real display
+		assert!(cli_yabai(&runner,).displays().await.is_err());
+		let state =
+			DaemonState::with_runner(Arc::new(runner.clone(),),).with_backend(cli_yabai(&runner,),);
+		let err = state.update_spaces().await.unwrap_err();
+		assert!(matches!(err.downcast_ref(), Some(RunError::TimedOut { .. })));
+	}
 
-		// Restore PATH
-		unsafe { std::env::set_var("PATH", &original_path,) };
+	#[tokio::test]
+	async fn test_state_queries_go_through_runner() {
+		let runner = FakeRunner::new();
+		runner.respond(
+			"yabai -m query --spaces",
+			r#"[{"index": 1, "display": 1, "has-focus": true, "windows": [7], "label": ""}]"#,
+		);
+		runner.respond("osascript -e tell application \"System Events\" to get name of first \
+		                application process whose frontmost is true", "Finder\n",);
+
+		let state =
+			DaemonState::with_runner(Arc::new(runner.clone(),),).with_backend(cli_yabai(&runner,),);
+		assert!(state.update_spaces().await.unwrap());
+		assert!(state.update_current_app().await.unwrap());
+		assert_eq!(state.current_app.read().await.as_deref(), Some("Finder"));
+		assert_eq!(runner.calls()[..2], [
+			"yabai -m query --spaces",
+			"yabai -m query --windows --window",
+		]);
 	}
 
 	#[tokio::test]
@@ -623,7 +688,7 @@ mod scenario_tests {
 			return;
 		}
 
-		let displays = yabai::get_displays().await;
+		let displays = utils::yabai().displays().await;
 		match displays {
 			Ok(displays,) => {
 				if displays.len() > 1 {
@@ -662,3 +727,239 @@ mod scenario_tests {
 		}
 	}
 }
+
+/// Change events published by `DaemonState`, without yabai
+#[cfg(test)]
+mod state_event_tests {
+	use super::*;
+	use crate::bars::BarManager;
+	use crate::events::EventManager;
+	use crate::events::Refresh;
+	use crate::events::SAFETY_NET_SYNC_PERIOD;
+	use crate::items::ItemRegistry;
+	use crate::listener::DaemonEvent;
+	use crate::listener::EventListener;
+	use crate::wm::ScriptedBackend;
+	use std::sync::Arc;
+	use crate::state::StateDependency;
+	use crate::state::StateEvent;
+	use std::collections::HashMap;
+	use tokio::io::AsyncWriteExt;
+	use tokio::net::UnixStream;
+	use tokio::sync::broadcast;
+
+	fn space(index: u32, has_focus: bool, windows: Vec<u32,>,) -> SpaceInfo {
+		SpaceInfo { index, display: 1, has_focus, windows, label: String::new(), }
+	}
+
+	fn window(id: u32, title: &str, has_focus: bool,) -> WindowInfo {
+		WindowInfo {
+			id,
+			app: "Terminal".to_string(),
+			title: title.to_string(),
+			space: 1,
+			display: 1,
+			has_focus,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_space_diff() {
+		let state = DaemonState::new();
+		let events =
+			state.apply_spaces(vec![space(1, true, vec![]), space(2, false, vec![7])],).await;
+		assert_eq!(events, vec![
+			StateEvent::SpaceAdded(1),
+			StateEvent::SpaceAdded(2),
+			StateEvent::SpaceFocusChanged { from: None, to: Some(1) },
+		]);
+
+		// Same data in a different window order is not a change
+		let events =
+			state.apply_spaces(vec![space(2, false, vec![7]), space(1, true, vec![])],).await;
+		assert!(events.is_empty());
+
+		let spaces =
+			vec![space(1, false, vec![8]), space(2, true, vec![7]), space(3, false, vec![])];
+		let events = state.apply_spaces(spaces,).await;
+		assert_eq!(events, vec![
+			StateEvent::SpaceAdded(3),
+			StateEvent::SpaceWindowsChanged(1),
+			StateEvent::SpaceFocusChanged { from: Some(1), to: Some(2) },
+		]);
+	}
+
+	#[tokio::test]
+	async fn test_space_diff_compares_every_field() {
+		let base = || vec![space(1, true, vec![7]), space(2, false, vec![])];
+		let moved: fn(&mut Vec<SpaceInfo,>,) = |spaces| spaces[1].display = 2;
+		let relabelled: fn(&mut Vec<SpaceInfo,>,) = |spaces| spaces[1].label = "web".to_string();
+		// A second focused space leaves the lowest one focused
+		let also_focused: fn(&mut Vec<SpaceInfo,>,) = |spaces| spaces[1].has_focus = true;
+		for change in [moved, relabelled, also_focused] {
+			let state = DaemonState::new();
+			state.apply_spaces(base(),).await;
+			let mut spaces = base();
+			change(&mut spaces,);
+			let events = state.apply_spaces(spaces,).await;
+			assert_eq!(events, vec![StateEvent::SpaceChanged(2)]);
+			assert_eq!(events[0].dependency(), Some(StateDependency::Spaces));
+		}
+	}
+
+	#[tokio::test]
+	async fn test_window_diff_compares_every_field() {
+		let base = || vec![window(1, "vim", true), window(2, "htop", false)];
+		let new_space: fn(&mut Vec<WindowInfo,>,) = |windows| windows[1].space = 2;
+		let new_display: fn(&mut Vec<WindowInfo,>,) = |windows| windows[1].display = 2;
+		let new_app: fn(&mut Vec<WindowInfo,>,) = |windows| windows[1].app = "iTerm2".to_string();
+		// A second focused window leaves the lowest one focused
+		let also_focused: fn(&mut Vec<WindowInfo,>,) = |windows| windows[1].has_focus = true;
+		for change in [new_space, new_display, new_app, also_focused] {
+			let state = DaemonState::new();
+			state.apply_windows(base(),).await;
+			let mut windows = base();
+			change(&mut windows,);
+			let events = state.apply_windows(windows,).await;
+			assert_eq!(events, vec![StateEvent::WindowChanged(2)]);
+			assert_eq!(events[0].dependency(), Some(StateDependency::Windows));
+		}
+	}
+
+	#[tokio::test]
+	async fn test_window_diff_sees_more_than_the_count() {
+		let state = DaemonState::new();
+		state.apply_windows(vec![window(1, "vim", true), window(2, "htop", false)],).await;
+
+		// Same number of windows, but a title and the focus changed
+		let windows = vec![window(1, "vim README.md", false), window(2, "htop", true)];
+		let events = state.apply_windows(windows,).await;
+		assert_eq!(events, vec![
+			StateEvent::WindowTitleChanged { id: 1, title: "vim README.md".to_string() },
+			StateEvent::WindowFocusChanged { from: Some(1), to: Some(2) },
+		]);
+
+		// One closed and another opened keeps the count too
+		let windows = vec![window(2, "htop", true), window(3, "man", false)];
+		let events = state.apply_windows(windows,).await;
+		assert_eq!(events, vec![StateEvent::WindowClosed(1), StateEvent::WindowOpened(3)]);
+		assert!(events.iter().all(|e| e.dependency() == Some(StateDependency::Windows)));
+	}
+
+	#[tokio::test]
+	async fn test_app_and_display_changes_are_published() {
+		let state = DaemonState::new();
+		let mut events_rx = state.subscribe();
+
+		state.apply_current_app("Terminal".to_string(),).await;
+		assert!(state.apply_current_app("Terminal".to_string()).await.is_empty());
+		state.apply_current_app("Safari".to_string(),).await;
+
+		let display = yabai::DisplayInfo {
+			index:      2,
+			is_builtin: false,
+			frame:      yabai::DisplayFrame { x: 0.0, y: 0.0, w: 1920.0, h: 1080.0, },
+		};
+		state.apply_displays(HashMap::from([("2".to_string(), display,),],),).await;
+		state.apply_displays(HashMap::new(),).await;
+		state.mark_provisioned("1",);
+
+		let mut received = Vec::new();
+		while let Ok(event,) = events_rx.try_recv() {
+			received.push(event,);
+		}
+		assert_eq!(received, vec![
+			StateEvent::AppSwitched { from: None, to: "Terminal".to_string() },
+			StateEvent::AppSwitched {
+				from: Some("Terminal".to_string(),),
+				to:   "Safari".to_string(),
+			},
+			StateEvent::DisplayAdded("2".to_string()),
+			StateEvent::DisplayRemoved("2".to_string()),
+			StateEvent::BarProvisioned("1".to_string()),
+		]);
+		assert_eq!(received[4].dependency(), None);
+	}
+
+	#[tokio::test]
+	async fn test_state_syncs_from_any_backend() -> Result<(),> {
+		let backend = ScriptedBackend::new();
+		let state = DaemonState::new().with_backend(Arc::new(backend.clone(),),);
+		assert_eq!(state.backend().name(), "scripted");
+
+		backend.set_spaces(vec![space(1, true, vec![1])],);
+		backend.set_windows(vec![window(1, "vim", true), window(2, "htop", false)],);
+		assert!(state.update_spaces().await?);
+		assert!(state.update_windows().await?);
+		assert!(state.update_current_app().await?);
+		assert_eq!(state.current_app.read().await.as_deref(), Some("Terminal"));
+
+		// Nothing moved, so nothing is published
+		assert!(!state.update_windows().await?);
+
+		// Nothing focused keeps the last app instead of switching away from it
+		let mut events_rx = state.subscribe();
+		backend.set_windows(vec![window(1, "vim", false)],);
+		assert!(state.update_current_app().await.is_err());
+		backend.set_unavailable(true,);
+		assert!(state.update_spaces().await.is_err());
+		assert!(state.update_current_app().await.is_err());
+		assert_eq!(state.current_app.read().await.as_deref(), Some("Terminal"));
+		assert!(events_rx.try_recv().is_err());
+		Ok((),)
+	}
+
+	#[test]
+	fn test_events_name_what_is_stale() {
+		let refresh = |name| Refresh::for_event(&DaemonEvent::new("yabai", name,),);
+		let windows_only = Refresh { windows: true, ..Refresh::default() };
+		assert_eq!(refresh("window_title_changed"), windows_only);
+		assert!(refresh("space_changed").spaces && !refresh("space_changed").displays);
+		assert_eq!(refresh("display_added"), Refresh { displays: true, ..Refresh::DESKTOP });
+		assert_eq!(refresh("system_woke"), Refresh::ALL);
+		assert!(refresh("window_resized").is_empty());
+
+		// sketchybar's own events, forwarded by item scripts
+		let sketchybar = |name| Refresh::for_event(&DaemonEvent::new("sketchybar", name,),);
+		assert_eq!(sketchybar("front_app_switched"), Refresh::FOCUS);
+		assert_eq!(sketchybar("space_change"), Refresh::DESKTOP);
+		assert_eq!(sketchybar("power_source_change"), Refresh { battery: true, ..Refresh::NONE });
+		assert_eq!(sketchybar("appearance_change"), Refresh { appearance: true, ..Refresh::NONE });
+		assert!(sketchybar("routine").is_empty());
+		assert!(Refresh::for_event(&DaemonEvent::new("cron", "space_changed")).is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_yabai_events_refresh_state_at_once() -> Result<(),> {
+		let backend = ScriptedBackend::new();
+		backend.set_spaces(vec![space(1, true, vec![]), space(2, false, vec![])],);
+		let state = DaemonState::new().with_backend(Arc::new(backend.clone(),),);
+		// Start in sync, so the manager's first pass publishes nothing
+		state.update_spaces().await?;
+		let mut events_rx = state.subscribe();
+
+		let socket = std::env::temp_dir()
+			.join(format!("sbd-state-events-{}.socket", std::process::id()),);
+		let listener = EventListener::bind(&socket,)?;
+		let (shutdown_tx, shutdown_rx,) = broadcast::channel(1,);
+		let bars = BarManager::new(state.clone(),);
+		let mut manager = EventManager::new(state.clone(), bars, ItemRegistry::new(), shutdown_rx,)
+			.with_listener(listener.clone(),)
+			.with_state_sync_period(SAFETY_NET_SYNC_PERIOD,);
+		let task = tokio::spawn(async move { manager.start_event_loops().await },);
+		sleep(Duration::from_millis(50,),).await;
+
+		// Switch space, long before the next poll
+		backend.set_spaces(vec![space(1, false, vec![]), space(2, true, vec![])],);
+		let mut stream = UnixStream::connect(&socket,).await?;
+		stream.write_all(b"yabai space_changed\n",).await?;
+
+		let event = tokio::time::timeout(Duration::from_secs(1,), events_rx.recv(),).await??;
+		assert_eq!(event, StateEvent::SpaceFocusChanged { from: Some(1), to: Some(2) });
+
+		shutdown_tx.send((),)?;
+		task.await??;
+		listener.close();
+		Ok((),)
+	}
+}
@@ -0,0 +1,346 @@
+use anyhow::Result;
+use anyhow::bail;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::helpers::colors::Colors;
+use crate::sketchybar::properties::BackgroundProperties;
+use crate::sketchybar::properties::BarProperties;
+use crate::sketchybar::properties::Color;
+use crate::sketchybar::properties::ItemProperties;
+
+/// The theme bars are colored with unless the config names another
+pub const DEFAULT_THEME: &str = "mocha";
+
+/// Names of the themes the daemon ships
+pub const BUNDLED_THEMES: &[&str] = &["mocha", "latte", "frappe", "gruvbox", "tokyo-night",];
+
+/// Colors by what they are for
+///
+/// The bundled themes set every role. A user theme sets the ones it changes
+/// and takes the rest from the theme it extends.
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema,)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+	/// Bundled or user theme this one is laid over, mocha when left out
+	pub extends:         Option<String,>,
+	/// Bar background
+	pub bar:             Option<Color,>,
+	/// Background of every item
+	pub item_background: Option<Color,>,
+	/// Border of items that have no accent
+	pub border:          Option<Color,>,
+	/// Text drawn on item backgrounds, e.g. space numbers
+	pub text:            Option<Color,>,
+	/// Borders of things that are there but idle, e.g. empty spaces
+	pub inactive:        Option<Color,>,
+	/// The focused space
+	pub focus:           Option<Color,>,
+	/// A battery running low
+	pub warning:         Option<Color,>,
+	/// A battery about to run out
+	pub critical:        Option<Color,>,
+	/// The color each item is drawn in
+	#[serde(default)]
+	pub accents:         Accents,
+}
+
+/// The color each built-in item is drawn in
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, JsonSchema,)]
+#[serde(default, deny_unknown_fields)]
+pub struct Accents {
+	pub clock:       Option<Color,>,
+	pub keyboard:    Option<Color,>,
+	/// Border of spaces with windows on them
+	pub spaces:      Option<Color,>,
+	pub current_app: Option<Color,>,
+	pub window:      Option<Color,>,
+	/// A charging or well charged battery
+	pub battery:     Option<Color,>,
+}
+
+/// Catppuccin Mocha, the look the daemon has always had
+pub const MOCHA: Theme = Theme {
+	extends:         None,
+	bar:             Some(Color(Colors::TRANSPARENT,),),
+	item_background: Some(Color(Colors::SURFACE0,),),
+	border:          Some(Color(0xffffffff,),),
+	text:            Some(Color(Colors::TEXT,),),
+	inactive:        Some(Color(Colors::OVERLAY0,),),
+	focus:           Some(Color(Colors::BLUE,),),
+	warning:         Some(Color(Colors::PEACH,),),
+	critical:        Some(Color(Colors::RED,),),
+	accents:         Accents {
+		clock:       Some(Color(Colors::FLAMINGO,),),
+		keyboard:    Some(Color(Colors::BLUE,),),
+		spaces:      Some(Color(Colors::GREEN,),),
+		current_app: Some(Color(Colors::MAUVE,),),
+		window:      Some(Color(Colors::GREEN,),),
+		battery:     Some(Color(Colors::GREEN,),),
+	},
+};
+
+/// Catppuccin Latte, the light flavor
+pub const LATTE: Theme = Theme {
+	extends:         None,
+	bar:             Some(Color(0x00000000,),),
+	item_background: Some(Color(0xffccd0da,),), // Surface0
+	border:          Some(Color(0xff4c4f69,),), // Text
+	text:            Some(Color(0xff4c4f69,),),
+	inactive:        Some(Color(0xff9ca0b0,),), // Overlay0
+	focus:           Some(Color(0xff1e66f5,),), // Blue
+	warning:         Some(Color(0xfffe640b,),), // Peach
+	critical:        Some(Color(0xffd20f39,),), // Red
+	accents:         Accents {
+		clock:       Some(Color(0xffdd7878,),), // Flamingo
+		keyboard:    Some(Color(0xff1e66f5,),),
+		spaces:      Some(Color(0xff40a02b,),), // Green
+		current_app: Some(Color(0xff8839ef,),), // Mauve
+		window:      Some(Color(0xff40a02b,),),
+		battery:     Some(Color(0xff40a02b,),),
+	},
+};
+
+/// Catppuccin Frappé
+pub const FRAPPE: Theme = Theme {
+	extends:         None,
+	bar:             Some(Color(0x00000000,),),
+	item_background: Some(Color(0xff414559,),), // Surface0
+	border:          Some(Color(0xffc6d0f5,),), // Text
+	text:            Some(Color(0xffc6d0f5,),),
+	inactive:        Some(Color(0xff737994,),), // Overlay0
+	focus:           Some(Color(0xff8caaee,),), // Blue
+	warning:         Some(Color(0xffef9f76,),), // Peach
+	critical:        Some(Color(0xffe78284,),), // Red
+	accents:         Accents {
+		clock:       Some(Color(0xffeebebe,),), // Flamingo
+		keyboard:    Some(Color(0xff8caaee,),),
+		spaces:      Some(Color(0xffa6d189,),), // Green
+		current_app: Some(Color(0xffca9ee6,),), // Mauve
+		window:      Some(Color(0xffa6d189,),),
+		battery:     Some(Color(0xffa6d189,),),
+	},
+};
+
+/// Gruvbox dark, medium contrast
+pub const GRUVBOX: Theme = Theme {
+	extends:         None,
+	bar:             Some(Color(0x00000000,),),
+	item_background: Some(Color(0xff3c3836,),), // bg1
+	border:          Some(Color(0xffebdbb2,),), // fg
+	text:            Some(Color(0xffebdbb2,),),
+	inactive:        Some(Color(0xff928374,),), // gray
+	focus:           Some(Color(0xff83a598,),), // blue
+	warning:         Some(Color(0xfffe8019,),), // orange
+	critical:        Some(Color(0xfffb4934,),), // red
+	accents:         Accents {
+		clock:       Some(Color(0xfffabd2f,),), // yellow
+		keyboard:    Some(Color(0xff83a598,),),
+		spaces:      Some(Color(0xffb8bb26,),), // green
+		current_app: Some(Color(0xffd3869b,),), // purple
+		window:      Some(Color(0xffb8bb26,),),
+		battery:     Some(Color(0xff8ec07c,),), // aqua
+	},
+};
+
+/// Tokyo Night, the night variant
+pub const TOKYO_NIGHT: Theme = Theme {
+	extends:         None,
+	bar:             Some(Color(0x00000000,),),
+	item_background: Some(Color(0xff292e42,),), // bg_highlight
+	border:          Some(Color(0xffc0caf5,),), // fg
+	text:            Some(Color(0xffc0caf5,),),
+	inactive:        Some(Color(0xff565f89,),), // comment
+	focus:           Some(Color(0xff7aa2f7,),), // blue
+	warning:         Some(Color(0xffff9e64,),), // orange
+	critical:        Some(Color(0xfff7768e,),), // red
+	accents:         Accents {
+		clock:       Some(Color(0xff7dcfff,),), // cyan
+		keyboard:    Some(Color(0xff7aa2f7,),),
+		spaces:      Some(Color(0xff9ece6a,),), // green
+		current_app: Some(Color(0xffbb9af7,),), // magenta
+		window:      Some(Color(0xff9ece6a,),),
+		battery:     Some(Color(0xff9ece6a,),),
+	},
+};
+
+impl Default for Theme {
+	fn default() -> Self {
+		MOCHA
+	}
+}
+
+impl Theme {
+	/// The bundled theme called `name`
+	pub fn bundled(name: &str,) -> Option<Self,> {
+		match name {
+			"mocha" => Some(MOCHA,),
+			"latte" => Some(LATTE,),
+			"frappe" => Some(FRAPPE,),
+			"gruvbox" => Some(GRUVBOX,),
+			"tokyo-night" => Some(TOKYO_NIGHT,),
+			_ => None,
+		}
+	}
+
+	/// The theme called `name`, among the bundled ones and `user`, with
+	/// every user theme it extends laid over its base
+	pub fn resolve(name: &str, user: &BTreeMap<String, Theme,>,) -> Result<Self,> {
+		let mut chain = Vec::new();
+		let mut next = name;
+		let mut theme = loop {
+			if let Some(theme,) = Self::bundled(next,) {
+				break theme;
+			}
+			let Some(theme,) = user.get(next,) else {
+				bail!("unknown theme `{}`, expected one of {}", next, names(user,).join(", "));
+			};
+			if chain.iter().any(|(seen, _,)| *seen == next,) {
+				bail!("theme `{}` extends itself", next);
+			}
+			chain.push((next, theme,),);
+			next = theme.extends.as_deref().unwrap_or(DEFAULT_THEME,);
+		};
+		for (_, user_theme,) in chain.iter().rev() {
+			theme.overlay(user_theme,);
+		}
+		Ok(theme,)
+	}
+
+	/// Set every color `other` sets, keeping the rest
+	pub fn overlay(&mut self, other: &Self,) {
+		let Self {
+			extends: _,
+			bar,
+			item_background,
+			border,
+			text,
+			inactive,
+			focus,
+			warning,
+			critical,
+			accents,
+		} = other;
+		set(&mut self.bar, bar,);
+		set(&mut self.item_background, item_background,);
+		set(&mut self.border, border,);
+		set(&mut self.text, text,);
+		set(&mut self.inactive, inactive,);
+		set(&mut self.focus, focus,);
+		set(&mut self.warning, warning,);
+		set(&mut self.critical, critical,);
+
+		let Accents { clock, keyboard, spaces, current_app, window, battery, } = accents;
+		set(&mut self.accents.clock, clock,);
+		set(&mut self.accents.keyboard, keyboard,);
+		set(&mut self.accents.spaces, spaces,);
+		set(&mut self.accents.current_app, current_app,);
+		set(&mut self.accents.window, window,);
+		set(&mut self.accents.battery, battery,);
+	}
+
+	/// The `--bar` properties the theme colors
+	pub fn bar_properties(&self,) -> BarProperties {
+		BarProperties { color: self.bar, ..Default::default() }
+	}
+
+	/// The `--default` properties the theme colors
+	pub fn default_properties(&self,) -> ItemProperties {
+		ItemProperties {
+			background: BackgroundProperties {
+				color: self.item_background,
+				border_color: self.border,
+				..Default::default()
+			},
+			..Default::default()
+		}
+	}
+
+	/// Battery color by charge: the accent while charging or above 45%,
+	/// then warning, then critical below 25%
+	pub fn battery(&self, charge: u8, charging: bool,) -> Option<Color,> {
+		match charge {
+			_ if charging => self.accents.battery,
+			45.. => self.accents.battery,
+			25..=44 => self.warning,
+			_ => self.critical,
+		}
+	}
+}
+
+/// Every theme name there is to pick from, bundled ones first
+pub fn names(user: &BTreeMap<String, Theme,>,) -> Vec<String,> {
+	BUNDLED_THEMES.iter().map(|name| name.to_string(),).chain(user.keys().cloned(),).collect()
+}
+
+fn set(field: &mut Option<Color,>, other: &Option<Color,>,) {
+	if other.is_some() {
+		*field = *other;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn user(themes: &[(&str, &str,)],) -> BTreeMap<String, Theme,> {
+		themes
+			.iter()
+			.map(|(name, toml,)| (name.to_string(), toml::from_str(toml,).unwrap(),),)
+			.collect()
+	}
+
+	#[test]
+	fn test_bundled_themes_set_every_role() {
+		for name in BUNDLED_THEMES {
+			let bundled = Theme::bundled(name,).unwrap();
+			let theme = Theme { extends: Some(name.to_string(),), ..bundled };
+			assert!(!format!("{:?}", theme).contains("None"), "{:?}", theme);
+		}
+		assert_eq!(Theme::default(), MOCHA);
+	}
+
+	#[test]
+	fn test_user_themes_extend_their_base() -> Result<(),> {
+		let themes = user(&[
+			("nord", "extends = \"frappe\"\nfocus = \"#88c0d0\"\naccents.clock = \"#ebcb8b\"\n",),
+			("nord-dim", "extends = \"nord\"\nitem_background = \"#2e3440\"\n",),
+			("plain", "focus = \"#ffffff\"\n",),
+		],);
+
+		let nord = Theme::resolve("nord-dim", &themes,)?;
+		assert_eq!(nord.item_background, Some(Color(0xff2e3440)));
+		assert_eq!(nord.focus, Some(Color(0xff88c0d0)));
+		assert_eq!(nord.accents.clock, Some(Color(0xffebcb8b)));
+		assert_eq!(nord.accents.keyboard, FRAPPE.accents.keyboard);
+
+		// Without `extends`, a theme is laid over mocha
+		let plain = Theme::resolve("plain", &themes,)?;
+		assert_eq!(plain.focus, Some(Color(0xffffffff)));
+		assert_eq!(plain.critical, MOCHA.critical);
+		Ok((),)
+	}
+
+	#[test]
+	fn test_unknown_and_circular_themes() {
+		let themes = user(&[("a", "extends = \"b\"\n",), ("b", "extends = \"a\"\n",)],);
+		let error = |name: &str| Theme::resolve(name, &themes,).unwrap_err().to_string();
+
+		assert_eq!(error("a"), "theme `a` extends itself");
+		assert_eq!(
+			error("solarized"),
+			"unknown theme `solarized`, expected one of mocha, latte, frappe, gruvbox, \
+			 tokyo-night, a, b"
+		);
+	}
+
+	#[test]
+	fn test_battery_colors() {
+		assert_eq!(MOCHA.battery(80, false), Some(Color(Colors::GREEN)));
+		assert_eq!(MOCHA.battery(10, true), Some(Color(Colors::GREEN)));
+		assert_eq!(MOCHA.battery(30, false), Some(Color(Colors::PEACH)));
+		assert_eq!(MOCHA.battery(10, false), Some(Color(Colors::RED)));
+		assert_eq!(LATTE.battery(44, false), LATTE.warning);
+	}
+}
@@ -0,0 +1,260 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::debug;
+use tracing::warn;
+
+use super::WindowManagerBackend;
+use super::default_displays;
+use crate::helpers::yabai::DisplayFrame;
+use crate::helpers::yabai::DisplayInfo;
+use crate::runner::Invocation;
+use crate::runner::RunError;
+use crate::runner::Runner;
+use crate::state::SpaceInfo;
+use crate::state::WindowInfo;
+
+const MONITOR_FORMAT: &str = "%{monitor-id} %{monitor-name}";
+const WORKSPACE_FORMAT: &str = "%{workspace} %{monitor-id} %{workspace-is-focused}";
+const WINDOW_FORMAT: &str = "%{window-id} %{app-name} %{window-title} %{workspace} %{monitor-id}";
+
+/// An `aerospace` listing as JSON, with the fields in `format`
+pub fn list(command: &str, args: &[&str], format: &str,) -> Invocation {
+	Invocation::new("aerospace", &[&[command,], args, &["--json", "--format", format,],].concat(),)
+}
+
+/// Backend that lists AeroSpace monitors, workspaces and windows
+///
+/// AeroSpace names its workspaces instead of numbering them, so each one is
+/// given the index of its position in `list-workspaces --all`, keeping its
+/// name as the space label. Monitor geometry is not reported and is left zero.
+#[derive(Debug, Clone,)]
+pub struct AeroSpaceBackend {
+	runner: Arc<dyn Runner,>,
+}
+
+impl AeroSpaceBackend {
+	pub fn new(runner: Arc<dyn Runner,>,) -> Self {
+		Self { runner, }
+	}
+
+	async fn workspaces(&self,) -> Result<Vec<AeroSpaceWorkspace,>,> {
+		let workspaces = list("list-workspaces", &["--all",], WORKSPACE_FORMAT,);
+		Ok(workspaces.json(self.runner.as_ref(),).await?,)
+	}
+
+	async fn list_windows(&self, scope: &str,) -> Result<Vec<AeroSpaceWindow,>,> {
+		let windows = list("list-windows", &[scope,], WINDOW_FORMAT,);
+		Ok(windows.json(self.runner.as_ref(),).await?,)
+	}
+}
+
+/// 1-based position of the workspace called `name`, or 0 if it is not listed
+fn workspace_index(workspaces: &[AeroSpaceWorkspace], name: &str,) -> u32 {
+	workspaces.iter().position(|w| w.workspace == name,).map_or(0, |i| i as u32 + 1,)
+}
+
+#[async_trait]
+impl WindowManagerBackend for AeroSpaceBackend {
+	fn name(&self,) -> &'static str {
+		"aerospace"
+	}
+
+	async fn displays(&self,) -> Result<HashMap<String, DisplayInfo,>,> {
+		let monitors = list("list-monitors", &[], MONITOR_FORMAT,);
+		let monitors: Vec<AeroSpaceMonitor,> = match monitors.json(self.runner.as_ref(),).await {
+			Ok(monitors,) => monitors,
+			Err(RunError::NotFound { .. } | RunError::Failed { .. },) => {
+				warn!("⚠️  AeroSpace not available, using default display");
+				return Ok(default_displays(),);
+			},
+			Err(e,) => return Err(e.into(),),
+		};
+
+		Ok(monitors
+			.into_iter()
+			.map(|monitor| {
+				let display_info = DisplayInfo {
+					index:      monitor.id,
+					is_builtin: monitor.name.contains("Built-in",) || monitor.id == 1,
+					frame:      DisplayFrame { x: 0.0, y: 0.0, w: 0.0, h: 0.0, },
+				};
+				(monitor.id.to_string(), display_info,)
+			},)
+			.collect(),)
+	}
+
+	async fn spaces(&self,) -> Result<Vec<SpaceInfo,>,> {
+		let workspaces = self.workspaces().await?;
+		let windows = self.list_windows("--all",).await?;
+
+		let spaces: Vec<SpaceInfo,> = workspaces
+			.iter()
+			.enumerate()
+			.map(|(i, workspace,)| SpaceInfo {
+				index:     i as u32 + 1,
+				display:   workspace.monitor_id,
+				has_focus: workspace.is_focused,
+				windows:   windows
+					.iter()
+					.filter(|w| w.workspace == workspace.workspace,)
+					.map(|w| w.id,)
+					.collect(),
+				label:     workspace.workspace.clone(),
+			},)
+			.collect();
+
+		debug!("📊 Queried {} workspaces from AeroSpace", spaces.len());
+		Ok(spaces,)
+	}
+
+	async fn windows(&self,) -> Result<Vec<WindowInfo,>,> {
+		let workspaces = self.workspaces().await?;
+		let focused = self.list_windows("--focused",).await?.first().map(|w| w.id,);
+
+		let windows: Vec<WindowInfo,> = self
+			.list_windows("--all",)
+			.await?
+			.into_iter()
+			.map(|w| WindowInfo {
+				id:        w.id,
+				space:     workspace_index(&workspaces, &w.workspace,),
+				display:   w.monitor_id,
+				has_focus: focused == Some(w.id,),
+				app:       w.app,
+				title:     w.title,
+			},)
+			.collect();
+
+		debug!("🪟 Queried {} windows from AeroSpace", windows.len());
+		Ok(windows,)
+	}
+
+	async fn focused_app(&self,) -> Result<String,> {
+		match self.list_windows("--focused",).await?.into_iter().next() {
+			Some(window,) => Ok(window.app,),
+			None => anyhow::bail!("AeroSpace reports no focused window"),
+		}
+	}
+}
+
+#[derive(Debug, Deserialize,)]
+struct AeroSpaceMonitor {
+	#[serde(rename = "monitor-id")]
+	id:   u32,
+	#[serde(rename = "monitor-name")]
+	name: String,
+}
+
+#[derive(Debug, Deserialize,)]
+struct AeroSpaceWorkspace {
+	workspace:  String,
+	#[serde(rename = "monitor-id")]
+	monitor_id: u32,
+	#[serde(rename = "workspace-is-focused")]
+	is_focused: bool,
+}
+
+#[derive(Debug, Deserialize,)]
+struct AeroSpaceWindow {
+	#[serde(rename = "window-id")]
+	id:         u32,
+	#[serde(rename = "app-name")]
+	app:        String,
+	#[serde(rename = "window-title")]
+	title:      String,
+	workspace:  String,
+	#[serde(rename = "monitor-id")]
+	monitor_id: u32,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::FakeRunner;
+
+	fn aerospace() -> (AeroSpaceBackend, FakeRunner,) {
+		let runner = FakeRunner::new();
+		let workspaces = list("list-workspaces", &["--all",], WORKSPACE_FORMAT,);
+		runner.respond(
+			&workspaces.command_line(),
+			r#"[
+				{"workspace": "1", "monitor-id": 1, "workspace-is-focused": false},
+				{"workspace": "web", "monitor-id": 2, "workspace-is-focused": true}
+			]"#,
+		);
+		let windows = list("list-windows", &["--all",], WINDOW_FORMAT,);
+		runner.respond(
+			&windows.command_line(),
+			r#"[
+				{"window-id": 11, "app-name": "Terminal", "window-title": "zsh",
+				 "workspace": "1", "monitor-id": 1},
+				{"window-id": 12, "app-name": "Safari", "window-title": "Docs",
+				 "workspace": "web", "monitor-id": 2}
+			]"#,
+		);
+		let focused = list("list-windows", &["--focused",], WINDOW_FORMAT,);
+		runner.respond(
+			&focused.command_line(),
+			r#"[{"window-id": 12, "app-name": "Safari", "window-title": "Docs",
+			     "workspace": "web", "monitor-id": 2}]"#,
+		);
+		(AeroSpaceBackend::new(Arc::new(runner.clone(),),), runner,)
+	}
+
+	#[tokio::test]
+	async fn test_workspaces_become_numbered_spaces() -> Result<(),> {
+		let (backend, _,) = aerospace();
+
+		let spaces = backend.spaces().await?;
+		assert_eq!(spaces, vec![
+			SpaceInfo {
+				index:     1,
+				display:   1,
+				has_focus: false,
+				windows:   vec![11],
+				label:     "1".to_string(),
+			},
+			SpaceInfo {
+				index:     2,
+				display:   2,
+				has_focus: true,
+				windows:   vec![12],
+				label:     "web".to_string(),
+			},
+		]);
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_windows_and_focus() -> Result<(),> {
+		let (backend, _,) = aerospace();
+
+		let windows = backend.windows().await?;
+		let safari = windows.iter().find(|w| w.id == 12,).unwrap();
+		assert_eq!((safari.space, safari.has_focus), (2, true));
+		assert!(!windows.iter().find(|w| w.id == 11).unwrap().has_focus);
+		assert_eq!(backend.focused_app().await?, "Safari");
+		Ok((),)
+	}
+
+	#[tokio::test]
+	async fn test_monitors_and_missing_aerospace() -> Result<(),> {
+		let (backend, runner,) = aerospace();
+		runner.respond(
+			&list("list-monitors", &[], MONITOR_FORMAT,).command_line(),
+			r#"[{"monitor-id": 1, "monitor-name": "Built-in Retina Display"},
+			    {"monitor-id": 2, "monitor-name": "DELL U2720Q"}]"#,
+		);
+		let displays = backend.displays().await?;
+		assert!(displays["1"].is_builtin);
+		assert!(!displays["2"].is_builtin);
+
+		let missing = AeroSpaceBackend::new(Arc::new(FakeRunner::new(),),);
+		assert_eq!(missing.displays().await?, default_displays());
+		assert!(missing.spaces().await.is_err());
+		Ok((),)
+	}
+}
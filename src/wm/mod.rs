@@ -0,0 +1,113 @@
+pub mod aerospace;
+pub mod scripted;
+pub mod yabai;
+
+use anyhow::Result;
+use anyhow::bail;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::helpers::yabai::DisplayFrame;
+use crate::helpers::yabai::DisplayInfo;
+use crate::runner::Runner;
+use crate::state::SpaceInfo;
+use crate::state::WindowInfo;
+
+pub use aerospace::AeroSpaceBackend;
+pub use scripted::ScriptedBackend;
+pub use yabai::YabaiBackend;
+
+/// Where the daemon learns about displays, spaces and windows
+///
+/// Every backend reports in the daemon's own types, so nothing past
+/// [`DaemonState`](crate::state::DaemonState) knows which window manager is
+/// running.
+#[async_trait]
+pub trait WindowManagerBackend: Debug + Send + Sync {
+	/// Short name for logs, e.g. `yabai`
+	fn name(&self,) -> &'static str;
+
+	/// Connected displays, keyed by display index
+	async fn displays(&self,) -> Result<HashMap<String, DisplayInfo,>,>;
+
+	async fn spaces(&self,) -> Result<Vec<SpaceInfo,>,>;
+
+	async fn windows(&self,) -> Result<Vec<WindowInfo,>,>;
+
+	/// Name of the frontmost application
+	async fn focused_app(&self,) -> Result<String,>;
+
+	/// Have the window manager report its changes to the daemon socket at
+	/// `socket`; `false` when it cannot, and the daemon has to poll
+	async fn watch(&self, _socket: &Path,) -> Result<bool,> {
+		Ok(false,)
+	}
+
+	/// Stop reporting to the daemon socket
+	async fn unwatch(&self,) -> Result<(),> {
+		Ok((),)
+	}
+}
+
+/// Which window manager the daemon talks to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default,)]
+pub enum WindowManager {
+	#[default]
+	Yabai,
+	AeroSpace,
+}
+
+impl FromStr for WindowManager {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str,) -> Result<Self,> {
+		match s {
+			"yabai" | "" => Ok(WindowManager::Yabai,),
+			"aerospace" => Ok(WindowManager::AeroSpace,),
+			other => bail!("Unknown window manager {:?}, expected yabai or aerospace", other),
+		}
+	}
+}
+
+impl WindowManager {
+	/// Read `SKETCHYBAR_DAEMON_WM`
+	pub fn from_env() -> Result<Self,> {
+		std::env::var("SKETCHYBAR_DAEMON_WM",).unwrap_or_default().parse()
+	}
+
+	/// The backend for this window manager, running its commands through `runner`
+	pub fn backend(self, runner: Arc<dyn Runner,>,) -> Arc<dyn WindowManagerBackend,> {
+		match self {
+			WindowManager::Yabai => Arc::new(YabaiBackend::new(runner,),),
+			WindowManager::AeroSpace => Arc::new(AeroSpaceBackend::new(runner,),),
+		}
+	}
+}
+
+/// The display to assume when the window manager is not running
+pub fn default_displays() -> HashMap<String, DisplayInfo,> {
+	HashMap::from([(
+		"1".to_string(),
+		DisplayInfo {
+			index:      1,
+			is_builtin: true,
+			frame:      DisplayFrame { x: 0.0, y: 0.0, w: 1920.0, h: 1080.0, },
+		},
+	),],)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_window_manager_from_str() {
+		assert_eq!("".parse::<WindowManager>().unwrap(), WindowManager::Yabai);
+		assert_eq!("aerospace".parse::<WindowManager>().unwrap(), WindowManager::AeroSpace);
+		assert!("amethyst".parse::<WindowManager>().is_err());
+	}
+}
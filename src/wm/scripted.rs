@@ -0,0 +1,109 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use super::WindowManagerBackend;
+use super::default_displays;
+use crate::helpers::yabai::DisplayInfo;
+use crate::state::SpaceInfo;
+use crate::state::WindowInfo;
+
+/// In-memory backend whose desktop is set by the test driving it
+///
+/// Clones share the same desktop, so a test can keep one handle and change
+/// spaces and windows while the daemon under test polls the others. The
+/// focused app follows the focused window unless set explicitly.
+#[derive(Debug, Clone,)]
+pub struct ScriptedBackend {
+	inner: Arc<Mutex<ScriptedDesktop,>,>,
+}
+
+#[derive(Debug,)]
+struct ScriptedDesktop {
+	displays:    HashMap<String, DisplayInfo,>,
+	spaces:      Vec<SpaceInfo,>,
+	windows:     Vec<WindowInfo,>,
+	focused_app: Option<String,>,
+	unavailable: bool,
+}
+
+impl Default for ScriptedBackend {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl ScriptedBackend {
+	/// One builtin display and nothing on it
+	pub fn new() -> Self {
+		Self {
+			inner: Arc::new(Mutex::new(ScriptedDesktop {
+				displays:    default_displays(),
+				spaces:      Vec::new(),
+				windows:     Vec::new(),
+				focused_app: None,
+				unavailable: false,
+			},),),
+		}
+	}
+
+	pub fn set_displays(&self, displays: Vec<DisplayInfo,>,) {
+		self.inner.lock().unwrap().displays =
+			displays.into_iter().map(|d| (d.index.to_string(), d,),).collect();
+	}
+
+	pub fn set_spaces(&self, spaces: Vec<SpaceInfo,>,) {
+		self.inner.lock().unwrap().spaces = spaces;
+	}
+
+	pub fn set_windows(&self, windows: Vec<WindowInfo,>,) {
+		self.inner.lock().unwrap().windows = windows;
+	}
+
+	pub fn set_focused_app(&self, app: &str,) {
+		self.inner.lock().unwrap().focused_app = Some(app.to_string(),);
+	}
+
+	/// Fail every query, as if the window manager had quit
+	pub fn set_unavailable(&self, unavailable: bool,) {
+		self.inner.lock().unwrap().unavailable = unavailable;
+	}
+
+	fn desktop<T,>(&self, read: impl FnOnce(&ScriptedDesktop,) -> T,) -> Result<T,> {
+		let desktop = self.inner.lock().unwrap();
+		if desktop.unavailable {
+			anyhow::bail!("scripted window manager is unavailable");
+		}
+		Ok(read(&desktop,),)
+	}
+}
+
+#[async_trait]
+impl WindowManagerBackend for ScriptedBackend {
+	fn name(&self,) -> &'static str {
+		"scripted"
+	}
+
+	async fn displays(&self,) -> Result<HashMap<String, DisplayInfo,>,> {
+		self.desktop(|desktop| desktop.displays.clone(),)
+	}
+
+	async fn spaces(&self,) -> Result<Vec<SpaceInfo,>,> {
+		self.desktop(|desktop| desktop.spaces.clone(),)
+	}
+
+	async fn windows(&self,) -> Result<Vec<WindowInfo,>,> {
+		self.desktop(|desktop| desktop.windows.clone(),)
+	}
+
+	async fn focused_app(&self,) -> Result<String,> {
+		self.desktop(|desktop| {
+			desktop.focused_app.clone().or_else(|| {
+				desktop.windows.iter().find(|w| w.has_focus,).map(|w| w.app.clone(),)
+			},)
+		},)?
+		.ok_or_else(|| anyhow::anyhow!("no focused window"),)
+	}
+}
@@ -0,0 +1,268 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::debug;
+use tracing::warn;
+
+use super::WindowManagerBackend;
+use super::default_displays;
+use crate::helpers::yabai::DisplayFrame;
+use crate::helpers::yabai::DisplayInfo;
+use crate::helpers::yabai::YabaiClient;
+use crate::runner::Invocation;
+use crate::runner::RunError;
+use crate::runner::Runner;
+use crate::state::SpaceInfo;
+use crate::state::WindowInfo;
+
+/// yabai events that make the synced state stale
+pub const SIGNAL_EVENTS: &[&str] = &[
+	"space_changed",
+	"space_created",
+	"space_destroyed",
+	"display_added",
+	"display_removed",
+	"display_changed",
+	"window_created",
+	"window_destroyed",
+	"window_focused",
+	"window_title_changed",
+	"window_minimized",
+	"window_deminimized",
+	"application_front_switched",
+	"application_terminated",
+	"system_woke",
+];
+
+/// Label of the signal the daemon registers for `event`
+pub fn signal_label(event: &str,) -> String {
+	format!("sketchybar_daemon_{}", event)
+}
+
+/// Shell command a signal runs to report `event` to the daemon socket
+pub fn signal_action(socket: &Path, event: &str,) -> String {
+	format!("echo 'yabai {}' | nc -U -w 1 '{}'", event, socket.display())
+}
+
+/// Backend that queries yabai, falling back to AppleScript for the focused app
+///
+/// Queries go over yabai's socket through a [`YabaiClient`], and through the
+/// CLI on `runner` while the socket is missing.
+#[derive(Debug, Clone,)]
+pub struct YabaiBackend {
+	client: YabaiClient,
+	runner: Arc<dyn Runner,>,
+}
+
+impl YabaiBackend {
+	pub fn new(runner: Arc<dyn Runner,>,) -> Self {
+		Self::with_client(YabaiClient::new(runner.clone(),), runner,)
+	}
+
+	pub fn with_client(client: YabaiClient, runner: Arc<dyn Runner,>,) -> Self {
+		Self { client, runner, }
+	}
+
+	pub fn client(&self,) -> &YabaiClient {
+		&self.client
+	}
+}
+
+#[async_trait]
+impl WindowManagerBackend for YabaiBackend {
+	fn name(&self,) -> &'static str {
+		"yabai"
+	}
+
+	async fn displays(&self,) -> Result<HashMap<String, DisplayInfo,>,> {
+		let yabai_displays: Vec<YabaiDisplay,> = match self.client.query(&["--displays",],).await {
+			Ok(yabai_displays,) => yabai_displays,
+			Err(RunError::NotFound { .. } | RunError::Failed { .. },) => {
+				// Fallback if yabai is not available
+				warn!("⚠️  yabai not available, using default display");
+				return Ok(default_displays(),);
+			},
+			// A slow or garbled answer says nothing about which displays are gone
+			Err(e,) => return Err(e.into(),),
+		};
+
+		let mut displays = HashMap::new();
+
+		for display in yabai_displays {
+			let display_info = DisplayInfo {
+				index:      display.index,
+				is_builtin: display.label.contains("Built-in",) || display.index == 1,
+				frame:      DisplayFrame {
+					x: display.frame.x,
+					y: display.frame.y,
+					w: display.frame.w,
+					h: display.frame.h,
+				},
+			};
+
+			displays.insert(display.index.to_string(), display_info,);
+		}
+
+		Ok(displays,)
+	}
+
+	async fn spaces(&self,) -> Result<Vec<SpaceInfo,>,> {
+		let yabai_spaces: Vec<YabaiSpace,> = self.client.query(&["--spaces",],).await?;
+
+		let spaces: Vec<SpaceInfo,> = yabai_spaces
+			.into_iter()
+			.map(|s| SpaceInfo {
+				index:     s.index,
+				display:   s.display,
+				has_focus: s.has_focus,
+				windows:   s.windows,
+				label:     s.label,
+			},)
+			.collect();
+
+		debug!("📊 Queried {} spaces from yabai", spaces.len());
+		Ok(spaces,)
+	}
+
+	async fn windows(&self,) -> Result<Vec<WindowInfo,>,> {
+		let yabai_windows: Vec<YabaiWindow,> = self.client.query(&["--windows",],).await?;
+
+		let windows: Vec<WindowInfo,> = yabai_windows
+			.into_iter()
+			.map(|w| WindowInfo {
+				id:        w.id,
+				app:       w.app,
+				title:     w.title,
+				space:     w.space,
+				display:   w.display,
+				has_focus: w.has_focus,
+			},)
+			.collect();
+
+		debug!("🪟 Queried {} windows from yabai", windows.len());
+		Ok(windows,)
+	}
+
+	async fn focused_app(&self,) -> Result<String,> {
+		// Try yabai first
+		if let Ok(window,) = self.client.query::<YabaiWindow,>(&["--windows", "--window",],).await {
+			return Ok(window.app,);
+		}
+
+		// Fallback to AppleScript
+		let app = Invocation::new("osascript", &[
+			"-e",
+			"tell application \"System Events\" to get name of first application process whose \
+			 frontmost is true",
+		],)
+		.output(self.runner.as_ref(),)
+		.await?;
+
+		Ok(app.trim().to_string(),)
+	}
+
+	/// Register a signal per [`SIGNAL_EVENTS`], replacing any left behind by
+	/// an earlier daemon under the same label
+	async fn watch(&self, socket: &Path,) -> Result<bool,> {
+		for event in SIGNAL_EVENTS {
+			let event_arg = format!("event={}", event);
+			let label_arg = format!("label={}", signal_label(event,));
+			let action_arg = format!("action={}", signal_action(socket, event,));
+			self.client.message(&["signal", "--add", &event_arg, &label_arg, &action_arg,],).await?;
+		}
+		debug!("📡 Registered {} yabai signals", SIGNAL_EVENTS.len());
+		Ok(true,)
+	}
+
+	async fn unwatch(&self,) -> Result<(),> {
+		let mut result = Ok((),);
+		for event in SIGNAL_EVENTS {
+			let label = signal_label(event,);
+			if let Err(e,) = self.client.message(&["signal", "--remove", &label,],).await {
+				debug!("Could not remove yabai signal {}: {}", label, e);
+				result = Err(e.into(),);
+			}
+		}
+		result
+	}
+}
+
+#[derive(Debug, Deserialize,)]
+struct YabaiDisplay {
+	index: u32,
+	label: String,
+	frame: YabaiFrame,
+}
+
+#[derive(Debug, Deserialize,)]
+struct YabaiFrame {
+	x: f64,
+	y: f64,
+	w: f64,
+	h: f64,
+}
+
+#[derive(Debug, Deserialize,)]
+struct YabaiSpace {
+	index:     u32,
+	display:   u32,
+	#[serde(rename = "has-focus")]
+	has_focus: bool,
+	windows:   Vec<u32,>,
+	label:     String,
+}
+
+#[derive(Debug, Deserialize,)]
+struct YabaiWindow {
+	id:        u32,
+	app:       String,
+	title:     String,
+	space:     u32,
+	display:   u32,
+	#[serde(rename = "has-focus")]
+	has_focus: bool,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::FakeRunner;
+
+	#[tokio::test]
+	async fn test_signals_are_registered_and_removed() -> Result<(),> {
+		let runner = FakeRunner::new();
+		let socket = Path::new("/tmp/sketchybar-daemon_test.socket",);
+		for event in SIGNAL_EVENTS {
+			let label = signal_label(event,);
+			let add = format!(
+				"yabai -m signal --add event={} label={} action={}",
+				event,
+				label,
+				signal_action(socket, event,)
+			);
+			runner.respond(&add, "",);
+			runner.respond(&format!("yabai -m signal --remove {}", label), "",);
+		}
+		let runner: Arc<FakeRunner,> = Arc::new(runner,);
+		let client = YabaiClient::with_socket("/nonexistent/yabai.socket", runner.clone(),);
+		let yabai = YabaiBackend::with_client(client, runner.clone(),);
+
+		assert!(yabai.watch(socket).await?);
+		assert_eq!(
+			runner.calls()[0],
+			"yabai -m signal --add event=space_changed label=sketchybar_daemon_space_changed \
+			 action=echo 'yabai space_changed' | nc -U -w 1 '/tmp/sketchybar-daemon_test.socket'"
+		);
+		yabai.unwatch().await?;
+		assert_eq!(runner.calls().len(), 2 * SIGNAL_EVENTS.len());
+
+		// Without yabai there is nothing to register with
+		let missing: Arc<FakeRunner,> = Arc::new(FakeRunner::new(),);
+		let client = YabaiClient::with_socket("/nonexistent/yabai.socket", missing.clone(),);
+		assert!(YabaiBackend::with_client(client, missing,).watch(socket).await.is_err());
+		Ok((),)
+	}
+}